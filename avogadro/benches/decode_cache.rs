@@ -0,0 +1,52 @@
+//! Simulated clock speed with and without the decode cache.
+//!
+//! Run with `cargo bench -p avr-avogadro --bench decode_cache`
+extern crate avr_avogadro;
+
+use avr_avogadro::core::assembler;
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use std::time::Instant;
+
+/// Clock cycles run by each measurement
+const CYCLES: usize = 20_000_000;
+
+const BLINK: &[u8] = include_bytes!("../tests/blink.bin");
+const CHECKSUM_SOURCE: &str = include_str!("../tests/checksum.s");
+
+/// Simulated MHz running `program` on a `device` MCU
+fn simulated_mhz(device: &str, program: &[u8], cached: bool) -> f64 {
+    let mut mcu: Mcu = McuFactory::create(device);
+    mcu.set_decode_cache_enabled(cached);
+    mcu.load_program_memory(program);
+    let start = Instant::now();
+    while mcu.get_cycle_count() < CYCLES {
+        mcu.step();
+    }
+    mcu.get_cycle_count() as f64 / start.elapsed().as_secs_f64() / 1e6
+}
+
+fn main() {
+    let checksum = assembler::assemble(CHECKSUM_SOURCE)
+        .expect("Cannot assemble checksum firmware")
+        .image;
+    let firmwares = [
+        ("blink", "attiny85", BLINK),
+        ("checksum", "atmega328p", &checksum[..]),
+    ];
+    println!(
+        "{:<10} {:>12} {:>12} {:>8}",
+        "firmware", "uncached", "cached", "speedup"
+    );
+    for (name, device, program) in firmwares {
+        let uncached = simulated_mhz(device, program, false);
+        let cached = simulated_mhz(device, program, true);
+        println!(
+            "{:<10} {:>8.2} MHz {:>8.2} MHz {:>7.2}x",
+            name,
+            uncached,
+            cached,
+            cached / uncached
+        );
+    }
+}
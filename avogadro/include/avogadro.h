@@ -0,0 +1,590 @@
+/* AVR-Avogadro C API, generated by cbindgen from src/ffi/mcu_wrapper.rs */
+
+#ifndef AVOGADRO_H
+#define AVOGADRO_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Version of the C API, incremented on incompatible changes
+#define AVOGADRO_API_VERSION 1
+
+// Mask of every `McuEventKind`
+#define MCU_EVENT_ALL 1023
+
+// Kind of an `McuEvent`, whose bit in event masks is `1 << kind`
+typedef enum McuEventKind {
+  // `value` is the raw instruction
+  MCU_EVENT_KIND_INSTRUCTION = 0,
+  // `address` and `value` are the data memory address and byte
+  MCU_EVENT_KIND_MEMORY_READ = 1,
+  MCU_EVENT_KIND_MEMORY_WRITE = 2,
+  MCU_EVENT_KIND_IO_READ = 3,
+  MCU_EVENT_KIND_IO_WRITE = 4,
+  // `address` is the vector
+  MCU_EVENT_KIND_INTERRUPT_ENTRY = 5,
+  MCU_EVENT_KIND_INTERRUPT_EXIT = 6,
+  MCU_EVENT_KIND_SLEEP = 7,
+  MCU_EVENT_KIND_WAKE = 8,
+  // `fault` tells which one
+  MCU_EVENT_KIND_FAULT = 9,
+} McuEventKind;
+
+// Kind of a fault event
+typedef enum McuFault {
+  MCU_FAULT_NONE = 0,
+  MCU_FAULT_BREAK = 1,
+  // `address` is the data memory address accessed
+  MCU_FAULT_ACCESS_VIOLATION = 2,
+  // `address` is the stack pointer
+  MCU_FAULT_STACK_COLLISION = 3,
+  // `address` is the return address read from the stack
+  MCU_FAULT_RETURN_MISMATCH = 4,
+} McuFault;
+
+// Status of a runner, from `mcu_runner_get_status`
+typedef enum McuRunStatus {
+  MCU_RUN_STATUS_PAUSED = 0,
+  MCU_RUN_STATUS_RUNNING = 1,
+  // Paused at a breakpoint
+  MCU_RUN_STATUS_BREAKPOINT = 2,
+  // The simulator panicked, only `mcu_runner_destroy` can be called
+  MCU_RUN_STATUS_STOPPED = 3,
+} McuRunStatus;
+
+// Source level step of `mcu_source_step`
+typedef enum McuSourceStep {
+  // Runs to the next source line, entering calls
+  MCU_SOURCE_STEP_INTO = 0,
+  // Runs to the next source line, running calls to completion
+  MCU_SOURCE_STEP_OVER = 1,
+  // Runs until the current function returns
+  MCU_SOURCE_STEP_OUT = 2,
+} McuSourceStep;
+
+// Result of C API calls
+typedef enum McuStatus {
+  MCU_STATUS_OK = 0,
+  // A required pointer argument, or the handle, was null
+  MCU_STATUS_NULL_POINTER = 1,
+  // An argument was out of range or not valid UTF-8
+  MCU_STATUS_INVALID_ARGUMENT = 2,
+  // A file couldn't be read or written
+  MCU_STATUS_IO_ERROR = 3,
+  // The operation had nothing to do, like stepping back without history
+  MCU_STATUS_NOT_FOUND = 4,
+  // The simulator panicked, in this call or a previous one
+  MCU_STATUS_PANIC = 5,
+} McuStatus;
+
+// Why a source level step stopped
+typedef enum McuStepStop {
+  MCU_STEP_STOP_LINE = 0,
+  MCU_STEP_STOP_RETURN = 1,
+  MCU_STEP_STOP_BREAKPOINT = 2,
+  MCU_STEP_STOP_CYCLE_LIMIT = 3,
+} McuStepStop;
+
+// Opaque MCU handle
+typedef struct AvogadroMcu AvogadroMcu;
+
+// Opaque handle of an MCU running on a worker thread. Its functions can be
+// called from any thread.
+typedef struct AvogadroRunner AvogadroRunner;
+
+// Event passed to hook callbacks
+typedef struct McuEvent {
+  enum McuEventKind kind;
+  // Address of the instruction
+  uint16_t pc;
+  uint16_t address;
+  uint16_t value;
+  enum McuFault fault;
+  uint64_t cycle;
+} McuEvent;
+
+// Hook callback, receiving the event and the user data pointer given to
+// `mcu_add_hook`
+typedef void (*McuEventCallback)(const struct McuEvent *p_event, void *p_user_data);
+
+// Registers and cycle count of a running MCU, taken between two
+// instructions
+typedef struct McuRunnerState {
+  uint8_t registers[32];
+  uint16_t program_counter;
+  uint16_t stack_pointer;
+  // SREG, carry being bit 0
+  uint8_t flags;
+  uint64_t cycle_count;
+} McuRunnerState;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns `AVOGADRO_API_VERSION` of the library, so programs can check it
+// matches the header they were built with
+uint32_t mcu_api_version(void);
+
+// Creates an MCU of model `p_device`, like "attiny85", keeping a call stack
+// for `mcu_get_backtrace`
+// Returns null if the model is not supported
+// # Safety
+//
+// `p_device` must be a valid C string
+struct AvogadroMcu *mcu_create(const char *p_device);
+
+// Frees an MCU created with `mcu_create`. Null handles are ignored.
+// # Safety
+//
+// `p_mcu` must be null or a handle returned by `mcu_create`, not used
+// afterwards
+void mcu_destroy(struct AvogadroMcu *p_mcu);
+
+// Executes one instruction
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_step(struct AvogadroMcu *p_mcu);
+
+// Calls `Mcu::load_from_file(filename)`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_filename` must be a valid C string
+enum McuStatus mcu_load_bin_file(struct AvogadroMcu *p_mcu,
+                                 const char *p_filename,
+                                 bool is_program);
+
+// Calls `Mcu::load_ihex_file(filename)`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_filename` must be a valid C string
+enum McuStatus mcu_load_ihex_file(struct AvogadroMcu *p_mcu, const char *p_filename);
+
+// Loads `memory_size` bytes from `p_memory` into data memory
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_memory` must be an array of at least `memory_size` bytes
+enum McuStatus mcu_load_data_memory(struct AvogadroMcu *p_mcu,
+                                    const uint8_t *p_memory,
+                                    size_t memory_size);
+
+// Loads `memory_size` bytes from `p_memory` into program memory
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_memory` must be an array of at least `memory_size` bytes
+enum McuStatus mcu_load_program_memory(struct AvogadroMcu *p_mcu,
+                                       const uint8_t *p_memory,
+                                       size_t memory_size);
+
+// Gets data stored in register `reg_num`, from 0 to 31
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_value` must point to a byte
+enum McuStatus mcu_get_register(const struct AvogadroMcu *p_mcu, uint8_t reg_num, uint8_t *p_value);
+
+// Sets data into register `reg_num`, from 0 to 31
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_set_register(struct AvogadroMcu *p_mcu, uint8_t reg_num, uint8_t value);
+
+// Puts registers data into a buffer
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `buffer` must be a char array with at least 32 bytes
+enum McuStatus mcu_get_register_array(const struct AvogadroMcu *p_mcu, uint8_t *buffer);
+
+// Sets every register from a buffer
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_reg` must be a char array with at least 32 bytes
+enum McuStatus mcu_set_register_array(struct AvogadroMcu *p_mcu, const uint8_t *p_reg);
+
+// Gets the program counter, in bytes
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_value` must point to a `uint16_t`
+enum McuStatus mcu_get_program_counter(const struct AvogadroMcu *p_mcu, uint16_t *p_value);
+
+// Sets the program counter, in bytes
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_set_program_counter(struct AvogadroMcu *p_mcu, uint16_t value);
+
+// Gets the stack pointer
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_value` must point to a `uint16_t`
+enum McuStatus mcu_get_stack_pointer(const struct AvogadroMcu *p_mcu, uint16_t *p_value);
+
+// Gets the instruction word at the program counter
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_value` must point to a `uint16_t`
+enum McuStatus mcu_get_current_instruction(const struct AvogadroMcu *p_mcu, uint16_t *p_value);
+
+// Gets the data memory byte at `address`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_value` must point to a byte
+enum McuStatus mcu_get_data_byte(const struct AvogadroMcu *p_mcu,
+                                 uint16_t address,
+                                 uint8_t *p_value);
+
+// Writes the current instruction, in assembly, as a zero terminated string
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `c_buffer` must be a char array with `buf_size` size
+enum McuStatus mcu_display_current_instruction(const struct AvogadroMcu *p_mcu,
+                                               uint8_t *c_buffer,
+                                               size_t buf_size);
+
+// Gets data memory size, in bytes
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_size` must point to a `size_t`
+enum McuStatus mcu_get_data_size(const struct AvogadroMcu *p_mcu, size_t *p_size);
+
+// Gets data memory contents, at most `buf_size` bytes
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `c_buffer` must be a char array with `buf_size` size
+enum McuStatus mcu_get_data_memory(const struct AvogadroMcu *p_mcu,
+                                   uint8_t *c_buffer,
+                                   size_t buf_size);
+
+// Gets program memory size, in bytes
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_size` must point to a `size_t`
+enum McuStatus mcu_get_program_size(const struct AvogadroMcu *p_mcu, size_t *p_size);
+
+// Gets program memory contents, at most `buf_size` bytes
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `c_buffer` must be a char array with `buf_size` size
+enum McuStatus mcu_get_program_memory(const struct AvogadroMcu *p_mcu,
+                                      uint8_t *c_buffer,
+                                      size_t buf_size);
+
+// Gets SREG, carry being bit 0
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_value` must point to a byte
+enum McuStatus mcu_get_flags(const struct AvogadroMcu *p_mcu, uint8_t *p_value);
+
+// Sets SREG, carry being bit 0
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_set_flags(struct AvogadroMcu *p_mcu, uint8_t flags);
+
+// Starts dumping waveforms into VCD file `p_filename`. `p_probes` is a
+// comma separated list of probes, as accepted by `VcdWriter::add_probe_spec`
+// (like "PB0,PORTB,sreg,pc,word:0x60"), and `clock_hz` converts cycles
+// into time.
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_filename` and `p_probes` must be valid C strings
+enum McuStatus mcu_vcd_start(struct AvogadroMcu *p_mcu,
+                             const char *p_filename,
+                             const char *p_probes,
+                             uint64_t clock_hz);
+
+// Stops dumping waveforms and closes the VCD file
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_vcd_stop(struct AvogadroMcu *p_mcu);
+
+// Saves registers, memories and cycle count into snapshot file `p_filename`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_filename` must be a valid C string
+enum McuStatus mcu_save_state(const struct AvogadroMcu *p_mcu, const char *p_filename);
+
+// Restores the machine state from snapshot file `p_filename`. On error the
+// MCU is left untouched.
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_filename` must be a valid C string
+enum McuStatus mcu_load_state(struct AvogadroMcu *p_mcu, const char *p_filename);
+
+// Starts recording execution history, using at most about `budget` bytes,
+// so steps can be undone. A budget of 0 stops recording.
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_set_history_budget(struct AvogadroMcu *p_mcu, size_t budget);
+
+// Undoes the last step
+// Returns `MCU_STATUS_NOT_FOUND` if there is no history left
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_step_back(struct AvogadroMcu *p_mcu);
+
+// Steps back until the program counter is `pc`
+// Returns `MCU_STATUS_NOT_FOUND` if the history ran out first
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_run_back_to(struct AvogadroMcu *p_mcu, uint16_t pc);
+
+// Steps back to the previous breakpoint or watchpoint hit
+// Returns `MCU_STATUS_NOT_FOUND` if the history ran out first
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_reverse_continue(struct AvogadroMcu *p_mcu);
+
+// Stops `mcu_reverse_continue` at program address `pc`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_add_breakpoint(struct AvogadroMcu *p_mcu, uint16_t pc);
+
+// Removes a breakpoint added with `mcu_add_breakpoint`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_remove_breakpoint(struct AvogadroMcu *p_mcu, uint16_t pc);
+
+// Stops `mcu_reverse_continue` at writes to data memory `address`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_add_watchpoint(struct AvogadroMcu *p_mcu, uint16_t address);
+
+// Removes a watchpoint added with `mcu_add_watchpoint`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_remove_watchpoint(struct AvogadroMcu *p_mcu, uint16_t address);
+
+// Loads functions, variables and source lines of ELF file `p_filename`,
+// used by the source level functions and backtraces below. The program
+// isn't loaded.
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_filename` must be a valid C string
+enum McuStatus mcu_load_debug_info(struct AvogadroMcu *p_mcu, const char *p_filename);
+
+// Steps by source lines, writing why it stopped into `p_stop`
+// Returns `MCU_STATUS_NOT_FOUND` if no debug info was loaded
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_stop` must point to an `McuStepStop`
+enum McuStatus mcu_source_step(struct AvogadroMcu *p_mcu,
+                               enum McuSourceStep step,
+                               enum McuStepStop *p_stop);
+
+// Writes the source file of the current instruction, as a zero terminated
+// string, and its line into `p_line`
+// Returns `MCU_STATUS_NOT_FOUND` if it has no line info
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `c_buffer` must be a char array with `buf_size` size
+enum McuStatus mcu_get_source_line(const struct AvogadroMcu *p_mcu,
+                                   uint8_t *c_buffer,
+                                   size_t buf_size,
+                                   uint32_t *p_line);
+
+// Writes the value of variable `p_name`, a local of the current function or
+// a global, formatted as a zero terminated string like "{x = 1, y = 2}"
+// Returns `MCU_STATUS_NOT_FOUND` if there is no such variable
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `p_name` must be a valid C string
+// `c_buffer` must be a char array with `buf_size` size
+enum McuStatus mcu_read_variable(const struct AvogadroMcu *p_mcu,
+                                 const char *p_name,
+                                 uint8_t *c_buffer,
+                                 size_t buf_size);
+
+// Writes parameters and local variables of the current function as a zero
+// terminated string, a "type name = value" line for each one
+// Returns `MCU_STATUS_NOT_FOUND` if no debug info was loaded
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `c_buffer` must be a char array with `buf_size` size
+enum McuStatus mcu_get_locals(const struct AvogadroMcu *p_mcu, uint8_t *c_buffer, size_t buf_size);
+
+// Writes the functions being executed as a zero terminated string, a
+// "#0  0x0012 in add+0x6" line for each frame from the current one. Frames
+// are named with the symbols of `mcu_load_debug_info`, and have their
+// source line if it's known.
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `c_buffer` must be a char array with `buf_size` size
+enum McuStatus mcu_get_backtrace(const struct AvogadroMcu *p_mcu,
+                                 uint8_t *c_buffer,
+                                 size_t buf_size);
+
+// Calls `callback` with `p_user_data` for every event whose kind is in the
+// `kinds` mask, like `MCU_EVENT_ALL`. Memory and I/O accesses are only
+// notified if their address is between `first_address` and `last_address`,
+// both included. Writes the hook id to `p_id`, for `mcu_remove_hook`.
+// # Safety
+//
+// `p_mcu` must be a valid handle
+// `callback` must be safe to call with `p_user_data` while the hook is
+// registered
+enum McuStatus mcu_add_hook(struct AvogadroMcu *p_mcu,
+                            uint32_t kinds,
+                            uint16_t first_address,
+                            uint16_t last_address,
+                            McuEventCallback callback,
+                            void *p_user_data,
+                            size_t *p_id);
+
+// Unregisters a hook added with `mcu_add_hook`
+// Returns `MCU_STATUS_NOT_FOUND` if there is no hook `id`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_remove_hook(struct AvogadroMcu *p_mcu, size_t id);
+
+// Calls `Mcu::enter_interrupt(vector)`
+// # Safety
+//
+// `p_mcu` must be a valid handle
+enum McuStatus mcu_enter_interrupt(struct AvogadroMcu *p_mcu, uint16_t vector);
+
+// Moves the MCU of `p_mcu` into a new runner thread, paused. Hook
+// callbacks are called from that thread while it runs.
+// Returns null if `p_mcu` is null or poisoned, in which case the MCU
+// handle is kept.
+// # Safety
+//
+// `p_mcu` must be a valid handle, not used afterwards unless null is
+// returned
+struct AvogadroRunner *mcu_runner_create(struct AvogadroMcu *p_mcu);
+
+// Stops the runner thread and frees the runner, giving back the MCU as a
+// new handle. Returns null if the simulator panicked or `p_runner` is null.
+// # Safety
+//
+// `p_runner` must be null or a handle returned by `mcu_runner_create`, not
+// used afterwards
+struct AvogadroMcu *mcu_runner_destroy(struct AvogadroRunner *p_runner);
+
+// Runs until `mcu_runner_pause` or a breakpoint
+// # Safety
+//
+// `p_runner` must be a valid handle
+enum McuStatus mcu_runner_run(const struct AvogadroRunner *p_runner);
+
+// Stops running, between two instructions
+// # Safety
+//
+// `p_runner` must be a valid handle
+enum McuStatus mcu_runner_pause(const struct AvogadroRunner *p_runner);
+
+// Runs `count` instructions, stopping early at a breakpoint after the
+// first one
+// # Safety
+//
+// `p_runner` must be a valid handle
+enum McuStatus mcu_runner_step(const struct AvogadroRunner *p_runner, size_t count);
+
+// Stops running at program address `pc`
+// # Safety
+//
+// `p_runner` must be a valid handle
+enum McuStatus mcu_runner_add_breakpoint(const struct AvogadroRunner *p_runner, uint16_t pc);
+
+// Removes a breakpoint added with `mcu_runner_add_breakpoint`
+// # Safety
+//
+// `p_runner` must be a valid handle
+enum McuStatus mcu_runner_remove_breakpoint(const struct AvogadroRunner *p_runner, uint16_t pc);
+
+// Paces running to `multiplier` times the MCU clock, like 1.0 for real
+// time. Zero or negative multipliers run as fast as possible, the default.
+// # Safety
+//
+// `p_runner` must be a valid handle
+enum McuStatus mcu_runner_set_speed(const struct AvogadroRunner *p_runner, double multiplier);
+
+// Gets the simulated clock cycles per second while running, measured over
+// the last half second. 0 while paused.
+// # Safety
+//
+// `p_runner` must be a valid handle
+// `p_hz` must point to a `double`
+enum McuStatus mcu_runner_get_achieved_speed(const struct AvogadroRunner *p_runner, double *p_hz);
+
+// Gets the status after the last command handled by the runner
+// # Safety
+//
+// `p_runner` must be a valid handle
+// `p_status` must point to an `McuRunStatus`
+enum McuStatus mcu_runner_get_status(const struct AvogadroRunner *p_runner,
+                                     enum McuRunStatus *p_status);
+
+// Waits at most `timeout_ms` milliseconds for the runner to stop running,
+// then gets its status
+// # Safety
+//
+// `p_runner` must be a valid handle
+// `p_status` must point to an `McuRunStatus`
+enum McuStatus mcu_runner_wait(const struct AvogadroRunner *p_runner,
+                               uint32_t timeout_ms,
+                               enum McuRunStatus *p_status);
+
+// Gets registers, flags and cycle count, all from the same instruction
+// boundary
+// # Safety
+//
+// `p_runner` must be a valid handle
+// `p_state` must point to an `McuRunnerState`
+enum McuStatus mcu_runner_get_state(const struct AvogadroRunner *p_runner,
+                                    struct McuRunnerState *p_state);
+
+// Gets data memory contents, at most `buf_size` bytes, copied between two
+// instructions
+// # Safety
+//
+// `p_runner` must be a valid handle
+// `c_buffer` must be a char array with `buf_size` size
+enum McuStatus mcu_runner_get_data_memory(const struct AvogadroRunner *p_runner,
+                                          uint8_t *c_buffer,
+                                          size_t buf_size);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* AVOGADRO_H */
@@ -21,8 +21,9 @@ impl Alu {
         register_bank.set_flags(flags);
     }
 
-    pub fn compare(rdu: usize, rru: usize, register_bank: &mut RegisterBank, carry: u8) {
-        Alu::substract_base(rdu, rru, register_bank, carry, false);
+    /// Compares registers, with carry for `CPC`
+    pub fn compare(rdu: usize, rru: usize, register_bank: &mut RegisterBank, with_carry: bool) {
+        Alu::substract_base(rdu, rru, register_bank, with_carry, false);
     }
 
     pub fn comp_skip(
@@ -93,19 +94,17 @@ impl Alu {
     // One register - One constant operations
     /// Substracts immediate to register
     pub fn subi(rdu: usize, constant: u8, register_bank: &mut RegisterBank) {
-        Alu::substract_imm_base(rdu, constant, register_bank, 0, true);
+        Alu::substract_imm_base(rdu, constant, register_bank, false, true);
     }
 
     /// Substracts immediate to register with carry
     pub fn sbci(rdu: usize, constant: u8, register_bank: &mut RegisterBank) {
-        let carry = register_bank.get_carry_as_u8();
-        Alu::substract_imm_base(rdu, constant, register_bank, carry, true);
+        Alu::substract_imm_base(rdu, constant, register_bank, true, true);
     }
 
-    /// Substracts immediate to register with carry
+    /// Compares register with immediate
     pub fn cpi(rdu: usize, constant: u8, register_bank: &mut RegisterBank) {
-        let carry = register_bank.get_carry_as_u8();
-        Alu::substract_imm_base(rdu, constant, register_bank, carry, false);
+        Alu::substract_imm_base(rdu, constant, register_bank, false, false);
     }
 
     /// One's complement
@@ -130,7 +129,7 @@ impl Alu {
         flags.neg = res & 0x80 != 0;
         flags.over = res == 0x80;
         flags.sign = flags.neg ^ flags.over;
-        flags.half = (res & 0x8) | (!register_bank.registers[rdu] & 0x8) != 0;
+        flags.half = (res | register_bank.registers[rdu]) & 0x8 != 0;
         register_bank.registers[rdu] = res;
         register_bank.set_flags(flags);
     }
@@ -193,18 +192,13 @@ impl Alu {
         register_bank.set_flags(flags);
     }
 
-    /// Arithmetic shift right
+    /// Rotate right through carry
     pub fn ror(rdu: usize, register_bank: &mut RegisterBank) {
         let mut flags = register_bank.get_flags();
         let old_carry = flags.carry;
         let value = register_bank.registers[rdu];
-        let res = value >> 1;
-        println!("Value: {:x}, res {:x}", value, res);
-        if old_carry {
-            register_bank.registers[rdu] = res | 0x80;
-        } else {
-            register_bank.registers[rdu] = res;
-        }
+        let res = value >> 1 | (old_carry as u8) << 7;
+        register_bank.registers[rdu] = res;
         flags.carry = value % 2 != 0;
         flags.zero = res == 0;
         flags.neg = old_carry;
@@ -213,55 +207,47 @@ impl Alu {
         register_bank.set_flags(flags);
     }
 
-    pub fn substract(rdu: usize, rru: usize, register_bank: &mut RegisterBank, carry: u8) {
-        Alu::substract_base(rdu, rru, register_bank, carry, true);
+    /// Substracts registers, with carry for `SBC`
+    pub fn substract(rdu: usize, rru: usize, register_bank: &mut RegisterBank, with_carry: bool) {
+        Alu::substract_base(rdu, rru, register_bank, with_carry, true);
     }
 
     fn substract_base(
         rdu: usize,
         rru: usize,
         register_bank: &mut RegisterBank,
-        carry: u8,
+        with_carry: bool,
         store_result: bool,
     ) {
-        // wrapping sub as it could overflow
-        let rd = register_bank.registers[rdu] as u8;
         let rr = register_bank.registers[rru];
-        let rr_plus_c = rr.wrapping_add(carry as u8);
-        let result = rd.wrapping_sub(rr_plus_c);
-        let hc_flags = (!rd & rr) | (rr & result) | (!rd & result);
-        let mut flags = register_bank.get_flags();
-        flags.carry = hc_flags & 0x80 != 0;
-        flags.half = hc_flags & 0x08 != 0;
-        flags.neg = result & 0x80 != 0;
-        let tmp_overflow = (rd & !rr & !result) | (!rd & rr & result);
-        flags.over = tmp_overflow & 0x80 != 0;
-        flags.zero = result == 0;
-        flags.sign = flags.neg ^ flags.over;
-        register_bank.set_flags(flags);
-        if store_result {
-            register_bank.registers[rdu] = result;
-        }
+        Alu::substract_imm_base(rdu, rr, register_bank, with_carry, store_result);
     }
 
     fn substract_imm_base(
         rdu: usize,
         constant: u8,
         register_bank: &mut RegisterBank,
-        carry: u8,
+        with_carry: bool,
         store_result: bool,
     ) {
-        let rd = register_bank.registers[rdu] as u8;
-        let const_with_carry = constant.wrapping_add(carry);
-        let result = rd.wrapping_sub(const_with_carry);
-        let hc_flags = (!rd & const_with_carry) | (const_with_carry & result) | (!rd & result);
+        let rd = register_bank.registers[rdu];
+        let carry = if with_carry {
+            register_bank.get_carry_as_u8()
+        } else {
+            0
+        };
+        // wrapping sub as it could overflow
+        let result = rd.wrapping_sub(constant).wrapping_sub(carry);
+        let hc_flags = (!rd & constant) | (constant & result) | (!rd & result);
         let mut flags = register_bank.get_flags();
         flags.carry = hc_flags & 0x80 != 0;
         flags.half = hc_flags & 0x08 != 0;
         flags.neg = result & 0x80 != 0;
-        let tmp_overflow = (rd & !const_with_carry & !result) | (!rd & const_with_carry & result);
+        let tmp_overflow = (rd & !constant & !result) | (!rd & constant & result);
         flags.over = tmp_overflow & 0x80 != 0;
-        flags.zero = result == 0;
+        // Substractions with carry only clear Z, so it tells if a whole
+        // multi-byte result is zero
+        flags.zero = result == 0 && (flags.zero || !with_carry);
         flags.sign = flags.neg ^ flags.over;
         register_bank.set_flags(flags);
         if store_result {
@@ -311,10 +297,22 @@ impl Alu {
         register_bank.set_flags(flags);
     }
 
+    /// Sets or clears a status register bit (`BSET`/`BCLR`)
+    pub fn set_clear_flag(op: u8, register_bank: &mut RegisterBank) {
+        let mask = 1 << (op & 0x7);
+        let flags = u8::from(register_bank.get_flags());
+        let flags = if op & 0x8 == 0 {
+            flags | mask
+        } else {
+            flags & !mask
+        };
+        register_bank.set_flags(flags.into());
+    }
+
     pub fn execute_bit_manip(address: u8, bit: u8, set: bool, memory_bank: &mut MemoryBank) {
-        let io_reg = memory_bank.get_data_byte((address + 0x20).into());
+        let io_reg = memory_bank.read_data_byte((address + 0x20).into());
         let mask = 1 << bit;
         let new_val = if set { io_reg | mask } else { io_reg & !mask };
-        memory_bank.set_data_byte((address + 0x20).into(), new_val);
+        memory_bank.write_data_byte((address + 0x20).into(), new_val);
     }
 }
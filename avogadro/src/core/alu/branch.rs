@@ -58,9 +58,9 @@ impl Alu {
         bit: u8,
         set: bool,
         register_bank: &mut RegisterBank,
-        memory_bank: &MemoryBank,
+        memory_bank: &mut MemoryBank,
     ) {
-        let io_reg = memory_bank.get_data_byte((address + 0x20).into());
+        let io_reg = memory_bank.read_data_byte((address + 0x20).into());
         let mask = 1 << bit;
         let skip_bit_set = io_reg & mask != 0;
         let should_skip = skip_bit_set == set;
@@ -1,5 +1,5 @@
 use super::Alu;
-use crate::core::decoder::CALL_JMP_ABS_22_ADDRESS;
+use crate::core::decoder::{CALL_JMP_ABS_22_ADDRESS, CALL_JMP_EINDZ_ADDRESS, CALL_JMP_Z_ADDRESS};
 use crate::core::memory_bank::MemoryBank;
 use crate::core::register_bank::RegisterBank;
 
@@ -35,15 +35,32 @@ impl Alu {
                     let addr_2 = (cur_instruction as u32 & 1) << 16;
                     let new_pc = (next_word + addr_1 + addr_2) * 2;
                     warn!("FIX: discarding new PC higher bits!");
-                    register_bank.set_program_counter(new_pc as u16)
+                    // The PC is incremented after executing, to land on the
+                    // target
+                    register_bank.set_program_counter((new_pc as u16).wrapping_sub(2))
+                }
+                // EIND would select a 128K page, past the 16 bit PC
+                CALL_JMP_Z_ADDRESS | CALL_JMP_EINDZ_ADDRESS => {
+                    let z = u16::from_le_bytes([
+                        register_bank.registers[30],
+                        register_bank.registers[31],
+                    ]);
+                    // The PC is incremented after executing, to land on Z
+                    register_bank.set_program_counter(z.wrapping_mul(2).wrapping_sub(2))
                 }
                 _ => warn!("Invalid jmp!"),
             }
         }
         if is_call {
-            let pc_to_store = (pc + 2).to_le_bytes();
-            memory_bank.set_data_byte(register_bank.stack_pointer, pc_to_store[0]);
-            memory_bank.set_data_byte(register_bank.stack_pointer + 1, pc_to_store[1]);
+            // `CALL` returns past its second word
+            let size = if !relative && address_bits == CALL_JMP_ABS_22_ADDRESS {
+                4
+            } else {
+                2
+            };
+            let pc_to_store = (pc + size).to_le_bytes();
+            memory_bank.write_data_byte(register_bank.stack_pointer, pc_to_store[0]);
+            memory_bank.write_data_byte(register_bank.stack_pointer + 1, pc_to_store[1]);
             if register_bank.stack_pointer < 2 {
                 register_bank.stack_pointer = memory_bank.data_size() as u16;
             }
@@ -115,18 +115,12 @@ impl Alu {
         let rdu = rd as usize;
         let rru = rr as usize;
         match op {
-            0x1 => {
-                let carry = register_bank.get_carry_as_u8();
-                Alu::compare(rdu, rru, register_bank, carry)
-            }
-            0x2 => {
-                let carry = register_bank.get_carry_as_u8();
-                Alu::substract(rdu, rru, register_bank, carry);
-            }
+            0x1 => Alu::compare(rdu, rru, register_bank, true),
+            0x2 => Alu::substract(rdu, rru, register_bank, true),
             0x3 => Alu::add(rdu, rru, register_bank, 0),
             0x4 => Alu::comp_skip(rdu, rru, register_bank, memory_bank),
-            0x5 => Alu::compare(rdu, rru, register_bank, 0),
-            0x6 => Alu::substract(rdu, rru, register_bank, 0),
+            0x5 => Alu::compare(rdu, rru, register_bank, false),
+            0x6 => Alu::substract(rdu, rru, register_bank, false),
             0x7 => {
                 let carry = register_bank.get_carry_as_u8();
                 Alu::add(rdu, rru, register_bank, carry)
@@ -140,6 +134,7 @@ impl Alu {
             MULSU_OP => Alu::mulsu(rdu, rru, register_bank),
             FMUL_OP => Alu::fmul(rdu, rru, register_bank),
             FMULS_OP => Alu::fmuls(rdu, rru, register_bank),
+            FMULSU_OP => Alu::fmulsu(rdu, rru, register_bank),
             _ => unreachable!(),
         }
     }
@@ -178,6 +173,7 @@ impl Alu {
             0x5 => Alu::asr(rdu, register_bank),
             0x6 => Alu::lsr(rdu, register_bank),
             0x7 => Alu::ror(rdu, register_bank),
+            0x8 => Alu::set_clear_flag(rd, register_bank),
             0xA => Alu::dec(rdu, register_bank),
             _ => warn!(
                 "Execute arith - Unknown arithmetic instruction opcode: {:x}",
@@ -186,7 +182,7 @@ impl Alu {
         }
     }
 
-    fn execute_zero_reg_op(op: u8, register_bank: &mut RegisterBank, memory_bank: &MemoryBank) {
+    fn execute_zero_reg_op(op: u8, register_bank: &mut RegisterBank, memory_bank: &mut MemoryBank) {
         match op {
             0x0 => Alu::ret(false, register_bank, memory_bank),
             0x1 => Alu::ret(true, register_bank, memory_bank),
@@ -2,19 +2,50 @@ use super::Alu;
 use crate::core::register_bank::RegisterBank;
 
 impl Alu {
+    /// Multiplies signed registers
     pub fn muls(rdu: usize, rru: usize, register_bank: &mut RegisterBank) {
-        unimplemented!();
+        let rd = register_bank.registers[rdu] as i8 as i16;
+        let rr = register_bank.registers[rru] as i8 as i16;
+        Alu::store_product((rd * rr) as u16, false, register_bank);
     }
 
+    /// Multiplies signed `Rd` with unsigned `Rr`
     pub fn mulsu(rdu: usize, rru: usize, register_bank: &mut RegisterBank) {
-        unimplemented!();
+        let rd = register_bank.registers[rdu] as i8 as i16;
+        let rr = register_bank.registers[rru] as i16;
+        Alu::store_product((rd * rr) as u16, false, register_bank);
     }
 
+    /// Multiplies unsigned fractional registers
     pub fn fmul(rdu: usize, rru: usize, register_bank: &mut RegisterBank) {
-        unimplemented!();
+        let rd = register_bank.registers[rdu] as u16;
+        let rr = register_bank.registers[rru] as u16;
+        Alu::store_product(rd * rr, true, register_bank);
     }
 
+    /// Multiplies signed fractional registers
     pub fn fmuls(rdu: usize, rru: usize, register_bank: &mut RegisterBank) {
-        unimplemented!();
+        let rd = register_bank.registers[rdu] as i8 as i16;
+        let rr = register_bank.registers[rru] as i8 as i16;
+        Alu::store_product((rd * rr) as u16, true, register_bank);
+    }
+
+    /// Multiplies signed `Rd` with unsigned `Rr` as fractional numbers
+    pub fn fmulsu(rdu: usize, rru: usize, register_bank: &mut RegisterBank) {
+        let rd = register_bank.registers[rdu] as i8 as i16;
+        let rr = register_bank.registers[rru] as i16;
+        Alu::store_product((rd * rr) as u16, true, register_bank);
+    }
+
+    /// Stores the product in `r1:r0`. Carry is the product MSB, fractional
+    /// products are shifted left before storing
+    fn store_product(product: u16, fractional: bool, register_bank: &mut RegisterBank) {
+        let result = if fractional { product << 1 } else { product };
+        let mut flags = register_bank.get_flags();
+        flags.carry = product & 0x8000 != 0;
+        flags.zero = result == 0;
+        register_bank.set_flags(flags);
+        register_bank.registers[0] = result as u8;
+        register_bank.registers[1] = (result >> 8) as u8;
     }
 }
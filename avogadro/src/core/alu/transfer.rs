@@ -17,11 +17,11 @@ impl Alu {
     ) {
         let real_address = address as u16 + 0x20;
         if is_in {
-            let data = memory_bank.get_data_byte(real_address);
+            let data = memory_bank.read_data_byte(real_address);
             register_bank.registers[reg as usize] = data;
         } else {
             let data = register_bank.registers[reg as usize];
-            memory_bank.set_data_byte(real_address, data);
+            memory_bank.write_data_byte(real_address, data);
         }
     }
 
@@ -33,7 +33,7 @@ impl Alu {
     ) {
         if is_pop {
             register_bank.stack_pointer += 1;
-            let data = memory_bank.get_data_byte(register_bank.stack_pointer);
+            let data = memory_bank.read_data_byte(register_bank.stack_pointer);
             register_bank.registers[reg as usize] = data;
         } else {
             if register_bank.stack_pointer == 0 {
@@ -41,7 +41,7 @@ impl Alu {
             }
             register_bank.stack_pointer -= 1;
             let data = register_bank.registers[reg as usize];
-            memory_bank.set_data_byte(register_bank.stack_pointer, data);
+            memory_bank.write_data_byte(register_bank.stack_pointer, data);
         }
     }
 
@@ -62,11 +62,11 @@ impl Alu {
         let base_address_hi = register_bank.registers[pointer as usize + 1];
         let address: u16 = ((base_address_hi as u16) << 8) + base_address_lo as u16 + offset as u16;
         if is_load {
-            let data = memory_bank.get_data_byte(address);
+            let data = memory_bank.read_data_byte(address);
             register_bank.registers[reg as usize] = data;
         } else {
             let data = register_bank.registers[reg as usize];
-            memory_bank.set_data_byte(address, data);
+            memory_bank.write_data_byte(address, data);
         }
     }
 
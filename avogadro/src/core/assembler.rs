@@ -0,0 +1,1072 @@
+use super::symbols::{Symbol, SymbolTable};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// Maximum nesting of `.equ` symbols defined in terms of other symbols
+const MAX_EQU_DEPTH: usize = 32;
+
+/// Assembling error, with the source line that caused it
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblerError {
+    /// Line number, starting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssemblerError {}
+
+type Result<T> = std::result::Result<T, AssemblerError>;
+
+/// Assembled program image and the labels defined in its source
+#[derive(Debug)]
+pub struct Program {
+    pub image: Vec<u8>,
+    pub symbols: SymbolTable,
+}
+
+/// Assembles GNU as style AVR source into a program image.
+///
+/// Supports labels, `.org`, `.byte`, `.word`, `.ascii`, `.asciz`, `.equ` and
+/// `.set`, expressions with `lo8`, `hi8`, `hlo8` and `pm`, and the AVR
+/// instruction set including aliases like `clr`, `lsl` or `brlo`.
+/// Relative operands written as `.+k` are relative to the next instruction,
+/// the same way `Display` and `avr-objdump` print them.
+pub fn assemble(source: &str) -> Result<Program> {
+    let mut assembler = Assembler::default();
+    for (index, line) in source.lines().enumerate() {
+        assembler.parse_line(index + 1, line)?;
+    }
+    assembler.encode()
+}
+
+/// Assembles source lines, panicking with the assembler error if any.
+/// Meant for tests:
+///
+/// ```
+/// use avr_avogadro::avr_asm;
+///
+/// let program = avr_asm!("ldi r16, 0x10", "add r16, r16");
+/// assert_eq!(program, vec![0x00, 0xe1, 0x00, 0x0f]);
+/// ```
+#[macro_export]
+macro_rules! avr_asm {
+    ($($line:expr),* $(,)?) => {
+        match $crate::core::assembler::assemble(&[$($line),*].join("\n")) {
+            Ok(program) => program.image,
+            Err(error) => panic!("avr_asm!: {}", error),
+        }
+    };
+}
+
+enum StatementKind {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Ascii(Vec<u8>),
+}
+
+struct Statement {
+    line: usize,
+    address: u32,
+    kind: StatementKind,
+}
+
+#[derive(Default)]
+struct Assembler {
+    statements: Vec<Statement>,
+    labels: HashMap<String, i64>,
+    label_order: Vec<String>,
+    equs: HashMap<String, (usize, String)>,
+    location: u32,
+}
+
+impl Assembler {
+    fn parse_line(&mut self, line: usize, text: &str) -> Result<()> {
+        let error = |message: String| AssemblerError { line, message };
+        let mut rest = strip_comment(text).trim();
+        // Labels, there might be more than one
+        while let Some(colon) = rest.find(':') {
+            let name = rest[..colon].trim();
+            if !is_identifier(name) {
+                break;
+            }
+            if self.labels.contains_key(name) || self.equs.contains_key(name) {
+                return Err(error(format!("symbol `{}` is already defined", name)));
+            }
+            self.labels
+                .insert(name.to_owned(), i64::from(self.location));
+            self.label_order.push(name.to_owned());
+            rest = rest[colon + 1..].trim();
+        }
+        if rest.is_empty() {
+            return Ok(());
+        }
+        let (keyword, arguments) = match rest.find(char::is_whitespace) {
+            Some(position) => (&rest[..position], rest[position..].trim()),
+            None => (rest, ""),
+        };
+        let keyword = keyword.to_ascii_lowercase();
+        let address = self.location;
+        let kind = match keyword.as_str() {
+            ".org" => {
+                let value = self.evaluate(arguments, line)?;
+                if value < i64::from(self.location) {
+                    return Err(error(format!(
+                        ".org moves location backwards to {:#x}",
+                        value
+                    )));
+                }
+                self.location = value as u32;
+                return Ok(());
+            }
+            ".equ" | ".set" => {
+                let operands = split_operands(arguments);
+                if operands.len() != 2 || !is_identifier(&operands[0]) {
+                    return Err(error(format!("expected `{} name, value`", keyword)));
+                }
+                if self.labels.contains_key(&operands[0]) {
+                    return Err(error(format!(
+                        "symbol `{}` is already defined",
+                        operands[0]
+                    )));
+                }
+                self.equs
+                    .insert(operands[0].clone(), (line, operands[1].clone()));
+                return Ok(());
+            }
+            ".byte" => {
+                let values = split_operands(arguments);
+                self.location += values.len() as u32;
+                StatementKind::Bytes(values)
+            }
+            ".word" => {
+                let values = split_operands(arguments);
+                self.location += values.len() as u32 * 2;
+                StatementKind::Words(values)
+            }
+            ".ascii" | ".asciz" => {
+                let mut bytes = parse_string(arguments).map_err(error)?;
+                if keyword == ".asciz" {
+                    bytes.push(0);
+                }
+                self.location += bytes.len() as u32;
+                StatementKind::Ascii(bytes)
+            }
+            ".text" | ".section" | ".global" | ".globl" | ".type" | ".size" | ".file" => {
+                return Ok(());
+            }
+            _ if keyword.starts_with('.') => {
+                return Err(error(format!("unsupported directive `{}`", keyword)));
+            }
+            _ => {
+                let size = instruction_size(&keyword)
+                    .ok_or_else(|| error(format!("unknown instruction `{}`", keyword)))?;
+                if !self.location.is_multiple_of(2) {
+                    return Err(error("instruction at odd address".to_owned()));
+                }
+                self.location += size;
+                StatementKind::Instruction {
+                    mnemonic: keyword,
+                    operands: split_operands(arguments),
+                }
+            }
+        };
+        self.statements.push(Statement {
+            line,
+            address,
+            kind,
+        });
+        Ok(())
+    }
+
+    fn encode(&self) -> Result<Program> {
+        let mut image = vec![0; self.location as usize];
+        for statement in &self.statements {
+            let line = statement.line;
+            let start = statement.address as usize;
+            let bytes = match &statement.kind {
+                StatementKind::Instruction { mnemonic, operands } => {
+                    let encoder = Encoder {
+                        assembler: self,
+                        line,
+                        address: statement.address,
+                        operands,
+                    };
+                    encoder
+                        .encode(mnemonic)?
+                        .iter()
+                        .flat_map(|word| word.to_le_bytes().to_vec())
+                        .collect()
+                }
+                StatementKind::Bytes(values) => values
+                    .iter()
+                    .map(|value| {
+                        let value = self.evaluate_at(value, line, statement.address)?;
+                        check_range(value, -128, 255, line).map(|value| value as u8)
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+                StatementKind::Words(values) => {
+                    let mut bytes = Vec::new();
+                    for value in values {
+                        let value = self.evaluate_at(value, line, statement.address)?;
+                        let value = check_range(value, -32768, 65535, line)? as u16;
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                    bytes
+                }
+                StatementKind::Ascii(bytes) => bytes.clone(),
+            };
+            image[start..start + bytes.len()].copy_from_slice(&bytes);
+        }
+        let mut symbols = SymbolTable::new();
+        for name in &self.label_order {
+            symbols.add(Symbol {
+                name: name.clone(),
+                address: self.labels[name] as u32,
+                size: 0,
+                is_global: true,
+            });
+        }
+        Ok(Program { image, symbols })
+    }
+
+    fn evaluate(&self, expression: &str, line: usize) -> Result<i64> {
+        self.evaluate_at(expression, line, self.location)
+    }
+
+    fn evaluate_at(&self, expression: &str, line: usize, location: u32) -> Result<i64> {
+        let mut parser = ExpressionParser {
+            assembler: self,
+            tokens: tokenize(expression).map_err(|message| AssemblerError { line, message })?,
+            position: 0,
+            location: i64::from(location),
+            depth: 0,
+        };
+        parser
+            .parse()
+            .map_err(|message| AssemblerError { line, message })
+    }
+}
+
+/// Encodes a single instruction into one or two words
+struct Encoder<'a> {
+    assembler: &'a Assembler,
+    line: usize,
+    address: u32,
+    operands: &'a [String],
+}
+
+impl<'a> Encoder<'a> {
+    fn encode(&self, mnemonic: &str) -> Result<Vec<u16>> {
+        let word = match mnemonic {
+            // Two registers
+            "add" | "adc" | "sub" | "sbc" | "and" | "or" | "eor" | "mov" | "cp" | "cpc"
+            | "cpse" | "mul" => {
+                self.expect_operands(2)?;
+                let rd = self.register(0)?;
+                let rr = self.register(1)?;
+                two_registers(two_register_opcode(mnemonic), rd, rr)
+            }
+            // Aliases of two register instructions using the same register
+            "lsl" | "rol" | "tst" | "clr" => {
+                self.expect_operands(1)?;
+                let rd = self.register(0)?;
+                let opcode = match mnemonic {
+                    "lsl" => two_register_opcode("add"),
+                    "rol" => two_register_opcode("adc"),
+                    "tst" => two_register_opcode("and"),
+                    _ => two_register_opcode("eor"),
+                };
+                two_registers(opcode, rd, rd)
+            }
+            // One register
+            "com" | "neg" | "swap" | "inc" | "asr" | "lsr" | "ror" | "dec" | "push" | "pop" => {
+                self.expect_operands(1)?;
+                let opcode = match mnemonic {
+                    "com" => 0x9400,
+                    "neg" => 0x9401,
+                    "swap" => 0x9402,
+                    "inc" => 0x9403,
+                    "asr" => 0x9405,
+                    "lsr" => 0x9406,
+                    "ror" => 0x9407,
+                    "dec" => 0x940A,
+                    "push" => 0x920F,
+                    _ => 0x900F,
+                };
+                opcode | (self.register(0)? << 4)
+            }
+            // Register and immediate
+            "cpi" | "sbci" | "subi" | "ori" | "sbr" | "andi" | "cbr" | "ldi" => {
+                self.expect_operands(2)?;
+                let rd = self.high_register(0)?;
+                let constant = self.constant(1, -128, 255)? as u16 & 0xFF;
+                let (opcode, constant) = match mnemonic {
+                    "cpi" => (0x3000, constant),
+                    "sbci" => (0x4000, constant),
+                    "subi" => (0x5000, constant),
+                    "ori" | "sbr" => (0x6000, constant),
+                    "andi" => (0x7000, constant),
+                    "cbr" => (0x7000, !constant & 0xFF),
+                    _ => (0xE000, constant),
+                };
+                register_constant(opcode, rd, constant)
+            }
+            "ser" => {
+                self.expect_operands(1)?;
+                register_constant(0xE000, self.high_register(0)?, 0xFF)
+            }
+            "adiw" | "sbiw" => {
+                self.expect_operands(2)?;
+                let rd = self.register_pair(0)?;
+                if rd < 24 {
+                    return Err(self.error(format!("{} needs r24, r26, r28 or r30", mnemonic)));
+                }
+                let constant = self.constant(1, 0, 63)? as u16;
+                let opcode = if mnemonic == "adiw" { 0x9600 } else { 0x9700 };
+                opcode | ((constant & 0x30) << 2) | (((rd - 24) / 2) << 4) | (constant & 0xF)
+            }
+            "movw" => {
+                self.expect_operands(2)?;
+                let rd = self.register_pair(0)?;
+                let rr = self.register_pair(1)?;
+                0x0100 | ((rd / 2) << 4) | (rr / 2)
+            }
+            "muls" => {
+                self.expect_operands(2)?;
+                let rd = self.high_register(0)?;
+                let rr = self.high_register(1)?;
+                0x0200 | ((rd - 16) << 4) | (rr - 16)
+            }
+            "mulsu" | "fmul" | "fmuls" | "fmulsu" => {
+                self.expect_operands(2)?;
+                let rd = self.register_in(0, 16, 23)?;
+                let rr = self.register_in(1, 16, 23)?;
+                let opcode = match mnemonic {
+                    "mulsu" => 0x0300,
+                    "fmul" => 0x0308,
+                    "fmuls" => 0x0380,
+                    _ => 0x0388,
+                };
+                opcode | ((rd - 16) << 4) | (rr - 16)
+            }
+            // Relative jumps and branches
+            "rjmp" | "rcall" => {
+                self.expect_operands(1)?;
+                let offset = self.relative_offset(0, -2048, 2047)?;
+                let opcode = if mnemonic == "rjmp" { 0xC000 } else { 0xD000 };
+                opcode | (offset as u16 & 0x0FFF)
+            }
+            "brbs" | "brbc" => {
+                self.expect_operands(2)?;
+                let bit = self.constant(0, 0, 7)? as u16;
+                let offset = self.relative_offset(1, -64, 63)?;
+                let opcode = if mnemonic == "brbs" { 0xF000 } else { 0xF400 };
+                opcode | ((offset as u16 & 0x7F) << 3) | bit
+            }
+            _ if branch_condition(mnemonic).is_some() => {
+                self.expect_operands(1)?;
+                let (set, bit) = branch_condition(mnemonic).unwrap();
+                let offset = self.relative_offset(0, -64, 63)?;
+                let opcode = if set { 0xF000 } else { 0xF400 };
+                opcode | ((offset as u16 & 0x7F) << 3) | bit
+            }
+            // Absolute jumps, two words
+            "jmp" | "call" => {
+                self.expect_operands(1)?;
+                let target = self.expression(0)?;
+                if !(0..0x80_0000).contains(&target) || target % 2 != 0 {
+                    return Err(self.error(format!("invalid jump target {:#x}", target)));
+                }
+                let target = (target / 2) as u32;
+                let opcode = if mnemonic == "jmp" { 0x940C } else { 0x940E };
+                let high = ((target & 0x3E_0000) >> 13) | ((target & 0x1_0000) >> 16);
+                return Ok(vec![opcode | high as u16, target as u16]);
+            }
+            // Data transfer
+            "lds" => {
+                self.expect_operands(2)?;
+                let rd = self.register(0)?;
+                let address = self.constant(1, 0, 0xFFFF)? as u16;
+                return Ok(vec![0x9000 | (rd << 4), address]);
+            }
+            "sts" => {
+                self.expect_operands(2)?;
+                let address = self.constant(0, 0, 0xFFFF)? as u16;
+                let rr = self.register(1)?;
+                return Ok(vec![0x9200 | (rr << 4), address]);
+            }
+            "ld" | "ldd" => {
+                self.expect_operands(2)?;
+                let rd = self.register(0)?;
+                self.pointer_transfer(1, false)? | (rd << 4)
+            }
+            "st" | "std" => {
+                self.expect_operands(2)?;
+                let rr = self.register(1)?;
+                self.pointer_transfer(0, true)? | (rr << 4)
+            }
+            "lpm" | "elpm" => {
+                let (plain, z, z_inc) = if mnemonic == "lpm" {
+                    (0x95C8, 0x9004, 0x9005)
+                } else {
+                    (0x95D8, 0x9006, 0x9007)
+                };
+                if self.operands.is_empty() {
+                    plain
+                } else {
+                    self.expect_operands(2)?;
+                    let rd = self.register(0)?;
+                    match self.operands[1].to_ascii_uppercase().as_str() {
+                        "Z" => z | (rd << 4),
+                        "Z+" => z_inc | (rd << 4),
+                        _ => return Err(self.error(format!("{} needs Z or Z+", mnemonic))),
+                    }
+                }
+            }
+            "spm" => {
+                if self.operands.is_empty() {
+                    0x95E8
+                } else if self.operands.len() == 1 && self.operands[0].eq_ignore_ascii_case("z+") {
+                    0x95F8
+                } else {
+                    return Err(self.error("spm takes no operands or Z+".to_owned()));
+                }
+            }
+            "xch" | "las" | "lac" | "lat" => {
+                self.expect_operands(2)?;
+                if !self.operands[0].eq_ignore_ascii_case("z") {
+                    return Err(self.error(format!("{} needs Z as first operand", mnemonic)));
+                }
+                let opcode = match mnemonic {
+                    "xch" => 0x9204,
+                    "las" => 0x9205,
+                    "lac" => 0x9206,
+                    _ => 0x9207,
+                };
+                opcode | (self.register(1)? << 4)
+            }
+            "in" => {
+                self.expect_operands(2)?;
+                let rd = self.register(0)?;
+                let address = self.constant(1, 0, 63)? as u16;
+                0xB000 | ((address & 0x30) << 5) | (rd << 4) | (address & 0xF)
+            }
+            "out" => {
+                self.expect_operands(2)?;
+                let address = self.constant(0, 0, 63)? as u16;
+                let rr = self.register(1)?;
+                0xB800 | ((address & 0x30) << 5) | (rr << 4) | (address & 0xF)
+            }
+            // Bit operations
+            "cbi" | "sbic" | "sbi" | "sbis" => {
+                self.expect_operands(2)?;
+                let address = self.constant(0, 0, 31)? as u16;
+                let bit = self.constant(1, 0, 7)? as u16;
+                let opcode = match mnemonic {
+                    "cbi" => 0x9800,
+                    "sbic" => 0x9900,
+                    "sbi" => 0x9A00,
+                    _ => 0x9B00,
+                };
+                opcode | (address << 3) | bit
+            }
+            "bld" | "bst" | "sbrc" | "sbrs" => {
+                self.expect_operands(2)?;
+                let rd = self.register(0)?;
+                let bit = self.constant(1, 0, 7)? as u16;
+                let opcode = match mnemonic {
+                    "bld" => 0xF800,
+                    "bst" => 0xFA00,
+                    "sbrc" => 0xFC00,
+                    _ => 0xFE00,
+                };
+                opcode | (rd << 4) | bit
+            }
+            "bset" | "bclr" => {
+                self.expect_operands(1)?;
+                let bit = self.constant(0, 0, 7)? as u16;
+                let opcode = if mnemonic == "bset" { 0x9408 } else { 0x9488 };
+                opcode | (bit << 4)
+            }
+            "des" => {
+                self.expect_operands(1)?;
+                0x940B | ((self.constant(0, 0, 15)? as u16) << 4)
+            }
+            _ => {
+                self.expect_operands(0)?;
+                match no_operand_opcode(mnemonic) {
+                    Some(opcode) => opcode,
+                    None => return Err(self.error(format!("unknown instruction `{}`", mnemonic))),
+                }
+            }
+        };
+        Ok(vec![word])
+    }
+
+    fn error(&self, message: String) -> AssemblerError {
+        AssemblerError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn expect_operands(&self, count: usize) -> Result<()> {
+        if self.operands.len() == count {
+            Ok(())
+        } else {
+            Err(self.error(format!(
+                "expected {} operands, found {}",
+                count,
+                self.operands.len()
+            )))
+        }
+    }
+
+    fn register(&self, index: usize) -> Result<u16> {
+        parse_register(&self.operands[index])
+            .ok_or_else(|| self.error(format!("invalid register `{}`", self.operands[index])))
+    }
+
+    fn register_in(&self, index: usize, min: u16, max: u16) -> Result<u16> {
+        let register = self.register(index)?;
+        if register < min || register > max {
+            return Err(self.error(format!("register must be in r{}..r{}", min, max)));
+        }
+        Ok(register)
+    }
+
+    fn high_register(&self, index: usize) -> Result<u16> {
+        self.register_in(index, 16, 31)
+    }
+
+    /// Even register, written as `r24` or as a pair `r25:r24`
+    fn register_pair(&self, index: usize) -> Result<u16> {
+        let operand = &self.operands[index];
+        let register = match operand.split_once(':') {
+            Some((high, low)) => {
+                let high = parse_register(high.trim());
+                let low = parse_register(low.trim());
+                match (high, low) {
+                    (Some(high), Some(low)) if high == low + 1 => Some(low),
+                    _ => None,
+                }
+            }
+            None => parse_register(operand),
+        };
+        match register {
+            Some(register) if register % 2 == 0 => Ok(register),
+            _ => Err(self.error(format!("invalid register pair `{}`", operand))),
+        }
+    }
+
+    fn expression(&self, index: usize) -> Result<i64> {
+        self.assembler
+            .evaluate_at(&self.operands[index], self.line, self.address)
+    }
+
+    fn constant(&self, index: usize, min: i64, max: i64) -> Result<i64> {
+        check_range(self.expression(index)?, min, max, self.line)
+    }
+
+    /// Offset in words from the next instruction. Operand is either a target
+    /// address or `.+k`/`.-k`, relative to the next instruction
+    fn relative_offset(&self, index: usize, min: i64, max: i64) -> Result<i64> {
+        let operand = self.operands[index].trim();
+        let next = i64::from(self.address) + 2;
+        let target = match operand.strip_prefix('.') {
+            Some(offset) if offset.trim_start().starts_with(['+', '-']) => {
+                next + self
+                    .assembler
+                    .evaluate_at(offset, self.line, self.address)?
+            }
+            _ => self.expression(index)?,
+        };
+        let offset = target - next;
+        if offset % 2 != 0 {
+            return Err(self.error(format!("odd jump offset {}", offset)));
+        }
+        check_range(offset / 2, min, max, self.line)
+    }
+
+    /// Encodes the pointer operand of `LD`/`ST`/`LDD`/`STD`
+    fn pointer_transfer(&self, index: usize, is_store: bool) -> Result<u16> {
+        let operand = self.operands[index].replace(' ', "").to_ascii_uppercase();
+        let store_bit = if is_store { 0x0200 } else { 0 };
+        let opcode = match operand.as_str() {
+            "X" => 0x900C,
+            "X+" => 0x900D,
+            "-X" => 0x900E,
+            "Y" => 0x8008,
+            "Y+" => 0x9009,
+            "-Y" => 0x900A,
+            "Z" => 0x8000,
+            "Z+" => 0x9001,
+            "-Z" => 0x9002,
+            _ => {
+                let (base, offset) = match operand.split_at(1) {
+                    ("Y", offset) if offset.starts_with('+') => (0x8008, &offset[1..]),
+                    ("Z", offset) if offset.starts_with('+') => (0x8000, &offset[1..]),
+                    _ => {
+                        return Err(self.error(format!(
+                            "invalid pointer operand `{}`",
+                            self.operands[index]
+                        )))
+                    }
+                };
+                let offset = self
+                    .assembler
+                    .evaluate_at(offset, self.line, self.address)?;
+                let offset = check_range(offset, 0, 63, self.line)? as u16;
+                base | ((offset & 0x20) << 8) | ((offset & 0x18) << 7) | (offset & 0x7)
+            }
+        };
+        Ok(opcode | store_bit)
+    }
+}
+
+fn two_register_opcode(mnemonic: &str) -> u16 {
+    match mnemonic {
+        "cpc" => 0x0400,
+        "sbc" => 0x0800,
+        "add" => 0x0C00,
+        "cpse" => 0x1000,
+        "cp" => 0x1400,
+        "sub" => 0x1800,
+        "adc" => 0x1C00,
+        "and" => 0x2000,
+        "eor" => 0x2400,
+        "or" => 0x2800,
+        "mov" => 0x2C00,
+        _ => 0x9C00, // mul
+    }
+}
+
+fn two_registers(opcode: u16, rd: u16, rr: u16) -> u16 {
+    opcode | ((rr & 0x10) << 5) | (rd << 4) | (rr & 0xF)
+}
+
+fn register_constant(opcode: u16, rd: u16, constant: u16) -> u16 {
+    opcode | ((constant & 0xF0) << 4) | ((rd - 16) << 4) | (constant & 0xF)
+}
+
+/// Returns whether the branch tests a set bit, and the SREG bit number
+fn branch_condition(mnemonic: &str) -> Option<(bool, u16)> {
+    let condition = match mnemonic {
+        "brcs" | "brlo" => (true, 0),
+        "breq" => (true, 1),
+        "brmi" => (true, 2),
+        "brvs" => (true, 3),
+        "brlt" => (true, 4),
+        "brhs" => (true, 5),
+        "brts" => (true, 6),
+        "brie" => (true, 7),
+        "brcc" | "brsh" => (false, 0),
+        "brne" => (false, 1),
+        "brpl" => (false, 2),
+        "brvc" => (false, 3),
+        "brge" => (false, 4),
+        "brhc" => (false, 5),
+        "brtc" => (false, 6),
+        "brid" => (false, 7),
+        _ => return None,
+    };
+    Some(condition)
+}
+
+fn no_operand_opcode(mnemonic: &str) -> Option<u16> {
+    let flags = ["c", "z", "n", "v", "s", "h", "t", "i"];
+    if let Some(bit) = mnemonic
+        .strip_prefix("se")
+        .and_then(|flag| flags.iter().position(|f| *f == flag))
+    {
+        return Some(0x9408 | ((bit as u16) << 4));
+    }
+    if let Some(bit) = mnemonic
+        .strip_prefix("cl")
+        .and_then(|flag| flags.iter().position(|f| *f == flag))
+    {
+        return Some(0x9488 | ((bit as u16) << 4));
+    }
+    let opcode = match mnemonic {
+        "nop" => 0x0000,
+        "ijmp" => 0x9409,
+        "eijmp" => 0x9419,
+        "icall" => 0x9509,
+        "eicall" => 0x9519,
+        "ret" => 0x9508,
+        "reti" => 0x9518,
+        "sleep" => 0x9588,
+        "break" => 0x9598,
+        "wdr" => 0x95A8,
+        _ => return None,
+    };
+    Some(opcode)
+}
+
+/// Size in bytes of an instruction, `None` if mnemonic is unknown
+fn instruction_size(mnemonic: &str) -> Option<u32> {
+    match mnemonic {
+        "lds" | "sts" | "jmp" | "call" => Some(4),
+        "add" | "adc" | "sub" | "sbc" | "and" | "or" | "eor" | "mov" | "cp" | "cpc" | "cpse"
+        | "mul" | "lsl" | "rol" | "tst" | "clr" | "com" | "neg" | "swap" | "inc" | "asr"
+        | "lsr" | "ror" | "dec" | "push" | "pop" | "cpi" | "sbci" | "subi" | "ori" | "sbr"
+        | "andi" | "cbr" | "ldi" | "ser" | "adiw" | "sbiw" | "movw" | "muls" | "mulsu" | "fmul"
+        | "fmuls" | "fmulsu" | "rjmp" | "rcall" | "brbs" | "brbc" | "ld" | "ldd" | "st" | "std"
+        | "lpm" | "elpm" | "spm" | "xch" | "las" | "lac" | "lat" | "in" | "out" | "cbi"
+        | "sbic" | "sbi" | "sbis" | "bld" | "bst" | "sbrc" | "sbrs" | "bset" | "bclr" | "des" => {
+            Some(2)
+        }
+        _ if branch_condition(mnemonic).is_some() || no_operand_opcode(mnemonic).is_some() => {
+            Some(2)
+        }
+        _ => None,
+    }
+}
+
+fn check_range(value: i64, min: i64, max: i64, line: usize) -> Result<i64> {
+    if value < min || value > max {
+        Err(AssemblerError {
+            line,
+            message: format!("value {} out of range [{}, {}]", value, min, max),
+        })
+    } else {
+        Ok(value)
+    }
+}
+
+fn parse_register(operand: &str) -> Option<u16> {
+    let number = operand.strip_prefix(['r', 'R'])?;
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok().filter(|register| *register < 32)
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+            text != "." && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+/// Removes `;` and `//` comments, ignoring those inside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' | '\'' if previous != '\\' => in_quotes = !in_quotes,
+            ';' if !in_quotes => return &line[..index],
+            '/' if !in_quotes && previous == '/' => return &line[..index - 1],
+            _ => (),
+        }
+        previous = c;
+    }
+    line
+}
+
+/// Splits operands by commas outside parentheses and quotes
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut depth = 0;
+    let mut in_quotes = false;
+    let mut current = String::new();
+    for c in text.chars() {
+        match c {
+            '"' | '\'' => in_quotes = !in_quotes,
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes => depth -= 1,
+            ',' if !in_quotes && depth == 0 => {
+                operands.push(current.trim().to_owned());
+                current.clear();
+                continue;
+            }
+            _ => (),
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_owned());
+    }
+    operands
+}
+
+/// Parses a double quoted string with C escapes
+fn parse_string(text: &str) -> std::result::Result<Vec<u8>, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, found `{}`", text))?;
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(other) => other,
+                None => return Err("unterminated escape sequence".to_owned()),
+            }
+        } else {
+            c
+        };
+        let mut buffer = [0; 4];
+        bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Operator(&'static str),
+    OpenParen,
+    CloseParen,
+}
+
+fn tokenize(expression: &str) -> std::result::Result<Vec<Token>, String> {
+    const OPERATORS: [&str; 12] = ["<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "!"];
+    let mut tokens = Vec::new();
+    let chars = expression.chars().collect::<Vec<_>>();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        } else if c == '(' {
+            tokens.push(Token::OpenParen);
+            index += 1;
+        } else if c == ')' {
+            tokens.push(Token::CloseParen);
+            index += 1;
+        } else if c == '\'' {
+            // Character literal
+            let (value, length) = match (chars.get(index + 1), chars.get(index + 2)) {
+                (Some('\\'), Some(escaped)) => {
+                    let value = match escaped {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        '0' => '\0',
+                        other => *other,
+                    };
+                    (value, 3)
+                }
+                (Some(value), _) => (*value, 2),
+                _ => return Err("unterminated character literal".to_owned()),
+            };
+            if chars.get(index + length) != Some(&'\'') {
+                return Err("unterminated character literal".to_owned());
+            }
+            tokens.push(Token::Number(value as i64));
+            index += length + 1;
+        } else if c.is_ascii_digit() {
+            let start = index;
+            while index < chars.len() && chars[index].is_ascii_alphanumeric() {
+                index += 1;
+            }
+            let literal = chars[start..index]
+                .iter()
+                .collect::<String>()
+                .to_lowercase();
+            let value = if let Some(hex) = literal.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(binary) = literal.strip_prefix("0b") {
+                i64::from_str_radix(binary, 2)
+            } else {
+                literal.parse()
+            };
+            let value = value.map_err(|_| format!("invalid number `{}`", literal))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = index;
+            index += 1;
+            while index < chars.len()
+                && (chars[index].is_ascii_alphanumeric()
+                    || chars[index] == '_'
+                    || chars[index] == '.')
+            {
+                index += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..index].iter().collect()));
+        } else {
+            let rest = chars[index..].iter().collect::<String>();
+            let operator = OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(*operator))
+                .ok_or_else(|| format!("unexpected character `{}`", c))?;
+            tokens.push(Token::Operator(operator));
+            index += operator.len();
+        }
+    }
+    if tokens.is_empty() {
+        return Err("missing expression".to_owned());
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser for constant expressions. Precedence, from
+/// lowest to highest: `|`, `^`, `&`, shifts, sums, products and unary
+/// operators.
+struct ExpressionParser<'a> {
+    assembler: &'a Assembler,
+    tokens: Vec<Token>,
+    position: usize,
+    location: i64,
+    depth: usize,
+}
+
+const BINARY_PRECEDENCE: [&[&str]; 5] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"]];
+
+impl<'a> ExpressionParser<'a> {
+    fn parse(&mut self) -> std::result::Result<i64, String> {
+        let value = self.parse_binary(0)?;
+        match self.tokens.get(self.position) {
+            None => Ok(value),
+            Some(token) => Err(format!("unexpected {:?} in expression", token)),
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> std::result::Result<i64, String> {
+        if level == BINARY_PRECEDENCE.len() {
+            return self.parse_product();
+        }
+        let mut value = self.parse_binary(level + 1)?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            let operator = *operator;
+            if !BINARY_PRECEDENCE[level].contains(&operator) {
+                break;
+            }
+            self.position += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            value = match operator {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => value.wrapping_shl(rhs as u32),
+                ">>" => value.wrapping_shr(rhs as u32),
+                "+" => value.wrapping_add(rhs),
+                _ => value.wrapping_sub(rhs),
+            };
+        }
+        Ok(value)
+    }
+
+    fn parse_product(&mut self) -> std::result::Result<i64, String> {
+        let mut value = self.parse_unary()?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            let operator = *operator;
+            if !["*", "/", "%"].contains(&operator) {
+                break;
+            }
+            self.position += 1;
+            let rhs = self.parse_unary()?;
+            value = match operator {
+                "*" => value.wrapping_mul(rhs),
+                _ if rhs == 0 => return Err("division by zero".to_owned()),
+                "/" => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    fn parse_unary(&mut self) -> std::result::Result<i64, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| "unexpected end of expression".to_owned())?;
+        self.position += 1;
+        match token {
+            Token::Operator("-") => Ok(self.parse_unary()?.wrapping_neg()),
+            Token::Operator("+") => self.parse_unary(),
+            Token::Operator("~") => Ok(!self.parse_unary()?),
+            Token::Operator("!") => Ok((self.parse_unary()? == 0) as i64),
+            Token::Number(value) => Ok(value),
+            Token::OpenParen => {
+                let value = self.parse_binary(0)?;
+                self.expect_close_paren()?;
+                Ok(value)
+            }
+            Token::Identifier(name) => {
+                if self.tokens.get(self.position) == Some(&Token::OpenParen) {
+                    self.position += 1;
+                    let argument = self.parse_binary(0)?;
+                    self.expect_close_paren()?;
+                    apply_function(&name, argument)
+                } else {
+                    self.symbol_value(&name)
+                }
+            }
+            Token::Operator(operator) => Err(format!("unexpected `{}`", operator)),
+            Token::CloseParen => Err("unexpected `)`".to_owned()),
+        }
+    }
+
+    fn expect_close_paren(&mut self) -> std::result::Result<(), String> {
+        if self.tokens.get(self.position) == Some(&Token::CloseParen) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err("missing `)`".to_owned())
+        }
+    }
+
+    fn symbol_value(&self, name: &str) -> std::result::Result<i64, String> {
+        if name == "." {
+            return Ok(self.location);
+        }
+        if let Some(value) = self.assembler.labels.get(name) {
+            return Ok(*value);
+        }
+        let (_, expression) = self
+            .assembler
+            .equs
+            .get(name)
+            .ok_or_else(|| format!("undefined symbol `{}`", name))?;
+        if self.depth >= MAX_EQU_DEPTH {
+            return Err(format!("recursive definition of `{}`", name));
+        }
+        let mut parser = ExpressionParser {
+            assembler: self.assembler,
+            tokens: tokenize(expression)?,
+            position: 0,
+            location: self.location,
+            depth: self.depth + 1,
+        };
+        parser.parse()
+    }
+}
+
+fn apply_function(name: &str, argument: i64) -> std::result::Result<i64, String> {
+    let value = match name.to_ascii_lowercase().as_str() {
+        "lo8" => argument & 0xFF,
+        "hi8" => (argument >> 8) & 0xFF,
+        "hlo8" | "hh8" => (argument >> 16) & 0xFF,
+        "hhi8" => (argument >> 24) & 0xFF,
+        "pm" | "gs" => argument >> 1,
+        "pm_lo8" => (argument >> 1) & 0xFF,
+        "pm_hi8" => (argument >> 9) & 0xFF,
+        "pm_hh8" => (argument >> 17) & 0xFF,
+        _ => return Err(format!("unknown function `{}`", name)),
+    };
+    Ok(value)
+}
@@ -0,0 +1,231 @@
+use super::decoder::call_size;
+use super::symbols::SymbolTable;
+use super::Instruction;
+use std::fmt;
+
+/// A call or interrupt that didn't return yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    /// Address of the call instruction, or of the interrupted one
+    pub call_site: u16,
+    /// Called function or interrupt vector
+    pub target: u16,
+    /// Return address stored in the stack by the call
+    pub return_address: u16,
+    /// Stack pointer right after the call
+    pub stack_pointer: u16,
+    pub is_interrupt: bool,
+}
+
+/// A return to an address other than the one stored by the last call,
+/// usually because the stack was overwritten
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnMismatch {
+    /// Address of the `ret` or `reti` instruction
+    pub pc: u16,
+    /// Return address stored by the last call
+    pub expected: u16,
+    /// Return address read from the stack
+    pub actual: u16,
+    pub cycle: usize,
+}
+
+/// A frame of a backtrace
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktraceFrame {
+    /// Current instruction for the innermost frame, call site for the others
+    pub pc: u16,
+    /// Function containing `pc` and the offset from its start
+    pub function: Option<(String, u32)>,
+    /// Source location, as "file:line"
+    pub source: Option<String>,
+    pub is_interrupt: bool,
+}
+
+/// Symbolized call stack, from the innermost frame. `Display` writes a
+/// frame per line like `#1  0x0002 in main+0x2`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Backtrace {
+    pub frames: Vec<BacktraceFrame>,
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            write!(f, "#{:<2} 0x{:04x}", i, frame.pc)?;
+            match &frame.function {
+                Some((name, 0)) => write!(f, " in {}", name)?,
+                Some((name, offset)) => write!(f, " in {}+0x{:x}", name, offset)?,
+                None => write!(f, " in ??")?,
+            }
+            if let Some(source) = &frame.source {
+                write!(f, " at {}", source)?;
+            }
+            if frame.is_interrupt {
+                write!(f, " <interrupt>")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shadow call stack, updated by `Mcu::step`.
+///
+/// Calls (`call`, `rcall`, `icall`, `eicall` and interrupts) push a frame and
+/// returns (`ret`, `reti`) pop it. Each frame keeps the return address the
+/// call stored in the stack, so a return reading another one is recorded as
+/// a `ReturnMismatch`. The frame returning to that address and those above
+/// it are popped anyway, or just the last frame if none does. Returns
+/// without frames, like the ones of code running before the call stack was
+/// attached, are ignored.
+///
+/// Stepping back doesn't rewind the call stack.
+pub struct CallStack {
+    symbols: SymbolTable,
+    frames: Vec<StackFrame>,
+    mismatches: Vec<ReturnMismatch>,
+}
+
+impl CallStack {
+    /// Creates a call stack naming functions with `symbols`, usually read
+    /// from an ELF file
+    pub fn new(symbols: SymbolTable) -> CallStack {
+        CallStack {
+            symbols,
+            frames: Vec::new(),
+            mismatches: Vec::new(),
+        }
+    }
+
+    /// Names functions with `symbols` from now on
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Calls and interrupts that didn't return yet, from the outermost one
+    pub fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns that didn't match the last call
+    pub fn mismatches(&self) -> &[ReturnMismatch] {
+        &self.mismatches
+    }
+
+    /// Returns and forgets the recorded mismatches
+    pub fn take_mismatches(&mut self) -> Vec<ReturnMismatch> {
+        std::mem::take(&mut self.mismatches)
+    }
+
+    /// Forgets every frame
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Pushes the frame of a call made from outside the program, like a
+    /// test calling a function
+    pub fn push(&mut self, frame: StackFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Records an interrupt at `pc`, the address of the instruction it
+    /// returns to, jumping to `vector` and leaving the stack pointer at
+    /// `stack_pointer`
+    pub fn enter_interrupt(&mut self, pc: u16, vector: u16, stack_pointer: u16) {
+        self.frames.push(StackFrame {
+            call_site: pc,
+            target: vector,
+            return_address: pc,
+            stack_pointer,
+            is_interrupt: true,
+        });
+    }
+
+    /// Backtrace of the program stopped at `pc`
+    pub fn backtrace(&self, pc: u16) -> Backtrace {
+        let mut frames = vec![self.backtrace_frame(pc, false)];
+        frames.extend(
+            self.frames
+                .iter()
+                .rev()
+                .map(|frame| self.backtrace_frame(frame.call_site, frame.is_interrupt)),
+        );
+        Backtrace { frames }
+    }
+
+    /// Records an executed `instruction`, fetched from `pc` as
+    /// `raw_instruction`, that left the program counter at `next_pc` and
+    /// the stack pointer at `stack_pointer`. `stacked_address` is the return
+    /// address written by a call or read by a return.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record(
+        &mut self,
+        pc: u16,
+        raw_instruction: u16,
+        instruction: &Instruction,
+        next_pc: u16,
+        stacked_address: u16,
+        stack_pointer: u16,
+        cycle: usize,
+    ) {
+        if let Some(size) = call_size(raw_instruction) {
+            // `rcall .+0` only makes room in the stack
+            if next_pc != pc.wrapping_add(size) {
+                self.frames.push(StackFrame {
+                    call_site: pc,
+                    target: next_pc,
+                    return_address: stacked_address,
+                    stack_pointer,
+                    is_interrupt: false,
+                });
+            }
+        } else if let Instruction::ZeroRegOp { op: 0..=1 } = instruction {
+            self.pop_frames(pc, stacked_address, cycle);
+        }
+    }
+
+    fn pop_frames(&mut self, pc: u16, address: u16, cycle: usize) {
+        let expected = match self.frames.last() {
+            Some(frame) => frame.return_address,
+            None => return,
+        };
+        if expected != address {
+            self.mismatches.push(ReturnMismatch {
+                pc,
+                expected,
+                actual: address,
+                cycle,
+            });
+        }
+        let position = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_address == address);
+        match position {
+            Some(position) => self.frames.truncate(position),
+            None => {
+                self.frames.pop();
+            }
+        }
+    }
+
+    fn backtrace_frame(&self, pc: u16, is_interrupt: bool) -> BacktraceFrame {
+        let function = match self.symbols.lookup(u32::from(pc)) {
+            Some((symbol, offset)) if symbol.size == 0 || offset < symbol.size => {
+                Some((symbol.name.clone(), offset))
+            }
+            _ => None,
+        };
+        BacktraceFrame {
+            pc,
+            function,
+            source: None,
+            is_interrupt,
+        }
+    }
+}
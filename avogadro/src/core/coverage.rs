@@ -0,0 +1,182 @@
+use super::disassembler::Disassembler;
+use super::line_table::LineTable;
+use super::Instruction;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+
+/// Outcomes of a conditional instruction
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BranchCounts {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Execution counters of a program, collected by `Mcu::step`.
+///
+/// Every executed instruction increments the counter of its first word.
+/// Conditional instructions (branches, skips and `cpse`) also count how many
+/// times they were taken, meaning the branch jumped or the next instruction
+/// was skipped.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    counts: Vec<u64>,
+    branches: BTreeMap<u16, BranchCounts>,
+}
+
+#[derive(Default)]
+struct LineCoverage {
+    count: u64,
+    /// Address of the conditional instructions of the line
+    branches: Vec<u16>,
+}
+
+impl Coverage {
+    /// Creates counters for a program memory of `program_size` bytes
+    pub fn new(program_size: usize) -> Coverage {
+        Coverage {
+            counts: vec![0; program_size.div_ceil(2)],
+            branches: BTreeMap::new(),
+        }
+    }
+
+    /// Times the instruction at byte address `address` was executed
+    pub fn count(&self, address: u16) -> u64 {
+        self.counts
+            .get(usize::from(address / 2))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Counters of the conditional instruction at `address`, if it was
+    /// executed
+    pub fn branch(&self, address: u16) -> Option<BranchCounts> {
+        self.branches.get(&address).copied()
+    }
+
+    /// Number of different instructions executed
+    pub fn executed_instructions(&self) -> usize {
+        self.counts.iter().filter(|count| **count > 0).count()
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.iter_mut().for_each(|count| *count = 0);
+        self.branches.clear();
+    }
+
+    /// Counts an execution of `instruction`, at `pc`. `taken` tells if a
+    /// conditional instruction jumped or skipped.
+    pub(crate) fn record(&mut self, pc: u16, instruction: &Instruction, taken: bool) {
+        if let Some(count) = self.counts.get_mut(usize::from(pc / 2)) {
+            *count += 1;
+        }
+        if is_conditional(instruction) {
+            let counts = self.branches.entry(pc).or_default();
+            if taken {
+                counts.taken += 1;
+            } else {
+                counts.not_taken += 1;
+            }
+        }
+    }
+
+    /// Writes a report of executed addresses, one per line:
+    /// `<address>: <count>`, followed by ` taken=<n> not_taken=<n>` on
+    /// conditional instructions. Used when there is no debug info.
+    pub fn write_raw(&self, out: &mut impl Write) -> io::Result<()> {
+        for (word, count) in self.counts.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            let address = (word * 2) as u16;
+            write!(out, "{:04x}: {}", address, count)?;
+            if let Some(branch) = self.branches.get(&address) {
+                write!(
+                    out,
+                    " taken={} not_taken={}",
+                    branch.taken, branch.not_taken
+                )?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an lcov tracefile, mapping addresses to source lines with
+    /// `lines`. `program` is decoded to find conditional instructions that
+    /// were never executed. The count of a line is the highest count of its
+    /// instructions.
+    pub fn write_lcov(
+        &self,
+        out: &mut impl Write,
+        test_name: &str,
+        program: &[u8],
+        lines: &LineTable,
+    ) -> io::Result<()> {
+        let disassembler = Disassembler::new(program);
+        let mut files: BTreeMap<&str, BTreeMap<u32, LineCoverage>> = BTreeMap::new();
+        for (row, end) in lines.ranges() {
+            let line = files
+                .entry(&row.file)
+                .or_default()
+                .entry(row.line)
+                .or_default();
+            let mut address = row.address;
+            while address < end {
+                let instruction = match disassembler.decode_at(address) {
+                    Some(instruction) => instruction,
+                    None => break,
+                };
+                line.count = line.count.max(self.count(address as u16));
+                if is_conditional(&instruction.instruction) {
+                    line.branches.push(address as u16);
+                }
+                address += instruction.size();
+            }
+        }
+        for (file, lines) in files {
+            writeln!(out, "TN:{}", test_name)?;
+            writeln!(out, "SF:{}", file)?;
+            let (mut branches_found, mut branches_hit) = (0, 0);
+            for (number, line) in &lines {
+                for (block, address) in line.branches.iter().enumerate() {
+                    let counts = self.branches.get(address);
+                    let outcomes = counts.map(|counts| [counts.taken, counts.not_taken]);
+                    for branch in 0..2 {
+                        match outcomes {
+                            Some(outcomes) => {
+                                let taken = outcomes[branch];
+                                writeln!(out, "BRDA:{},{},{},{}", number, block, branch, taken)?;
+                                if taken > 0 {
+                                    branches_hit += 1;
+                                }
+                            }
+                            None => writeln!(out, "BRDA:{},{},{},-", number, block, branch)?,
+                        }
+                        branches_found += 1;
+                    }
+                }
+            }
+            writeln!(out, "BRF:{}", branches_found)?;
+            writeln!(out, "BRH:{}", branches_hit)?;
+            for (number, line) in &lines {
+                writeln!(out, "DA:{},{}", number, line.count)?;
+            }
+            let lines_hit = lines.values().filter(|line| line.count > 0).count();
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(out, "LH:{}", lines_hit)?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
+
+/// Branches, skips and `cpse`
+fn is_conditional(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Branch { .. }
+            | Instruction::SkipOp { .. }
+            | Instruction::TwoRegOp { op: 0x4, .. }
+    )
+}
@@ -0,0 +1,54 @@
+use super::alu;
+use super::decoder;
+use super::Instruction;
+
+impl Instruction {
+    /// Clock cycles taken by the instruction on the AVRe core (ATtiny and
+    /// ATmega with 16 bit PC), not counting the extra cycle of a taken branch
+    /// or a skip, which depend on the execution.
+    pub fn cycles(&self) -> usize {
+        match self {
+            Instruction::BitManipOp { .. } => 2,
+            Instruction::Branch { .. } => 1,
+            Instruction::CallJmp {
+                is_call,
+                relative,
+                address,
+            } => match (*is_call, *relative, *address) {
+                (false, true, _) => 2,
+                (true, true, _) => 3,
+                // IJMP, EIJMP, ICALL and EICALL
+                (false, false, decoder::CALL_JMP_Z_ADDRESS) => 2,
+                (false, false, decoder::CALL_JMP_EINDZ_ADDRESS) => 2,
+                (true, false, decoder::CALL_JMP_Z_ADDRESS) => 3,
+                (false, false, _) => 3,
+                (true, false, _) => 4,
+            },
+            Instruction::InOut { .. } => 1,
+            Instruction::Nop => 1,
+            Instruction::OneRegOp { .. } => 1,
+            Instruction::PushPop { .. } => 2,
+            // ADIW and SBIW work on register pairs
+            Instruction::RegConstOp { op, .. } => match op {
+                0x96 | 0x97 => 2,
+                _ => 1,
+            },
+            Instruction::SkipOp { .. } => 1,
+            Instruction::TransferIndirect { .. } => 2,
+            Instruction::TransferChangePointer { .. } => 2,
+            // Multiplications
+            Instruction::TwoRegOp { op, .. } => match *op {
+                alu::MULS_OP..=alu::FMULSU_OP => 2,
+                _ => 1,
+            },
+            Instruction::Unsupported { .. } => 1,
+            Instruction::ZeroRegOp { op } => match op {
+                // ret and reti
+                0x0 | 0x1 => 4,
+                // lpm and elpm
+                0xc | 0xd => 3,
+                _ => 1,
+            },
+        }
+    }
+}
@@ -0,0 +1,808 @@
+use super::mcu::Mcu;
+use super::symbols::DATA_SPACE_OFFSET;
+use gimli::{AttributeValue, EndianSlice, EvaluationResult, LittleEndian, UnitOffset};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+type Slice<'a> = EndianSlice<'a, LittleEndian>;
+type Die<'u, 'a> = gimli::DebuggingInformationEntry<'u, 'u, Slice<'a>>;
+type Node<'u, 't, 'a> = gimli::EntriesTreeNode<'u, 'u, 't, Slice<'a>>;
+
+/// DWARF register of the stack pointer in avr-gcc output
+const STACK_POINTER_REGISTER: u16 = 32;
+
+/// Index of a type in `DebugInfo`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeId(usize);
+
+/// How base type values are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseEncoding {
+    Signed,
+    Unsigned,
+    SignedChar,
+    UnsignedChar,
+    Boolean,
+    Float,
+}
+
+/// Field of a struct or union
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub name: String,
+    /// Offset from the start of the struct, in bytes
+    pub offset: u16,
+    pub type_id: Option<TypeId>,
+}
+
+/// C types, as described by DWARF. `None` type ids are `void`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Base {
+        name: String,
+        size: u16,
+        encoding: BaseEncoding,
+    },
+    /// Data memory pointer
+    Pointer {
+        target: Option<TypeId>,
+    },
+    /// Multidimensional arrays are arrays of arrays
+    Array {
+        element: Option<TypeId>,
+        count: u16,
+    },
+    Struct {
+        name: Option<String>,
+        size: u16,
+        members: Vec<Member>,
+        is_union: bool,
+    },
+    Enum {
+        name: Option<String>,
+        size: u16,
+        values: Vec<(String, i64)>,
+    },
+    Typedef {
+        name: String,
+        target: Option<TypeId>,
+    },
+    Function,
+}
+
+/// Value of a variable, decoded by its type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Signed(i64),
+    Unsigned(u64),
+    Char(u8),
+    Bool(bool),
+    Float(f64),
+    Pointer(u16),
+    /// Enumerator value, with its name if it has one
+    Enum {
+        value: i64,
+        name: Option<String>,
+    },
+    Array(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+    /// Optimized out, or its location isn't supported
+    Unavailable,
+}
+
+/// Global or local variable, or function parameter
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: String,
+    pub type_id: Option<TypeId>,
+    pub is_parameter: bool,
+    location: Location,
+}
+
+/// Function with its parameters and local variables, including those of
+/// nested blocks
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    /// Program memory byte addresses of its code
+    pub address: Range<u32>,
+    pub variables: Vec<Variable>,
+    frame_base: Location,
+}
+
+/// DWARF location description, which may depend on the program counter
+#[derive(Debug, Clone)]
+struct Location {
+    encoding: gimli::Encoding,
+    /// Entries without a range are valid everywhere. No entries means
+    /// optimized out.
+    entries: Vec<(Option<Range<u32>>, Vec<u8>)>,
+}
+
+/// Variables, functions and types read from DWARF debug info
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    types: Vec<Type>,
+    functions: Vec<Function>,
+    globals: Vec<Variable>,
+}
+
+impl DebugInfo {
+    pub fn new() -> DebugInfo {
+        DebugInfo::default()
+    }
+
+    /// Reads the compilation units of `dwarf`
+    pub(crate) fn read(dwarf: &gimli::Dwarf<Slice>) -> Result<DebugInfo, gimli::Error> {
+        let mut info = DebugInfo::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let mut reader = UnitReader {
+                dwarf,
+                unit: &unit,
+                type_ids: HashMap::new(),
+                qualifiers: HashMap::new(),
+                info: &mut info,
+            };
+            reader.read()?;
+        }
+        Ok(info)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.globals.is_empty()
+    }
+
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    pub fn globals(&self) -> &[Variable] {
+        &self.globals
+    }
+
+    pub fn get_type(&self, type_id: TypeId) -> &Type {
+        &self.types[type_id.0]
+    }
+
+    /// Returns the function with code at program byte address `pc`
+    pub fn function_at(&self, pc: u32) -> Option<&Function> {
+        self.functions
+            .iter()
+            .find(|function| function.address.contains(&pc))
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Variable> {
+        self.globals.iter().find(|variable| variable.name == name)
+    }
+
+    /// Size of values of type `type_id`, in bytes
+    pub fn type_size(&self, type_id: Option<TypeId>) -> u16 {
+        match type_id.map(|type_id| self.get_type(type_id)) {
+            Some(Type::Base { size, .. })
+            | Some(Type::Struct { size, .. })
+            | Some(Type::Enum { size, .. }) => *size,
+            Some(Type::Pointer { .. }) => 2,
+            Some(Type::Array { element, count }) => self.type_size(*element).saturating_mul(*count),
+            Some(Type::Typedef { target, .. }) => self.type_size(*target),
+            Some(Type::Function) | None => 0,
+        }
+    }
+
+    /// Name of type `type_id`, as written in C
+    pub fn type_name(&self, type_id: Option<TypeId>) -> String {
+        match type_id.map(|type_id| self.get_type(type_id)) {
+            Some(Type::Base { name, .. }) | Some(Type::Typedef { name, .. }) => name.clone(),
+            Some(Type::Pointer { target }) => format!("{} *", self.type_name(*target)),
+            Some(Type::Array { element, count }) => {
+                format!("{} [{}]", self.type_name(*element), count)
+            }
+            Some(Type::Struct { name, is_union, .. }) => {
+                let keyword = if *is_union { "union" } else { "struct" };
+                format!("{} {}", keyword, name.as_deref().unwrap_or("{...}"))
+            }
+            Some(Type::Enum { name, .. }) => {
+                format!("enum {}", name.as_deref().unwrap_or("{...}"))
+            }
+            Some(Type::Function) => "void ()".to_owned(),
+            None => "void".to_owned(),
+        }
+    }
+
+    /// Reads `variable` of `function`, or a global variable if `function` is
+    /// `None`. Locals are only valid while the PC is in their function.
+    pub fn value(&self, mcu: &Mcu, variable: &Variable, function: Option<&Function>) -> Value {
+        let pc = u32::from(mcu.get_program_counter());
+        let frame_base = function.and_then(|function| function.frame_base.frame_base(mcu, pc));
+        let size = self.type_size(variable.type_id);
+        match variable.location.read(mcu, pc, frame_base, size) {
+            Some(bytes) => self.decode(variable.type_id, &bytes),
+            None => Value::Unavailable,
+        }
+    }
+
+    /// Parameters and local variables of the function at the current PC
+    pub fn locals(&self, mcu: &Mcu) -> Vec<(&Variable, Value)> {
+        let pc = u32::from(mcu.get_program_counter());
+        match self.function_at(pc) {
+            Some(function) => function
+                .variables
+                .iter()
+                .map(|variable| (variable, self.value(mcu, variable, Some(function))))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Reads variable `name`, looking for it in the function at the current
+    /// PC first, and then in globals
+    pub fn read_variable(&self, mcu: &Mcu, name: &str) -> Option<Value> {
+        let pc = u32::from(mcu.get_program_counter());
+        if let Some(function) = self.function_at(pc) {
+            let local = function.variables.iter().find(|local| local.name == name);
+            if let Some(local) = local {
+                return Some(self.value(mcu, local, Some(function)));
+            }
+        }
+        self.global(name)
+            .map(|global| self.value(mcu, global, None))
+    }
+
+    /// Decodes `bytes`, which are as long as type `type_id`
+    fn decode(&self, type_id: Option<TypeId>, bytes: &[u8]) -> Value {
+        let value_type = match type_id {
+            Some(type_id) => self.get_type(type_id),
+            None => return Value::Unavailable,
+        };
+        let raw = bytes
+            .iter()
+            .rev()
+            .fold(0_u64, |value, byte| value << 8 | u64::from(*byte));
+        let signed = sign_extend(raw, bytes.len());
+        match value_type {
+            Type::Base { encoding, .. } => match encoding {
+                BaseEncoding::Signed => Value::Signed(signed),
+                BaseEncoding::Unsigned => Value::Unsigned(raw),
+                BaseEncoding::SignedChar | BaseEncoding::UnsignedChar => Value::Char(raw as u8),
+                BaseEncoding::Boolean => Value::Bool(raw != 0),
+                BaseEncoding::Float => match bytes.len() {
+                    4 => Value::Float(f64::from(f32::from_bits(raw as u32))),
+                    8 => Value::Float(f64::from_bits(raw)),
+                    _ => Value::Unavailable,
+                },
+            },
+            Type::Pointer { .. } => Value::Pointer(raw as u16),
+            Type::Array { element, count } => {
+                let size = usize::from(self.type_size(*element));
+                let values = bytes
+                    .chunks_exact(size.max(1))
+                    .take(usize::from(*count))
+                    .map(|element_bytes| self.decode(*element, element_bytes));
+                Value::Array(values.collect())
+            }
+            Type::Struct { members, .. } => {
+                let fields = members.iter().map(|member| {
+                    let start = usize::from(member.offset);
+                    let end = start + usize::from(self.type_size(member.type_id));
+                    let value = match bytes.get(start..end) {
+                        Some(member_bytes) => self.decode(member.type_id, member_bytes),
+                        None => Value::Unavailable,
+                    };
+                    (member.name.clone(), value)
+                });
+                Value::Struct(fields.collect())
+            }
+            Type::Enum { values, .. } => {
+                let enumerator = values
+                    .iter()
+                    .find(|(_, value)| *value == signed || *value == raw as i64);
+                Value::Enum {
+                    value: enumerator.map_or(signed, |(_, value)| *value),
+                    name: enumerator.map(|(name, _)| name.clone()),
+                }
+            }
+            Type::Typedef { target, .. } => self.decode(*target, bytes),
+            Type::Function => Value::Unavailable,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Formats values like gdb does
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Signed(value) => write!(f, "{}", value),
+            Value::Unsigned(value) => write!(f, "{}", value),
+            Value::Char(value) => write!(f, "{} '{}'", value, (*value as char).escape_default()),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Pointer(address) => write!(f, "0x{:04x}", address),
+            Value::Enum {
+                name: Some(name), ..
+            } => write!(f, "{}", name),
+            Value::Enum { value, name: None } => write!(f, "{}", value),
+            Value::Array(values) => {
+                write!(f, "{{")?;
+                for (i, value) in values.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{}", separator, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Struct(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{} = {}", separator, name, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Unavailable => write!(f, "<optimized out>"),
+        }
+    }
+}
+
+impl Location {
+    /// Evaluates the frame base of a function, an address
+    fn frame_base(&self, mcu: &Mcu, pc: u32) -> Option<u64> {
+        match self.evaluate(mcu, pc, None)?.first()?.location {
+            gimli::Location::Address { address } => Some(address),
+            gimli::Location::Register { register } => register_value(mcu, register.0),
+            _ => None,
+        }
+    }
+
+    /// Reads the `size` bytes of a value at this location
+    fn read(&self, mcu: &Mcu, pc: u32, frame_base: Option<u64>, size: u16) -> Option<Vec<u8>> {
+        let size = usize::from(size);
+        let mut bytes = Vec::with_capacity(size);
+        for piece in self.evaluate(mcu, pc, frame_base)? {
+            let length = piece
+                .size_in_bits
+                .map_or(size.saturating_sub(bytes.len()), |bits| bits as usize / 8);
+            match piece.location {
+                gimli::Location::Address { address } => {
+                    let address = data_address(address);
+                    bytes.extend(
+                        (0..length).map(|i| mcu.get_data_byte(address.wrapping_add(i as u16))),
+                    );
+                }
+                gimli::Location::Register { register } => {
+                    let registers = usize::from(register.0)..usize::from(register.0) + length;
+                    bytes.extend_from_slice(mcu.get_register_array().get(registers)?);
+                }
+                gimli::Location::Value { value } => {
+                    let value = value.to_u64(!0).ok()?.to_le_bytes();
+                    bytes.extend_from_slice(value.get(..length)?);
+                }
+                gimli::Location::Bytes { value } => {
+                    bytes.extend_from_slice(value.slice().get(..length)?);
+                }
+                _ => return None,
+            }
+        }
+        if bytes.len() < size {
+            return None;
+        }
+        bytes.truncate(size);
+        Some(bytes)
+    }
+
+    /// Evaluates the expression valid at `pc`, returning its pieces
+    fn evaluate(
+        &self,
+        mcu: &Mcu,
+        pc: u32,
+        frame_base: Option<u64>,
+    ) -> Option<Vec<gimli::Piece<Slice<'_>>>> {
+        let (_, expression) = self
+            .entries
+            .iter()
+            .find(|(range, _)| range.as_ref().is_none_or(|range| range.contains(&pc)))?;
+        let expression = gimli::Expression(EndianSlice::new(expression, LittleEndian));
+        let mut evaluation = expression.evaluation(self.encoding);
+        let mut result = evaluation.evaluate().ok()?;
+        loop {
+            result = match result {
+                EvaluationResult::Complete => return Some(evaluation.result()),
+                EvaluationResult::RequiresMemory { address, size, .. } => {
+                    let address = data_address(address);
+                    let value = (0..u16::from(size)).rev().fold(0, |value, i| {
+                        value << 8 | u64::from(mcu.get_data_byte(address.wrapping_add(i)))
+                    });
+                    evaluation.resume_with_memory(gimli::Value::Generic(value))
+                }
+                EvaluationResult::RequiresRegister { register, .. } => {
+                    let value = register_value(mcu, register.0)?;
+                    evaluation.resume_with_register(gimli::Value::Generic(value))
+                }
+                EvaluationResult::RequiresFrameBase => {
+                    evaluation.resume_with_frame_base(frame_base?)
+                }
+                EvaluationResult::RequiresRelocatedAddress(address) => {
+                    evaluation.resume_with_relocated_address(address)
+                }
+                _ => return None,
+            }
+            .ok()?;
+        }
+    }
+}
+
+/// Value of DWARF register `register` used as a base address. Registers 26,
+/// 28 and 30 are the X, Y and Z pointers, and 32 is the stack pointer.
+fn register_value(mcu: &Mcu, register: u16) -> Option<u64> {
+    match register {
+        26 | 28 | 30 => {
+            let low = mcu.get_register(register as u8);
+            let high = mcu.get_register(register as u8 + 1);
+            Some(u64::from(u16::from_le_bytes([low, high])))
+        }
+        STACK_POINTER_REGISTER => Some(u64::from(mcu.get_stack_pointer())),
+        0..=31 => Some(u64::from(mcu.get_register(register as u8))),
+        _ => None,
+    }
+}
+
+/// Converts a DWARF address into a data memory address, removing the offset
+/// of data symbols
+fn data_address(address: u64) -> u16 {
+    if address >= u64::from(DATA_SPACE_OFFSET) {
+        (address - u64::from(DATA_SPACE_OFFSET)) as u16
+    } else {
+        address as u16
+    }
+}
+
+fn sign_extend(value: u64, size: usize) -> i64 {
+    if size == 0 || size >= 8 {
+        return value as i64;
+    }
+    let shift = 64 - size * 8;
+    ((value << shift) as i64) >> shift
+}
+
+/// Reads the debug info entries of one compilation unit
+struct UnitReader<'a, 'u> {
+    dwarf: &'u gimli::Dwarf<Slice<'a>>,
+    unit: &'u gimli::Unit<Slice<'a>>,
+    type_ids: HashMap<UnitOffset, TypeId>,
+    /// `const` and `volatile` qualifiers, which are skipped
+    qualifiers: HashMap<UnitOffset, Option<UnitOffset>>,
+    info: &'u mut DebugInfo,
+}
+
+impl<'a, 'u> UnitReader<'a, 'u> {
+    /// Reserves an id for each type, so types can refer to types defined
+    /// later, and then reads types, functions and variables
+    fn read(&mut self) -> Result<(), gimli::Error> {
+        let mut entries = self.unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            match entry.tag() {
+                gimli::DW_TAG_const_type
+                | gimli::DW_TAG_volatile_type
+                | gimli::DW_TAG_restrict_type
+                | gimli::DW_TAG_atomic_type => {
+                    self.qualifiers
+                        .insert(entry.offset(), type_reference(entry)?);
+                }
+                tag if is_type(tag) => {
+                    self.type_ids
+                        .insert(entry.offset(), TypeId(self.info.types.len()));
+                    self.info.types.push(Type::Function);
+                }
+                _ => (),
+            }
+        }
+        let mut tree = self.unit.entries_tree(None)?;
+        self.read_node(tree.root()?, None)
+    }
+
+    fn read_node(
+        &mut self,
+        node: Node<'u, '_, 'a>,
+        function: Option<&mut Function>,
+    ) -> Result<(), gimli::Error> {
+        let entry = node.entry().clone();
+        match entry.tag() {
+            gimli::DW_TAG_subprogram => {
+                if let Some(mut function) = self.read_function(&entry)? {
+                    self.read_children(node, Some(&mut function))?;
+                    self.info.functions.push(function);
+                }
+            }
+            gimli::DW_TAG_variable | gimli::DW_TAG_formal_parameter => {
+                let variable = self.read_variable(&entry)?;
+                match function {
+                    Some(function) => function.variables.push(variable),
+                    None if !variable.location.entries.is_empty() => {
+                        self.info.globals.push(variable)
+                    }
+                    None => (),
+                }
+            }
+            tag if is_type(tag) => self.read_type(&entry, node)?,
+            _ => self.read_children(node, function)?,
+        }
+        Ok(())
+    }
+
+    fn read_children(
+        &mut self,
+        node: Node<'u, '_, 'a>,
+        mut function: Option<&mut Function>,
+    ) -> Result<(), gimli::Error> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            self.read_node(child, function.as_deref_mut())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a function definition. Declarations and functions without an
+    /// address range are skipped.
+    fn read_function(&self, entry: &Die<'_, 'a>) -> Result<Option<Function>, gimli::Error> {
+        let low = match entry.attr_value(gimli::DW_AT_low_pc)? {
+            Some(value) => match self.dwarf.attr_address(self.unit, value)? {
+                Some(low) => low,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        let high = match entry.attr_value(gimli::DW_AT_high_pc)? {
+            Some(AttributeValue::Addr(high)) => high,
+            Some(value) => match value.udata_value() {
+                Some(size) => low + size,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        Ok(Some(Function {
+            name: self.name(entry)?.unwrap_or_default(),
+            address: low as u32..high as u32,
+            variables: Vec::new(),
+            frame_base: self.read_location(entry, gimli::DW_AT_frame_base)?,
+        }))
+    }
+
+    fn read_variable(&self, entry: &Die<'_, 'a>) -> Result<Variable, gimli::Error> {
+        let declaration = self.declaration(entry)?;
+        let type_reference = match type_reference(entry)? {
+            Some(type_reference) => Some(type_reference),
+            None => match &declaration {
+                Some(declaration) => type_reference(declaration)?,
+                None => None,
+            },
+        };
+        Ok(Variable {
+            name: self.name(entry)?.unwrap_or_default(),
+            type_id: self.resolve(type_reference),
+            is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
+            location: self.read_location(entry, gimli::DW_AT_location)?,
+        })
+    }
+
+    /// Reads a type into the id reserved for it
+    fn read_type(
+        &mut self,
+        entry: &Die<'_, 'a>,
+        node: Node<'u, '_, 'a>,
+    ) -> Result<(), gimli::Error> {
+        let type_id = match self.type_ids.get(&entry.offset()) {
+            Some(type_id) => *type_id,
+            None => return Ok(()),
+        };
+        let name = self.name(entry)?;
+        let size = entry
+            .attr_value(gimli::DW_AT_byte_size)?
+            .and_then(|value| value.udata_value())
+            .unwrap_or(0) as u16;
+        let target = self.resolve(type_reference(entry)?);
+        let value_type = match entry.tag() {
+            gimli::DW_TAG_base_type => {
+                let encoding = match entry.attr_value(gimli::DW_AT_encoding)? {
+                    Some(AttributeValue::Encoding(gimli::DW_ATE_signed)) => BaseEncoding::Signed,
+                    Some(AttributeValue::Encoding(gimli::DW_ATE_signed_char)) => {
+                        BaseEncoding::SignedChar
+                    }
+                    Some(AttributeValue::Encoding(gimli::DW_ATE_unsigned_char)) => {
+                        BaseEncoding::UnsignedChar
+                    }
+                    Some(AttributeValue::Encoding(gimli::DW_ATE_boolean)) => BaseEncoding::Boolean,
+                    Some(AttributeValue::Encoding(gimli::DW_ATE_float)) => BaseEncoding::Float,
+                    _ => BaseEncoding::Unsigned,
+                };
+                Type::Base {
+                    name: name.unwrap_or_default(),
+                    size,
+                    encoding,
+                }
+            }
+            gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => Type::Pointer { target },
+            gimli::DW_TAG_typedef => Type::Typedef {
+                name: name.unwrap_or_default(),
+                target,
+            },
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type | gimli::DW_TAG_class_type => {
+                let mut members = Vec::new();
+                let mut children = node.children();
+                while let Some(child) = children.next()? {
+                    let member = child.entry();
+                    if member.tag() == gimli::DW_TAG_member {
+                        members.push(Member {
+                            name: self.name(member)?.unwrap_or_default(),
+                            offset: self.member_offset(member)?,
+                            type_id: self.resolve(type_reference(member)?),
+                        });
+                    }
+                }
+                Type::Struct {
+                    name,
+                    size,
+                    members,
+                    is_union: entry.tag() == gimli::DW_TAG_union_type,
+                }
+            }
+            gimli::DW_TAG_enumeration_type => {
+                let mut values = Vec::new();
+                let mut children = node.children();
+                while let Some(child) = children.next()? {
+                    let enumerator = child.entry();
+                    let value = enumerator.attr_value(gimli::DW_AT_const_value)?;
+                    let value = value.and_then(|value| match value {
+                        AttributeValue::Udata(value) => Some(value as i64),
+                        value => value.sdata_value(),
+                    });
+                    if let (Some(name), Some(value)) = (self.name(enumerator)?, value) {
+                        values.push((name, value));
+                    }
+                }
+                Type::Enum { name, size, values }
+            }
+            gimli::DW_TAG_array_type => {
+                let mut counts = Vec::new();
+                let mut children = node.children();
+                while let Some(child) = children.next()? {
+                    let subrange = child.entry();
+                    if subrange.tag() != gimli::DW_TAG_subrange_type {
+                        continue;
+                    }
+                    let count = match subrange.attr_value(gimli::DW_AT_count)? {
+                        Some(count) => count.udata_value(),
+                        None => subrange
+                            .attr_value(gimli::DW_AT_upper_bound)?
+                            .and_then(|bound| bound.udata_value())
+                            .map(|bound| bound + 1),
+                    };
+                    counts.push(count.unwrap_or(0) as u16);
+                }
+                // Inner dimensions are new array types
+                let mut element = target;
+                for count in counts.iter().skip(1).rev() {
+                    self.info.types.push(Type::Array {
+                        element,
+                        count: *count,
+                    });
+                    element = Some(TypeId(self.info.types.len() - 1));
+                }
+                Type::Array {
+                    element,
+                    count: counts.first().copied().unwrap_or(0),
+                }
+            }
+            _ => Type::Function,
+        };
+        self.info.types[type_id.0] = value_type;
+        Ok(())
+    }
+
+    /// Returns the type of a type reference, skipping qualifiers
+    fn resolve(&self, mut offset: Option<UnitOffset>) -> Option<TypeId> {
+        // Bounded, in case of malformed qualifier loops
+        for _ in 0..8 {
+            match self.qualifiers.get(&offset?) {
+                Some(target) => offset = *target,
+                None => return self.type_ids.get(&offset?).copied(),
+            }
+        }
+        None
+    }
+
+    /// Name of an entry, or of its declaration for definitions outside of
+    /// it
+    fn name(&self, entry: &Die<'_, 'a>) -> Result<Option<String>, gimli::Error> {
+        let value = match entry.attr_value(gimli::DW_AT_name)? {
+            Some(value) => value,
+            None => match self.declaration(entry)? {
+                Some(declaration) => match declaration.attr_value(gimli::DW_AT_name)? {
+                    Some(value) => value,
+                    None => return Ok(None),
+                },
+                None => return Ok(None),
+            },
+        };
+        let name = self.dwarf.attr_string(self.unit, value)?;
+        Ok(Some(name.to_string_lossy().into_owned()))
+    }
+
+    /// Entry declaring a definition, through `DW_AT_specification` or
+    /// `DW_AT_abstract_origin`
+    fn declaration(&self, entry: &Die<'_, 'a>) -> Result<Option<Die<'u, 'a>>, gimli::Error> {
+        for attribute in &[gimli::DW_AT_specification, gimli::DW_AT_abstract_origin] {
+            if let Some(AttributeValue::UnitRef(offset)) = entry.attr_value(*attribute)? {
+                return Ok(Some(self.unit.entry(offset)?));
+            }
+        }
+        Ok(None)
+    }
+
+    fn member_offset(&self, entry: &Die<'_, 'a>) -> Result<u16, gimli::Error> {
+        let offset = match entry.attr_value(gimli::DW_AT_data_member_location)? {
+            // DWARF 2 style, `DW_OP_plus_uconst`
+            Some(AttributeValue::Exprloc(expression)) => {
+                match expression.operations(self.unit.encoding()).next()? {
+                    Some(gimli::Operation::PlusConstant { value }) => value,
+                    _ => 0,
+                }
+            }
+            Some(value) => value.udata_value().unwrap_or(0),
+            None => 0,
+        };
+        Ok(offset as u16)
+    }
+
+    /// Reads a location expression or list. Entries of lists are made
+    /// absolute.
+    fn read_location(
+        &self,
+        entry: &Die<'_, 'a>,
+        attribute: gimli::DwAt,
+    ) -> Result<Location, gimli::Error> {
+        let mut location = Location {
+            encoding: self.unit.encoding(),
+            entries: Vec::new(),
+        };
+        match entry.attr_value(attribute)? {
+            Some(AttributeValue::Exprloc(expression)) => {
+                location.entries.push((None, expression.0.slice().to_vec()));
+            }
+            Some(value) => {
+                if let Some(mut list) = self.dwarf.attr_locations(self.unit, value)? {
+                    while let Some(list_entry) = list.next()? {
+                        let range = list_entry.range.begin as u32..list_entry.range.end as u32;
+                        location
+                            .entries
+                            .push((Some(range), list_entry.data.0.slice().to_vec()));
+                    }
+                }
+            }
+            None => (),
+        }
+        Ok(location)
+    }
+}
+
+fn is_type(tag: gimli::DwTag) -> bool {
+    matches!(
+        tag,
+        gimli::DW_TAG_base_type
+            | gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_typedef
+            | gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_union_type
+            | gimli::DW_TAG_class_type
+            | gimli::DW_TAG_enumeration_type
+            | gimli::DW_TAG_array_type
+            | gimli::DW_TAG_subroutine_type
+    )
+}
+
+fn type_reference(entry: &Die) -> Result<Option<UnitOffset>, gimli::Error> {
+    match entry.attr_value(gimli::DW_AT_type)? {
+        Some(AttributeValue::UnitRef(offset)) => Ok(Some(offset)),
+        _ => Ok(None),
+    }
+}
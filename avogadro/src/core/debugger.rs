@@ -0,0 +1,162 @@
+use super::call_stack::Backtrace;
+use super::debug_info::{Value, Variable};
+use super::decoder::{call_size, is_return};
+use super::elf::ElfFile;
+use super::line_table::LineRow;
+use super::mcu::Mcu;
+
+/// Default limit of a single source step, in clock cycles
+pub const DEFAULT_CYCLE_LIMIT: usize = 10_000_000;
+
+/// Why a source level step stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStop {
+    /// At the first instruction of a source line
+    Line,
+    /// Right after returning from the function, usually in the middle of a
+    /// line of its caller
+    Return,
+    /// At a breakpoint of the MCU
+    Breakpoint,
+    /// The cycle limit was reached first
+    CycleLimit,
+}
+
+/// Source level debugger for programs with DWARF debug info, steps by
+/// source lines and reads variables. Steps run the MCU with `Mcu::step`, so
+/// tracers, history and other attachments see every instruction.
+///
+/// Calls and returns are found through the stack pointer, so firmware must
+/// set it up before the first call.
+pub struct SourceDebugger {
+    elf: ElfFile,
+    cycle_limit: usize,
+}
+
+impl SourceDebugger {
+    pub fn new(elf: ElfFile) -> SourceDebugger {
+        SourceDebugger {
+            elf,
+            cycle_limit: DEFAULT_CYCLE_LIMIT,
+        }
+    }
+
+    /// Limits each step to `cycle_limit` clock cycles, so stepping over an
+    /// endless loop returns
+    pub fn with_cycle_limit(mut self, cycle_limit: usize) -> SourceDebugger {
+        self.cycle_limit = cycle_limit;
+        self
+    }
+
+    pub fn get_elf(&self) -> &ElfFile {
+        &self.elf
+    }
+
+    /// Source line of the current instruction
+    pub fn location(&self, mcu: &Mcu) -> Option<&LineRow> {
+        self.elf.lines.find(u32::from(mcu.get_program_counter()))
+    }
+
+    /// Runs until reaching another source line, entering called functions
+    pub fn step(&self, mcu: &mut Mcu) -> StepStop {
+        self.run_to_line(mcu, false)
+    }
+
+    /// Runs until reaching another source line of this function or its
+    /// callers, running called functions to completion
+    pub fn step_over(&self, mcu: &mut Mcu) -> StepStop {
+        self.run_to_line(mcu, true)
+    }
+
+    /// Runs until the current function returns
+    pub fn step_out(&self, mcu: &mut Mcu) -> StepStop {
+        let frame = mcu.get_stack_pointer();
+        let end = mcu.get_cycle_count() + self.cycle_limit;
+        loop {
+            let is_return =
+                is_return(mcu.get_current_instruction()) && mcu.get_stack_pointer() >= frame;
+            mcu.step();
+            if is_return {
+                return StepStop::Return;
+            }
+            if let Some(stop) = self.check_stop(mcu, end) {
+                return stop;
+            }
+        }
+    }
+
+    /// Backtrace with the source line of each frame, if the MCU keeps a call
+    /// stack
+    pub fn backtrace(&self, mcu: &Mcu) -> Option<Backtrace> {
+        let mut backtrace = mcu.backtrace()?;
+        for frame in &mut backtrace.frames {
+            frame.source = self
+                .elf
+                .lines
+                .find(u32::from(frame.pc))
+                .map(|row| format!("{}:{}", row.file, row.line));
+        }
+        Some(backtrace)
+    }
+
+    /// Parameters and local variables of the function at the current PC
+    pub fn locals(&self, mcu: &Mcu) -> Vec<(&Variable, Value)> {
+        self.elf.debug_info.locals(mcu)
+    }
+
+    /// Reads local or global variable `name`
+    pub fn read_variable(&self, mcu: &Mcu, name: &str) -> Option<Value> {
+        self.elf.debug_info.read_variable(mcu, name)
+    }
+
+    /// Steps until the PC is at the start of a line table row of another
+    /// line, or back at the start of the current line, as loops do.
+    /// Instructions without line info are stepped through.
+    fn run_to_line(&self, mcu: &mut Mcu, over_calls: bool) -> StepStop {
+        let start = self
+            .location(mcu)
+            .map(|row| (row.address, row.file.clone(), row.line));
+        let end = mcu.get_cycle_count() + self.cycle_limit;
+        loop {
+            if over_calls && call_size(mcu.get_current_instruction()).is_some() {
+                let frame = mcu.get_stack_pointer();
+                mcu.step();
+                while mcu.get_stack_pointer() < frame {
+                    if let Some(stop) = self.check_stop(mcu, end) {
+                        return stop;
+                    }
+                    mcu.step();
+                }
+            } else {
+                mcu.step();
+            }
+            if let Some(stop) = self.check_stop(mcu, end) {
+                return stop;
+            }
+            let pc = u32::from(mcu.get_program_counter());
+            let row = match self.elf.lines.find(pc) {
+                Some(row) if row.address == pc => row,
+                _ => continue,
+            };
+            let is_new_line = match &start {
+                Some((address, file, line)) => {
+                    row.address == *address || row.line != *line || row.file != *file
+                }
+                None => true,
+            };
+            if is_new_line {
+                return StepStop::Line;
+            }
+        }
+    }
+
+    fn check_stop(&self, mcu: &Mcu, end: usize) -> Option<StepStop> {
+        if mcu.get_breakpoints().contains(&mcu.get_program_counter()) {
+            Some(StepStop::Breakpoint)
+        } else if mcu.get_cycle_count() >= end {
+            Some(StepStop::CycleLimit)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use super::decoder::Decoder;
+use super::Instruction;
+
+/// An instruction decoded from a flash word
+#[derive(Debug, Clone, Copy)]
+pub struct DecodedWord {
+    pub raw_instruction: u16,
+    pub instruction: Instruction,
+    /// Clock cycles taken, without the extra ones of taken branches and skips
+    pub cycles: usize,
+}
+
+impl DecodedWord {
+    pub fn decode(raw_instruction: u16) -> DecodedWord {
+        let instruction = Decoder::decode(raw_instruction);
+        DecodedWord {
+            raw_instruction,
+            cycles: instruction.cycles(),
+            instruction,
+        }
+    }
+}
+
+/// Instructions decoded from program memory, one entry per flash word.
+/// Words are decoded the first time they are executed, and decoded again
+/// after being written, so the cache must be invalidated on every program
+/// memory write.
+pub struct DecodeCache {
+    words: Vec<Option<DecodedWord>>,
+    enabled: bool,
+}
+
+impl DecodeCache {
+    /// Creates an enabled, empty cache for `program_size` bytes of flash
+    pub fn new(program_size: usize) -> DecodeCache {
+        DecodeCache {
+            words: vec![None; program_size / 2],
+            enabled: true,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Disabling the cache empties it, every word is then decoded each time
+    /// it is executed
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.invalidate_all();
+    }
+
+    /// Cached instruction at word `index`, if decoded since last written
+    pub fn get(&self, index: usize) -> Option<DecodedWord> {
+        self.words.get(index).copied().flatten()
+    }
+
+    /// Caches `decoded` at word `index`, if enabled
+    pub fn insert(&mut self, index: usize, decoded: DecodedWord) {
+        if self.enabled {
+            if let Some(word) = self.words.get_mut(index) {
+                *word = Some(decoded);
+            }
+        }
+    }
+
+    /// Forgets the words holding program memory bytes `start..end`
+    pub fn invalidate(&mut self, start: usize, end: usize) {
+        let end = end.div_ceil(2).min(self.words.len());
+        for word in self.words.iter_mut().take(end).skip(start / 2) {
+            *word = None;
+        }
+    }
+
+    /// Forgets every word, resizing the cache to `program_size` bytes of
+    /// flash
+    pub fn reset(&mut self, program_size: usize) {
+        self.words = vec![None; program_size / 2];
+    }
+
+    fn invalidate_all(&mut self) {
+        self.words.iter_mut().for_each(|word| *word = None);
+    }
+}
@@ -7,6 +7,9 @@ pub const CALL_JMP_ABS_22_ADDRESS: u16 = 0;
 pub const CALL_JMP_Z_ADDRESS: u16 = 1;
 pub const CALL_JMP_EINDZ_ADDRESS: u16 = 2;
 
+const LDS_STS_MASK: RawInstruction = 0xFC0F;
+const JMP_CALL_MASK: RawInstruction = 0xFE0C;
+
 /// # Decoder
 ///
 /// Decodes and executes instructions
@@ -92,7 +95,10 @@ fn decode_misc_mult_op(raw_instruction: RawInstruction) -> Instruction {
                 rr: rr + 8,
             },
         },
-        _ => unreachable!(),
+        // Only `nop` (0x0000) is defined
+        _ => Instruction::Unsupported {
+            instruction: raw_instruction,
+        },
     }
 }
 
@@ -236,22 +242,41 @@ fn decode_misc_op(raw_instruction: RawInstruction) -> Instruction {
                     _ => Instruction::ZeroRegOp { op: sub_op },
                 }
             } else if is_call_jmp(raw_instruction) {
-                let is_call = raw_instruction & 0x2 == 0;
+                // JMP: 1001 010k kkkk 110k, CALL: 1001 010k kkkk 111k
+                let is_call = raw_instruction & 0x2 != 0;
                 Instruction::CallJmp {
                     is_call,
                     relative: false,
                     address: CALL_JMP_ABS_22_ADDRESS,
                 }
+            } else if raw_instruction & 0xFEEF == 0x9409 {
+                // IJMP, EIJMP, ICALL and EICALL: 1001 010c 000e 1001
+                let address = if raw_instruction & 0x10 == 0 {
+                    CALL_JMP_Z_ADDRESS
+                } else {
+                    CALL_JMP_EINDZ_ADDRESS
+                };
+                Instruction::CallJmp {
+                    is_call: raw_instruction & 0x0100 != 0,
+                    relative: false,
+                    address,
+                }
             } else {
                 let op = (raw_instruction & 0xF) as u8;
                 let rd = ((raw_instruction & 0x01F0) >> 4) as u8;
-                Instruction::OneRegOp { rd, op }
+                match op {
+                    // Reserved, other indirect jump encodings, and DES
+                    0x4 | 0x9 | 0xB => Instruction::Unsupported {
+                        instruction: raw_instruction,
+                    },
+                    _ => Instruction::OneRegOp { rd, op },
+                }
             }
         }
         0x0600 | 0x0700 => {
             let op = (raw_instruction & 0xFF00) >> 8;
             let rd = ((raw_instruction & 0x30) >> 4) as u8;
-            let constant = (((raw_instruction & 0xC0) >> 2) + raw_instruction & 0xF) as u8;
+            let constant = (((raw_instruction & 0xC0) >> 2) | (raw_instruction & 0xF)) as u8;
             Instruction::RegConstOp { op, rd, constant }
         }
         0x0800 | 0x0A00 => {
@@ -315,6 +340,30 @@ fn decode_branch_skip_status_op(raw_instruction: RawInstruction) -> Instruction
     }
 }
 
+/// Returns true if `raw_instruction` is the first word of a two word instruction
+/// (`LDS`, `STS`, `JMP` or `CALL`)
+pub fn is_two_word_instruction(raw_instruction: RawInstruction) -> bool {
+    raw_instruction & LDS_STS_MASK == 0x9000 || raw_instruction & JMP_CALL_MASK == 0x940C
+}
+
+/// Size in bytes of `raw_instruction` if it's a call: `call`, `rcall`,
+/// `icall` or `eicall`
+pub fn call_size(raw_instruction: RawInstruction) -> Option<u16> {
+    if raw_instruction & 0xFE0E == 0x940E {
+        Some(4)
+    } else if raw_instruction & 0xF000 == 0xD000 || raw_instruction & 0xFFEF == 0x9509 {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// True if `raw_instruction` returns from a call or interrupt: `ret` or
+/// `reti`
+pub fn is_return(raw_instruction: RawInstruction) -> bool {
+    raw_instruction & 0xFFEF == 0x9508
+}
+
 fn is_call_jmp(raw_instruction: u16) -> bool {
     raw_instruction & 0x0E0C == 0x40C
 }
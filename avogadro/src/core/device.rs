@@ -0,0 +1,196 @@
+use super::memory_map::{MemoryMap, Region};
+
+/// First I/O register in data memory
+const IO_START: u16 = 0x20;
+/// Reserved ATtiny85 I/O registers
+const ATTINY85_RESERVED: [u16; 11] = [
+    0x00, 0x01, 0x02, 0x09, 0x0A, 0x0B, 0x0C, 0x19, 0x1A, 0x1B, 0x3C,
+];
+/// Read-only ATtiny85 I/O registers: ADCL, ADCH and USIBR
+const ATTINY85_READ_ONLY: [u16; 3] = [0x04, 0x05, 0x10];
+
+/// A block of the data address space
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataRegion {
+    pub start: u16,
+    /// Size in bytes
+    pub length: usize,
+    pub region: Region,
+}
+
+impl DataRegion {
+    pub fn new(start: u16, length: usize, region: Region) -> DataRegion {
+        DataRegion {
+            start,
+            length,
+            region,
+        }
+    }
+
+    /// First address after the region
+    pub fn end(&self) -> usize {
+        usize::from(self.start) + self.length
+    }
+}
+
+/// A GPIO port, whose `PINx`, `DDRx` and `PORTx` registers are consecutive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Port {
+    /// Port letter, like `'B'` for `PB0` to `PB7`
+    pub name: char,
+    /// `PORTx` data memory address, after `PINx` and `DDRx`
+    pub address: u16,
+}
+
+impl Port {
+    pub fn new(name: char, address: u16) -> Port {
+        Port { name, address }
+    }
+
+    /// Port `name` in `ports`, if the device has it
+    pub fn find(ports: &[Port], name: char) -> Option<Port> {
+        ports.iter().find(|port| port.name == name).copied()
+    }
+}
+
+/// Ports of the MCUs created with `Mcu::new`, in the ATmega16 layout
+pub const DEFAULT_PORTS: [Port; 4] = [
+    Port {
+        name: 'A',
+        address: 0x3B,
+    },
+    Port {
+        name: 'B',
+        address: 0x38,
+    },
+    Port {
+        name: 'C',
+        address: 0x35,
+    },
+    Port {
+        name: 'D',
+        address: 0x32,
+    },
+];
+
+/// Memory layout of an MCU model
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceDescriptor {
+    pub name: String,
+    /// Flash size in bytes
+    pub program_size: usize,
+    /// Clock speed in kHz, the default fuses one or the usual board one
+    pub speed: usize,
+    /// Data memory regions, from the lowest address. Every region but
+    /// external RAM is backed by data memory.
+    pub data_regions: Vec<DataRegion>,
+    /// Reserved I/O registers, as data memory addresses
+    pub reserved: Vec<u16>,
+    /// Read-only I/O registers, as data memory addresses
+    pub read_only: Vec<u16>,
+    /// GPIO ports
+    pub ports: Vec<Port>,
+}
+
+impl DeviceDescriptor {
+    /// Descriptor of MCU model `name`, or `None` if it's not supported
+    pub fn find(name: &str) -> Option<DeviceDescriptor> {
+        match name {
+            "attiny85" => Some(DeviceDescriptor {
+                name: name.to_owned(),
+                program_size: 8 * 1024,
+                speed: 1000,
+                data_regions: vec![
+                    DataRegion::new(0x00, 0x20, Region::Registers),
+                    DataRegion::new(0x20, 0x40, Region::Io),
+                    DataRegion::new(0x60, 512, Region::Sram),
+                ],
+                reserved: ATTINY85_RESERVED.iter().map(|a| IO_START + a).collect(),
+                read_only: ATTINY85_READ_ONLY.iter().map(|a| IO_START + a).collect(),
+                ports: vec![Port::new('B', 0x38)],
+            }),
+            "atmega328p" => Some(DeviceDescriptor {
+                name: name.to_owned(),
+                program_size: 32 * 1024,
+                speed: 16000,
+                data_regions: vec![
+                    DataRegion::new(0x00, 0x20, Region::Registers),
+                    DataRegion::new(0x20, 0x40, Region::Io),
+                    DataRegion::new(0x60, 0xA0, Region::ExtendedIo),
+                    DataRegion::new(0x100, 2 * 1024, Region::Sram),
+                ],
+                reserved: Vec::new(),
+                read_only: Vec::new(),
+                ports: vec![
+                    Port::new('B', 0x25),
+                    Port::new('C', 0x28),
+                    Port::new('D', 0x2B),
+                ],
+            }),
+            "atmega2560" => Some(DeviceDescriptor {
+                name: name.to_owned(),
+                program_size: 256 * 1024,
+                speed: 16000,
+                data_regions: vec![
+                    DataRegion::new(0x00, 0x20, Region::Registers),
+                    DataRegion::new(0x20, 0x40, Region::Io),
+                    DataRegion::new(0x60, 0x1A0, Region::ExtendedIo),
+                    DataRegion::new(0x200, 8 * 1024, Region::Sram),
+                    DataRegion::new(0x2200, 0xDE00, Region::ExternalRam),
+                ],
+                reserved: Vec::new(),
+                read_only: Vec::new(),
+                ports: vec![
+                    Port::new('A', 0x22),
+                    Port::new('B', 0x25),
+                    Port::new('C', 0x28),
+                    Port::new('D', 0x2B),
+                    Port::new('E', 0x2E),
+                    Port::new('F', 0x31),
+                    Port::new('G', 0x34),
+                    Port::new('H', 0x102),
+                    Port::new('J', 0x105),
+                    Port::new('K', 0x108),
+                    Port::new('L', 0x10B),
+                ],
+            }),
+            _ => None,
+        }
+    }
+
+    /// Bytes of data memory, up to the end of the last region backed by it
+    pub fn data_size(&self) -> usize {
+        self.data_regions
+            .iter()
+            .filter(|data_region| data_region.region != Region::ExternalRam)
+            .map(DataRegion::end)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Last SRAM address
+    pub fn ramend(&self) -> u16 {
+        self.data_regions
+            .iter()
+            .filter(|data_region| data_region.region == Region::Sram)
+            .map(|data_region| (data_region.end() - 1) as u16)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Memory map with the regions and registers of the device
+    pub fn memory_map(&self) -> MemoryMap {
+        let mut memory_map = MemoryMap::empty();
+        for data_region in &self.data_regions {
+            let start = u32::from(data_region.start);
+            memory_map.add_region(start..data_region.end() as u32, data_region.region);
+        }
+        for address in &self.reserved {
+            memory_map.add_reserved(*address);
+        }
+        for address in &self.read_only {
+            memory_map.add_read_only(*address);
+        }
+        memory_map
+    }
+}
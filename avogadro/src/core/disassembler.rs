@@ -0,0 +1,369 @@
+use super::decoder::{self, Decoder};
+use super::symbols::{SymbolTable, DATA_SPACE_OFFSET};
+use super::Instruction;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// `avr-objdump` collapses this many zero bytes (or more) into `...`
+const SKIP_ZEROES: usize = 8;
+/// ... and also fewer than this many zero bytes at the end of a symbol
+const SKIP_ZEROES_AT_END: usize = 3;
+/// Width, in bytes, of the raw bytes column
+const OCTETS_PER_LINE: usize = 4;
+
+/// An instruction read from a program image
+#[derive(Debug)]
+pub struct DisassembledInstruction {
+    /// Byte address in program memory
+    pub address: u32,
+    /// Raw words. `LDS`, `STS`, `JMP` and `CALL` take two words
+    pub words: Vec<u16>,
+    /// Decoded first word
+    pub instruction: Instruction,
+    /// Absolute byte address of jumps, calls and branches
+    pub target: Option<u32>,
+    /// Data memory address used by `LDS` and `STS`
+    pub data_address: Option<u16>,
+}
+
+impl DisassembledInstruction {
+    /// Instruction size in bytes
+    pub fn size(&self) -> u32 {
+        self.words.len() as u32 * 2
+    }
+
+    /// Mnemonic and operands. Relative targets are written as `.+k` and
+    /// absolute ones in hexadecimal, as `avr-objdump` does.
+    pub fn text(&self) -> String {
+        self.format_text(None)
+    }
+
+    /// Comment `avr-objdump` appends to the instruction, without the target
+    /// address annotation (decimal value of constants and I/O addresses)
+    pub fn operand_comment(&self) -> Option<String> {
+        match self.instruction {
+            Instruction::RegConstOp { constant, .. } => Some(format!("{}", constant)),
+            Instruction::InOut { address, .. }
+            | Instruction::BitManipOp { address, .. }
+            | Instruction::SkipOp { address, .. } => Some(format!("{}", address)),
+            Instruction::TransferIndirect { offset, .. } if offset != 0 => {
+                Some(format!("0x{:02x}", offset))
+            }
+            Instruction::Unsupported { .. } if self.words.len() == 1 => Some("????".to_owned()),
+            _ => None,
+        }
+    }
+
+    /// Formats the instruction replacing its target with `label`, if any
+    fn format_text(&self, label: Option<&str>) -> String {
+        let first = self.words[0];
+        if let Some(data_address) = self.data_address {
+            let reg = (first & 0x01F0) >> 4;
+            return if first & 0x0200 == 0 {
+                format!("lds\tr{}, 0x{:04X}", reg, data_address)
+            } else {
+                format!("sts\t0x{:04X}, r{}", data_address, reg)
+            };
+        }
+        let display = format!("{}", self.instruction);
+        let mnemonic = display.split('\t').next().unwrap_or_default();
+        match (&self.instruction, self.target) {
+            (
+                Instruction::CallJmp {
+                    relative: false, ..
+                },
+                Some(target),
+            ) => {
+                let op_str = if first & 0x2 != 0 { "call" } else { "jmp" };
+                match label {
+                    Some(label) => format!("{}\t{}", op_str, label),
+                    // C's `%#x` prints zero without prefix
+                    None if target == 0 => format!("{}\t0", op_str),
+                    None => format!("{}\t{:#x}", op_str, target),
+                }
+            }
+            (Instruction::CallJmp { .. }, Some(target))
+            | (Instruction::Branch { .. }, Some(target)) => match label {
+                Some(label) => format!("{}\t{}", mnemonic, label),
+                None => {
+                    let offset = target as i64 - self.address as i64 - 2;
+                    format!("{}\t.{:<+8}", mnemonic, offset)
+                }
+            },
+            _ => display,
+        }
+    }
+}
+
+/// # Disassembler
+///
+/// Walks a program image decoding every instruction. Output can be a listing
+/// with labels, suitable for reassembling, or text laid out like
+/// `avr-objdump -d`.
+pub struct Disassembler<'a> {
+    program: &'a [u8],
+    symbols: SymbolTable,
+    vector_count: usize,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(program: &'a [u8]) -> Disassembler<'a> {
+        Disassembler {
+            program,
+            symbols: SymbolTable::new(),
+            vector_count: 0,
+        }
+    }
+
+    /// Symbols used for labels and target annotations
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Number of entries of the interrupt vector table at the start of the
+    /// image. Entry size (`RJMP` or `JMP`) is guessed from the first entry.
+    pub fn set_vector_count(&mut self, vector_count: usize) {
+        self.vector_count = vector_count;
+    }
+
+    /// Decodes the instruction located at `address`
+    pub fn decode_at(&self, address: u32) -> Option<DisassembledInstruction> {
+        let first = self.word_at(address)?;
+        let instruction = Decoder::decode(first);
+        let second = if decoder::is_two_word_instruction(first) {
+            self.word_at(address + 2)
+        } else {
+            None
+        };
+        let mut disassembled = DisassembledInstruction {
+            address,
+            words: vec![first],
+            instruction,
+            target: None,
+            data_address: None,
+        };
+        if let Some(second) = second {
+            disassembled.words.push(second);
+            if first & 0xFE0C == 0x940C {
+                let high_bits = u32::from(first & 1) | u32::from((first & 0x01F0) >> 3);
+                disassembled.target = Some(((high_bits << 16) | u32::from(second)) * 2);
+            } else {
+                disassembled.data_address = Some(second);
+            }
+        }
+        let next = i64::from(address) + 2;
+        match disassembled.instruction {
+            Instruction::Branch { offset, .. } => {
+                disassembled.target = Some((next + i64::from(offset) * 2) as u32);
+            }
+            Instruction::CallJmp {
+                relative: true,
+                address: offset_bits,
+                ..
+            } => {
+                let offset = ((i64::from(offset_bits) & 0xFFF) ^ 0x800) - 0x800;
+                disassembled.target = Some((next + offset * 2) as u32);
+            }
+            _ => (),
+        }
+        Some(disassembled)
+    }
+
+    /// Decodes the whole program image
+    pub fn instructions(&self) -> Vec<DisassembledInstruction> {
+        let mut instructions = Vec::new();
+        let mut address = 0;
+        while let Some(instruction) = self.decode_at(address) {
+            address += instruction.size();
+            instructions.push(instruction);
+        }
+        instructions
+    }
+
+    /// Labels for the listing: program memory symbols, `__vectors` for the
+    /// vector table and `L_xxxx` for every target without a symbol
+    pub fn labels(&self) -> BTreeMap<u32, String> {
+        let mut labels = BTreeMap::new();
+        for instruction in self.instructions() {
+            if let Some(target) = instruction.target {
+                if (target as usize) < self.program.len() {
+                    labels.insert(target, format!("L_{:04x}", target));
+                }
+            }
+        }
+        if self.vector_count > 0 {
+            labels.insert(0, "__vectors".to_owned());
+        }
+        for symbol in self.symbols.iter().rev() {
+            if symbol.address < DATA_SPACE_OFFSET {
+                labels.insert(symbol.address, symbol.name.clone());
+            }
+        }
+        labels
+    }
+
+    /// Writes a listing with one label or instruction per line. Every target
+    /// is replaced by a label, so the listing can be fed back to an assembler.
+    pub fn write_listing<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        let labels = self.labels();
+        let vector_size = self.vector_entry_size();
+        for instruction in self.instructions() {
+            let address = instruction.address;
+            if let Some(label) = labels.get(&address) {
+                writeln!(out, "{}:", label)?;
+            }
+            let target_label = instruction.target.and_then(|target| labels.get(&target));
+            let text = instruction.format_text(target_label.map(String::as_str));
+            let mut comments = Vec::new();
+            if address % vector_size == 0 && ((address / vector_size) as usize) < self.vector_count
+            {
+                comments.push(format!("vector {}", address / vector_size));
+            }
+            comments.extend(instruction.operand_comment());
+            if comments.is_empty() {
+                writeln!(out, "\t{}\t; {:04x}", text, address)?;
+            } else {
+                writeln!(
+                    out,
+                    "\t{}\t; {:04x}: {}",
+                    text,
+                    address,
+                    comments.join(", ")
+                )?;
+            }
+        }
+        if !self.program.len().is_multiple_of(2) {
+            let last = self.program.len() - 1;
+            writeln!(out, "\t.byte\t0x{:02x}\t; {:04x}", self.program[last], last)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the disassembly with the same layout as `avr-objdump -d`,
+    /// starting from the `Disassembly of section` line
+    pub fn write_objdump<W: fmt::Write>(&self, out: &mut W, section: &str) -> fmt::Result {
+        writeln!(out, "Disassembly of section {}:", section)?;
+        let end = self.program.len();
+        let skip_address_chars = address_chars_to_skip(end as u32);
+        let mut starts = self
+            .symbols
+            .iter()
+            .map(|symbol| symbol.address as usize)
+            .filter(|address| *address < end)
+            .collect::<Vec<_>>();
+        starts.dedup();
+        if starts.first() != Some(&0) {
+            starts.insert(0, 0);
+        }
+        for (index, start) in starts.iter().enumerate() {
+            let stop = starts.get(index + 1).copied().unwrap_or(end);
+            let name = self
+                .symbols
+                .get(*start as u32)
+                .map_or(section, |symbol| symbol.name.as_str());
+            writeln!(out)?;
+            writeln!(out, "{:08x} <{}>:", start, name)?;
+            self.write_objdump_range(out, *start, stop, skip_address_chars)?;
+        }
+        Ok(())
+    }
+
+    fn write_objdump_range<W: fmt::Write>(
+        &self,
+        out: &mut W,
+        start: usize,
+        stop: usize,
+        skip_address_chars: usize,
+    ) -> fmt::Result {
+        let mut address = start;
+        while address < stop {
+            let zeroes = self.program[address..stop]
+                .iter()
+                .take_while(|byte| **byte == 0)
+                .count();
+            let zeroes_to_end = address + zeroes == stop;
+            if zeroes >= SKIP_ZEROES || (zeroes_to_end && zeroes < SKIP_ZEROES_AT_END && zeroes > 0)
+            {
+                writeln!(out, "\t...")?;
+                address += if zeroes_to_end { zeroes } else { zeroes & !3 };
+                continue;
+            }
+            let mut hex_address = format!("{:08x}", address).split_off(skip_address_chars);
+            let leading_zeroes = hex_address[..hex_address.len() - 1]
+                .chars()
+                .take_while(|c| *c == '0')
+                .count();
+            hex_address.replace_range(..leading_zeroes, &" ".repeat(leading_zeroes));
+            write!(out, "{}:\t", hex_address)?;
+            let instruction = match self.decode_at(address as u32) {
+                Some(instruction) => instruction,
+                None => {
+                    writeln!(
+                        out,
+                        "{:02x}          \t.byte\t0x{:02x}",
+                        self.program[address], self.program[address]
+                    )?;
+                    break;
+                }
+            };
+            let size = instruction.size() as usize;
+            for byte in &self.program[address..address + size] {
+                write!(out, "{:02x} ", byte)?;
+            }
+            write!(
+                out,
+                "{}\t",
+                "   ".repeat(OCTETS_PER_LINE.saturating_sub(size))
+            )?;
+            write!(out, "{}", instruction.text())?;
+            if let Some(comment) = instruction.operand_comment() {
+                write!(out, "\t; {}", comment)?;
+            }
+            if let Some(target) = instruction.target {
+                write!(out, "\t; {}", self.annotate(target))?;
+            }
+            if let Some(data_address) = instruction.data_address {
+                let address = DATA_SPACE_OFFSET + u32::from(data_address);
+                write!(out, "\t; {}", self.annotate(address))?;
+            }
+            writeln!(out)?;
+            address += size;
+        }
+        Ok(())
+    }
+
+    /// Formats an address as `0x1e <symbol+0x2>`
+    fn annotate(&self, address: u32) -> String {
+        match self.symbols.lookup(address) {
+            Some((symbol, 0)) => format!("0x{:x} <{}>", address, symbol.name),
+            Some((symbol, offset)) => format!("0x{:x} <{}+0x{:x}>", address, symbol.name, offset),
+            None => format!("0x{:x}", address),
+        }
+    }
+
+    /// Size in bytes of vector table entries, guessed from the first entry
+    fn vector_entry_size(&self) -> u32 {
+        self.decode_at(0)
+            .map_or(2, |instruction| instruction.size())
+    }
+
+    fn word_at(&self, address: u32) -> Option<u16> {
+        let address = address as usize;
+        let bytes = self.program.get(address..address + 2)?;
+        Some(u16::from(bytes[0]) | (u16::from(bytes[1]) << 8))
+    }
+}
+
+/// `avr-objdump` drops leading zeros of addresses in groups of four digits,
+/// depending on the highest address of the section
+fn address_chars_to_skip(end_address: u32) -> usize {
+    let zeroes = format!("{:08x}", end_address)
+        .chars()
+        .take_while(|c| *c == '0')
+        .count();
+    if zeroes == 0 {
+        0
+    } else {
+        (zeroes - 1) & !3
+    }
+}
@@ -1,4 +1,5 @@
 use super::alu;
+use super::decoder;
 use super::Instruction;
 use super::PointerRegister;
 use super::RawInstruction;
@@ -54,7 +55,7 @@ impl fmt::Display for Instruction {
                 post_inc,
             } => display_transfer_change_pointer(f, *is_load, *pointer, *dest, *post_inc),
             Instruction::TwoRegOp { op, rd, rr } => display_two_reg_op(f, *op, *rd, *rr),
-            Instruction::Unsupported { instruction } => write!(f, ".word\t0x{:02x}", *instruction),
+            Instruction::Unsupported { instruction } => write!(f, ".word\t0x{:04x}", *instruction),
             Instruction::ZeroRegOp { op } => display_zero_reg_op(f, *op),
         }
     }
@@ -111,9 +112,10 @@ fn display_one_reg_op(f: &mut fmt::Formatter<'_>, op: RawInstruction, rd: u8) ->
         0x6 => write!(f, "lsr\tr{}", rd),
         0x7 => write!(f, "ror\tr{}", rd),
         0x8 => display_set_clear(f, rd),
+        0xA => write!(f, "dec\tr{}", rd),
         _ => {
-            let word = 0x9404 + ((rd as u16) << 4);
-            write!(f, ".word\t0x{:x}", word)
+            let word = 0x9400 | (rd as u16) << 4 | op;
+            write!(f, ".word\t0x{:04x}", word)
         }
     }
 }
@@ -178,8 +180,10 @@ fn display_arith_costant(
         0x7 => write!(f, "andi\tr{}, 0x{:02X}", real_rd, constant),
         // ldi is technically a transfer instruction
         0xE => write!(f, "ldi\tr{}, 0x{:02X}", real_rd, constant),
-        0x96 => write!(f, "adiw\tr{}, 0x{:02X}", real_rd, constant),
-        0x97 => write!(f, "sbiw\tr{}, 0x{:02X}", real_rd, constant),
+        // Word operations use register pairs r24, r26, r28 and r30. Their 6
+        // bit constant is lowercase, as avr-objdump prints it
+        0x96 => write!(f, "adiw\tr{}, 0x{:02x}", 24 + rd * 2, constant),
+        0x97 => write!(f, "sbiw\tr{}, 0x{:02x}", 24 + rd * 2, constant),
         _ => unreachable!(),
     }
 }
@@ -228,7 +232,11 @@ fn display_calljmp(
         };
         write!(f, "r{}\t.{:+}", op_str, offset * 2)
     } else {
-        write!(f, "{}\t, 0x{:x}", op_str, address)
+        match address {
+            decoder::CALL_JMP_Z_ADDRESS => write!(f, "i{}", op_str),
+            decoder::CALL_JMP_EINDZ_ADDRESS => write!(f, "ei{}", op_str),
+            _ => write!(f, "{}\t, 0x{:x}", op_str, address),
+        }
     }
 }
 
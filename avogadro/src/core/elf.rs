@@ -0,0 +1,183 @@
+use super::debug_info::DebugInfo;
+use super::line_table::{LineRow, LineTable};
+use super::symbols::{Symbol, SymbolTable, DATA_SPACE_OFFSET};
+use gimli::{EndianSlice, LittleEndian};
+use object::elf;
+use object::read::elf::{ElfFile32, ProgramHeader};
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A program read from an ELF file, as linked by avr-gcc
+#[derive(Debug, Clone, Default)]
+pub struct ElfFile {
+    /// Program memory image, including initial values of `.data`
+    pub program: Vec<u8>,
+    pub symbols: SymbolTable,
+    /// Source lines, empty if the file has no debug info
+    pub lines: LineTable,
+    /// Functions, variables and types, empty if the file has no debug info
+    pub debug_info: DebugInfo,
+}
+
+impl ElfFile {
+    /// Reads ELF file `filename`
+    pub fn load(filename: &str) -> io::Result<ElfFile> {
+        ElfFile::parse(&fs::read(filename)?)
+    }
+
+    /// Parses the contents of an ELF file
+    pub fn parse(data: &[u8]) -> io::Result<ElfFile> {
+        let file = ElfFile32::<object::Endianness>::parse(data).map_err(invalid_data)?;
+        if file.elf_header().e_machine.get(file.endian()) != elf::EM_AVR {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not an AVR ELF file",
+            ));
+        }
+        let sections = load_dwarf_sections(&file).map_err(invalid_data)?;
+        let dwarf = sections.borrow(|section| EndianSlice::new(section, LittleEndian));
+        Ok(ElfFile {
+            program: read_program(&file)?,
+            symbols: read_symbols(&file),
+            lines: read_line_table(&dwarf).map_err(invalid_data)?,
+            debug_info: DebugInfo::read(&dwarf).map_err(invalid_data)?,
+        })
+    }
+}
+
+/// Returns true if `filename` starts with the ELF magic number
+pub fn is_elf_file(filename: &str) -> bool {
+    let mut magic = [0; 4];
+    fs::File::open(filename)
+        .and_then(|mut file| io::Read::read_exact(&mut file, &mut magic))
+        .is_ok()
+        && magic == elf::ELFMAG
+}
+
+/// Places loadable segments at their physical (load) address, so `.data`
+/// initial values follow `.text` as they do in flash. Relocatable files have
+/// no segments, so their allocated sections are used instead.
+fn read_program(file: &ElfFile32<object::Endianness>) -> io::Result<Vec<u8>> {
+    let endian = file.endian();
+    let mut chunks = Vec::new();
+    for header in file.elf_program_headers() {
+        if header.p_type(endian) != elf::PT_LOAD || header.p_filesz(endian) == 0 {
+            continue;
+        }
+        let address = header.p_paddr(endian);
+        if address < DATA_SPACE_OFFSET {
+            let data = header
+                .data(endian, file.data())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid segment data"))?;
+            chunks.push((address as usize, data));
+        }
+    }
+    if file.elf_program_headers().is_empty() {
+        for section in file.sections() {
+            let flags = match section.flags() {
+                object::SectionFlags::Elf { sh_flags } => sh_flags,
+                _ => 0,
+            };
+            let is_alloc = flags & u64::from(elf::SHF_ALLOC) != 0;
+            if is_alloc && section.address() < u64::from(DATA_SPACE_OFFSET) {
+                chunks.push((
+                    section.address() as usize,
+                    section.data().map_err(invalid_data)?,
+                ));
+            }
+        }
+    }
+    let mut program = Vec::new();
+    for (address, data) in chunks {
+        let end = address + data.len();
+        if program.len() < end {
+            program.resize(end, 0);
+        }
+        program[address..end].copy_from_slice(data);
+    }
+    Ok(program)
+}
+
+fn read_symbols(file: &ElfFile32<object::Endianness>) -> SymbolTable {
+    let mut symbols = SymbolTable::new();
+    for symbol in file.symbols() {
+        let is_named = matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data)
+            || (symbol.kind() == SymbolKind::Unknown && !symbol.is_undefined());
+        match symbol.name() {
+            Ok(name) if is_named && !name.is_empty() => symbols.add(Symbol {
+                name: name.to_owned(),
+                address: symbol.address() as u32,
+                size: symbol.size() as u32,
+                is_global: symbol.is_global(),
+            }),
+            _ => (),
+        }
+    }
+    symbols
+}
+
+/// Loads DWARF sections, missing ones are empty
+fn load_dwarf_sections<'a>(
+    file: &ElfFile32<'a, object::Endianness>,
+) -> Result<gimli::DwarfSections<Cow<'a, [u8]>>, gimli::Error> {
+    gimli::DwarfSections::load(|id| -> Result<Cow<[u8]>, gimli::Error> {
+        Ok(file
+            .section_by_name(id.name())
+            .and_then(|section| section.uncompressed_data().ok())
+            .unwrap_or(Cow::Borrowed(&[])))
+    })
+}
+
+fn read_line_table(
+    dwarf: &gimli::Dwarf<EndianSlice<LittleEndian>>,
+) -> Result<LineTable, gimli::Error> {
+    let mut rows = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let program = match unit.line_program.clone() {
+            Some(program) => program,
+            None => continue,
+        };
+        let comp_dir = unit
+            .comp_dir
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut program_rows = program.rows();
+        while let Some((header, row)) = program_rows.next_row()? {
+            let file = match row.file(header) {
+                Some(file) => file,
+                None => continue,
+            };
+            let mut path = Path::new(&comp_dir).to_path_buf();
+            if let Some(directory) = file.directory(header) {
+                path.push(
+                    dwarf
+                        .attr_string(&unit, directory)?
+                        .to_string_lossy()
+                        .as_ref(),
+                );
+            }
+            path.push(
+                dwarf
+                    .attr_string(&unit, file.path_name())?
+                    .to_string_lossy()
+                    .as_ref(),
+            );
+            rows.push(LineRow {
+                address: row.address() as u32,
+                file: path.to_string_lossy().into_owned(),
+                line: row.line().map_or(0, |line| line.get() as u32),
+                end_sequence: row.end_sequence(),
+            });
+        }
+    }
+    Ok(LineTable::from_rows(rows))
+}
+
+fn invalid_data(error: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
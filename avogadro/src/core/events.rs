@@ -0,0 +1,238 @@
+use super::call_stack::ReturnMismatch;
+use super::memory_map::AccessViolation;
+use super::stack_monitor::StackCollision;
+use std::ops::RangeInclusive;
+
+/// Kind of an `Event`, used to filter the ones a hook receives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Instruction = 0,
+    MemoryRead = 1,
+    MemoryWrite = 2,
+    IoRead = 3,
+    IoWrite = 4,
+    InterruptEntry = 5,
+    InterruptExit = 6,
+    Sleep = 7,
+    Wake = 8,
+    Fault = 9,
+}
+
+impl EventKind {
+    /// Bit of the kind in an event mask
+    pub fn mask(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Mask of every event kind
+pub const ALL_EVENTS: u32 = 0x3FF;
+
+/// A data memory access made by an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// Address of the instruction
+    pub pc: u16,
+    pub address: u16,
+    /// Value read or written
+    pub value: u8,
+    pub cycle: usize,
+}
+
+/// Something that went wrong, recorded by the attached checkers
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// `BREAK` executed
+    Break {
+        pc: u16,
+        cycle: usize,
+    },
+    /// Rejected access, in strict mode
+    AccessViolation(AccessViolation),
+    StackCollision(StackCollision),
+    ReturnMismatch(ReturnMismatch),
+}
+
+/// Something the MCU did, notified to hooks
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Instruction executed, with the cycle it started at
+    Instruction {
+        pc: u16,
+        raw_instruction: u16,
+        cycle: usize,
+    },
+    /// SRAM, register file or unmapped address read by an instruction
+    MemoryRead(MemoryAccess),
+    MemoryWrite(MemoryAccess),
+    /// I/O or extended I/O register read by an instruction
+    IoRead(MemoryAccess),
+    IoWrite(MemoryAccess),
+    /// Interrupt entered with `Mcu::enter_interrupt`, at the instruction it
+    /// returns to
+    InterruptEntry {
+        pc: u16,
+        vector: u16,
+        cycle: usize,
+    },
+    /// `reti` executed
+    InterruptExit {
+        pc: u16,
+        cycle: usize,
+    },
+    /// `sleep` executed
+    Sleep {
+        pc: u16,
+        cycle: usize,
+    },
+    /// Sleep ended by an interrupt
+    Wake {
+        pc: u16,
+        cycle: usize,
+    },
+    Fault(Fault),
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Instruction { .. } => EventKind::Instruction,
+            Event::MemoryRead(_) => EventKind::MemoryRead,
+            Event::MemoryWrite(_) => EventKind::MemoryWrite,
+            Event::IoRead(_) => EventKind::IoRead,
+            Event::IoWrite(_) => EventKind::IoWrite,
+            Event::InterruptEntry { .. } => EventKind::InterruptEntry,
+            Event::InterruptExit { .. } => EventKind::InterruptExit,
+            Event::Sleep { .. } => EventKind::Sleep,
+            Event::Wake { .. } => EventKind::Wake,
+            Event::Fault(_) => EventKind::Fault,
+        }
+    }
+
+    /// Data memory address of memory and I/O accesses
+    pub fn address(&self) -> Option<u16> {
+        match self {
+            Event::MemoryRead(access)
+            | Event::MemoryWrite(access)
+            | Event::IoRead(access)
+            | Event::IoWrite(access) => Some(access.address),
+            _ => None,
+        }
+    }
+}
+
+/// Receives events from an `Mcu`. Closures taking an `&Event` are observers
+/// too.
+pub trait Observer: Send {
+    fn notify(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event) + Send> Observer for F {
+    fn notify(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Events a hook receives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventFilter {
+    /// Mask of `EventKind`s
+    pub kinds: u32,
+    /// Data memory addresses of the memory and I/O accesses received, every
+    /// address if `None`. Other events aren't filtered by address.
+    pub addresses: Option<RangeInclusive<u16>>,
+}
+
+impl EventFilter {
+    /// Receives every event
+    pub fn all() -> EventFilter {
+        EventFilter {
+            kinds: ALL_EVENTS,
+            addresses: None,
+        }
+    }
+
+    /// Receives events of `kinds`
+    pub fn kinds(kinds: &[EventKind]) -> EventFilter {
+        EventFilter {
+            kinds: kinds.iter().fold(0, |mask, kind| mask | kind.mask()),
+            addresses: None,
+        }
+    }
+
+    /// Only receives memory and I/O accesses to `addresses`
+    pub fn with_addresses(self, addresses: RangeInclusive<u16>) -> EventFilter {
+        EventFilter {
+            addresses: Some(addresses),
+            ..self
+        }
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        if self.kinds & event.kind().mask() == 0 {
+            return false;
+        }
+        match (&self.addresses, event.address()) {
+            (Some(addresses), Some(address)) => addresses.contains(&address),
+            _ => true,
+        }
+    }
+}
+
+struct Hook {
+    id: usize,
+    filter: EventFilter,
+    observer: Box<dyn Observer>,
+}
+
+/// Hooks registered on an `Mcu`, notified by `Mcu::step`
+#[derive(Default)]
+pub(crate) struct Hooks {
+    hooks: Vec<Hook>,
+    next_id: usize,
+}
+
+impl Hooks {
+    /// Registers `observer`, returning its id
+    pub fn add(&mut self, filter: EventFilter, observer: Box<dyn Observer>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.hooks.push(Hook {
+            id,
+            filter,
+            observer,
+        });
+        id
+    }
+
+    /// Unregisters hook `id`. Returns false if there is no such hook.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.hooks.len();
+        self.hooks.retain(|hook| hook.id != id);
+        self.hooks.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Mask of the kinds some hook receives
+    pub fn kinds(&self) -> u32 {
+        self.hooks
+            .iter()
+            .fold(0, |mask, hook| mask | hook.filter.kinds)
+    }
+
+    pub fn wants(&self, kind: EventKind) -> bool {
+        self.kinds() & kind.mask() != 0
+    }
+
+    /// Notifies `event` to the hooks whose filter matches it
+    pub fn notify(&mut self, event: &Event) {
+        for hook in &mut self.hooks {
+            if hook.filter.matches(event) {
+                hook.observer.notify(event);
+            }
+        }
+    }
+}
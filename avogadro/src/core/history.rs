@@ -0,0 +1,238 @@
+use super::register_bank::Flags;
+use super::snapshot::Snapshot;
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+/// Steps between checkpoints, unless set with `with_checkpoint_interval`
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 1000;
+
+/// State overwritten by a single step, used to undo it
+pub(crate) struct UndoRecord {
+    /// Changed registers, with their previous values
+    pub registers: Vec<(u8, u8)>,
+    pub program_counter: u16,
+    pub stack_pointer: u16,
+    pub flags: Flags,
+    pub cycle_count: usize,
+    pub sleeping: bool,
+    /// Data memory writes, with the overwritten values, in order
+    pub memory: Vec<(u16, u8)>,
+}
+
+impl UndoRecord {
+    fn size(&self) -> usize {
+        size_of::<UndoRecord>()
+            + self.registers.len() * size_of::<(u8, u8)>()
+            + self.memory.len() * size_of::<(u16, u8)>()
+    }
+}
+
+/// Execution history used for reverse execution.
+///
+/// Each step pushes an undo record with the registers, SREG, SP and memory
+/// it overwrote, and a snapshot of the whole machine is taken every few
+/// steps. When the memory used goes over budget, the oldest records and
+/// checkpoints are dropped: steps without undo records are recomputed by
+/// restoring a previous checkpoint and running forward from it.
+pub struct History {
+    budget: usize,
+    checkpoint_interval: u64,
+    /// Steps recorded since the history was enabled
+    step_index: u64,
+    /// Snapshots taken before step `index`, in order
+    checkpoints: VecDeque<(u64, Snapshot)>,
+    checkpoint_bytes: usize,
+    /// Undo records of the last steps, up to `step_index`
+    journal: VecDeque<UndoRecord>,
+    journal_bytes: usize,
+    /// Steps that entered an interrupt instead of executing an instruction,
+    /// with the vector, so they can be replayed
+    interrupts: VecDeque<(u64, u16)>,
+}
+
+impl History {
+    /// Creates an empty history using at most about `budget` bytes
+    pub fn new(budget: usize) -> History {
+        History {
+            budget,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            step_index: 0,
+            checkpoints: VecDeque::new(),
+            checkpoint_bytes: 0,
+            journal: VecDeque::new(),
+            journal_bytes: 0,
+            interrupts: VecDeque::new(),
+        }
+    }
+
+    /// Sets the number of steps between checkpoints. Shorter intervals make
+    /// stepping back past the undo records faster, but use more memory.
+    pub fn with_checkpoint_interval(mut self, steps: u64) -> History {
+        self.checkpoint_interval = steps.max(1);
+        self
+    }
+
+    /// Number of steps that can be undone
+    pub fn len(&self) -> u64 {
+        let journal_start = self.journal_start();
+        let earliest = match self.checkpoints.front() {
+            Some((index, _)) => journal_start.min(*index),
+            None => journal_start,
+        };
+        self.step_index - earliest
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Approximate memory used by undo records and checkpoints, in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.journal_bytes + self.checkpoint_bytes
+    }
+
+    /// Forgets every recorded step, used when the state is changed by
+    /// something other than a step
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.checkpoint_bytes = 0;
+        self.journal.clear();
+        self.journal_bytes = 0;
+        self.interrupts.clear();
+    }
+
+    pub(crate) fn step_index(&self) -> u64 {
+        self.step_index
+    }
+
+    /// True if a snapshot should be taken before the next step
+    pub(crate) fn needs_checkpoint(&self) -> bool {
+        match self.checkpoints.back() {
+            Some((index, _)) => {
+                *index != self.step_index
+                    && self.step_index.is_multiple_of(self.checkpoint_interval)
+            }
+            None => true,
+        }
+    }
+
+    pub(crate) fn push_checkpoint(&mut self, snapshot: Snapshot) {
+        self.checkpoint_bytes += snapshot_size(&snapshot);
+        self.checkpoints.push_back((self.step_index, snapshot));
+        self.enforce_budget();
+    }
+
+    /// Records the undo information of a step
+    pub(crate) fn push(&mut self, record: UndoRecord) {
+        self.journal_bytes += record.size();
+        self.journal.push_back(record);
+        self.step_index += 1;
+        self.enforce_budget();
+    }
+
+    /// Records the undo information of an interrupt entry, as a step
+    pub(crate) fn push_interrupt(&mut self, vector: u16, record: UndoRecord) {
+        self.drop_interrupts_from(self.step_index);
+        self.interrupts.push_back((self.step_index, vector));
+        self.push(record);
+    }
+
+    /// Vector of the interrupt entered at step `index`, if that step
+    /// entered one
+    pub(crate) fn interrupt_at(&self, index: u64) -> Option<u16> {
+        self.interrupts
+            .iter()
+            .find(|(step, _)| *step == index)
+            .map(|(_, vector)| *vector)
+    }
+
+    /// Takes the undo record of the last step, if it's still in the journal
+    pub(crate) fn pop(&mut self) -> Option<UndoRecord> {
+        let record = self.journal.pop_back()?;
+        self.journal_bytes -= record.size();
+        self.step_index -= 1;
+        self.drop_checkpoints_after(self.step_index);
+        self.drop_interrupts_from(self.step_index);
+        Some(record)
+    }
+
+    /// Latest checkpoint taken before the last step
+    pub(crate) fn checkpoint_before_last_step(&self) -> Option<(u64, &Snapshot)> {
+        self.checkpoints
+            .iter()
+            .rev()
+            .find(|(index, _)| *index < self.step_index)
+            .map(|(index, snapshot)| (*index, snapshot))
+    }
+
+    /// Moves back to checkpoint `index`, dropping later undo records so
+    /// they can be recorded again. Later interrupts are kept to be replayed.
+    pub(crate) fn rewind_to(&mut self, index: u64) {
+        self.journal.clear();
+        self.journal_bytes = 0;
+        self.step_index = index;
+        self.drop_checkpoints_after(index);
+    }
+
+    fn journal_start(&self) -> u64 {
+        self.step_index - self.journal.len() as u64
+    }
+
+    fn drop_checkpoints_after(&mut self, index: u64) {
+        while let Some((last_index, snapshot)) = self.checkpoints.back() {
+            if *last_index <= index {
+                break;
+            }
+            self.checkpoint_bytes -= snapshot_size(snapshot);
+            self.checkpoints.pop_back();
+        }
+    }
+
+    fn drop_interrupts_from(&mut self, index: u64) {
+        while self
+            .interrupts
+            .back()
+            .is_some_and(|(step, _)| *step >= index)
+        {
+            self.interrupts.pop_back();
+        }
+    }
+
+    /// Drops the oldest records or checkpoints until the history fits in its
+    /// budget, always keeping the last ones
+    fn enforce_budget(&mut self) {
+        while self.memory_usage() > self.budget {
+            let journal_start = self.journal_start();
+            let oldest_checkpoint = self.checkpoints.front().map(|(index, _)| *index);
+            let drop_checkpoint = match oldest_checkpoint {
+                Some(index) if self.checkpoints.len() > 1 => {
+                    index <= journal_start || self.journal.len() <= 1
+                }
+                _ => false,
+            };
+            if drop_checkpoint {
+                if let Some((_, snapshot)) = self.checkpoints.pop_front() {
+                    self.checkpoint_bytes -= snapshot_size(&snapshot);
+                }
+            } else if self.journal.len() > 1 {
+                if let Some(record) = self.journal.pop_front() {
+                    self.journal_bytes -= record.size();
+                }
+            } else {
+                break;
+            }
+        }
+        let earliest = self.step_index - self.len();
+        while self
+            .interrupts
+            .front()
+            .is_some_and(|(step, _)| *step < earliest)
+        {
+            self.interrupts.pop_front();
+        }
+    }
+}
+
+fn snapshot_size(snapshot: &Snapshot) -> usize {
+    size_of::<Snapshot>() + snapshot.data_memory.len() + snapshot.program_memory.len()
+}
@@ -0,0 +1,59 @@
+/// A row of a DWARF line table: instructions from `address` up to the next
+/// row come from line `line` of `file`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineRow {
+    /// Program memory byte address
+    pub address: u32,
+    pub file: String,
+    pub line: u32,
+    /// True for the address following the last instruction of a sequence
+    pub end_sequence: bool,
+}
+
+/// Address to source line mapping, sorted by address
+#[derive(Debug, Clone, Default)]
+pub struct LineTable {
+    rows: Vec<LineRow>,
+}
+
+impl LineTable {
+    pub fn new() -> LineTable {
+        LineTable { rows: Vec::new() }
+    }
+
+    /// Creates a table from rows in any order. Each sequence must end with an
+    /// `end_sequence` row.
+    pub fn from_rows(mut rows: Vec<LineRow>) -> LineTable {
+        // End rows go first, so they don't hide a sequence starting at the
+        // same address
+        rows.sort_by_key(|row| (row.address, !row.end_sequence));
+        LineTable { rows }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn rows(&self) -> &[LineRow] {
+        &self.rows
+    }
+
+    /// Returns the row of the instruction at `address`, if any
+    pub fn find(&self, address: u32) -> Option<&LineRow> {
+        let position = self.rows.partition_point(|row| row.address <= address);
+        let row = self.rows[..position].last()?;
+        if row.end_sequence {
+            None
+        } else {
+            Some(row)
+        }
+    }
+
+    /// Returns every row with its address range end, skipping empty ranges
+    pub fn ranges(&self) -> impl Iterator<Item = (&LineRow, u32)> {
+        self.rows
+            .windows(2)
+            .filter(|pair| !pair[0].end_sequence && pair[1].address > pair[0].address)
+            .map(|pair| (&pair[0], pair[1].address))
+    }
+}
@@ -0,0 +1,60 @@
+use super::elf;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+/// Reads a raw binary file, as generated by `avr-objcopy -O binary`
+pub fn read_bin_file(filename: &str) -> io::Result<Vec<u8>> {
+    let mut file = File::open(filename)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Reads an Intel HEX file into a memory image. Data records are placed at
+/// their offset, gaps between records are filled with zeros.
+pub fn read_ihex_file(filename: &str) -> io::Result<Vec<u8>> {
+    let mut buffer = String::new();
+    let mut file = File::open(filename)?;
+    file.read_to_string(&mut buffer)?;
+    parse_ihex(&buffer)
+}
+
+/// Parses Intel HEX contents into a memory image
+pub fn parse_ihex(contents: &str) -> io::Result<Vec<u8>> {
+    let mut image = Vec::new();
+    let mut base_address = 0;
+    for record in ihex::Reader::new(contents) {
+        let record = record.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        match record {
+            ihex::Record::Data { offset, value } => {
+                let start = base_address + offset as usize;
+                let end = start + value.len();
+                if image.len() < end {
+                    image.resize(end, 0);
+                }
+                image[start..end].copy_from_slice(&value);
+            }
+            ihex::Record::ExtendedSegmentAddress(segment) => {
+                base_address = (segment as usize) << 4;
+            }
+            ihex::Record::ExtendedLinearAddress(upper) => {
+                base_address = (upper as usize) << 16;
+            }
+            _ => (),
+        }
+    }
+    Ok(image)
+}
+
+/// Reads a program image, choosing the format by file extension. ELF files
+/// are detected by their contents.
+pub fn read_program_file(filename: &str) -> io::Result<Vec<u8>> {
+    if filename.ends_with(".hex") || filename.ends_with(".ihex") {
+        read_ihex_file(filename)
+    } else if elf::is_elf_file(filename) {
+        Ok(elf::ElfFile::load(filename)?.program)
+    } else {
+        read_bin_file(filename)
+    }
+}
@@ -1,22 +1,68 @@
 use super::alu::Alu;
+use super::call_stack::{Backtrace, CallStack};
+use super::coverage::Coverage;
 use super::decoder::Decoder;
+use super::device::{DeviceDescriptor, Port, DEFAULT_PORTS};
+use super::events::{Event, EventFilter, EventKind, Fault, Hooks, MemoryAccess, Observer};
+use super::history::{History, UndoRecord};
+use super::loader;
 use super::memory_bank::MemoryBank;
+use super::memory_map::{AccessMode, AccessViolation, MemoryMap, Region};
+use super::profiler::Profiler;
 use super::register_bank::{Flags, RegisterBank};
+use super::semihost::Semihost;
+use super::shadow::ShadowMemory;
+use super::snapshot::Snapshot;
+use super::stack_monitor::StackMonitor;
+use super::trace::{TraceEntry, Tracer};
+use super::vcd::VcdWriter;
+use super::Instruction;
 
 use std::fmt::Write;
-use std::fs::File;
 use std::io;
-use std::io::Read;
 use std::slice::from_raw_parts_mut;
 
 pub struct Mcu {
     memory_bank: MemoryBank,
     reg_bank: RegisterBank,
     cycle_count: usize,
-    speed: usize
+    speed: usize,
+    device: Option<String>,
+    ramend: u16,
+    ports: Vec<Port>,
+    tracer: Option<Tracer>,
+    vcd_writer: Option<VcdWriter>,
+    history: Option<History>,
+    coverage: Option<Coverage>,
+    profiler: Option<Profiler>,
+    call_stack: Option<CallStack>,
+    stack_monitor: Option<StackMonitor>,
+    shadow: Option<ShadowMemory>,
+    semihost: Option<Semihost>,
+    access_violations: Vec<AccessViolation>,
+    hooks: Hooks,
+    sleeping: bool,
+    breakpoints: Vec<u16>,
+    watchpoints: Vec<u16>,
+}
+
+/// Observers detached from an `Mcu` while it replays steps
+struct Observers {
+    tracer: Option<Tracer>,
+    vcd_writer: Option<VcdWriter>,
+    coverage: Option<Coverage>,
+    profiler: Option<Profiler>,
+    call_stack: Option<CallStack>,
+    stack_monitor: Option<StackMonitor>,
+    shadow: Option<ShadowMemory>,
+    semihost: Option<Semihost>,
+    access_violations: Vec<AccessViolation>,
+    hooks: Hooks,
 }
 
 impl Mcu {
+    /// Creates an MCU whose data memory is SRAM up to `data_size`, with a
+    /// `speed` kHz clock
     pub fn new(data_size: usize, program_size: usize, speed: usize) -> Mcu {
         let memory_bank = MemoryBank::new(data_size, program_size).unwrap();
         let reg_bank = RegisterBank::new();
@@ -25,56 +71,671 @@ impl Mcu {
             reg_bank,
             memory_bank,
             cycle_count,
-            speed
+            speed,
+            device: None,
+            ramend: (data_size - 1) as u16,
+            ports: DEFAULT_PORTS.to_vec(),
+            tracer: None,
+            vcd_writer: None,
+            history: None,
+            coverage: None,
+            profiler: None,
+            call_stack: None,
+            stack_monitor: None,
+            shadow: None,
+            semihost: None,
+            access_violations: Vec::new(),
+            hooks: Hooks::default(),
+            sleeping: false,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
         }
     }
 
+    /// Creates an MCU with the memory layout of `device`
+    pub fn from_device(device: &DeviceDescriptor) -> Mcu {
+        let mut mcu = Mcu::new(device.data_size(), device.program_size, device.speed);
+        mcu.device = Some(device.name.clone());
+        mcu.ramend = device.ramend();
+        mcu.ports = device.ports.clone();
+        mcu.set_memory_map(device.memory_map());
+        mcu
+    }
+
+    /// Device model name, `None` if created with `Mcu::new`
+    pub fn get_device_name(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+
+    /// Clock speed in kHz, used to pace execution to real time
     pub fn get_speed(&self) -> usize {
         self.speed
     }
 
+    pub fn set_speed(&mut self, speed: usize) {
+        self.speed = speed;
+    }
+
+    /// GPIO ports of the device, A to D in the ATmega16 layout if created
+    /// with `Mcu::new`
+    pub fn get_ports(&self) -> &[Port] {
+        &self.ports
+    }
+
+    /// True if executed instructions are kept decoded, the default. Every
+    /// program memory write invalidates the words written.
+    pub fn is_decode_cache_enabled(&self) -> bool {
+        self.memory_bank.is_decode_cache_enabled()
+    }
+
+    /// Disabling the decode cache makes every step fetch and decode its
+    /// instruction again, which is slower but otherwise the same
+    pub fn set_decode_cache_enabled(&mut self, enabled: bool) {
+        self.memory_bank.set_decode_cache_enabled(enabled);
+    }
+
     pub fn step(&mut self) {
-        self.execute_step();
+        self.update_checkpoint();
+        let sleeping_before = self.sleeping;
+        let pc = self.reg_bank.get_program_counter();
+        let word = self.memory_bank.get_decoded_word(pc);
+        let instruction = word.raw_instruction;
+        let decoded = word.instruction;
+        let traced = match &self.tracer {
+            Some(tracer) => tracer.is_traced(pc),
+            None => false,
+        };
+        let registers_before = self.reg_bank.registers;
+        let flags_before = self.reg_bank.get_flags();
+        let sreg_before = u8::from(flags_before);
+        let stack_pointer_before = self.reg_bank.get_stack_pointer();
+        let faults_before = self.fault_counts();
+        if let Some(shadow) = &mut self.shadow {
+            shadow.execute(
+                pc,
+                &decoded,
+                &self.reg_bank,
+                &self.memory_bank,
+                self.cycle_count,
+            );
+        }
+        Alu::execute(&decoded, &mut self.reg_bank, &mut self.memory_bank);
+        let accesses = self.memory_bank.take_access_log();
+        for (address, is_write, kind) in self.memory_bank.take_access_violations() {
+            let violation = AccessViolation {
+                pc,
+                address,
+                is_write,
+                region: self.memory_bank.get_memory_map().region(address),
+                kind,
+                cycle: self.cycle_count,
+            };
+            warn!("{}", violation);
+            self.access_violations.push(violation);
+        }
+        self.update_semihost(&decoded, pc);
+        let extra_cycles = self.extra_cycles(&decoded, pc);
+        let cycles = word.cycles + extra_cycles;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(pc, &decoded, extra_cycles > 0);
+        }
         self.reg_bank.increment_pc(&self.memory_bank);
+        if let Some(profiler) = &mut self.profiler {
+            let next_pc = self.reg_bank.get_program_counter();
+            profiler.record(pc, instruction, &decoded, next_pc, cycles as u64);
+        }
+        if let Some(call_stack) = &mut self.call_stack {
+            let next_pc = self.reg_bank.get_program_counter();
+            // Returns read the address where the stack pointer is now, calls
+            // write it where the stack pointer was
+            let stacked_at = match decoded {
+                Instruction::ZeroRegOp { op: 0..=1 } => self.reg_bank.get_stack_pointer(),
+                _ => stack_pointer_before,
+            };
+            let stacked_address = u16::from_le_bytes([
+                self.memory_bank.get_data_byte(stacked_at),
+                self.memory_bank.get_data_byte(stacked_at.wrapping_add(1)),
+            ]);
+            call_stack.record(
+                pc,
+                instruction,
+                &decoded,
+                next_pc,
+                stacked_address,
+                self.reg_bank.get_stack_pointer(),
+                self.cycle_count,
+            );
+        }
+        if let Some(stack_monitor) = &mut self.stack_monitor {
+            let memory_bank = &self.memory_bank;
+            let heap_end = stack_monitor.get_brkval_address().map(|address| {
+                u16::from_le_bytes([
+                    memory_bank.get_data_byte(address),
+                    memory_bank.get_data_byte(address.wrapping_add(1)),
+                ])
+            });
+            let interrupt = self
+                .call_stack
+                .as_ref()
+                .and_then(|call_stack| {
+                    call_stack
+                        .frames()
+                        .iter()
+                        .rev()
+                        .find(|frame| frame.is_interrupt)
+                })
+                .map(|frame| (frame.target, frame.stack_pointer));
+            stack_monitor.record(
+                pc,
+                self.reg_bank.get_stack_pointer(),
+                heap_end,
+                interrupt,
+                self.cycle_count,
+            );
+        }
+        if let Some(tracer) = &mut self.tracer {
+            let memory_writes = self.memory_bank.take_write_log();
+            if traced {
+                let registers_after = self.reg_bank.registers;
+                let registers = (0..32)
+                    .filter(|i| registers_before[*i] != registers_after[*i])
+                    .map(|i| (i as u8, registers_after[i]))
+                    .collect();
+                tracer.record(TraceEntry {
+                    cycle: self.cycle_count as u64,
+                    pc,
+                    raw_instruction: instruction,
+                    registers,
+                    sreg_before,
+                    sreg_after: u8::from(self.reg_bank.get_flags()),
+                    stack_pointer: self.reg_bank.get_stack_pointer(),
+                    memory_writes,
+                });
+            }
+        }
+        if let Some(history) = &mut self.history {
+            let registers_after = self.reg_bank.registers;
+            let registers = (0..32)
+                .filter(|i| registers_before[*i] != registers_after[*i])
+                .map(|i| (i as u8, registers_before[i]))
+                .collect();
+            history.push(UndoRecord {
+                registers,
+                program_counter: pc,
+                stack_pointer: stack_pointer_before,
+                flags: flags_before,
+                cycle_count: self.cycle_count,
+                sleeping: sleeping_before,
+                memory: self.memory_bank.take_undo_log(),
+            });
+        }
+        if !self.hooks.is_empty() {
+            self.notify_step(pc, instruction, &decoded, &accesses, faults_before);
+        }
+        if let Instruction::ZeroRegOp { op: 0x8 } = decoded {
+            self.sleeping = true;
+        }
+        self.cycle_count += cycles;
+        if let Some(mut vcd_writer) = self.vcd_writer.take() {
+            vcd_writer.sample(self);
+            self.vcd_writer = Some(vcd_writer);
+        }
+    }
+
+    /// Undoes the last step. Returns false if there is no history left.
+    pub fn step_back(&mut self) -> bool {
+        match self.pop_history() {
+            Some(record) => {
+                self.undo(&record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Steps back until the program counter is `pc`. Returns false if the
+    /// history ran out first.
+    pub fn run_back_to(&mut self, pc: u16) -> bool {
+        while let Some(record) = self.pop_history() {
+            self.undo(&record);
+            if self.get_program_counter() == pc {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Steps back until reaching a breakpoint, or until undoing a write into
+    /// a watchpoint, leaving the PC at the instruction that wrote it.
+    /// Returns false if the history ran out first.
+    pub fn reverse_continue(&mut self) -> bool {
+        while let Some(record) = self.pop_history() {
+            self.undo(&record);
+            let watched = record
+                .memory
+                .iter()
+                .any(|(address, _)| self.watchpoints.contains(address));
+            if watched || self.breakpoints.contains(&self.get_program_counter()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Starts recording execution history so steps can be undone. `None`
+    /// stops recording. Observers, like the tracer, the VCD writer, coverage
+    /// or hooks, are not rewound, and don't see the steps replayed to step
+    /// back.
+    pub fn set_history(&mut self, history: Option<History>) {
+        self.memory_bank.set_undo_log_enabled(history.is_some());
+        self.history = history;
+    }
+
+    pub fn get_history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Starts counting executed instructions and branches into `coverage`,
+    /// replacing the previous one. `None` stops counting.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        self.coverage = coverage;
+    }
+
+    pub fn get_coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Stops counting, returning the collected counters
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Starts attributing executed cycles to functions with `profiler`,
+    /// replacing the previous one. `None` stops profiling.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn get_profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Stops profiling, returning the collected profile
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Starts keeping a shadow call stack, replacing the previous one. `None`
+    /// stops it.
+    pub fn set_call_stack(&mut self, call_stack: Option<CallStack>) {
+        self.call_stack = call_stack;
+    }
+
+    pub fn get_call_stack(&self) -> Option<&CallStack> {
+        self.call_stack.as_ref()
+    }
+
+    pub fn get_call_stack_mut(&mut self) -> Option<&mut CallStack> {
+        self.call_stack.as_mut()
+    }
+
+    /// Stops keeping the call stack, returning it
+    pub fn take_call_stack(&mut self) -> Option<CallStack> {
+        self.call_stack.take()
+    }
+
+    /// Backtrace from the current instruction, if a call stack is kept
+    pub fn backtrace(&self) -> Option<Backtrace> {
+        let pc = self.get_program_counter();
+        self.call_stack
+            .as_ref()
+            .map(|call_stack| call_stack.backtrace(pc))
+    }
+
+    /// Starts tracking stack usage and collisions with `stack_monitor`,
+    /// replacing the previous one. `None` stops it.
+    pub fn set_stack_monitor(&mut self, stack_monitor: Option<StackMonitor>) {
+        self.stack_monitor = stack_monitor;
+    }
+
+    pub fn get_stack_monitor(&self) -> Option<&StackMonitor> {
+        self.stack_monitor.as_ref()
+    }
+
+    pub fn get_stack_monitor_mut(&mut self) -> Option<&mut StackMonitor> {
+        self.stack_monitor.as_mut()
+    }
+
+    /// Stops tracking the stack, returning the monitor
+    pub fn take_stack_monitor(&mut self) -> Option<StackMonitor> {
+        self.stack_monitor.take()
+    }
+
+    /// Starts tracking defined bits with `shadow`, replacing the previous
+    /// one. Registers and SRAM are filled with power-on values, as its
+    /// `FillPolicy` says. `None` stops it.
+    pub fn set_shadow_memory(&mut self, mut shadow: Option<ShadowMemory>) {
+        if let Some(shadow) = &mut shadow {
+            shadow.set_memory_map(self.memory_bank.get_memory_map());
+            let mut data = vec![0; self.memory_bank.data_size()];
+            self.memory_bank.copy_from_data_memory(&mut data);
+            shadow.power_on(&mut self.reg_bank.registers, &mut data);
+            self.memory_bank.copy_into_data_memory(&data);
+            self.clear_history();
+        }
+        self.shadow = shadow;
+    }
+
+    pub fn get_shadow_memory(&self) -> Option<&ShadowMemory> {
+        self.shadow.as_ref()
+    }
+
+    pub fn get_shadow_memory_mut(&mut self) -> Option<&mut ShadowMemory> {
+        self.shadow.as_mut()
+    }
+
+    /// Stops tracking defined bits, returning the shadow memory
+    pub fn take_shadow_memory(&mut self) -> Option<ShadowMemory> {
+        self.shadow.take()
+    }
+
+    /// Maps a debug I/O device into data memory, replacing the previous one.
+    /// `None` unmaps it.
+    pub fn set_semihost(&mut self, semihost: Option<Semihost>) {
+        let range = semihost.as_ref().map(Semihost::range);
+        self.memory_bank.set_device_range(range);
+        self.semihost = semihost;
+    }
+
+    pub fn get_semihost(&self) -> Option<&Semihost> {
+        self.semihost.as_ref()
+    }
+
+    pub fn get_semihost_mut(&mut self) -> Option<&mut Semihost> {
+        self.semihost.as_mut()
+    }
+
+    /// Unmaps the debug I/O device, returning it
+    pub fn take_semihost(&mut self) -> Option<Semihost> {
+        self.memory_bank.set_device_range(None);
+        self.semihost.take()
+    }
+
+    pub fn get_memory_map(&self) -> &MemoryMap {
+        self.memory_bank.get_memory_map()
+    }
+
+    /// Replaces the data memory map, checked in strict mode
+    pub fn set_memory_map(&mut self, memory_map: MemoryMap) {
+        self.memory_bank.set_memory_map(memory_map);
+    }
+
+    pub fn get_access_mode(&self) -> AccessMode {
+        self.memory_bank.get_access_mode()
+    }
+
+    /// Sets how instructions accessing memory outside the map are handled.
+    /// Accesses made with `get_data_byte` and `set_data_byte` are never
+    /// checked.
+    pub fn set_access_mode(&mut self, access_mode: AccessMode) {
+        self.memory_bank.set_access_mode(access_mode);
+    }
+
+    /// Accesses rejected in strict mode so far
+    pub fn access_violations(&self) -> &[AccessViolation] {
+        &self.access_violations
+    }
+
+    /// Returns and forgets the rejected accesses
+    pub fn take_access_violations(&mut self) -> Vec<AccessViolation> {
+        std::mem::take(&mut self.access_violations)
+    }
+
+    /// Registers `observer` to receive the events matching `filter`,
+    /// returning an id for `remove_hook`. Closures taking an `&Event` are
+    /// observers.
+    pub fn add_hook(&mut self, filter: EventFilter, observer: impl Observer + 'static) -> usize {
+        let id = self.hooks.add(filter, Box::new(observer));
+        self.update_access_log();
+        id
+    }
+
+    /// Unregisters hook `id`. Returns false if there is no such hook.
+    pub fn remove_hook(&mut self, id: usize) -> bool {
+        let removed = self.hooks.remove(id);
+        self.update_access_log();
+        removed
+    }
+
+    /// Enters the interrupt at `vector` as the hardware does: pushes the
+    /// program counter, clears the global interrupt flag and jumps to the
+    /// vector, taking 4 cycles. Peripherals don't raise interrupts yet, so
+    /// embedders call this. Entering an interrupt is recorded in the history
+    /// as a step, so it can be stepped back.
+    pub fn enter_interrupt(&mut self, vector: u16) {
+        self.update_checkpoint();
+        let pc = self.reg_bank.get_program_counter();
+        let stack_pointer_before = self.reg_bank.get_stack_pointer();
+        let flags_before = self.reg_bank.get_flags();
+        let [low, high] = pc.to_le_bytes();
+        for (address, value) in [
+            (stack_pointer_before, low),
+            (stack_pointer_before.wrapping_add(1), high),
+        ] {
+            if let Some(shadow) = &mut self.shadow {
+                shadow.define_data(address);
+            }
+            self.memory_bank.set_data_byte(address, value);
+        }
+        // Not writes made by an instruction
+        self.memory_bank.take_write_log();
+        self.memory_bank.take_device_writes();
+        let stack_pointer = if stack_pointer_before < 2 {
+            self.get_data_size() as u16 - 2
+        } else {
+            stack_pointer_before - 2
+        };
+        self.reg_bank.stack_pointer = stack_pointer;
+        self.reg_bank.set_flags(Flags {
+            int: false,
+            ..flags_before
+        });
+        self.reg_bank.program_counter = vector;
+        let sleeping = std::mem::take(&mut self.sleeping);
+        if let Some(history) = &mut self.history {
+            let record = UndoRecord {
+                registers: Vec::new(),
+                program_counter: pc,
+                stack_pointer: stack_pointer_before,
+                flags: flags_before,
+                cycle_count: self.cycle_count,
+                sleeping,
+                memory: self.memory_bank.take_undo_log(),
+            };
+            history.push_interrupt(vector, record);
+        }
+        if let Some(call_stack) = &mut self.call_stack {
+            call_stack.enter_interrupt(pc, vector, stack_pointer);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_interrupt(pc, vector);
+        }
+        let cycle = self.cycle_count;
+        if sleeping {
+            self.hooks.notify(&Event::Wake { pc, cycle });
+        }
+        self.hooks
+            .notify(&Event::InterruptEntry { pc, vector, cycle });
+        self.cycle_count += 4;
+    }
+
+    /// Whether `sleep` was executed and no interrupt came since. Instructions
+    /// keep running while sleeping.
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        if !self.breakpoints.contains(&pc) {
+            self.breakpoints.push(pc);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.retain(|breakpoint| *breakpoint != pc);
+    }
+
+    pub fn get_breakpoints(&self) -> &[u16] {
+        &self.breakpoints
+    }
+
+    /// Adds a data memory address whose writes stop `reverse_continue`
+    pub fn add_watchpoint(&mut self, address: u16) {
+        if !self.watchpoints.contains(&address) {
+            self.watchpoints.push(address);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, address: u16) {
+        self.watchpoints.retain(|watchpoint| *watchpoint != address);
+    }
+
+    pub fn get_watchpoints(&self) -> &[u16] {
+        &self.watchpoints
+    }
+
+    /// Clock cycles elapsed since the MCU was created
+    pub fn get_cycle_count(&self) -> usize {
+        self.cycle_count
+    }
+
+    /// Starts recording executed instructions into `tracer`, replacing the
+    /// previous one. `None` stops tracing.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.memory_bank.set_write_log_enabled(tracer.is_some());
+        self.tracer = tracer;
+    }
+
+    pub fn get_tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    /// Stops tracing, returning the tracer with the recorded entries
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.memory_bank.set_write_log_enabled(false);
+        self.tracer.take()
+    }
+
+    /// Starts dumping waveforms with `vcd_writer`, sampling the current state
+    /// and then after each step. `None` stops dumping.
+    pub fn set_vcd_writer(&mut self, vcd_writer: Option<VcdWriter>) {
+        self.vcd_writer = vcd_writer.map(|mut vcd_writer| {
+            vcd_writer.sample(self);
+            vcd_writer
+        });
+    }
+
+    /// Stops dumping waveforms, returning the writer to finish it
+    pub fn take_vcd_writer(&mut self) -> Option<VcdWriter> {
+        self.vcd_writer.take()
+    }
+
+    /// Takes a snapshot of registers, memories, cycle count, debug I/O device
+    /// and breakpoints
+    pub fn snapshot(&self) -> Snapshot {
+        let mut data_memory = vec![0; self.memory_bank.data_size()];
+        self.memory_bank.copy_from_data_memory(&mut data_memory);
+        let mut program_memory = vec![0; self.memory_bank.program_size()];
+        self.memory_bank
+            .copy_from_program_memory(&mut program_memory);
+        Snapshot {
+            registers: self.reg_bank.registers,
+            program_counter: self.reg_bank.program_counter,
+            stack_pointer: self.reg_bank.stack_pointer,
+            flags: self.reg_bank.get_flags(),
+            data_memory,
+            program_memory,
+            cycle_count: self.cycle_count as u64,
+            sleeping: self.sleeping,
+            device: self.device.clone(),
+            ramend: self.ramend,
+            semihost: self.semihost.clone(),
+            breakpoints: self.breakpoints.clone(),
+            watchpoints: self.watchpoints.clone(),
+        }
+    }
+
+    /// Restores a snapshot taken from a MCU of the same device model and
+    /// memory sizes
+    pub fn restore(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        if snapshot.device != self.device || snapshot.ramend != self.ramend {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Snapshot device doesn't match this MCU",
+            ));
+        }
+        if snapshot.data_memory.len() != self.memory_bank.data_size()
+            || snapshot.program_memory.len() != self.memory_bank.program_size()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Snapshot memory sizes don't match this MCU",
+            ));
+        }
+        self.apply_snapshot(snapshot);
+        // Stepping back applies checkpoints too, but keeps the debugger
+        // state and the device output
+        self.set_semihost(snapshot.semihost.clone());
+        self.breakpoints = snapshot.breakpoints.clone();
+        self.watchpoints = snapshot.watchpoints.clone();
+        self.clear_history();
+        Ok(())
+    }
+
+    /// Saves the machine state into file `filename`
+    pub fn save_state(&self, filename: &str) -> io::Result<()> {
+        self.snapshot().save(filename)
+    }
+
+    /// Restores the machine state saved with `save_state`
+    pub fn load_state(&mut self, filename: &str) -> io::Result<()> {
+        self.restore(&Snapshot::load(filename)?)
     }
 
     pub fn load_data_memory(&mut self, memory: &[u8]) {
         self.memory_bank.copy_into_data_memory(memory);
+        if let Some(shadow) = &mut self.shadow {
+            shadow.define_all();
+        }
+        self.clear_history();
     }
 
     pub fn load_program_memory(&mut self, memory: &[u8]) {
         self.memory_bank.copy_into_program_memory(memory);
+        self.clear_history();
+    }
+
+    /// Patches the program memory byte at `address`
+    pub fn set_program_byte(&mut self, address: u16, value: u8) {
+        self.memory_bank.set_program_byte(address, value);
+        self.clear_history();
     }
 
     pub fn load_ihex_file(&mut self, filename: &str) -> io::Result<()> {
-        let mut buffer = String::new();
-        let mut file = File::open(filename)?;
-        file.read_to_string(&mut buffer)?;
-        let reader = ihex::Reader::new(&buffer);
-        let buffer = reader
-            .flatten()
-            .filter_map(|record| {
-                if let ihex::Record::Data { offset: _, value } = record {
-                    Some(value)
-                } else {
-                    None
-                }
-            })
-            .flatten()
-            .collect::<Vec<_>>();
+        let buffer = loader::read_ihex_file(filename)?;
         self.memory_bank.copy_into_program_memory(&buffer);
+        self.clear_history();
         Ok(())
     }
 
     pub fn load_from_file(&mut self, filename: &str, is_program: bool) -> io::Result<()> {
-        let mut file = File::open(filename)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
+        let buffer = loader::read_bin_file(filename)?;
         if is_program {
             self.memory_bank.copy_into_program_memory(&buffer);
         } else {
             self.memory_bank.copy_into_data_memory(&buffer);
         }
+        self.clear_history();
         Ok(())
     }
 
@@ -83,6 +744,11 @@ impl Mcu {
         self.memory_bank.data_size()
     }
 
+    /// Last SRAM address, where the stack usually starts
+    pub fn get_ramend(&self) -> u16 {
+        self.ramend
+    }
+
     /// Returns size of main memory bank, in bytes
     pub fn get_program_size(&self) -> usize {
         self.memory_bank.program_size()
@@ -92,6 +758,21 @@ impl Mcu {
         self.memory_bank.get_data_byte(address)
     }
 
+    /// Writes a data memory byte, like a peripheral driving an input pin
+    pub fn set_data_byte(&mut self, address: u16, value: u8) {
+        if let Some(shadow) = &mut self.shadow {
+            shadow.define_data(address);
+        }
+        if self.memory_bank.get_data_byte(address) != value {
+            self.memory_bank.set_data_byte(address, value);
+            // Not a write made by an instruction
+            self.memory_bank.take_write_log();
+            self.memory_bank.take_undo_log();
+            self.memory_bank.take_device_writes();
+            self.clear_history();
+        }
+    }
+
     /// Copies content from data memory into buffer array.
     /// If buffer is smaller than memory copies at most *buf_size* elements.
     /// # Safety
@@ -117,7 +798,13 @@ impl Mcu {
     }
 
     pub fn set_register(&mut self, reg_num: u8, value: u8) {
-        self.reg_bank.registers[reg_num as usize] = value;
+        if let Some(shadow) = &mut self.shadow {
+            shadow.define_register(reg_num);
+        }
+        if self.reg_bank.registers[reg_num as usize] != value {
+            self.reg_bank.registers[reg_num as usize] = value;
+            self.clear_history();
+        }
     }
 
     pub fn get_register_array(&self) -> [u8; 32] {
@@ -125,7 +812,13 @@ impl Mcu {
     }
 
     pub fn set_register_array(&mut self, reg_array: [u8; 32]) {
-        self.reg_bank.registers = reg_array;
+        if let Some(shadow) = &mut self.shadow {
+            (0..32).for_each(|reg| shadow.define_register(reg));
+        }
+        if self.reg_bank.registers != reg_array {
+            self.reg_bank.registers = reg_array;
+            self.clear_history();
+        }
     }
 
     pub fn get_program_counter(&self) -> u16 {
@@ -133,7 +826,10 @@ impl Mcu {
     }
 
     pub fn set_program_counter(&mut self, value: u16) {
-        self.reg_bank.set_program_counter(value);
+        if self.reg_bank.get_program_counter() != value {
+            self.reg_bank.set_program_counter(value);
+            self.clear_history();
+        }
     }
 
     pub fn get_current_instruction(&self) -> u16 {
@@ -144,6 +840,13 @@ impl Mcu {
         self.reg_bank.get_stack_pointer()
     }
 
+    pub fn set_stack_pointer(&mut self, value: u16) {
+        if self.reg_bank.get_stack_pointer() != value {
+            self.reg_bank.stack_pointer = value;
+            self.clear_history();
+        }
+    }
+
     pub fn display_current_instruction(&self, buf: &mut String) {
         let instruction = self.fetch();
         let decoded = Decoder::decode(instruction);
@@ -155,13 +858,241 @@ impl Mcu {
     }
 
     pub fn set_flags(&mut self, flags: Flags) {
-        self.reg_bank.set_flags(flags);
+        if self.reg_bank.get_flags() != flags {
+            self.reg_bank.set_flags(flags);
+            self.clear_history();
+        }
     }
 
-    fn execute_step(&mut self) {
-        let instruction = self.fetch();
-        let decoded = Decoder::decode(instruction);
-        Alu::execute(&decoded, &mut self.reg_bank, &mut self.memory_bank);
+    fn apply_snapshot(&mut self, snapshot: &Snapshot) {
+        self.memory_bank
+            .copy_into_data_memory(&snapshot.data_memory);
+        self.memory_bank
+            .copy_into_program_memory(&snapshot.program_memory);
+        self.reg_bank.registers = snapshot.registers;
+        self.reg_bank.program_counter = snapshot.program_counter;
+        self.reg_bank.stack_pointer = snapshot.stack_pointer;
+        self.reg_bank.set_flags(snapshot.flags);
+        self.cycle_count = snapshot.cycle_count as usize;
+        self.sleeping = snapshot.sleeping;
+    }
+
+    /// Takes a checkpoint if the history needs one before the next step
+    fn update_checkpoint(&mut self) {
+        if self.history.as_ref().is_some_and(History::needs_checkpoint) {
+            let snapshot = self.snapshot();
+            if let Some(history) = &mut self.history {
+                history.push_checkpoint(snapshot);
+            }
+        }
+    }
+
+    /// Recorded steps can't be replayed once the state is changed from
+    /// outside, so history starts again from the current state
+    fn clear_history(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    /// Takes the undo record of the last step. If it was dropped to save
+    /// memory, restores the previous checkpoint and runs forward to record
+    /// it again.
+    fn pop_history(&mut self) -> Option<UndoRecord> {
+        let history = self.history.as_mut()?;
+        if let Some(record) = history.pop() {
+            return Some(record);
+        }
+        let target = history.step_index();
+        let (index, snapshot) = history.checkpoint_before_last_step()?;
+        let snapshot = snapshot.clone();
+        history.rewind_to(index);
+        self.apply_snapshot(&snapshot);
+        // Replayed steps were observed when first run
+        let observers = self.take_observers();
+        for step in index..target {
+            match self.history.as_ref()?.interrupt_at(step) {
+                Some(vector) => self.enter_interrupt(vector),
+                None => self.step(),
+            }
+        }
+        self.memory_bank.take_write_log();
+        self.memory_bank.take_device_writes();
+        self.restore_observers(observers);
+        self.history.as_mut()?.pop()
+    }
+
+    /// Detaches everything that records or reacts to steps. Semihost stays
+    /// attached, as replayed steps write to the device again, but works on a
+    /// copy.
+    fn take_observers(&mut self) -> Observers {
+        Observers {
+            tracer: self.tracer.take(),
+            vcd_writer: self.vcd_writer.take(),
+            coverage: self.coverage.take(),
+            profiler: self.profiler.take(),
+            call_stack: self.call_stack.take(),
+            stack_monitor: self.stack_monitor.take(),
+            shadow: self.shadow.take(),
+            semihost: self.semihost.clone(),
+            access_violations: std::mem::take(&mut self.access_violations),
+            hooks: std::mem::take(&mut self.hooks),
+        }
+    }
+
+    fn restore_observers(&mut self, observers: Observers) {
+        self.tracer = observers.tracer;
+        self.vcd_writer = observers.vcd_writer;
+        self.coverage = observers.coverage;
+        self.profiler = observers.profiler;
+        self.call_stack = observers.call_stack;
+        self.stack_monitor = observers.stack_monitor;
+        self.shadow = observers.shadow;
+        self.semihost = observers.semihost;
+        self.access_violations = observers.access_violations;
+        self.hooks = observers.hooks;
+    }
+
+    fn undo(&mut self, record: &UndoRecord) {
+        for (address, value) in record.memory.iter().rev() {
+            self.memory_bank.set_data_byte(*address, *value);
+        }
+        self.memory_bank.take_undo_log();
+        self.memory_bank.take_write_log();
+        self.memory_bank.take_device_writes();
+        for (reg_num, value) in &record.registers {
+            self.reg_bank.registers[*reg_num as usize] = *value;
+        }
+        self.reg_bank.program_counter = record.program_counter;
+        self.reg_bank.stack_pointer = record.stack_pointer;
+        self.reg_bank.set_flags(record.flags);
+        self.cycle_count = record.cycle_count;
+        self.sleeping = record.sleeping;
+    }
+
+    /// Passes instruction writes to the debug I/O device, and traps `BREAK`
+    fn update_semihost(&mut self, decoded: &Instruction, pc: u16) {
+        let semihost = match &mut self.semihost {
+            Some(semihost) => semihost,
+            None => return,
+        };
+        if let Instruction::ZeroRegOp { op: 0x9 } = decoded {
+            semihost.trap(pc);
+        }
+        for (address, value) in self.memory_bank.take_device_writes() {
+            if let Some(bytes) = semihost.write(address, value, self.cycle_count) {
+                for (offset, byte) in (0..).zip(bytes.iter()) {
+                    self.memory_bank.set_data_byte(address + offset, *byte);
+                }
+            }
+        }
+        // Stored values aren't device writes
+        self.memory_bank.take_device_writes();
+    }
+
+    /// Logs instruction memory accesses while some hook receives them
+    fn update_access_log(&mut self) {
+        let kinds = [
+            EventKind::MemoryRead,
+            EventKind::MemoryWrite,
+            EventKind::IoRead,
+            EventKind::IoWrite,
+        ];
+        let enabled = kinds.iter().any(|kind| self.hooks.wants(*kind));
+        self.memory_bank.set_access_log_enabled(enabled);
+    }
+
+    /// Recorded return mismatches and stack collisions
+    fn fault_counts(&self) -> (usize, usize) {
+        let mismatches = self.call_stack.as_ref().map_or(0, |c| c.mismatches().len());
+        let collisions = self
+            .stack_monitor
+            .as_ref()
+            .map_or(0, |s| s.collisions().len());
+        (mismatches, collisions)
+    }
+
+    /// Notifies hooks of the instruction executed at `pc`, the memory it
+    /// accessed and the faults it caused
+    fn notify_step(
+        &mut self,
+        pc: u16,
+        instruction: u16,
+        decoded: &Instruction,
+        accesses: &[(u16, u8, bool)],
+        (mismatches, collisions): (usize, usize),
+    ) {
+        let cycle = self.cycle_count;
+        let mut events = vec![Event::Instruction {
+            pc,
+            raw_instruction: instruction,
+            cycle,
+        }];
+        for (address, value, is_write) in accesses {
+            let access = MemoryAccess {
+                pc,
+                address: *address,
+                value: *value,
+                cycle,
+            };
+            let is_io = matches!(
+                self.memory_bank.get_memory_map().region(*address),
+                Region::Io | Region::ExtendedIo
+            );
+            events.push(match (is_io, is_write) {
+                (false, false) => Event::MemoryRead(access),
+                (false, true) => Event::MemoryWrite(access),
+                (true, false) => Event::IoRead(access),
+                (true, true) => Event::IoWrite(access),
+            });
+        }
+        match decoded {
+            Instruction::ZeroRegOp { op: 0x1 } => events.push(Event::InterruptExit { pc, cycle }),
+            Instruction::ZeroRegOp { op: 0x8 } => events.push(Event::Sleep { pc, cycle }),
+            Instruction::ZeroRegOp { op: 0x9 } => {
+                events.push(Event::Fault(Fault::Break { pc, cycle }))
+            }
+            _ => (),
+        }
+        let violations = self
+            .access_violations
+            .iter()
+            .rev()
+            .take_while(|violation| violation.cycle == cycle && violation.pc == pc);
+        let mut faults: Vec<_> = violations
+            .map(|violation| Fault::AccessViolation(violation.clone()))
+            .collect();
+        faults.reverse();
+        if let Some(call_stack) = &self.call_stack {
+            let new = call_stack.mismatches().iter().skip(mismatches);
+            faults.extend(new.map(|mismatch| Fault::ReturnMismatch(mismatch.clone())));
+        }
+        if let Some(stack_monitor) = &self.stack_monitor {
+            let new = stack_monitor.collisions().iter().skip(collisions);
+            faults.extend(new.map(|collision| Fault::StackCollision(collision.clone())));
+        }
+        events.extend(faults.into_iter().map(Event::Fault));
+        for event in &events {
+            self.hooks.notify(event);
+        }
+    }
+
+    /// Extra cycles of a branch taken by its SREG bit test, or of a skip,
+    /// detected by a program counter moved by the ALU
+    fn extra_cycles(&self, decoded: &Instruction, pc: u16) -> usize {
+        let new_pc = self.reg_bank.get_program_counter();
+        match decoded {
+            // `br* .+0` is taken without moving the program counter
+            Instruction::Branch { op, test_set, .. } => {
+                let sreg = u8::from(self.reg_bank.get_flags());
+                usize::from((sreg >> op & 1 != 0) == *test_set)
+            }
+            // Skips and cpse
+            Instruction::SkipOp { .. } | Instruction::TwoRegOp { op: 0x4, .. } => {
+                (new_pc.wrapping_sub(pc) / 2) as usize
+            }
+            _ => 0,
+        }
     }
 
     fn fetch(&self) -> u16 {
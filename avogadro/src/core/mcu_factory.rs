@@ -1,12 +1,18 @@
+use super::device::DeviceDescriptor;
 use super::mcu::Mcu;
 
 pub struct McuFactory;
 
 impl McuFactory {
     pub fn create(mcu_name: &str) -> Mcu {
-        match mcu_name {
-            "attiny85" => Mcu::new(512, 8 * 1024, 1000),
-            _ => unimplemented!(),
+        match McuFactory::try_create(mcu_name) {
+            Some(mcu) => mcu,
+            None => unimplemented!(),
         }
     }
+
+    /// Creates MCU model `mcu_name`, or returns `None` if it's not supported
+    pub fn try_create(mcu_name: &str) -> Option<Mcu> {
+        DeviceDescriptor::find(mcu_name).map(|device| Mcu::from_device(&device))
+    }
 }
@@ -1,26 +1,47 @@
+use super::decode_cache::{DecodeCache, DecodedWord};
+use super::memory_map::{AccessMode, MemoryMap, ViolationKind};
+use std::ops::Range;
+
 /// Microcontroller main memory
 pub struct MemoryBank {
     data_memory: Vec<u8>,
     program_memory: Vec<u8>,
-    address_mask: u16,
+    write_log: Option<Vec<(u16, u8)>>,
+    undo_log: Option<Vec<(u16, u8)>>,
+    device_range: Option<Range<u16>>,
+    device_writes: Vec<(u16, u8)>,
+    memory_map: MemoryMap,
+    access_mode: AccessMode,
+    access_violations: Vec<(u16, bool, ViolationKind)>,
+    access_log: Option<Vec<(u16, u8, bool)>>,
+    decode_cache: DecodeCache,
 }
 
 type AvogadroError = u8;
 type Result<T> = std::result::Result<T, AvogadroError>;
 
 impl MemoryBank {
-    /// Creates a new memory bank. Capacity *MUST* be a power of 2
+    /// Creates a new memory bank. Data memory must fit in the 64K data
+    /// address space.
     pub fn new(data_size: usize, program_size: usize) -> Result<MemoryBank> {
-        if data_size & (data_size - 1) != 0 {
+        if data_size == 0 || data_size > 0x10000 || program_size == 0 {
             return Err(1);
         }
         let data_memory = vec![0; data_size];
         let program_memory = vec![0; program_size];
-        let address_mask = (data_size - 1) as u16;
+        let decode_cache = DecodeCache::new(program_size);
         Ok(MemoryBank {
             data_memory,
             program_memory,
-            address_mask,
+            write_log: None,
+            undo_log: None,
+            device_range: None,
+            device_writes: Vec::new(),
+            memory_map: MemoryMap::new(data_size),
+            access_mode: AccessMode::Compat,
+            access_violations: Vec::new(),
+            access_log: None,
+            decode_cache,
         })
     }
 
@@ -30,49 +51,246 @@ impl MemoryBank {
 
     pub fn set_program_memory(&mut self, data: &[u8]) {
         self.program_memory = data.to_owned();
+        self.decode_cache.reset(data.len());
     }
 
-    /// Returns a byte located at `address` position
+    /// Returns a byte located at `address` position, 0 if there is no
+    /// memory there
     pub fn get_data_byte(&self, address: u16) -> u8 {
-        let wrapped_address = address & self.address_mask;
-        self.data_memory[wrapped_address as usize]
+        self.index(address)
+            .map_or(0, |index| self.data_memory[index])
     }
 
-    /// Sets a byte at `address` position
+    /// Sets a byte at `address` position, ignored if there is no memory
+    /// there
     pub fn set_data_byte(&mut self, address: u16, data: u8) {
-        let wrapped_address = address & self.address_mask;
-        self.data_memory[wrapped_address as usize] = data
+        let index = match self.index(address) {
+            Some(index) => index,
+            None => return,
+        };
+        let wrapped_address = index as u16;
+        if let Some(log) = &mut self.undo_log {
+            log.push((wrapped_address, self.data_memory[index]));
+        }
+        self.data_memory[index] = data;
+        if let Some(log) = &mut self.write_log {
+            log.push((wrapped_address, data));
+        }
+        if let Some(range) = &self.device_range {
+            if range.contains(&wrapped_address) {
+                self.device_writes.push((wrapped_address, data));
+            }
+        }
+    }
+
+    /// Data memory index accessed at `address`. Like the address decoder,
+    /// only the bits needed to address data memory are used, so memory
+    /// mirrors every power of two above its size. Addresses between the end
+    /// of data memory and that power of two, like external RAM, have no
+    /// memory behind them.
+    fn index(&self, address: u16) -> Option<usize> {
+        let mask = self.data_memory.len().next_power_of_two() - 1;
+        let index = usize::from(address) & mask;
+        if index < self.data_memory.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Reads the byte at `address` for an instruction, checking it against
+    /// the memory map in strict mode
+    pub fn read_data_byte(&mut self, address: u16) -> u8 {
+        let data = if self.is_accessible(address, false) {
+            self.get_data_byte(address)
+        } else {
+            0
+        };
+        if let Some(log) = &mut self.access_log {
+            log.push((address, data, false));
+        }
+        data
+    }
+
+    /// Writes a byte at `address` for an instruction, checking it against
+    /// the memory map in strict mode
+    pub fn write_data_byte(&mut self, address: u16, data: u8) {
+        if self.is_accessible(address, true) {
+            self.set_data_byte(address, data);
+        }
+        if let Some(log) = &mut self.access_log {
+            log.push((address, data, true));
+        }
+    }
+
+    /// Records a violation if the access is rejected in strict mode.
+    /// Returns false if it shouldn't be made.
+    fn is_accessible(&mut self, address: u16, is_write: bool) -> bool {
+        if self.access_mode == AccessMode::Compat {
+            return true;
+        }
+        if let Some(range) = &self.device_range {
+            if range.contains(&address) {
+                return true;
+            }
+        }
+        let violation = self.memory_map.check(address, is_write);
+        if let Some(kind) = violation {
+            self.access_violations.push((address, is_write, kind));
+        }
+        match violation {
+            Some(ViolationKind::Unmapped) | Some(ViolationKind::ReadOnly) => false,
+            _ => usize::from(address) < self.data_memory.len(),
+        }
+    }
+
+    pub fn get_memory_map(&self) -> &MemoryMap {
+        &self.memory_map
+    }
+
+    /// Replaces the data memory map, used in strict mode
+    pub fn set_memory_map(&mut self, memory_map: MemoryMap) {
+        self.memory_map = memory_map;
+    }
+
+    pub fn get_access_mode(&self) -> AccessMode {
+        self.access_mode
+    }
+
+    pub fn set_access_mode(&mut self, access_mode: AccessMode) {
+        self.access_mode = access_mode;
+    }
+
+    /// Returns accesses rejected since the last call (address, whether it
+    /// was a write and why), in order
+    pub fn take_access_violations(&mut self) -> Vec<(u16, bool, ViolationKind)> {
+        std::mem::take(&mut self.access_violations)
+    }
+
+    /// Sets the data memory addresses mapped to a device, whose writes are
+    /// kept until `take_device_writes` is called. `None` unmaps it.
+    pub fn set_device_range(&mut self, range: Option<Range<u16>>) {
+        self.device_range = range;
+        self.device_writes.clear();
+    }
+
+    /// Returns writes to the device addresses (address and value) since the
+    /// last call, in order
+    pub fn take_device_writes(&mut self) -> Vec<(u16, u8)> {
+        std::mem::take(&mut self.device_writes)
+    }
+
+    /// Enables or disables logging of data memory writes made by instructions
+    pub fn set_write_log_enabled(&mut self, enabled: bool) {
+        self.write_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Returns data memory writes (address and value) logged since the last
+    /// call, in order
+    pub fn take_write_log(&mut self) -> Vec<(u16, u8)> {
+        self.write_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Enables or disables logging of data memory reads and writes made by
+    /// instructions
+    pub fn set_access_log_enabled(&mut self, enabled: bool) {
+        self.access_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Returns data memory accesses (address, value and whether it was a
+    /// write) logged since the last call, in order
+    pub fn take_access_log(&mut self) -> Vec<(u16, u8, bool)> {
+        self.access_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Enables or disables logging of values overwritten by instructions
+    pub fn set_undo_log_enabled(&mut self, enabled: bool) {
+        self.undo_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Returns data memory overwritten since the last call (address and
+    /// previous value), in order
+    pub fn take_undo_log(&mut self) -> Vec<(u16, u8)> {
+        self.undo_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
     }
 
     /// Returns a 2 byte word located at `address`
     pub fn get_program_word(&self, address: u16) -> u16 {
-        let wrapped_address = address & self.address_mask;
-        let instruction = u16::from(self.program_memory[wrapped_address as usize]);
-        instruction + ((u16::from(self.program_memory[wrapped_address as usize + 1])) << 8)
+        let size = self.program_memory.len();
+        let wrapped_address = usize::from(address) % size;
+        let instruction = u16::from(self.program_memory[wrapped_address]);
+        instruction + ((u16::from(self.program_memory[(wrapped_address + 1) % size])) << 8)
+    }
+
+    /// Sets the program memory byte at `address`, like a debugger patching
+    /// the program
+    pub fn set_program_byte(&mut self, address: u16, data: u8) {
+        let wrapped_address = usize::from(address) % self.program_memory.len();
+        self.program_memory[wrapped_address] = data;
+        self.decode_cache
+            .invalidate(wrapped_address, wrapped_address + 1);
+    }
+
+    /// Returns the instruction at `address`, decoded. Decoded instructions
+    /// are cached until program memory is written.
+    pub fn get_decoded_word(&mut self, address: u16) -> DecodedWord {
+        let size = self.program_memory.len();
+        let wrapped_address = usize::from(address) % size;
+        // Words split at odd addresses or the end of memory aren't cached
+        let index = wrapped_address / 2;
+        let cacheable = wrapped_address.is_multiple_of(2) && wrapped_address + 1 < size;
+        if cacheable {
+            if let Some(decoded) = self.decode_cache.get(index) {
+                return decoded;
+            }
+        }
+        let decoded = DecodedWord::decode(self.get_program_word(address));
+        if cacheable {
+            self.decode_cache.insert(index, decoded);
+        }
+        decoded
+    }
+
+    pub fn is_decode_cache_enabled(&self) -> bool {
+        self.decode_cache.is_enabled()
+    }
+
+    pub fn set_decode_cache_enabled(&mut self, enabled: bool) {
+        self.decode_cache.set_enabled(enabled);
     }
 
     /// Copies values at array `data` into data memory.
     pub fn copy_into_data_memory(&mut self, data: &[u8]) {
         let n_bytes = std::cmp::min(data.len(), self.data_memory.len());
-        self.data_memory[..n_bytes].copy_from_slice(&data);
+        self.data_memory[..n_bytes].copy_from_slice(&data[..n_bytes]);
     }
 
     /// Copies values from data memory into array `data`.
     pub fn copy_from_data_memory(&self, data: &mut [u8]) {
         let n_bytes = std::cmp::min(data.len(), self.data_memory.len());
-        data[..n_bytes].copy_from_slice(&self.data_memory);
+        data[..n_bytes].copy_from_slice(&self.data_memory[..n_bytes]);
     }
 
     /// Copies values at array `data` into program memory.
     pub fn copy_into_program_memory(&mut self, data: &[u8]) {
         let n_bytes = std::cmp::min(data.len(), self.program_memory.len());
-        self.program_memory[..n_bytes].copy_from_slice(&data);
+        self.program_memory[..n_bytes].copy_from_slice(&data[..n_bytes]);
+        self.decode_cache.invalidate(0, n_bytes);
     }
 
     /// Copies values from program memory into array `data`.
     pub fn copy_from_program_memory(&self, data: &mut [u8]) {
         let n_bytes = std::cmp::min(data.len(), self.program_memory.len());
-        data[..n_bytes].copy_from_slice(&self.program_memory);
+        data[..n_bytes].copy_from_slice(&self.program_memory[..n_bytes]);
     }
 
     /// SRAM memory size in bytes
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::ops::Range;
+
+/// First I/O register in data memory
+const IO_START: u32 = 0x20;
+/// First extended I/O register in data memory, reached with `ld`/`st` only
+const EXTENDED_IO_START: u32 = 0x60;
+
+/// Part of the data address space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// General purpose registers, r0 to r31
+    Registers,
+    /// I/O registers, reachable with `in` and `out`
+    Io,
+    /// I/O registers reachable with `ld` and `st` only
+    ExtendedIo,
+    Sram,
+    ExternalRam,
+    /// Addresses without memory behind them
+    Unmapped,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Registers => write!(f, "register"),
+            Region::Io => write!(f, "I/O"),
+            Region::ExtendedIo => write!(f, "extended I/O"),
+            Region::Sram => write!(f, "SRAM"),
+            Region::ExternalRam => write!(f, "external RAM"),
+            Region::Unmapped => write!(f, "unmapped"),
+        }
+    }
+}
+
+/// How instructions accessing memory outside the map are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    /// Data memory mirrors every power of two above its size, addresses
+    /// above it up to that power of two read 0 and ignore writes, and every
+    /// register is writable, like the hardware does with most of them
+    Compat,
+    /// Accesses to unmapped or reserved addresses and writes to read-only
+    /// registers are recorded as `AccessViolation`s. Unmapped addresses
+    /// don't wrap: reads return 0 and writes are dropped, as are writes to
+    /// read-only registers.
+    Strict,
+}
+
+/// Why an access was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// No memory at the address
+    Unmapped,
+    /// Register reserved by the device
+    Reserved,
+    /// Write to a read-only register
+    ReadOnly,
+}
+
+/// An instruction accessed an address it shouldn't, in strict mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessViolation {
+    /// Address of the instruction
+    pub pc: u16,
+    /// Data memory address accessed
+    pub address: u16,
+    pub is_write: bool,
+    pub region: Region,
+    pub kind: ViolationKind,
+    pub cycle: usize,
+}
+
+impl fmt::Display for AccessViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = if self.is_write { "Write to" } else { "Read of" };
+        let kind = match self.kind {
+            ViolationKind::Unmapped => "",
+            ViolationKind::Reserved => "reserved ",
+            ViolationKind::ReadOnly => "read-only ",
+        };
+        write!(
+            f,
+            "{} {}{} address 0x{:04x} at 0x{:04x}, cycle {}",
+            access, kind, self.region, self.address, self.pc, self.cycle
+        )
+    }
+}
+
+/// Data address space of a device: named regions, and the reserved and
+/// read-only registers in them. Addresses outside every region are
+/// unmapped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryMap {
+    regions: Vec<(Range<u32>, Region)>,
+    reserved: Vec<u16>,
+    read_only: Vec<u16>,
+}
+
+impl MemoryMap {
+    /// Creates a map without regions, where every address is unmapped
+    pub fn empty() -> MemoryMap {
+        MemoryMap {
+            regions: Vec::new(),
+            reserved: Vec::new(),
+            read_only: Vec::new(),
+        }
+    }
+
+    /// Creates the usual map of a device with 64 I/O registers: registers,
+    /// I/O and SRAM up to `data_size`
+    pub fn new(data_size: usize) -> MemoryMap {
+        let mut memory_map = MemoryMap::empty();
+        memory_map.add_region(0..IO_START, Region::Registers);
+        memory_map.add_region(IO_START..EXTENDED_IO_START, Region::Io);
+        memory_map.add_region(EXTENDED_IO_START..data_size as u32, Region::Sram);
+        memory_map
+    }
+
+    /// Maps `range` to `region`, over any region added before
+    pub fn add_region(&mut self, range: Range<u32>, region: Region) {
+        self.regions.insert(0, (range, region));
+    }
+
+    /// Marks the register at data memory `address` as reserved
+    pub fn add_reserved(&mut self, address: u16) {
+        self.reserved.push(address);
+    }
+
+    /// Marks the register at data memory `address` as read-only
+    pub fn add_read_only(&mut self, address: u16) {
+        self.read_only.push(address);
+    }
+
+    /// Regions, from the last one added
+    pub fn regions(&self) -> &[(Range<u32>, Region)] {
+        &self.regions
+    }
+
+    /// Region containing `address`
+    pub fn region(&self, address: u16) -> Region {
+        self.regions
+            .iter()
+            .find(|(range, _)| range.contains(&u32::from(address)))
+            .map_or(Region::Unmapped, |(_, region)| *region)
+    }
+
+    pub fn is_reserved(&self, address: u16) -> bool {
+        self.reserved.contains(&address)
+    }
+
+    pub fn is_read_only(&self, address: u16) -> bool {
+        self.read_only.contains(&address)
+    }
+
+    /// Why an access to `address` should be rejected, if it should
+    pub fn check(&self, address: u16, is_write: bool) -> Option<ViolationKind> {
+        if self.region(address) == Region::Unmapped {
+            Some(ViolationKind::Unmapped)
+        } else if self.is_reserved(address) {
+            Some(ViolationKind::Reserved)
+        } else if is_write && self.is_read_only(address) {
+            Some(ViolationKind::ReadOnly)
+        } else {
+            None
+        }
+    }
+}
@@ -3,11 +3,40 @@
 /// need register bank to read operands and store results. Some instructions
 /// (like load-store ones) need a memory bank too.
 mod alu;
+/// AVR assembler for GNU as style sources, mostly used to write test programs
+pub mod assembler;
+/// Shadow call stack, with mismatched return detection and backtraces
+pub mod call_stack;
+/// Execution counters per instruction and branch, with lcov export
+pub mod coverage;
+/// Clock cycles taken by each instruction
+mod cycles;
+/// Cache of the instructions decoded from program memory
+pub mod decode_cache;
 /// Instruction decoder. Parses words fetched in the memory bank into structs
 /// that the ALU can execute.
 pub mod decoder;
+/// DWARF functions, variables and types, decoded from MCU memory
+pub mod debug_info;
+/// Source level stepping and variable inspection for programs with debug info
+pub mod debugger;
+/// Device descriptors, the memory layout of each MCU model
+pub mod device;
+/// Disassembler for whole program images, with labels and `avr-objdump`
+/// compatible output
+pub mod disassembler;
 /// Implementation of fmt::Display
 mod display_instruction;
+/// ELF file reader, with symbols and DWARF line tables
+pub mod elf;
+/// Event hooks, notified of instructions, memory accesses, interrupts and faults
+pub mod events;
+/// Execution history of undo records and checkpoints, used to step back
+pub mod history;
+/// Address to source line mapping
+pub mod line_table;
+/// Program image loaders for binary and Intel HEX files
+pub mod loader;
 /// Controller module, which contains a memory bank, registers and an
 /// ALU for instruction execution.
 pub mod mcu;
@@ -15,8 +44,30 @@ pub mod mcu;
 pub mod mcu_factory;
 /// Memory bank, the main memory of the microcontroller
 pub mod memory_bank;
+/// Data memory map with named regions, for strict memory access checking
+pub mod memory_map;
+/// Cycle profiler with call graph and folded stacks
+pub mod profiler;
 /// Register bank, holds general purpose registers, program counter, and flags
 pub mod register_bank;
+/// Runs an MCU on a worker thread, driven through a thread-safe handle
+pub mod runner;
+/// Debug I/O device for firmware output, exit codes and cycle counts
+pub mod semihost;
+/// Shadow memory of defined bits, reports uses of uninitialized values
+pub mod shadow;
+/// Machine state snapshots, saved to and restored from versioned files
+pub mod snapshot;
+/// Stack high-water marks and stack/heap collision detection
+pub mod stack_monitor;
+/// Symbol tables, used to name program and data addresses
+pub mod symbols;
+/// Real-time pacing of execution, with achieved speed measurement
+pub mod throttle;
+/// Execution tracer, records state changes made by each instruction
+pub mod trace;
+/// Value Change Dump writer, for waveforms of pins, registers and memory
+pub mod vcd;
 
 type RawInstruction = u16;
 
@@ -27,7 +78,7 @@ pub enum PointerRegister {
     Z,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 /// Decoded instructions
 pub enum Instruction {
     BitManipOp {
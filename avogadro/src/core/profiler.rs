@@ -0,0 +1,340 @@
+use super::decoder::call_size;
+use super::symbols::SymbolTable;
+use super::Instruction;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+/// Name of the code not covered by any symbol
+const UNKNOWN_FUNCTION: &str = "[unknown]";
+
+/// Cycles and calls of a function
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+    /// Cycles spent in the function itself
+    pub self_cycles: u64,
+    /// Cycles spent in the function and everything it called
+    pub total_cycles: u64,
+    pub calls: u64,
+}
+
+/// Calls from a function to another one
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallEdge {
+    pub caller: String,
+    pub callee: String,
+    pub calls: u64,
+    /// Cycles spent in the callee, and everything it called, when called
+    /// from the caller
+    pub cycles: u64,
+}
+
+#[derive(Default)]
+struct FunctionStats {
+    self_cycles: u64,
+    total_cycles: u64,
+    calls: u64,
+}
+
+#[derive(Default)]
+struct EdgeStats {
+    calls: u64,
+    cycles: u64,
+}
+
+/// A call that didn't return yet
+struct Frame {
+    caller: usize,
+    return_address: u16,
+}
+
+/// Cycle profiler, collected by `Mcu::step`.
+///
+/// Executed cycles are attributed to the function containing the program
+/// counter, looked up in the symbol table. Calls (`call`, `rcall`, `icall`,
+/// `eicall` and interrupts) push a frame and returns (`ret`, `reti`) pop it, so
+/// cycles are also attributed to every function in the call stack. A return
+/// to an address no frame expects (like a `ret` used as a computed jump) just
+/// pops the last frame.
+pub struct Profiler {
+    symbols: SymbolTable,
+    names: Vec<String>,
+    name_ids: HashMap<String, usize>,
+    /// Function id of each executed address
+    function_ids: HashMap<u16, usize>,
+    functions: Vec<FunctionStats>,
+    edges: HashMap<(usize, usize), EdgeStats>,
+    frames: Vec<Frame>,
+    /// Interned call stacks, as caller function ids from the outermost one
+    stacks: Vec<Vec<usize>>,
+    stack_ids: HashMap<Vec<usize>, usize>,
+    current_stack: usize,
+    /// Cycles of each function by call stack
+    folded: HashMap<(usize, usize), u64>,
+    total_cycles: u64,
+}
+
+impl Profiler {
+    /// Creates a profiler naming functions with `symbols`, usually read from
+    /// an ELF file
+    pub fn new(symbols: SymbolTable) -> Profiler {
+        let mut stack_ids = HashMap::new();
+        stack_ids.insert(Vec::new(), 0);
+        Profiler {
+            symbols,
+            names: Vec::new(),
+            name_ids: HashMap::new(),
+            function_ids: HashMap::new(),
+            functions: Vec::new(),
+            edges: HashMap::new(),
+            frames: Vec::new(),
+            stacks: vec![Vec::new()],
+            stack_ids,
+            current_stack: 0,
+            folded: HashMap::new(),
+            total_cycles: 0,
+        }
+    }
+
+    /// Cycles recorded since the profiler was created or cleared
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    /// Number of calls that didn't return yet
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Profile of every executed or called function, by self cycles in
+    /// descending order
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions: Vec<FunctionProfile> = self
+            .functions
+            .iter()
+            .enumerate()
+            .map(|(id, stats)| FunctionProfile {
+                name: self.names[id].clone(),
+                self_cycles: stats.self_cycles,
+                total_cycles: stats.total_cycles,
+                calls: stats.calls,
+            })
+            .collect();
+        functions.sort_by(|a, b| {
+            b.self_cycles
+                .cmp(&a.self_cycles)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        functions
+    }
+
+    /// Profile of function `name`, if it was executed or called
+    pub fn function(&self, name: &str) -> Option<FunctionProfile> {
+        self.functions()
+            .into_iter()
+            .find(|function| function.name == name)
+    }
+
+    /// Call graph edges, sorted by caller and callee
+    pub fn call_edges(&self) -> Vec<CallEdge> {
+        let mut edges: Vec<CallEdge> = self
+            .edges
+            .iter()
+            .map(|((caller, callee), stats)| CallEdge {
+                caller: self.names[*caller].clone(),
+                callee: self.names[*callee].clone(),
+                calls: stats.calls,
+                cycles: stats.cycles,
+            })
+            .collect();
+        edges.sort_by(|a, b| (&a.caller, &a.callee).cmp(&(&b.caller, &b.callee)));
+        edges
+    }
+
+    /// Forgets the collected cycles and calls, keeping the current call stack
+    pub fn clear(&mut self) {
+        self.functions
+            .iter_mut()
+            .for_each(|stats| *stats = FunctionStats::default());
+        self.edges.clear();
+        self.folded.clear();
+        self.total_cycles = 0;
+    }
+
+    /// Records an interrupt at `pc`, the address of the instruction it
+    /// returns to, jumping to `vector`
+    pub fn enter_interrupt(&mut self, pc: u16, vector: u16) {
+        let caller = self.function_id(pc);
+        self.push_frame(caller, vector, pc);
+    }
+
+    /// Records an executed `instruction`, fetched from `pc` as
+    /// `raw_instruction`, that took `cycles` and left the program counter at
+    /// `next_pc`
+    pub(crate) fn record(
+        &mut self,
+        pc: u16,
+        raw_instruction: u16,
+        instruction: &Instruction,
+        next_pc: u16,
+        cycles: u64,
+    ) {
+        let function = self.function_id(pc);
+        self.add_cycles(function, cycles);
+        if let Some(size) = call_size(raw_instruction) {
+            let return_address = pc.wrapping_add(size);
+            // `rcall .+0` only makes room in the stack
+            if next_pc != return_address {
+                self.push_frame(function, next_pc, return_address);
+            }
+        } else if let Instruction::ZeroRegOp { op: 0..=1 } = instruction {
+            self.pop_frames(next_pc);
+        }
+    }
+
+    /// Writes a flat profile, one function per line, sorted by self cycles
+    pub fn write_flat(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "{:>7} {:>12} {:>12} {:>8}  function",
+            "%self", "self", "total", "calls"
+        )?;
+        for function in self.functions() {
+            let percent = if self.total_cycles > 0 {
+                function.self_cycles as f64 * 100.0 / self.total_cycles as f64
+            } else {
+                0.0
+            };
+            writeln!(
+                out,
+                "{:>7.2} {:>12} {:>12} {:>8}  {}",
+                percent, function.self_cycles, function.total_cycles, function.calls, function.name
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the call graph, one `caller -> callee calls=<n> cycles=<n>`
+    /// edge per line
+    pub fn write_call_graph(&self, out: &mut impl Write) -> io::Result<()> {
+        for edge in self.call_edges() {
+            writeln!(
+                out,
+                "{} -> {} calls={} cycles={}",
+                edge.caller, edge.callee, edge.calls, edge.cycles
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes folded stacks, `outer;inner;function <cycles>` per line, the
+    /// input format of flamegraph tools
+    pub fn write_folded(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut lines: Vec<(String, u64)> = self
+            .folded
+            .iter()
+            .map(|((stack, function), cycles)| {
+                let mut names: Vec<&str> = self.stacks[*stack]
+                    .iter()
+                    .map(|id| self.names[*id].as_str())
+                    .collect();
+                names.push(&self.names[*function]);
+                (names.join(";"), *cycles)
+            })
+            .collect();
+        lines.sort();
+        for (stack, cycles) in lines {
+            writeln!(out, "{} {}", stack, cycles)?;
+        }
+        Ok(())
+    }
+
+    fn add_cycles(&mut self, function: usize, cycles: u64) {
+        self.total_cycles += cycles;
+        self.functions[function].self_cycles += cycles;
+        *self
+            .folded
+            .entry((self.current_stack, function))
+            .or_default() += cycles;
+        let stack = &self.stacks[self.current_stack];
+        let path: Vec<usize> = stack.iter().copied().chain(Some(function)).collect();
+        // Recursive functions and edges are counted once per step
+        for (i, id) in path.iter().enumerate() {
+            if !path[..i].contains(id) {
+                self.functions[*id].total_cycles += cycles;
+            }
+        }
+        for i in 1..path.len() {
+            let edge = (path[i - 1], path[i]);
+            let repeated = (1..i).any(|j| (path[j - 1], path[j]) == edge);
+            if !repeated {
+                self.edges.entry(edge).or_default().cycles += cycles;
+            }
+        }
+    }
+
+    fn push_frame(&mut self, caller: usize, target: u16, return_address: u16) {
+        let callee = self.function_id(target);
+        self.functions[callee].calls += 1;
+        self.edges.entry((caller, callee)).or_default().calls += 1;
+        self.frames.push(Frame {
+            caller,
+            return_address,
+        });
+        self.update_stack();
+    }
+
+    /// Pops the frame returning to `address` and every frame above it, or
+    /// just the last frame if none does
+    fn pop_frames(&mut self, address: u16) {
+        let position = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_address == address);
+        match position {
+            Some(position) => self.frames.truncate(position),
+            None => {
+                self.frames.pop();
+            }
+        }
+        self.update_stack();
+    }
+
+    fn update_stack(&mut self) {
+        let stack: Vec<usize> = self.frames.iter().map(|frame| frame.caller).collect();
+        self.current_stack = match self.stack_ids.get(&stack) {
+            Some(id) => *id,
+            None => {
+                let id = self.stacks.len();
+                self.stacks.push(stack.clone());
+                self.stack_ids.insert(stack, id);
+                id
+            }
+        };
+    }
+
+    fn function_id(&mut self, pc: u16) -> usize {
+        if let Some(id) = self.function_ids.get(&pc) {
+            return *id;
+        }
+        let name = match self.symbols.lookup(u32::from(pc)) {
+            Some((symbol, offset)) if symbol.size == 0 || offset < symbol.size => {
+                symbol.name.clone()
+            }
+            _ => UNKNOWN_FUNCTION.to_owned(),
+        };
+        let id = match self.name_ids.get(&name) {
+            Some(id) => *id,
+            None => {
+                let id = self.names.len();
+                self.names.push(name.clone());
+                self.name_ids.insert(name, id);
+                self.functions.push(FunctionStats::default());
+                id
+            }
+        };
+        self.function_ids.insert(pc, id);
+        id
+    }
+}
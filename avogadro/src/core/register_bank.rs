@@ -131,3 +131,18 @@ impl From<Flags> for u8 {
         result
     }
 }
+
+impl From<u8> for Flags {
+    fn from(value: u8) -> Flags {
+        Flags {
+            carry: value & 1 != 0,
+            zero: value & (1 << 1) != 0,
+            neg: value & (1 << 2) != 0,
+            over: value & (1 << 3) != 0,
+            sign: value & (1 << 4) != 0,
+            half: value & (1 << 5) != 0,
+            trans: value & (1 << 6) != 0,
+            int: value & (1 << 7) != 0,
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use super::mcu::Mcu;
+use super::snapshot::Snapshot;
+use super::throttle::Throttle;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Instructions run between checks for new commands while running
+const BATCH_STEPS: usize = 1000;
+
+/// What the worker thread is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    /// Waiting for commands
+    Paused,
+    Running,
+    /// Paused because the program counter reached a breakpoint
+    Breakpoint(u16),
+    /// The worker thread is gone, after a panic in it
+    Stopped,
+}
+
+enum Command {
+    Run,
+    Pause,
+    Step(usize),
+    AddBreakpoint(u16),
+    RemoveBreakpoint(u16),
+    SetSpeed(Option<f64>),
+    /// Runs a call with the MCU, which sends its result back
+    Call(Box<dyn FnOnce(&mut Mcu) + Send>),
+    Stop,
+}
+
+/// Status shared with the worker, notified on every change
+type SharedStatus = Arc<(Mutex<RunStatus>, Condvar)>;
+
+/// Runs an `Mcu` on a worker thread. Commands are queued and handled in
+/// order between instructions, so every call made with the MCU sees the
+/// effects of the commands sent before it, and snapshots are never taken in
+/// the middle of an instruction. The runner can be shared between threads.
+pub struct McuRunner {
+    commands: Sender<Command>,
+    status: SharedStatus,
+    /// Bits of the achieved speed, in Hz
+    achieved_hz: Arc<AtomicU64>,
+    worker: Option<JoinHandle<Mcu>>,
+}
+
+impl McuRunner {
+    /// Moves `mcu` into a new worker thread, paused
+    pub fn new(mcu: Mcu) -> McuRunner {
+        let (commands, receiver) = channel();
+        let status = Arc::new((Mutex::new(RunStatus::Paused), Condvar::new()));
+        let achieved_hz = Arc::new(AtomicU64::new(0));
+        let worker_status = Arc::clone(&status);
+        let worker_achieved_hz = Arc::clone(&achieved_hz);
+        let worker = thread::Builder::new()
+            .name("mcu-runner".to_owned())
+            .spawn(move || work(mcu, receiver, worker_status, worker_achieved_hz))
+            .expect("Cannot spawn MCU runner thread");
+        McuRunner {
+            commands,
+            status,
+            achieved_hz,
+            worker: Some(worker),
+        }
+    }
+
+    /// Runs until paused or a breakpoint is reached
+    pub fn run(&self) -> bool {
+        self.send(Command::Run)
+    }
+
+    pub fn pause(&self) -> bool {
+        self.send(Command::Pause)
+    }
+
+    /// Runs `count` instructions, stopping early at a breakpoint after the
+    /// first one
+    pub fn step(&self, count: usize) -> bool {
+        self.send(Command::Step(count))
+    }
+
+    pub fn add_breakpoint(&self, pc: u16) -> bool {
+        self.send(Command::AddBreakpoint(pc))
+    }
+
+    pub fn remove_breakpoint(&self, pc: u16) -> bool {
+        self.send(Command::RemoveBreakpoint(pc))
+    }
+
+    /// Paces running to `multiplier` times the MCU clock, `Mcu::get_speed`,
+    /// like 1.0 for real time. Runs as fast as possible if `None`, the
+    /// default. Simulated time, counted in cycles, isn't affected. The clock
+    /// is read again on every `set_speed` and `run`, so changes made with
+    /// `with_mcu` apply from then on.
+    pub fn set_speed(&self, multiplier: Option<f64>) -> bool {
+        self.send(Command::SetSpeed(multiplier))
+    }
+
+    /// Simulated clock cycles per second while running, measured over the
+    /// last half second. 0 while paused.
+    pub fn achieved_hz(&self) -> f64 {
+        f64::from_bits(self.achieved_hz.load(Ordering::Relaxed))
+    }
+
+    /// Status after the last command handled by the worker
+    pub fn status(&self) -> RunStatus {
+        let (status, _) = &*self.status;
+        *status.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits at most `timeout` for the runner to stop running after the
+    /// commands sent before, returning its status
+    pub fn wait(&self, timeout: Duration) -> RunStatus {
+        self.with_mcu(|_| ());
+        self.wait_while(timeout, |status| status == RunStatus::Running)
+    }
+
+    /// Runs `call` with the MCU on the worker thread, after the commands
+    /// sent before, and returns its result. Returns `None` if the worker is
+    /// gone.
+    pub fn with_mcu<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&mut Mcu) -> T + Send + 'static,
+    ) -> Option<T> {
+        let (reply, result) = channel();
+        let call = move |mcu: &mut Mcu| {
+            let _ = reply.send(call(mcu));
+        };
+        if !self.send(Command::Call(Box::new(call))) {
+            return None;
+        }
+        match result.recv() {
+            Ok(result) => Some(result),
+            Err(_) => {
+                // The worker panicked, its status is set while unwinding
+                self.wait_while(Duration::MAX, |status| status != RunStatus::Stopped);
+                None
+            }
+        }
+    }
+
+    /// Takes a snapshot between two instructions, even while running
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        self.with_mcu(|mcu| mcu.snapshot())
+    }
+
+    /// Stops the worker thread and gives the MCU back. Returns `None` if the
+    /// worker panicked.
+    pub fn into_mcu(mut self) -> Option<Mcu> {
+        self.stop()
+    }
+
+    /// Waits at most `timeout` while `condition` holds for the status
+    fn wait_while(&self, timeout: Duration, condition: impl Fn(RunStatus) -> bool) -> RunStatus {
+        let (status, changed) = &*self.status;
+        let status = status.lock().unwrap_or_else(PoisonError::into_inner);
+        let (status, _) = changed
+            .wait_timeout_while(status, timeout, |status| condition(*status))
+            .unwrap_or_else(PoisonError::into_inner);
+        *status
+    }
+
+    /// Queues `command`. Returns false if the worker is gone.
+    fn send(&self, command: Command) -> bool {
+        self.commands.send(command).is_ok()
+    }
+
+    fn stop(&mut self) -> Option<Mcu> {
+        self.send(Command::Stop);
+        self.worker.take().and_then(|worker| worker.join().ok())
+    }
+}
+
+impl Drop for McuRunner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Sets the shared status when dropped, so a panic in the worker is seen as
+/// `RunStatus::Stopped`
+struct StatusGuard(SharedStatus);
+
+impl StatusGuard {
+    fn set(&self, new_status: RunStatus) {
+        let (status, changed) = &*self.0;
+        *status.lock().unwrap_or_else(PoisonError::into_inner) = new_status;
+        changed.notify_all();
+    }
+}
+
+impl Drop for StatusGuard {
+    fn drop(&mut self) {
+        self.set(RunStatus::Stopped);
+    }
+}
+
+/// Worker thread loop, handling commands and running the MCU in batches
+fn work(
+    mut mcu: Mcu,
+    commands: Receiver<Command>,
+    status: SharedStatus,
+    achieved_hz: Arc<AtomicU64>,
+) -> Mcu {
+    let status = StatusGuard(status);
+    let mut throttle = Throttle::new(clock_hz(&mcu), None);
+    let mut running = false;
+    loop {
+        let command = if running {
+            // Waits for the next batch to be due, or a command
+            let delay = throttle.delay(mcu.get_cycle_count(), Instant::now());
+            match commands.recv_timeout(delay) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return mcu,
+            }
+        } else {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return mcu,
+            }
+        };
+        let was_running = running;
+        match command {
+            Some(Command::Run) => {
+                running = true;
+                status.set(RunStatus::Running);
+            }
+            Some(Command::Pause) => {
+                running = false;
+                status.set(RunStatus::Paused);
+            }
+            Some(Command::Step(count)) => match step(&mut mcu, count, usize::MAX) {
+                Some(pc) => {
+                    running = false;
+                    status.set(RunStatus::Breakpoint(pc));
+                }
+                None if !running => status.set(RunStatus::Paused),
+                None => {}
+            },
+            Some(Command::AddBreakpoint(pc)) => mcu.add_breakpoint(pc),
+            Some(Command::RemoveBreakpoint(pc)) => mcu.remove_breakpoint(pc),
+            Some(Command::SetSpeed(multiplier)) => {
+                throttle.set_multiplier(multiplier);
+                throttle.set_clock_hz(clock_hz(&mcu));
+                throttle.reset(mcu.get_cycle_count(), Instant::now());
+            }
+            Some(Command::Call(call)) => call(&mut mcu),
+            Some(Command::Stop) => return mcu,
+            None => {}
+        }
+        if running && !was_running {
+            throttle.set_clock_hz(clock_hz(&mcu));
+            throttle.reset(mcu.get_cycle_count(), Instant::now());
+        }
+        if running {
+            let now = Instant::now();
+            let cycle_limit = throttle.cycle_limit(mcu.get_cycle_count(), now);
+            if let Some(pc) = step(&mut mcu, BATCH_STEPS, cycle_limit) {
+                running = false;
+                status.set(RunStatus::Breakpoint(pc));
+            }
+            throttle.record(mcu.get_cycle_count(), Instant::now());
+        }
+        let speed = if running { throttle.achieved_hz() } else { 0.0 };
+        achieved_hz.store(speed.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Clock frequency of `mcu`, `Mcu::get_speed` being in kHz
+fn clock_hz(mcu: &Mcu) -> u64 {
+    mcu.get_speed() as u64 * 1000
+}
+
+/// Runs `count` instructions, stopping at cycle `cycle_limit` or a
+/// breakpoint reached after the first instruction. Returns the breakpoint,
+/// if one stopped it.
+fn step(mcu: &mut Mcu, count: usize, cycle_limit: usize) -> Option<u16> {
+    for _ in 0..count {
+        if mcu.get_cycle_count() >= cycle_limit {
+            break;
+        }
+        mcu.step();
+        let pc = mcu.get_program_counter();
+        if mcu.get_breakpoints().contains(&pc) {
+            return Some(pc);
+        }
+    }
+    None
+}
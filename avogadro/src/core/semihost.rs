@@ -0,0 +1,103 @@
+use std::ops::Range;
+
+/// Default device address, I/O registers 0x00 to 0x05. Free on the
+/// ATtiny85, but not on the ATmegas, where 0x23 to 0x25 are port B.
+pub const DEFAULT_ADDRESS: u16 = 0x20;
+/// Writes a character to the host output
+pub const PUTCHAR: u16 = 0;
+/// Writes the exit code, stopping the simulation
+pub const EXIT: u16 = 1;
+/// Writing latches the cycle counter, read as a 32 bit little endian value
+/// from this offset
+pub const CYCLES: u16 = 2;
+/// Device size in bytes
+pub const SIZE: u16 = 6;
+
+/// Why firmware stopped the simulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// Exit code written to `EXIT`
+    Exit(u8),
+    /// `BREAK` executed at this address
+    Trap(u16),
+}
+
+/// Debug I/O device, lets firmware report results to the host without a
+/// UART model
+#[derive(Debug, Clone, PartialEq)]
+pub struct Semihost {
+    address: u16,
+    output: Vec<u8>,
+    stop: Option<Stop>,
+}
+
+impl Semihost {
+    /// Creates a device mapped at data memory `address`
+    pub fn new(address: u16) -> Semihost {
+        Semihost {
+            address,
+            output: Vec::new(),
+            stop: None,
+        }
+    }
+
+    /// Device state saved in a snapshot, with output not taken yet
+    pub(crate) fn restored(address: u16, output: Vec<u8>, stop: Option<Stop>) -> Semihost {
+        Semihost {
+            address,
+            output,
+            stop,
+        }
+    }
+
+    pub fn get_address(&self) -> u16 {
+        self.address
+    }
+
+    /// Data memory addresses of the device
+    pub fn range(&self) -> Range<u16> {
+        self.address..self.address + SIZE
+    }
+
+    /// Handles a write of `value` at data memory `address`. Returns the bytes
+    /// to store from `address` on, if any
+    pub fn write(&mut self, address: u16, value: u8, cycle: usize) -> Option<[u8; 4]> {
+        match address.checked_sub(self.address)? {
+            PUTCHAR => self.output.push(value),
+            EXIT => {
+                self.stop.get_or_insert(Stop::Exit(value));
+            }
+            CYCLES => return Some((cycle as u32).to_le_bytes()),
+            _ => (),
+        }
+        None
+    }
+
+    /// Stops the simulation on a `BREAK` instruction at `pc`
+    pub fn trap(&mut self, pc: u16) {
+        self.stop.get_or_insert(Stop::Trap(pc));
+    }
+
+    /// Exit code or trap that stopped the firmware, if any
+    pub fn get_stop(&self) -> Option<Stop> {
+        self.stop
+    }
+
+    /// Exit code written by the firmware, if it exited
+    pub fn get_exit_code(&self) -> Option<u8> {
+        match self.stop {
+            Some(Stop::Exit(code)) => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Characters written since the last `take_output`
+    pub fn get_output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Returns characters written since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+}
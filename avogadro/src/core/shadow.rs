@@ -0,0 +1,531 @@
+use super::alu::{FMULSU_OP, FMULS_OP, FMUL_OP, MOVW_OP, MULSU_OP, MULS_OP};
+use super::decoder::{CALL_JMP_EINDZ_ADDRESS, CALL_JMP_Z_ADDRESS};
+use super::memory_bank::MemoryBank;
+use super::memory_map::{MemoryMap, Region};
+use super::register_bank::RegisterBank;
+use super::Instruction;
+use super::PointerRegister;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Mask of a byte whose bits are all defined
+pub const DEFINED: u8 = 0xFF;
+/// First I/O register address in data memory
+const IO_START: u16 = 0x20;
+/// Carry, zero, negative, overflow, sign and half carry flags
+const ARITHMETIC_FLAGS: u8 = 0x3F;
+/// Zero, negative, overflow and sign flags
+const LOGIC_FLAGS: u8 = 0x1E;
+const CARRY_FLAG: u8 = 0x01;
+/// Carry and zero flags
+const PRODUCT_FLAGS: u8 = 0x03;
+
+/// Values of registers and SRAM at power-on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillPolicy {
+    Zeros,
+    /// Every byte is 0xFF
+    Ones,
+    /// Pseudo random bytes, the same for the same seed
+    Random(u64),
+}
+
+impl FillPolicy {
+    /// Fills `bytes` with power-on values
+    pub fn fill(&self, bytes: &mut [u8]) {
+        match self {
+            FillPolicy::Zeros => bytes.iter_mut().for_each(|byte| *byte = 0),
+            FillPolicy::Ones => bytes.iter_mut().for_each(|byte| *byte = 0xFF),
+            FillPolicy::Random(seed) => {
+                // xorshift64*, which needs a non zero state
+                let mut state = seed ^ 0x9E37_79B9_7F4A_7C15;
+                for byte in bytes {
+                    state ^= state >> 12;
+                    state ^= state << 25;
+                    state ^= state >> 27;
+                    *byte = (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// How an uninitialized value was used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UseKind {
+    /// A conditional branch or skip depends on it
+    Branch,
+    /// An indirect jump, call or return goes to it
+    IndirectJump,
+    /// It was written to I/O register `address`
+    IoWrite { address: u16 },
+    /// A load or store goes through a pointer holding it
+    Address,
+}
+
+/// An instruction that used an uninitialized value
+#[derive(Debug, Clone, PartialEq)]
+pub struct UninitializedUse {
+    pub pc: u16,
+    pub kind: UseKind,
+    pub cycle: usize,
+}
+
+impl fmt::Display for UninitializedUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            UseKind::Branch => write!(
+                f,
+                "Conditional branch at 0x{:04x} depends on an uninitialized value",
+                self.pc
+            )?,
+            UseKind::IndirectJump => write!(
+                f,
+                "Jump at 0x{:04x} goes to an uninitialized address",
+                self.pc
+            )?,
+            UseKind::IoWrite { address } => write!(
+                f,
+                "Uninitialized value written to I/O 0x{:02x} at 0x{:04x}",
+                address, self.pc
+            )?,
+            UseKind::Address => write!(
+                f,
+                "Memory access at 0x{:04x} through an uninitialized pointer",
+                self.pc
+            )?,
+        }
+        write!(f, ", cycle {}", self.cycle)
+    }
+}
+
+/// Shadow memory of defined bits, like valgrind memcheck, updated by
+/// `Mcu::step`.
+///
+/// Registers and SRAM start undefined, filled by `Mcu::set_shadow_memory`
+/// as the `FillPolicy` says. I/O registers and flags start defined, as
+/// their reset values are. Moves copy defined bits, bitwise operations
+/// track them bit by bit (`andi r24, 0x0f` defines the 4 high bits, and
+/// `eor r1, r1` all of them), and other operations give an undefined
+/// result if any input bit is undefined.
+///
+/// Each instruction is reported once per kind of use, when a conditional
+/// branch or skip depends on an undefined value, an indirect jump or return
+/// goes to an undefined address, a pointer is undefined, or an undefined
+/// value is written to I/O. Values written by the host, like
+/// `Mcu::set_data_byte`, are defined. Stepping back doesn't rewind it.
+pub struct ShadowMemory {
+    policy: FillPolicy,
+    registers: [u8; 32],
+    data: Vec<u8>,
+    /// Region of each data memory byte
+    regions: Vec<Region>,
+    flags: u8,
+    uses: Vec<UninitializedUse>,
+    reported: HashSet<(u16, UseKind)>,
+}
+
+impl ShadowMemory {
+    /// Creates a shadow memory for `data_size` bytes of data memory, laid
+    /// out with SRAM from 0x60 until `Mcu::set_shadow_memory` sets the
+    /// memory map of the MCU
+    pub fn new(data_size: usize, policy: FillPolicy) -> ShadowMemory {
+        let mut shadow = ShadowMemory {
+            policy,
+            registers: [0; 32],
+            data: vec![DEFINED; data_size],
+            regions: Vec::new(),
+            flags: DEFINED,
+            uses: Vec::new(),
+            reported: HashSet::new(),
+        };
+        shadow.set_memory_map(&MemoryMap::new(data_size));
+        shadow
+    }
+
+    pub fn get_policy(&self) -> FillPolicy {
+        self.policy
+    }
+
+    /// Defined bits of register `reg`
+    pub fn register_mask(&self, reg: u8) -> u8 {
+        self.registers[usize::from(reg)]
+    }
+
+    /// Defined bits of data memory byte `address`
+    pub fn data_mask(&self, address: u16) -> u8 {
+        self.data[self.index(address)]
+    }
+
+    /// Defined bits of SREG
+    pub fn flags_mask(&self) -> u8 {
+        self.flags
+    }
+
+    /// Marks register `reg` as defined
+    pub fn define_register(&mut self, reg: u8) {
+        self.registers[usize::from(reg)] = DEFINED;
+    }
+
+    /// Marks data memory byte `address` as defined
+    pub fn define_data(&mut self, address: u16) {
+        let index = self.index(address);
+        self.data[index] = DEFINED;
+    }
+
+    /// Marks data memory byte `address` as undefined, like memory freed by
+    /// the firmware
+    pub fn undefine_data(&mut self, address: u16) {
+        let index = self.index(address);
+        self.data[index] = 0;
+    }
+
+    /// Marks every register, data memory byte and flag as defined
+    pub fn define_all(&mut self) {
+        self.registers = [DEFINED; 32];
+        self.data.iter_mut().for_each(|mask| *mask = DEFINED);
+        self.flags = DEFINED;
+    }
+
+    /// Uses of uninitialized values reported so far
+    pub fn uses(&self) -> &[UninitializedUse] {
+        &self.uses
+    }
+
+    /// Returns and forgets the reported uses. Instructions already reported
+    /// aren't reported again.
+    pub fn take_uses(&mut self) -> Vec<UninitializedUse> {
+        std::mem::take(&mut self.uses)
+    }
+
+    /// Lays data memory out as `memory_map`: SRAM is undefined, and the
+    /// rest defined
+    pub(crate) fn set_memory_map(&mut self, memory_map: &MemoryMap) {
+        self.regions = (0..self.data.len())
+            .map(|address| memory_map.region(address as u16))
+            .collect();
+        for (mask, region) in self.data.iter_mut().zip(&self.regions) {
+            *mask = if *region == Region::Sram { 0 } else { DEFINED };
+        }
+    }
+
+    /// Fills registers and SRAM with their power-on values
+    pub(crate) fn power_on(&self, registers: &mut [u8; 32], data: &mut [u8]) {
+        let sram: Vec<_> = (0..data.len().min(self.regions.len()))
+            .filter(|address| self.regions[*address] == Region::Sram)
+            .collect();
+        let mut values = vec![0; registers.len() + sram.len()];
+        self.policy.fill(&mut values);
+        let (register_values, sram_values) = values.split_at(registers.len());
+        registers.copy_from_slice(register_values);
+        for (address, value) in sram.iter().zip(sram_values) {
+            data[*address] = *value;
+        }
+    }
+
+    /// Updates the defined bits written by `instruction`, fetched from `pc`,
+    /// before it's executed with `register_bank` and `memory_bank`
+    pub(crate) fn execute(
+        &mut self,
+        pc: u16,
+        instruction: &Instruction,
+        register_bank: &RegisterBank,
+        memory_bank: &MemoryBank,
+        cycle: usize,
+    ) {
+        let values = &register_bank.registers;
+        let stack_pointer = register_bank.get_stack_pointer();
+        match *instruction {
+            Instruction::BitManipOp { address, bit, .. } => {
+                let index = self.index(u16::from(address) + IO_START);
+                self.data[index] |= 1 << bit;
+            }
+            Instruction::Branch { op, .. } if self.flags & (1 << op) == 0 => {
+                self.report(pc, UseKind::Branch, cycle);
+            }
+            Instruction::CallJmp {
+                is_call,
+                relative,
+                address,
+            } => {
+                // `ijmp`, `icall`, `eijmp` and `eicall` jump to Z
+                let is_indirect = !relative
+                    && (address == CALL_JMP_Z_ADDRESS || address == CALL_JMP_EINDZ_ADDRESS);
+                if is_indirect && self.registers[30] & self.registers[31] != DEFINED {
+                    self.report(pc, UseKind::IndirectJump, cycle);
+                }
+                if is_call {
+                    self.define_data(stack_pointer);
+                    self.define_data(stack_pointer.wrapping_add(1));
+                }
+            }
+            Instruction::InOut {
+                is_in,
+                reg,
+                address,
+            } => {
+                let address = u16::from(address) + IO_START;
+                if is_in {
+                    self.registers[usize::from(reg)] = self.data_mask(address);
+                } else {
+                    self.store(pc, address, self.registers[usize::from(reg)], cycle);
+                }
+            }
+            Instruction::PushPop { is_pop, reg } => {
+                if is_pop {
+                    let mask = self.data_mask(stack_pointer.wrapping_add(1));
+                    self.registers[usize::from(reg)] = mask;
+                } else {
+                    let top = if stack_pointer == 0 {
+                        memory_bank.data_size() as u16
+                    } else {
+                        stack_pointer
+                    };
+                    let index = self.index(top.wrapping_sub(1));
+                    self.data[index] = self.registers[usize::from(reg)];
+                }
+            }
+            Instruction::RegConstOp { op, rd, constant } => {
+                self.execute_with_constant(op, rd, constant)
+            }
+            Instruction::SkipOp { address, bit, .. }
+                if self.data_mask(u16::from(address) + IO_START) & (1 << bit) == 0 =>
+            {
+                self.report(pc, UseKind::Branch, cycle);
+            }
+            Instruction::TransferIndirect {
+                is_load,
+                pointer,
+                dest,
+                offset,
+            } => {
+                let address = self.pointer(pc, pointer, values, cycle);
+                self.transfer(
+                    pc,
+                    is_load,
+                    address.wrapping_add(offset.into()),
+                    dest,
+                    cycle,
+                );
+            }
+            Instruction::TransferChangePointer {
+                is_load,
+                pointer,
+                dest,
+                post_inc,
+            } => {
+                let address = self.pointer(pc, pointer, values, cycle);
+                let address = if post_inc {
+                    address
+                } else {
+                    address.wrapping_sub(1)
+                };
+                self.transfer(pc, is_load, address, dest, cycle);
+            }
+            Instruction::TwoRegOp { op, rd, rr } => {
+                self.execute_two_reg(pc, op, rd, rr, values, cycle)
+            }
+            Instruction::OneRegOp { rd, op } => self.execute_one_reg(op, rd),
+            Instruction::ZeroRegOp { op: 0..=1 } => {
+                let mut top = stack_pointer.wrapping_add(2);
+                if top >= (memory_bank.data_size() - 1) as u16 {
+                    top = 0;
+                }
+                let address = self.data_mask(top) & self.data_mask(top.wrapping_add(1));
+                if address != DEFINED {
+                    self.report(pc, UseKind::IndirectJump, cycle);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn execute_with_constant(&mut self, op: u16, rd: u8, constant: u8) {
+        let rd = usize::from(rd) + 16;
+        let mask = self.registers[rd];
+        match op {
+            // cpi
+            0x3 => self.set_flags(ARITHMETIC_FLAGS, mask == DEFINED),
+            // sbci
+            0x4 => self.arithmetic(rd, &[mask], true),
+            // subi
+            0x5 => self.arithmetic(rd, &[mask], false),
+            // ori, bits set by the constant are defined
+            0x6 => self.logic(rd, mask | constant),
+            // andi, bits cleared by the constant are defined
+            0x7 => self.logic(rd, mask | !constant),
+            // ldi
+            0xE => self.registers[rd] = DEFINED,
+            // adiw and sbiw
+            0x96 | 0x97 => {
+                let rd = 24 + (rd - 16) * 2;
+                let defined = self.registers[rd] & self.registers[rd + 1] == DEFINED;
+                let mask = if defined { DEFINED } else { 0 };
+                self.registers[rd] = mask;
+                self.registers[rd + 1] = mask;
+                self.set_flags(ARITHMETIC_FLAGS, defined);
+            }
+            _ => (),
+        }
+    }
+
+    fn execute_two_reg(
+        &mut self,
+        pc: u16,
+        op: u16,
+        rd: u8,
+        rr: u8,
+        values: &[u8; 32],
+        cycle: usize,
+    ) {
+        let (rd, rr) = (usize::from(rd), usize::from(rr));
+        let (rd_mask, rr_mask) = (self.registers[rd], self.registers[rr]);
+        let carry = self.flags & CARRY_FLAG != 0;
+        match op {
+            // cpc
+            0x1 => self.set_flags(ARITHMETIC_FLAGS, rd_mask & rr_mask == DEFINED && carry),
+            // sbc and adc
+            0x2 | 0x7 => self.arithmetic(rd, &[rd_mask, rr_mask], true),
+            // sub of a register from itself is zero
+            0x6 if rd == rr => {
+                self.registers[rd] = DEFINED;
+                self.set_flags(ARITHMETIC_FLAGS, true);
+            }
+            // add and sub
+            0x3 | 0x6 => self.arithmetic(rd, &[rd_mask, rr_mask], false),
+            // cpse
+            0x4 if rd != rr && rd_mask & rr_mask != DEFINED => {
+                self.report(pc, UseKind::Branch, cycle);
+            }
+            // cp
+            0x5 => self.set_flags(ARITHMETIC_FLAGS, rd_mask & rr_mask == DEFINED),
+            // and, a defined zero defines the result bit
+            0x8 => {
+                let zeros = (rd_mask & !values[rd]) | (rr_mask & !values[rr]);
+                self.logic(rd, (rd_mask & rr_mask) | zeros);
+            }
+            // eor of a register with itself is zero
+            0x9 if rd == rr => self.logic(rd, DEFINED),
+            0x9 => self.logic(rd, rd_mask & rr_mask),
+            // or, a defined one defines the result bit
+            0xA => {
+                let ones = (rd_mask & values[rd]) | (rr_mask & values[rr]);
+                self.logic(rd, (rd_mask & rr_mask) | ones);
+            }
+            // mov
+            0xB..=0xF => self.registers[rd] = rr_mask,
+            MOVW_OP => {
+                self.registers[rd] = rr_mask;
+                self.registers[rd + 1] = self.registers[rr + 1];
+            }
+            MULS_OP | MULSU_OP | FMUL_OP | FMULS_OP | FMULSU_OP => {
+                let defined = rd_mask & rr_mask == DEFINED;
+                let mask = if defined { DEFINED } else { 0 };
+                self.registers[0] = mask;
+                self.registers[1] = mask;
+                self.set_flags(PRODUCT_FLAGS, defined);
+            }
+            _ => (),
+        }
+    }
+
+    fn execute_one_reg(&mut self, op: u8, rd: u8) {
+        let rd = usize::from(rd);
+        let mask = self.registers[rd];
+        match op {
+            // com, its carry is always set
+            0x0 => {
+                self.logic(rd, mask);
+                self.flags |= CARRY_FLAG;
+            }
+            // swap
+            0x2 => self.registers[rd] = mask.rotate_left(4),
+            // inc and dec
+            0x3 | 0xA => {
+                let carry = self.flags & CARRY_FLAG;
+                self.arithmetic(rd, &[mask], false);
+                self.flags = (self.flags & !CARRY_FLAG) | carry;
+            }
+            // ror
+            0x7 => self.arithmetic(rd, &[mask], true),
+            // neg, asr and lsr
+            0x1 | 0x5 | 0x6 => self.arithmetic(rd, &[mask], false),
+            // bset and bclr, `rd` holds the flag
+            0x8 => self.flags |= 1 << (rd & 0x7),
+            _ => (),
+        }
+    }
+
+    /// Result of an operation defined only if every input bit is, along
+    /// with its flags
+    fn arithmetic(&mut self, rd: usize, inputs: &[u8], uses_carry: bool) {
+        let carry = !uses_carry || self.flags & CARRY_FLAG != 0;
+        let defined = carry && inputs.iter().all(|mask| *mask == DEFINED);
+        self.registers[rd] = if defined { DEFINED } else { 0 };
+        self.set_flags(ARITHMETIC_FLAGS, defined);
+    }
+
+    /// Result of a bitwise operation, with flags defined if every result bit
+    /// is
+    fn logic(&mut self, rd: usize, mask: u8) {
+        self.registers[rd] = mask;
+        self.set_flags(LOGIC_FLAGS, mask == DEFINED);
+    }
+
+    fn set_flags(&mut self, flags: u8, defined: bool) {
+        if defined {
+            self.flags |= flags;
+        } else {
+            self.flags &= !flags;
+        }
+    }
+
+    /// Address held by `pointer`, reporting it if it's undefined
+    fn pointer(
+        &mut self,
+        pc: u16,
+        pointer: PointerRegister,
+        values: &[u8; 32],
+        cycle: usize,
+    ) -> u16 {
+        let low = match pointer {
+            PointerRegister::X => 26,
+            PointerRegister::Y => 28,
+            PointerRegister::Z => 30,
+        };
+        if self.registers[low] & self.registers[low + 1] != DEFINED {
+            self.report(pc, UseKind::Address, cycle);
+        }
+        u16::from_le_bytes([values[low], values[low + 1]])
+    }
+
+    fn transfer(&mut self, pc: u16, is_load: bool, address: u16, reg: u8, cycle: usize) {
+        if is_load {
+            self.registers[usize::from(reg)] = self.data_mask(address);
+        } else {
+            self.store(pc, address, self.registers[usize::from(reg)], cycle);
+        }
+    }
+
+    fn store(&mut self, pc: u16, address: u16, mask: u8, cycle: usize) {
+        let index = self.index(address);
+        self.data[index] = mask;
+        let is_io = matches!(self.regions[index], Region::Io | Region::ExtendedIo);
+        if is_io && mask != DEFINED {
+            let address = index as u16;
+            self.report(pc, UseKind::IoWrite { address }, cycle);
+        }
+    }
+
+    fn report(&mut self, pc: u16, kind: UseKind, cycle: usize) {
+        if self.reported.insert((pc, kind)) {
+            let uninitialized_use = UninitializedUse { pc, kind, cycle };
+            warn!("{}", uninitialized_use);
+            self.uses.push(uninitialized_use);
+        }
+    }
+
+    fn index(&self, address: u16) -> usize {
+        usize::from(address) % self.data.len()
+    }
+}
@@ -0,0 +1,239 @@
+use super::register_bank::Flags;
+use super::semihost::{Semihost, Stop};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+/// First bytes of a snapshot file
+const MAGIC: &[u8; 4] = b"AVSS";
+/// Snapshot format version, increment on incompatible changes
+const FORMAT_VERSION: u16 = 1;
+
+/// Section tags. Each section is stored as tag, little endian `u32` length
+/// and payload, so new state (like peripherals) can be added as sections
+/// that older readers skip.
+const REGISTERS_TAG: &[u8; 4] = b"REGS";
+const DATA_MEMORY_TAG: &[u8; 4] = b"DATA";
+const PROGRAM_MEMORY_TAG: &[u8; 4] = b"PROG";
+const CYCLES_TAG: &[u8; 4] = b"CYCL";
+const SLEEPING_TAG: &[u8; 4] = b"SLEP";
+const DEVICE_TAG: &[u8; 4] = b"DEVC";
+const SEMIHOST_TAG: &[u8; 4] = b"SEMI";
+const BREAKPOINTS_TAG: &[u8; 4] = b"BRKP";
+const WATCHPOINTS_TAG: &[u8; 4] = b"WTCH";
+const END_TAG: &[u8; 4] = b"END ";
+
+/// Whole machine state, taken with `Mcu::snapshot` and applied with
+/// `Mcu::restore`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub registers: [u8; 32],
+    pub program_counter: u16,
+    pub stack_pointer: u16,
+    pub flags: Flags,
+    pub data_memory: Vec<u8>,
+    pub program_memory: Vec<u8>,
+    pub cycle_count: u64,
+    /// `sleep` executed and no interrupt entered since
+    pub sleeping: bool,
+    /// Device model name, `None` for MCUs created with `Mcu::new`
+    pub device: Option<String>,
+    pub ramend: u16,
+    /// Debug I/O device, with its pending output and stop reason
+    pub semihost: Option<Semihost>,
+    pub breakpoints: Vec<u16>,
+    pub watchpoints: Vec<u16>,
+}
+
+impl Snapshot {
+    /// Saves snapshot into file `filename`
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(filename)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+
+    /// Loads a snapshot saved with `save`
+    pub fn load(filename: &str) -> io::Result<Snapshot> {
+        Snapshot::read_from(&mut BufReader::new(File::open(filename)?))
+    }
+
+    /// Writes the `AVSS` magic, format version and state sections
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        let mut registers = self.registers.to_vec();
+        registers.extend_from_slice(&self.program_counter.to_le_bytes());
+        registers.extend_from_slice(&self.stack_pointer.to_le_bytes());
+        registers.push(self.flags.into());
+        write_section(out, REGISTERS_TAG, &registers)?;
+        write_section(out, DATA_MEMORY_TAG, &self.data_memory)?;
+        write_section(out, PROGRAM_MEMORY_TAG, &self.program_memory)?;
+        write_section(out, CYCLES_TAG, &self.cycle_count.to_le_bytes())?;
+        write_section(out, SLEEPING_TAG, &[u8::from(self.sleeping)])?;
+        let mut device = self.ramend.to_le_bytes().to_vec();
+        device.extend_from_slice(self.device.as_deref().unwrap_or_default().as_bytes());
+        write_section(out, DEVICE_TAG, &device)?;
+        if let Some(semihost) = &self.semihost {
+            write_section(out, SEMIHOST_TAG, &encode_semihost(semihost))?;
+        }
+        write_section(out, BREAKPOINTS_TAG, &encode_addresses(&self.breakpoints))?;
+        write_section(out, WATCHPOINTS_TAG, &encode_addresses(&self.watchpoints))?;
+        write_section(out, END_TAG, &[])
+    }
+
+    /// Reads a snapshot written by `write_to`
+    pub fn read_from(input: &mut impl Read) -> io::Result<Snapshot> {
+        let mut header = [0; 6];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid_data("Not a snapshot file"));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(&format!(
+                "Unsupported snapshot version {}",
+                version
+            )));
+        }
+        let mut registers = None;
+        let mut data_memory = None;
+        let mut program_memory = None;
+        let mut cycle_count = None;
+        let mut sleeping = None;
+        let mut device = None;
+        let mut semihost = None;
+        let mut breakpoints = None;
+        let mut watchpoints = None;
+        loop {
+            let (tag, payload) = read_section(input)?;
+            match &tag {
+                REGISTERS_TAG => registers = Some(payload),
+                DATA_MEMORY_TAG => data_memory = Some(payload),
+                PROGRAM_MEMORY_TAG => program_memory = Some(payload),
+                CYCLES_TAG => {
+                    let bytes = payload.try_into().map_err(|_| invalid_data("Bad CYCL"))?;
+                    cycle_count = Some(u64::from_le_bytes(bytes))
+                }
+                SLEEPING_TAG => match payload[..] {
+                    [value] => sleeping = Some(value != 0),
+                    _ => return Err(invalid_data("Bad SLEP")),
+                },
+                DEVICE_TAG => device = Some(decode_device(&payload)?),
+                SEMIHOST_TAG => semihost = Some(decode_semihost(&payload)?),
+                BREAKPOINTS_TAG => breakpoints = Some(decode_addresses(&payload, "BRKP")?),
+                WATCHPOINTS_TAG => watchpoints = Some(decode_addresses(&payload, "WTCH")?),
+                END_TAG => break,
+                // Sections added after this reader
+                _ => {}
+            }
+        }
+        let registers = registers.ok_or_else(|| invalid_data("Missing REGS section"))?;
+        if registers.len() != 37 {
+            return Err(invalid_data("Bad REGS section"));
+        }
+        let (ramend, device) = device.ok_or_else(|| invalid_data("Missing DEVC section"))?;
+        let mut register_array = [0; 32];
+        register_array.copy_from_slice(&registers[..32]);
+        Ok(Snapshot {
+            registers: register_array,
+            program_counter: u16::from_le_bytes([registers[32], registers[33]]),
+            stack_pointer: u16::from_le_bytes([registers[34], registers[35]]),
+            flags: Flags::from(registers[36]),
+            data_memory: data_memory.ok_or_else(|| invalid_data("Missing DATA section"))?,
+            program_memory: program_memory.ok_or_else(|| invalid_data("Missing PROG section"))?,
+            cycle_count: cycle_count.ok_or_else(|| invalid_data("Missing CYCL section"))?,
+            sleeping: sleeping.ok_or_else(|| invalid_data("Missing SLEP section"))?,
+            device,
+            ramend,
+            semihost,
+            breakpoints: breakpoints.ok_or_else(|| invalid_data("Missing BRKP section"))?,
+            watchpoints: watchpoints.ok_or_else(|| invalid_data("Missing WTCH section"))?,
+        })
+    }
+}
+
+/// `DEVC` payload: `RAMEND` and the device name, empty if unknown
+fn decode_device(payload: &[u8]) -> io::Result<(u16, Option<String>)> {
+    if payload.len() < 2 {
+        return Err(invalid_data("Bad DEVC"));
+    }
+    let name = String::from_utf8(payload[2..].to_vec()).map_err(|_| invalid_data("Bad DEVC"))?;
+    let ramend = u16::from_le_bytes([payload[0], payload[1]]);
+    Ok((ramend, Some(name).filter(|name| !name.is_empty())))
+}
+
+/// `SEMI` payload: address, stop kind (0 none, 1 exit, 2 trap), stop value
+/// and pending output
+fn encode_semihost(semihost: &Semihost) -> Vec<u8> {
+    let mut payload = semihost.get_address().to_le_bytes().to_vec();
+    let (kind, value) = match semihost.get_stop() {
+        None => (0, 0),
+        Some(Stop::Exit(code)) => (1, u16::from(code)),
+        Some(Stop::Trap(pc)) => (2, pc),
+    };
+    payload.push(kind);
+    payload.extend_from_slice(&value.to_le_bytes());
+    payload.extend_from_slice(semihost.get_output());
+    payload
+}
+
+fn decode_semihost(payload: &[u8]) -> io::Result<Semihost> {
+    if payload.len() < 5 {
+        return Err(invalid_data("Bad SEMI"));
+    }
+    let value = u16::from_le_bytes([payload[3], payload[4]]);
+    let stop = match payload[2] {
+        0 => None,
+        1 => Some(Stop::Exit(value as u8)),
+        2 => Some(Stop::Trap(value)),
+        _ => return Err(invalid_data("Bad SEMI")),
+    };
+    let address = u16::from_le_bytes([payload[0], payload[1]]);
+    Ok(Semihost::restored(address, payload[5..].to_vec(), stop))
+}
+
+fn encode_addresses(addresses: &[u16]) -> Vec<u8> {
+    addresses
+        .iter()
+        .flat_map(|address| address.to_le_bytes())
+        .collect()
+}
+
+fn decode_addresses(payload: &[u8], tag: &str) -> io::Result<Vec<u16>> {
+    if !payload.len().is_multiple_of(2) {
+        return Err(invalid_data(&format!("Bad {}", tag)));
+    }
+    Ok(payload
+        .chunks_exact(2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect())
+}
+
+fn write_section(out: &mut impl Write, tag: &[u8; 4], payload: &[u8]) -> io::Result<()> {
+    out.write_all(tag)?;
+    out.write_all(&(payload.len() as u32).to_le_bytes())?;
+    out.write_all(payload)
+}
+
+fn read_section(input: &mut impl Read) -> io::Result<([u8; 4], Vec<u8>)> {
+    let mut header = [0; 8];
+    input.read_exact(&mut header)?;
+    let mut tag = [0; 4];
+    tag.copy_from_slice(&header[..4]);
+    let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    let mut payload = Vec::new();
+    input.take(u64::from(length)).read_to_end(&mut payload)?;
+    if payload.len() != length as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Truncated snapshot",
+        ));
+    }
+    Ok((tag, payload))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,303 @@
+use super::symbols::{SymbolTable, DATA_SPACE_OFFSET};
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+
+/// Name of the context not running a task or an interrupt
+const MAIN_CONTEXT: &str = "main";
+
+/// What happens when the stack descends into static data or the heap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackPolicy {
+    /// Collisions are logged and recorded, the firmware keeps running
+    Warn,
+    /// The first collision is also returned by `StackMonitor::get_fault`,
+    /// so runners stop
+    Fault,
+}
+
+/// Memory overwritten by the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackRegion {
+    /// `.data` and `.bss`, below `__heap_start` or `__bss_end`
+    StaticData,
+    /// The malloc heap, below `__brkval`
+    Heap,
+}
+
+impl fmt::Display for StackRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackRegion::StaticData => write!(f, "static data"),
+            StackRegion::Heap => write!(f, "heap"),
+        }
+    }
+}
+
+/// The stack grew into another region
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackCollision {
+    /// Address of the instruction that grew the stack
+    pub pc: u16,
+    pub stack_pointer: u16,
+    /// First address above the overwritten region
+    pub limit: u16,
+    pub region: StackRegion,
+    pub cycle: usize,
+}
+
+impl fmt::Display for StackCollision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Stack overflow into {} at 0x{:04x}: SP 0x{:04x}, limit 0x{:04x}, cycle {}",
+            self.region, self.pc, self.stack_pointer, self.limit, self.cycle
+        )
+    }
+}
+
+/// Stack usage of a context: the main program, a task or an interrupt
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackUsage {
+    pub name: String,
+    /// Lowest stack pointer reached
+    pub lowest_stack_pointer: u16,
+    /// Most bytes used at once
+    pub high_water: u16,
+}
+
+struct Context {
+    name: String,
+    /// Stack pointer when the context had its stack empty
+    top: u16,
+    lowest_stack_pointer: u16,
+}
+
+impl Context {
+    fn new(name: String, top: u16) -> Context {
+        Context {
+            name,
+            top,
+            lowest_stack_pointer: top,
+        }
+    }
+}
+
+/// Stack high-water marks and stack/heap collision detection, updated by
+/// `Mcu::step`.
+///
+/// Static data ends at `__heap_start`, or `__bss_end`, and the heap at the
+/// value of `__brkval` once malloc used it, all avr-libc symbols. Every time
+/// the stack grows, the stack pointer is checked against both, and the first
+/// instruction of each excursion into them is recorded as a
+/// `StackCollision`.
+///
+/// The lowest stack pointer is also tracked per context. Interrupts, found
+/// in the call stack if one is kept, are measured from their entry. Tasks
+/// are registered with `add_task` and found by their stack range. Anything
+/// else is `main`, measured from `__stack`.
+pub struct StackMonitor {
+    policy: StackPolicy,
+    symbols: SymbolTable,
+    static_end: Option<u16>,
+    brkval: Option<u16>,
+    stack_pointer: Option<u16>,
+    colliding: bool,
+    collisions: Vec<StackCollision>,
+    contexts: Vec<Context>,
+    tasks: Vec<(Range<u16>, usize)>,
+}
+
+impl StackMonitor {
+    /// Creates a monitor finding the heap and static data with `symbols`,
+    /// usually read from an ELF file. The main stack starts at `__stack`, or
+    /// at `ramend` if there is no such symbol.
+    pub fn new(symbols: SymbolTable, ramend: u16, policy: StackPolicy) -> StackMonitor {
+        let static_end =
+            data_symbol(&symbols, "__heap_start").or_else(|| data_symbol(&symbols, "__bss_end"));
+        let brkval = data_symbol(&symbols, "__brkval");
+        let top = data_symbol(&symbols, "__stack").unwrap_or(ramend);
+        StackMonitor {
+            policy,
+            symbols,
+            static_end,
+            brkval,
+            stack_pointer: None,
+            colliding: false,
+            collisions: Vec::new(),
+            contexts: vec![Context::new(MAIN_CONTEXT.to_owned(), top)],
+            tasks: Vec::new(),
+        }
+    }
+
+    pub fn get_policy(&self) -> StackPolicy {
+        self.policy
+    }
+
+    /// End of `.data` and `.bss`, if the symbols have it
+    pub fn get_static_end(&self) -> Option<u16> {
+        self.static_end
+    }
+
+    /// Address of the `__brkval` variable, read by `Mcu::step` to find the
+    /// end of the heap
+    pub fn get_brkval_address(&self) -> Option<u16> {
+        self.brkval
+    }
+
+    /// Registers task `name`, whose stack takes `stack` addresses
+    pub fn add_task(&mut self, name: &str, stack: Range<u16>) {
+        let id = self.contexts.len();
+        self.contexts
+            .push(Context::new(name.to_owned(), stack.end.wrapping_sub(1)));
+        self.tasks.push((stack, id));
+    }
+
+    /// Collisions recorded so far, the first instruction of each excursion
+    pub fn collisions(&self) -> &[StackCollision] {
+        &self.collisions
+    }
+
+    /// Returns and forgets the recorded collisions
+    pub fn take_collisions(&mut self) -> Vec<StackCollision> {
+        std::mem::take(&mut self.collisions)
+    }
+
+    /// First collision, if the policy is `StackPolicy::Fault`
+    pub fn get_fault(&self) -> Option<&StackCollision> {
+        match self.policy {
+            StackPolicy::Fault => self.collisions.first(),
+            StackPolicy::Warn => None,
+        }
+    }
+
+    /// Stack usage of every context that ran, `main` first
+    pub fn usage(&self) -> Vec<StackUsage> {
+        self.contexts
+            .iter()
+            .filter(|context| {
+                context.name == MAIN_CONTEXT || context.lowest_stack_pointer < context.top
+            })
+            .map(|context| StackUsage {
+                name: context.name.clone(),
+                lowest_stack_pointer: context.lowest_stack_pointer,
+                high_water: context.top.wrapping_sub(context.lowest_stack_pointer),
+            })
+            .collect()
+    }
+
+    /// Writes the stack usage, a context per line
+    pub fn write_report(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{:>10} {:>10}  context", "lowest SP", "high-water")?;
+        for usage in self.usage() {
+            writeln!(
+                out,
+                "{:>10} {:>10}  {}",
+                format!("0x{:04x}", usage.lowest_stack_pointer),
+                usage.high_water,
+                usage.name
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Records the stack pointer left by the instruction at `pc`. `heap_end`
+    /// is the value of `__brkval`, and `interrupt` the vector and entry stack
+    /// pointer of the innermost interrupt being served.
+    pub(crate) fn record(
+        &mut self,
+        pc: u16,
+        stack_pointer: u16,
+        heap_end: Option<u16>,
+        interrupt: Option<(u16, u16)>,
+        cycle: usize,
+    ) {
+        let grew = self
+            .stack_pointer
+            .is_some_and(|previous| stack_pointer < previous);
+        self.stack_pointer = Some(stack_pointer);
+        if !grew {
+            if self.collision(stack_pointer, heap_end).is_none() {
+                self.colliding = false;
+            }
+            return;
+        }
+        let context = match interrupt {
+            Some((vector, entry)) => self.interrupt_context(vector, entry),
+            None => self.task_context(stack_pointer),
+        };
+        let context = &mut self.contexts[context];
+        context.lowest_stack_pointer = context.lowest_stack_pointer.min(stack_pointer);
+
+        match self.collision(stack_pointer, heap_end) {
+            Some((limit, region)) if !self.colliding => {
+                self.colliding = true;
+                let collision = StackCollision {
+                    pc,
+                    stack_pointer,
+                    limit,
+                    region,
+                    cycle,
+                };
+                warn!("{}", collision);
+                self.collisions.push(collision);
+            }
+            Some(_) => (),
+            None => self.colliding = false,
+        }
+    }
+
+    /// Region the stack pointer is in, with its end
+    fn collision(&self, stack_pointer: u16, heap_end: Option<u16>) -> Option<(u16, StackRegion)> {
+        let static_end = self.static_end?;
+        // The stack pointer is the first free byte, so it may be at the end
+        if stack_pointer < static_end.wrapping_sub(1) {
+            return Some((static_end, StackRegion::StaticData));
+        }
+        match heap_end {
+            Some(heap_end) if heap_end != 0 && stack_pointer < heap_end.wrapping_sub(1) => {
+                Some((heap_end, StackRegion::Heap))
+            }
+            _ => None,
+        }
+    }
+
+    fn task_context(&self, stack_pointer: u16) -> usize {
+        self.tasks
+            .iter()
+            .find(|(stack, _)| stack.contains(&stack_pointer))
+            .map_or(0, |(_, id)| *id)
+    }
+
+    fn interrupt_context(&mut self, vector: u16, entry: u16) -> usize {
+        let name = match self.symbols.get(u32::from(vector)) {
+            Some(symbol) => symbol.name.clone(),
+            None => format!("interrupt 0x{:04x}", vector),
+        };
+        match self
+            .contexts
+            .iter()
+            .position(|context| context.name == name)
+        {
+            Some(id) => {
+                // Measured from the highest entry
+                let context = &mut self.contexts[id];
+                context.top = context.top.max(entry);
+                id
+            }
+            None => {
+                self.contexts.push(Context::new(name, entry));
+                self.contexts.len() - 1
+            }
+        }
+    }
+}
+
+/// Data memory address of symbol `name`
+fn data_symbol(symbols: &SymbolTable, name: &str) -> Option<u16> {
+    symbols
+        .by_name(name)
+        .filter(|symbol| symbol.address >= DATA_SPACE_OFFSET)
+        .map(|symbol| (symbol.address - DATA_SPACE_OFFSET) as u16)
+}
@@ -0,0 +1,126 @@
+/// Base address of data memory symbols in avr-gcc output
+pub const DATA_SPACE_OFFSET: u32 = 0x0080_0000;
+/// Base address of EEPROM symbols in avr-gcc output
+pub const EEPROM_SPACE_OFFSET: u32 = 0x0081_0000;
+
+/// A named address, as listed by `avr-nm` or an ELF symbol table.
+///
+/// Addresses follow avr-gcc conventions: program memory symbols are byte
+/// addresses starting at 0, while data memory symbols are offset by
+/// `DATA_SPACE_OFFSET`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    pub size: u32,
+    pub is_global: bool,
+}
+
+/// Symbols sorted by address
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable {
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Parses the output of `avr-nm` (`<address> <type> <name>` lines).
+    /// Lines without an address (undefined symbols) are ignored.
+    pub fn from_nm(text: &str) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        for line in text.lines() {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 3 {
+                continue;
+            }
+            let (address, size, kind, name) = if fields.len() >= 4 {
+                // `avr-nm -S` output includes symbol size
+                (fields[0], fields[1], fields[2], fields[3])
+            } else {
+                (fields[0], "0", fields[1], fields[2])
+            };
+            if let (Ok(address), Ok(size)) = (
+                u32::from_str_radix(address, 16),
+                u32::from_str_radix(size, 16),
+            ) {
+                let is_global = kind.chars().all(|c| c.is_ascii_uppercase());
+                table.add(Symbol {
+                    name: name.to_owned(),
+                    address,
+                    size,
+                    is_global,
+                });
+            }
+        }
+        table
+    }
+
+    /// Adds a symbol keeping the table sorted. If another symbol has the same
+    /// address, global symbols are placed first.
+    pub fn add(&mut self, symbol: Symbol) {
+        let position = self.symbols.partition_point(|other| {
+            other.address < symbol.address
+                || (other.address == symbol.address && (other.is_global || !symbol.is_global))
+        });
+        self.symbols.insert(position, symbol);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Symbol> {
+        self.symbols.iter()
+    }
+
+    /// Returns the preferred symbol placed exactly at `address`
+    pub fn get(&self, address: u32) -> Option<&Symbol> {
+        let position = self
+            .symbols
+            .partition_point(|other| other.address < address);
+        self.symbols
+            .get(position)
+            .filter(|symbol| symbol.address == address)
+    }
+
+    /// Returns a symbol by its name
+    pub fn by_name(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Finds the closest symbol at or before `address` in the same address
+    /// space, returning it along with the offset from its start
+    pub fn lookup(&self, address: u32) -> Option<(&Symbol, u32)> {
+        let space = address_space(address);
+        let end = self
+            .symbols
+            .partition_point(|other| other.address <= address);
+        let closest = self.symbols[..end]
+            .iter()
+            .rev()
+            .find(|symbol| address_space(symbol.address) == space)?;
+        // Prefer the first (global) symbol placed at that same address
+        let symbol = self.get(closest.address).unwrap_or(closest);
+        Some((symbol, address - symbol.address))
+    }
+}
+
+/// 0 for program memory, 1 for data memory and 2 for EEPROM
+fn address_space(address: u32) -> u8 {
+    if address >= EEPROM_SPACE_OFFSET {
+        2
+    } else if address >= DATA_SPACE_OFFSET {
+        1
+    } else {
+        0
+    }
+}
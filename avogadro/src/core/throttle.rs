@@ -0,0 +1,159 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How far execution may run ahead of the wall clock before waiting
+const SLICE: Duration = Duration::from_millis(1);
+/// Lag after which the schedule restarts instead of catching up, like after
+/// the host stalled
+const MAX_LAG: Duration = Duration::from_millis(100);
+/// Wall time over which the achieved speed is measured
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Paces execution to wall-clock time, at a multiple of the MCU clock.
+///
+/// Instructions run at full speed in slices of about a millisecond, and the
+/// wait after each slice is computed from the start of the run instead of
+/// the end of the previous wait, so a late wake-up is made up by the next
+/// slices instead of adding up. Simulated time is still the cycle count:
+/// pacing only delays instructions, so anything timed in cycles (baud
+/// rates, timers, VCD timestamps) keeps its timing at every speed.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    clock_hz: u64,
+    multiplier: Option<f64>,
+    /// Wall time and cycle count the schedule started at
+    start: Instant,
+    start_cycle: usize,
+    /// Wall time and cycle count the speed measurement started at
+    report_start: Instant,
+    report_cycle: usize,
+    achieved_hz: f64,
+}
+
+impl Throttle {
+    /// Paces a `clock_hz` clock at `multiplier` times real time, like 1.0 or
+    /// 0.1. Runs as fast as possible if `multiplier` is `None`, or not a
+    /// positive number.
+    pub fn new(clock_hz: u64, multiplier: Option<f64>) -> Throttle {
+        let now = Instant::now();
+        Throttle {
+            clock_hz: clock_hz.max(1),
+            multiplier: valid_multiplier(multiplier),
+            start: now,
+            start_cycle: 0,
+            report_start: now,
+            report_cycle: 0,
+            achieved_hz: 0.0,
+        }
+    }
+
+    pub fn get_clock_hz(&self) -> u64 {
+        self.clock_hz
+    }
+
+    /// Changes the clock paced, from the next `reset`
+    pub fn set_clock_hz(&mut self, clock_hz: u64) {
+        self.clock_hz = clock_hz.max(1);
+    }
+
+    pub fn get_multiplier(&self) -> Option<f64> {
+        self.multiplier
+    }
+
+    /// Changes the speed, from the next `reset`
+    pub fn set_multiplier(&mut self, multiplier: Option<f64>) {
+        self.multiplier = valid_multiplier(multiplier);
+    }
+
+    /// Restarts the schedule and the speed measurement at `cycle`, like
+    /// when starting to run after a pause
+    pub fn reset(&mut self, cycle: usize, now: Instant) {
+        self.start = now;
+        self.start_cycle = cycle;
+        self.report_start = now;
+        self.report_cycle = cycle;
+        self.achieved_hz = 0.0;
+    }
+
+    /// Cycle count execution may run up to at `now`, the MCU being at
+    /// `cycle`. `usize::MAX` if unthrottled.
+    pub fn cycle_limit(&mut self, cycle: usize, now: Instant) -> usize {
+        let rate = match self.rate() {
+            Some(rate) => rate,
+            None => return usize::MAX,
+        };
+        let lag = self.due_time(cycle).map_or(Duration::ZERO, |due_time| {
+            now.saturating_duration_since(due_time)
+        });
+        if lag > MAX_LAG {
+            self.start = now;
+            self.start_cycle = cycle;
+        }
+        let ahead = (now + SLICE).saturating_duration_since(self.start);
+        self.start_cycle
+            .saturating_add((ahead.as_secs_f64() * rate) as usize)
+    }
+
+    /// Wall time to wait at `now` before running cycle `cycle`
+    pub fn delay(&self, cycle: usize, now: Instant) -> Duration {
+        match self.rate() {
+            Some(_) => self.due_time(cycle).map_or(Duration::MAX, |due_time| {
+                due_time.saturating_duration_since(now)
+            }),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Sleeps until cycle `cycle` is due. Returns the cycle limit to run up
+    /// to afterwards.
+    pub fn pace(&mut self, cycle: usize) -> usize {
+        let delay = self.delay(cycle, Instant::now());
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        let now = Instant::now();
+        self.record(cycle, now);
+        self.cycle_limit(cycle, now)
+    }
+
+    /// Updates the achieved speed with the MCU at `cycle`
+    pub fn record(&mut self, cycle: usize, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.report_start);
+        if elapsed >= REPORT_INTERVAL {
+            let cycles = cycle.saturating_sub(self.report_cycle);
+            self.achieved_hz = cycles as f64 / elapsed.as_secs_f64();
+            self.report_start = now;
+            self.report_cycle = cycle;
+        }
+    }
+
+    /// Simulated clock cycles per second, measured over the last half
+    /// second. 0 until measured.
+    pub fn achieved_hz(&self) -> f64 {
+        self.achieved_hz
+    }
+
+    /// Achieved speed as a multiple of real time
+    pub fn achieved_multiplier(&self) -> f64 {
+        self.achieved_hz / self.clock_hz as f64
+    }
+
+    /// Cycles per second of wall time, if throttled
+    fn rate(&self) -> Option<f64> {
+        self.multiplier
+            .map(|multiplier| self.clock_hz as f64 * multiplier)
+    }
+
+    /// Wall time at which `cycle` is due, `None` if too far to be
+    /// represented
+    fn due_time(&self, cycle: usize) -> Option<Instant> {
+        let rate = self.rate().unwrap_or(f64::INFINITY);
+        let cycles = cycle.saturating_sub(self.start_cycle);
+        let delay = Duration::try_from_secs_f64(cycles as f64 / rate).ok()?;
+        self.start.checked_add(delay)
+    }
+}
+
+fn valid_multiplier(multiplier: Option<f64>) -> Option<f64> {
+    multiplier.filter(|multiplier| multiplier.is_finite() && *multiplier > 0.0)
+}
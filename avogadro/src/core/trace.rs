@@ -0,0 +1,235 @@
+use super::decoder::Decoder;
+use std::collections::vec_deque;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::ops::RangeInclusive;
+
+/// First bytes of a binary trace file
+const MAGIC: &[u8; 4] = b"AVTR";
+/// Binary trace format version, increment on incompatible changes
+const FORMAT_VERSION: u8 = 1;
+/// SREG bit names, from bit 7 to bit 0
+const SREG_BITS: &[u8; 8] = b"ITHSVNZC";
+
+/// State changes made by one executed instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    /// Cycle count before the instruction was executed
+    pub cycle: u64,
+    pub pc: u16,
+    /// First word of the instruction
+    pub raw_instruction: u16,
+    /// Changed registers, with their new values
+    pub registers: Vec<(u8, u8)>,
+    pub sreg_before: u8,
+    pub sreg_after: u8,
+    /// Stack pointer after execution
+    pub stack_pointer: u16,
+    /// Data memory writes (address and value), in order
+    pub memory_writes: Vec<(u16, u8)>,
+}
+
+impl TraceEntry {
+    /// Decoded instruction, as displayed in the GUI
+    pub fn disassembly(&self) -> String {
+        Decoder::decode(self.raw_instruction).to_string()
+    }
+
+    fn write_binary(&self, out: &mut impl io::Write) -> io::Result<()> {
+        out.write_all(&self.cycle.to_le_bytes())?;
+        out.write_all(&self.pc.to_le_bytes())?;
+        out.write_all(&self.raw_instruction.to_le_bytes())?;
+        out.write_all(&[self.sreg_before, self.sreg_after])?;
+        out.write_all(&self.stack_pointer.to_le_bytes())?;
+        out.write_all(&[self.registers.len() as u8])?;
+        for (register, value) in &self.registers {
+            out.write_all(&[*register, *value])?;
+        }
+        out.write_all(&(self.memory_writes.len() as u16).to_le_bytes())?;
+        for (address, value) in &self.memory_writes {
+            out.write_all(&address.to_le_bytes())?;
+            out.write_all(&[*value])?;
+        }
+        Ok(())
+    }
+
+    /// Reads an entry, returns `None` at the end of the input
+    fn read_binary(input: &mut impl io::Read) -> io::Result<Option<TraceEntry>> {
+        let mut cycle = [0; 8];
+        match input.read_exact(&mut cycle) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let mut header = [0; 9];
+        input.read_exact(&mut header)?;
+        let mut registers = vec![(0, 0); header[8] as usize];
+        for register in &mut registers {
+            let mut buffer = [0; 2];
+            input.read_exact(&mut buffer)?;
+            *register = (buffer[0], buffer[1]);
+        }
+        let mut count = [0; 2];
+        input.read_exact(&mut count)?;
+        let mut memory_writes = vec![(0, 0); u16::from_le_bytes(count) as usize];
+        for write in &mut memory_writes {
+            let mut buffer = [0; 3];
+            input.read_exact(&mut buffer)?;
+            *write = (u16::from_le_bytes([buffer[0], buffer[1]]), buffer[2]);
+        }
+        Ok(Some(TraceEntry {
+            cycle: u64::from_le_bytes(cycle),
+            pc: u16::from_le_bytes([header[0], header[1]]),
+            raw_instruction: u16::from_le_bytes([header[2], header[3]]),
+            registers,
+            sreg_before: header[4],
+            sreg_after: header[5],
+            stack_pointer: u16::from_le_bytes([header[6], header[7]]),
+            memory_writes,
+        }))
+    }
+}
+
+/// One line per instruction, like
+/// `      12  0036: b388  in r24, 0x18           r24=01 SREG=......Z.->........ SP=025f [0060]=12`
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let disassembly = self.disassembly().replace('\t', " ");
+        write!(
+            f,
+            "{:>8}  {:04x}: {:04x}  {:<24}",
+            self.cycle, self.pc, self.raw_instruction, disassembly
+        )?;
+        for (register, value) in &self.registers {
+            write!(f, " r{}={:02x}", register, value)?;
+        }
+        if self.sreg_before != self.sreg_after {
+            write!(
+                f,
+                " SREG={}->{}",
+                display_sreg(self.sreg_before),
+                display_sreg(self.sreg_after)
+            )?;
+        }
+        write!(f, " SP={:04x}", self.stack_pointer)?;
+        for (address, value) in &self.memory_writes {
+            write!(f, " [{:04x}]={:02x}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn display_sreg(sreg: u8) -> String {
+    SREG_BITS
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            if sreg & (0x80 >> index) != 0 {
+                *name as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+/// Records executed instructions. By default every instruction is kept, a
+/// ring buffer tracer keeps only the last ones, which is useful to know how
+/// the program reached some state.
+#[derive(Debug, Clone, Default)]
+pub struct Tracer {
+    entries: VecDeque<TraceEntry>,
+    capacity: Option<usize>,
+    address_ranges: Vec<RangeInclusive<u16>>,
+}
+
+impl Tracer {
+    /// Creates a tracer that keeps every instruction
+    pub fn new() -> Tracer {
+        Tracer::default()
+    }
+
+    /// Creates a tracer that keeps only the last `capacity` instructions
+    pub fn ring_buffer(capacity: usize) -> Tracer {
+        Tracer {
+            capacity: Some(capacity),
+            ..Tracer::default()
+        }
+    }
+
+    /// Only trace instructions with program address between `start` and
+    /// `end` (both included). Can be called many times to trace several ranges.
+    pub fn add_address_range(&mut self, start: u16, end: u16) {
+        self.address_ranges.push(start..=end);
+    }
+
+    /// Returns true if an instruction at `pc` passes the address filters
+    pub fn is_traced(&self, pc: u16) -> bool {
+        self.address_ranges.is_empty() || self.address_ranges.iter().any(|r| r.contains(&pc))
+    }
+
+    pub fn record(&mut self, entry: TraceEntry) {
+        if let Some(capacity) = self.capacity {
+            if capacity == 0 {
+                return;
+            }
+            if self.entries.len() == capacity {
+                self.entries.pop_front();
+            }
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Recorded entries, oldest first
+    pub fn entries(&self) -> vec_deque::Iter<'_, TraceEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Writes the human readable trace, one instruction per line
+    pub fn write_text(&self, out: &mut impl io::Write) -> io::Result<()> {
+        for entry in &self.entries {
+            writeln!(out, "{}", entry)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the compact binary trace: a `AVTR` magic and version byte,
+    /// followed by entries with little endian fields
+    pub fn write_binary(&self, out: &mut impl io::Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[FORMAT_VERSION])?;
+        for entry in &self.entries {
+            entry.write_binary(out)?;
+        }
+        Ok(())
+    }
+
+    /// Reads entries written by `write_binary`
+    pub fn read_binary(input: &mut impl io::Read) -> io::Result<Vec<TraceEntry>> {
+        let mut header = [0; 5];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a trace file or unsupported version",
+            ));
+        }
+        let mut entries = Vec::new();
+        while let Some(entry) = TraceEntry::read_binary(input)? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
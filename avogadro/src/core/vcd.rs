@@ -0,0 +1,284 @@
+use super::device::Port;
+use super::mcu::Mcu;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+/// SREG bit names, from bit 0 to bit 7
+const SREG_BITS: [&str; 8] = ["C", "Z", "N", "V", "S", "H", "T", "I"];
+
+/// A value sampled from the MCU after each instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Probe {
+    /// GPIO pin level. `port` is the `PORTx` data memory address: output pins
+    /// (`DDRx` bit set) follow `PORTx`, input pins follow `PINx`.
+    Pin {
+        port: u16,
+        bit: u8,
+    },
+    /// Data memory byte, including I/O registers like `PORTB`
+    DataByte(u16),
+    /// Little endian data memory word, like a 16 bit variable
+    DataWord(u16),
+    /// SREG bit, 0 (carry) to 7 (interrupt)
+    SregBit(u8),
+    ProgramCounter,
+}
+
+impl Probe {
+    /// Parses a single probe of `VcdWriter::add_probe_spec`, like `PB3` or
+    /// `word:0x60`, on a device with `ports`, like `Mcu::get_ports`
+    pub fn parse(spec: &str, ports: &[Port]) -> Option<Probe> {
+        parse_probe(&spec.trim().to_ascii_uppercase(), ports)
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            Probe::Pin { .. } | Probe::SregBit(_) => 1,
+            Probe::DataByte(_) => 8,
+            Probe::DataWord(_) | Probe::ProgramCounter => 16,
+        }
+    }
+
+    /// Reads the probed value from `mcu`
+    pub fn sample(&self, mcu: &Mcu) -> u16 {
+        match *self {
+            Probe::Pin { port, bit } => {
+                let is_output = mcu.get_data_byte(port - 1) & (1 << bit) != 0;
+                let register = if is_output { port } else { port - 2 };
+                u16::from(mcu.get_data_byte(register) >> bit & 1)
+            }
+            Probe::DataByte(address) => u16::from(mcu.get_data_byte(address)),
+            Probe::DataWord(address) => u16::from_le_bytes([
+                mcu.get_data_byte(address),
+                mcu.get_data_byte(address.wrapping_add(1)),
+            ]),
+            Probe::SregBit(bit) => u16::from(u8::from(mcu.get_flags()) >> bit & 1),
+            Probe::ProgramCounter => mcu.get_program_counter(),
+        }
+    }
+}
+
+struct Signal {
+    name: String,
+    probe: Probe,
+    code: String,
+    last_value: Option<u16>,
+}
+
+/// Writes Value Change Dump files, viewable with GTKWave. Probes are sampled
+/// after each instruction and timestamps are taken from the cycle counter.
+pub struct VcdWriter {
+    out: Box<dyn Write + Send>,
+    clock_hz: u64,
+    signals: Vec<Signal>,
+    header_written: bool,
+    last_time: Option<u64>,
+    error: Option<io::Error>,
+}
+
+impl VcdWriter {
+    /// Creates a writer for a MCU running at `clock_hz`, used to convert
+    /// cycles into time.
+    pub fn new(out: Box<dyn Write + Send>, clock_hz: u64) -> VcdWriter {
+        VcdWriter {
+            out,
+            clock_hz: clock_hz.max(1),
+            signals: Vec::new(),
+            header_written: false,
+            last_time: None,
+            error: None,
+        }
+    }
+
+    /// Creates a writer into file `filename`
+    pub fn create(filename: &str, clock_hz: u64) -> io::Result<VcdWriter> {
+        let file = File::create(filename)?;
+        Ok(VcdWriter::new(Box::new(BufWriter::new(file)), clock_hz))
+    }
+
+    /// Adds a traced value. Probes must be added before the first sample.
+    pub fn add_probe(&mut self, name: &str, probe: Probe) -> Result<(), String> {
+        if self.header_written {
+            return Err("Probes must be added before sampling".to_owned());
+        }
+        let code = identifier_code(self.signals.len());
+        self.signals.push(Signal {
+            name: name.to_owned(),
+            probe,
+            code,
+            last_value: None,
+        });
+        Ok(())
+    }
+
+    /// Adds probes from a text description:
+    /// * `pc`: program counter
+    /// * `sreg`: every SREG bit, or `sreg.Z` for a single one
+    /// * `PB3`: pin 3 of port B
+    /// * `PORTB`, `DDRB` or `PINB`: I/O register of a port
+    /// * `0x60` or `byte:0x60`: data memory byte, `word:0x60` for a word
+    ///
+    /// A name can be given with `name=spec`, like `counter=word:0x60`. Ports
+    /// are the ones of the device, `ports`, like `Mcu::get_ports`.
+    pub fn add_probe_spec(&mut self, spec: &str, ports: &[Port]) -> Result<(), String> {
+        let (name, spec) = match spec.split_once('=') {
+            Some((name, spec)) => (Some(name.trim()), spec.trim()),
+            None => (None, spec.trim()),
+        };
+        let upper = spec.to_ascii_uppercase();
+        if upper == "SREG" && name.is_none() {
+            for (bit, bit_name) in SREG_BITS.iter().enumerate() {
+                self.add_probe(&format!("SREG_{}", bit_name), Probe::SregBit(bit as u8))?;
+            }
+            return Ok(());
+        }
+        let probe = parse_probe(&upper, ports).ok_or_else(|| format!("Invalid probe: {}", spec))?;
+        let default_name = upper.replace(['.', ':'], "_");
+        self.add_probe(name.unwrap_or(&default_name), probe)
+    }
+
+    /// Samples all probes, writing values that changed since last sample
+    pub fn sample(&mut self, mcu: &Mcu) {
+        if self.error.is_some() {
+            return;
+        }
+        let time =
+            (mcu.get_cycle_count() as u128 * 1_000_000_000_000 / self.clock_hz as u128) as u64;
+        let values = self
+            .signals
+            .iter()
+            .map(|signal| signal.probe.sample(mcu))
+            .collect::<Vec<_>>();
+        if let Err(e) = self.write_changes(time, &values) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Flushes the output, returning the first write error, if any
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if !self.header_written {
+            self.write_header()?;
+        }
+        self.out.flush()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        writeln!(self.out, "$version avr-avogadro $end")?;
+        writeln!(self.out, "$timescale 1ps $end")?;
+        writeln!(self.out, "$scope module mcu $end")?;
+        for signal in &self.signals {
+            writeln!(
+                self.out,
+                "$var wire {} {} {} $end",
+                signal.probe.width(),
+                signal.code,
+                signal.name
+            )?;
+        }
+        writeln!(self.out, "$upscope $end")?;
+        writeln!(self.out, "$enddefinitions $end")?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn write_changes(&mut self, time: u64, values: &[u16]) -> io::Result<()> {
+        let first_sample = !self.header_written;
+        if first_sample {
+            self.write_header()?;
+        }
+        let mut time_written = false;
+        for (signal, value) in self.signals.iter_mut().zip(values) {
+            if signal.last_value == Some(*value) {
+                continue;
+            }
+            if !time_written && self.last_time != Some(time) {
+                writeln!(self.out, "#{}", time)?;
+                if first_sample {
+                    writeln!(self.out, "$dumpvars")?;
+                }
+                self.last_time = Some(time);
+            }
+            time_written = true;
+            if signal.probe.width() == 1 {
+                writeln!(self.out, "{}{}", value, signal.code)?;
+            } else {
+                writeln!(
+                    self.out,
+                    "b{:0width$b} {}",
+                    value,
+                    signal.code,
+                    width = signal.probe.width()
+                )?;
+            }
+            signal.last_value = Some(*value);
+        }
+        if first_sample && time_written {
+            writeln!(self.out, "$end")?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_probe(spec: &str, ports: &[Port]) -> Option<Probe> {
+    if spec == "PC" {
+        return Some(Probe::ProgramCounter);
+    }
+    if let Some(bit_name) = spec.strip_prefix("SREG.") {
+        let bit = SREG_BITS.iter().position(|name| *name == bit_name)?;
+        return Some(Probe::SregBit(bit as u8));
+    }
+    if let Some(address) = spec.strip_prefix("BYTE:") {
+        return parse_address(address).map(Probe::DataByte);
+    }
+    if let Some(address) = spec.strip_prefix("WORD:") {
+        return parse_address(address).map(Probe::DataWord);
+    }
+    if let Some(address) = parse_address(spec) {
+        return Some(Probe::DataByte(address));
+    }
+    for (prefix, offset) in &[("PORT", 0), ("DDR", 1), ("PIN", 2)] {
+        if let Some(letter) = spec.strip_prefix(prefix) {
+            return port_address(letter, ports).map(|port| Probe::DataByte(port - offset));
+        }
+    }
+    let mut chars = spec.strip_prefix('P')?.chars();
+    let name = chars.next()?;
+    let bit = chars.as_str().parse::<u8>().ok().filter(|bit| *bit < 8)?;
+    let port = Port::find(ports, name)?.address;
+    Some(Probe::Pin { port, bit })
+}
+
+/// `PORTx` address of port `letter`, if it's one of `ports`
+fn port_address(letter: &str, ports: &[Port]) -> Option<u16> {
+    let mut chars = letter.chars();
+    match (chars.next(), chars.next()) {
+        (Some(name), None) => Port::find(ports, name).map(|port| port.address),
+        _ => None,
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    match text.strip_prefix("0X") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// VCD identifiers use printable characters from `!` to `~`
+fn identifier_code(index: usize) -> String {
+    let mut code = String::new();
+    let mut index = index;
+    loop {
+        code.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            break;
+        }
+        index -= 1;
+    }
+    code
+}
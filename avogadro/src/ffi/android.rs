@@ -1,87 +1,878 @@
+//! Functions of `com.mlafroce.avogadro.wrapper.AvrAvogadroWrapper`.
+//!
+//! MCUs are handled as `long` pointers, returned by `createMcu` and freed
+//! with `freeMcu`. Errors are thrown as Java exceptions: a null handle as
+//! `NullPointerException`, invalid registers or addresses as
+//! `IndexOutOfBoundsException`, invalid names as `IllegalArgumentException`,
+//! unreadable files as `java.io.IOException` and simulator panics as
+//! `RuntimeException`. The returned value is meaningless when an exception
+//! is thrown.
+//!
+//! `createRunner` moves an MCU into a runner, which runs it on a worker
+//! thread and can be called from any thread, like the UI one. `freeRunner`
+//! gives the MCU back.
+#![allow(non_snake_case)]
+use crate::core::loader;
 use crate::core::mcu::Mcu;
 use crate::core::mcu_factory::McuFactory;
-use crate::ffi::mcu_wrapper;
-
-use std::convert::TryInto;
-
-#[allow(non_snake_case)]
-pub mod android {
-    extern crate jni;
-
-    use self::jni::objects::{JClass, JString};
-    use self::jni::sys::{jbyteArray, jchar, jlong};
-    use self::jni::JNIEnv;
-    use super::*;
-
-    #[no_mangle]
-    pub unsafe extern "C" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_createMcu(
-        env: JNIEnv,
-        _: JClass,
-        java_pattern: JString,
-    ) -> jlong {
-        let mcu_box = Box::new(McuFactory::create("attiny85"));
-        Box::into_raw(mcu_box) as i64
+use crate::core::register_bank::Flags;
+use crate::core::runner::{McuRunner, RunStatus};
+use crate::core::vcd::Probe;
+
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jboolean, jbyteArray, jchar, jdouble, jint, jintArray, jlong, jstring, JNI_FALSE};
+use jni::JNIEnv;
+use std::convert::TryFrom;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+
+/// Cycles run by `mcuRunUntil` when the given limit isn't positive
+const DEFAULT_MAX_CYCLES: usize = 1_000_000;
+
+/// A Java exception to throw
+struct Exception {
+    class: &'static str,
+    message: String,
+}
+
+type JniResult<T> = Result<T, Exception>;
+
+/// Value returned to Java along with a thrown exception
+trait Fallback {
+    fn fallback() -> Self;
+}
+
+impl Fallback for () {
+    fn fallback() -> Self {}
+}
+
+macro_rules! zero_fallback {
+    ($($type:ty),*) => {
+        $(impl Fallback for $type {
+            fn fallback() -> Self {
+                0
+            }
+        })*
+    };
+}
+
+zero_fallback!(jboolean, jchar, jint, jlong);
+
+impl Fallback for jdouble {
+    fn fallback() -> Self {
+        0.0
     }
+}
 
-    #[no_mangle]
-    pub unsafe extern "C" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_freeMcu(
-        env: JNIEnv,
-        _: JClass,
-        ptr: jlong,
-    ) {
-        let drop = unsafe { Box::from_raw(ptr as *mut Mcu) };
+/// Java objects fall back to null
+impl<T> Fallback for *mut T {
+    fn fallback() -> Self {
+        std::ptr::null_mut()
     }
+}
 
-    #[no_mangle]
-    pub unsafe extern "C" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuLoadProgramMemory(
-        env: JNIEnv,
-        _: JClass,
-        ptr: jlong,
-        program_memory: jbyteArray,
-    ) {
-        let rust_ptr = ptr as *mut Mcu;
-        let rust_memory = env.convert_byte_array(program_memory).unwrap();
-        mcu_wrapper::mcu_load_program_memory(
-            &mut *rust_ptr,
-            rust_memory.as_ptr(),
-            rust_memory.len(),
-        );
+#[no_mangle]
+pub extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_createMcu(
+    mut env: JNIEnv,
+    _: JClass,
+    device: JString,
+) -> jlong {
+    let result = get_string(&mut env, &device).and_then(|device| {
+        McuFactory::try_create(&device)
+            .map(|mcu| Box::into_raw(Box::new(mcu)) as jlong)
+            .ok_or_else(|| illegal_argument(format!("Unsupported MCU: {}", device)))
+    });
+    throw_on_error(&mut env, result)
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu` or `freeRunner`,
+/// not used afterwards
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_freeMcu(
+    _: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) {
+    if ptr != 0 {
+        drop(Box::from_raw(ptr as *mut Mcu));
     }
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuLoadProgramMemory(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    program_memory: JByteArray,
+) {
+    with_mcu(&mut env, ptr, |env, mcu| {
+        let memory = env.convert_byte_array(&program_memory).map_err(jni_error)?;
+        mcu.load_program_memory(&memory);
+        Ok(())
+    })
+}
+
+/// Loads a binary, Intel HEX or ELF program file
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuLoadProgramFile(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    filename: JString,
+) {
+    with_mcu(&mut env, ptr, |env, mcu| {
+        let filename = get_string(env, &filename)?;
+        let program = loader::read_program_file(&filename).map_err(|e| Exception {
+            class: "java/io/IOException",
+            message: format!("Cannot read {}: {}", filename, e),
+        })?;
+        mcu.load_program_memory(&program);
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuStep(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) {
+    with_mcu(&mut env, ptr, |_, mcu| {
+        mcu.step();
+        Ok(())
+    })
+}
+
+/// Runs for at least `cycles` clock cycles, stopping early at a
+/// breakpoint. Returns true if a breakpoint was hit.
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuRun(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    cycles: jlong,
+) -> jboolean {
+    with_mcu(&mut env, ptr, |_, mcu| {
+        let cycles = usize::try_from(cycles)
+            .map_err(|_| illegal_argument(format!("Invalid cycle count: {}", cycles)))?;
+        let end = mcu.get_cycle_count() + cycles;
+        Ok(run_to_cycle(mcu, end, None).into())
+    })
+}
+
+/// Runs until the program counter reaches `pc` or a breakpoint, for at
+/// most `maxCycles`. Returns true if `pc` was reached.
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuRunUntil(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    pc: jint,
+    max_cycles: jlong,
+) -> jboolean {
+    with_mcu(&mut env, ptr, |_, mcu| {
+        let pc = to_address(pc)?;
+        let max_cycles = usize::try_from(max_cycles)
+            .ok()
+            .filter(|cycles| *cycles > 0)
+            .unwrap_or(DEFAULT_MAX_CYCLES);
+        let end = mcu.get_cycle_count() + max_cycles;
+        run_to_cycle(mcu, end, Some(pc));
+        Ok((mcu.get_program_counter() == pc).into())
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetCycleCount(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jlong {
+    with_mcu(&mut env, ptr, |_, mcu| Ok(mcu.get_cycle_count() as jlong))
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetRegister(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    reg_num: jchar,
+) -> jchar {
+    with_mcu(&mut env, ptr, |_, mcu| {
+        Ok(mcu.get_register(to_register(reg_num)?).into())
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuSetRegister(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    reg_num: jchar,
+    value: jchar,
+) {
+    with_mcu(&mut env, ptr, |_, mcu| {
+        let register = to_register(reg_num)?;
+        let value = u8::try_from(value)
+            .map_err(|_| illegal_argument(format!("Invalid register value: {}", value)))?;
+        mcu.set_register(register, value);
+        Ok(())
+    })
+}
+
+/// Gets the 32 general purpose registers
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetRegisterArray(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jbyteArray {
+    with_mcu(&mut env, ptr, |env, mcu| {
+        byte_array(env, &mcu.get_register_array())
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetProgramCounter(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jint {
+    with_mcu(&mut env, ptr, |_, mcu| Ok(mcu.get_program_counter().into()))
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuSetProgramCounter(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    pc: jint,
+) {
+    with_mcu(&mut env, ptr, |_, mcu| {
+        mcu.set_program_counter(to_address(pc)?);
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetStackPointer(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jint {
+    with_mcu(&mut env, ptr, |_, mcu| Ok(mcu.get_stack_pointer().into()))
+}
+
+/// Gets SREG, carry being bit 0
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetFlags(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jint {
+    with_mcu(&mut env, ptr, |_, mcu| Ok(u8::from(mcu.get_flags()).into()))
+}
+
+/// Sets SREG, carry being bit 0
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuSetFlags(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    sreg: jint,
+) {
+    with_mcu(&mut env, ptr, |_, mcu| {
+        let sreg = u8::try_from(sreg)
+            .map_err(|_| illegal_argument(format!("Invalid SREG value: {}", sreg)))?;
+        mcu.set_flags(Flags::from(sreg));
+        Ok(())
+    })
+}
+
+/// Reads `length` bytes of data memory, I/O registers included
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuReadDataMemory(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    address: jint,
+    length: jint,
+) -> jbyteArray {
+    with_mcu(&mut env, ptr, |env, mcu| {
+        let mut memory = vec![0; mcu.get_data_size()];
+        // `memory` has the size of the data memory
+        mcu.get_data_memory(memory.as_mut_ptr(), memory.len());
+        byte_array(env, memory_range(&memory, address, length)?)
+    })
+}
+
+/// Reads `length` bytes of program memory
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuReadProgramMemory(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    address: jint,
+    length: jint,
+) -> jbyteArray {
+    with_mcu(&mut env, ptr, |env, mcu| {
+        let mut memory = vec![0; mcu.get_program_size()];
+        // `memory` has the size of the program memory
+        mcu.get_program_memory(memory.as_mut_ptr(), memory.len());
+        byte_array(env, memory_range(&memory, address, length)?)
+    })
+}
+
+/// Disassembles the instruction at the program counter
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuDisplayCurrentInstruction(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jstring {
+    with_mcu(&mut env, ptr, |env, mcu| {
+        let mut instruction = String::new();
+        mcu.display_current_instruction(&mut instruction);
+        env.new_string(instruction)
+            .map(|string| string.into_raw())
+            .map_err(jni_error)
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuAddBreakpoint(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    pc: jint,
+) {
+    with_mcu(&mut env, ptr, |_, mcu| {
+        mcu.add_breakpoint(to_address(pc)?);
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuRemoveBreakpoint(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    pc: jint,
+) {
+    with_mcu(&mut env, ptr, |_, mcu| {
+        mcu.remove_breakpoint(to_address(pc)?);
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetBreakpoints(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jintArray {
+    with_mcu(&mut env, ptr, |env, mcu| {
+        let breakpoints: Vec<jint> = mcu
+            .get_breakpoints()
+            .iter()
+            .map(|pc| jint::from(*pc))
+            .collect();
+        let array = env
+            .new_int_array(breakpoints.len() as jint)
+            .map_err(jni_error)?;
+        env.set_int_array_region(&array, 0, &breakpoints)
+            .map_err(jni_error)?;
+        Ok(array.into_raw())
+    })
+}
+
+/// Level of GPIO pin `pin`, like `"PB0"`. Output pins read `PORTx`,
+/// input pins read `PINx`.
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetPin(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    pin: JString,
+) -> jboolean {
+    with_mcu(&mut env, ptr, |env, mcu| {
+        let probe = parse_pin(&get_string(env, &pin)?, mcu)?;
+        Ok((probe.sample(mcu) != 0).into())
+    })
+}
+
+/// Drives input pin `pin`, like `"PB0"`, by setting its `PINx` bit
+/// # Safety
+///
+/// `ptr` must be 0 or an MCU returned by `createMcu`, not freed or
+/// moved into a runner
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuSetPin(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    pin: JString,
+    level: jboolean,
+) {
+    with_mcu(&mut env, ptr, |env, mcu| {
+        if let Probe::Pin { port, bit } = parse_pin(&get_string(env, &pin)?, mcu)? {
+            let input_register = port - 2;
+            let value = mcu.get_data_byte(input_register);
+            let value = if level != JNI_FALSE {
+                value | 1 << bit
+            } else {
+                value & !(1 << bit)
+            };
+            mcu.set_data_byte(input_register, value);
+        }
+        Ok(())
+    })
+}
 
-    #[no_mangle]
-    pub unsafe extern "C" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuStep(
-        env: JNIEnv,
-        _: JClass,
-        ptr: jlong,
-    ) {
-        let rust_ptr = ptr as *mut Mcu;
-        mcu_wrapper::mcu_step(&mut *rust_ptr);
+/// Moves MCU `mcuPtr` into a new runner, paused. The MCU handle can't be
+/// used until `freeRunner` gives it back.
+/// # Safety
+///
+/// `mcu_ptr` must be 0 or an MCU returned by `createMcu` or
+/// `freeRunner`, not used afterwards
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_createRunner(
+    mut env: JNIEnv,
+    _: JClass,
+    mcu_ptr: jlong,
+) -> jlong {
+    if mcu_ptr == 0 {
+        return throw_on_error(&mut env, Err(null_handle("MCU")));
     }
+    let mcu = Box::from_raw(mcu_ptr as *mut Mcu);
+    Box::into_raw(Box::new(McuRunner::new(*mcu))) as jlong
+}
+
+/// Stops and frees a runner, returning its MCU handle
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not used
+/// afterwards
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_freeRunner(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jlong {
+    if ptr == 0 {
+        return throw_on_error(&mut env, Err(null_handle("runner")));
+    }
+    let runner = Box::from_raw(ptr as *mut McuRunner);
+    let result = runner
+        .into_mcu()
+        .map(|mcu| Box::into_raw(Box::new(mcu)) as jlong)
+        .ok_or_else(simulator_panic);
+    throw_on_error(&mut env, result)
+}
+
+/// Runs until `runnerPause` or a breakpoint
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerRun(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) {
+    with_runner(&mut env, ptr, |_, runner| sent(runner.run()))
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerPause(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) {
+    with_runner(&mut env, ptr, |_, runner| sent(runner.pause()))
+}
+
+/// Runs `count` instructions, stopping early at a breakpoint after the
+/// first one
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerStep(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    count: jint,
+) {
+    with_runner(&mut env, ptr, |_, runner| {
+        let count = usize::try_from(count)
+            .map_err(|_| illegal_argument(format!("Invalid step count: {}", count)))?;
+        sent(runner.step(count))
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerAddBreakpoint(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    pc: jint,
+) {
+    with_runner(&mut env, ptr, |_, runner| {
+        sent(runner.add_breakpoint(to_address(pc)?))
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerRemoveBreakpoint(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    pc: jint,
+) {
+    with_runner(&mut env, ptr, |_, runner| {
+        sent(runner.remove_breakpoint(to_address(pc)?))
+    })
+}
+
+/// Paces running to `multiplier` times the MCU clock, like 1.0 for real
+/// time. Zero or negative multipliers run as fast as possible.
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerSetSpeed(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    multiplier: jdouble,
+) {
+    with_runner(&mut env, ptr, |_, runner| {
+        let multiplier = Some(multiplier).filter(|multiplier| *multiplier > 0.0);
+        sent(runner.set_speed(multiplier))
+    })
+}
+
+/// Simulated clock cycles per second while running, 0 while paused
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerGetAchievedSpeed(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jdouble {
+    with_runner(&mut env, ptr, |_, runner| Ok(runner.achieved_hz()))
+}
+
+/// Status of the runner: 0 paused, 1 running, 2 paused at a breakpoint
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerGetStatus(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jint {
+    with_runner(&mut env, ptr, |_, runner| match runner.status() {
+        RunStatus::Paused => Ok(0),
+        RunStatus::Running => Ok(1),
+        RunStatus::Breakpoint(_) => Ok(2),
+        RunStatus::Stopped => Err(simulator_panic()),
+    })
+}
+
+/// Gets the program counter followed by the 32 general purpose registers,
+/// all from the same instruction boundary
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerGetState(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+) -> jintArray {
+    with_runner(&mut env, ptr, |env, runner| {
+        let state = runner
+            .with_mcu(|mcu| {
+                let mut state = vec![jint::from(mcu.get_program_counter())];
+                state.extend(mcu.get_register_array().iter().map(|r| jint::from(*r)));
+                state
+            })
+            .ok_or_else(simulator_panic)?;
+        let array = env.new_int_array(state.len() as jint).map_err(jni_error)?;
+        env.set_int_array_region(&array, 0, &state)
+            .map_err(jni_error)?;
+        Ok(array.into_raw())
+    })
+}
+
+/// Reads `length` bytes of data memory between two instructions
+/// # Safety
+///
+/// `ptr` must be 0 or a runner returned by `createRunner`, not freed
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerReadDataMemory(
+    mut env: JNIEnv,
+    _: JClass,
+    ptr: jlong,
+    address: jint,
+    length: jint,
+) -> jbyteArray {
+    with_runner(&mut env, ptr, |env, runner| {
+        let memory = runner
+            .with_mcu(|mcu| {
+                let mut memory = vec![0; mcu.get_data_size()];
+                // `memory` has the size of the data memory
+                unsafe { mcu.get_data_memory(memory.as_mut_ptr(), memory.len()) };
+                memory
+            })
+            .ok_or_else(simulator_panic)?;
+        byte_array(env, memory_range(&memory, address, length)?)
+    })
+}
+
+/// Runs `call` with the runner of handle `ptr`, throwing its error as a
+/// Java exception
+unsafe fn with_runner<T: Fallback>(
+    env: &mut JNIEnv,
+    ptr: jlong,
+    call: impl FnOnce(&mut JNIEnv, &McuRunner) -> JniResult<T>,
+) -> T {
+    let result = match (ptr as *const McuRunner).as_ref() {
+        Some(runner) => call(env, runner),
+        None => Err(null_handle("runner")),
+    };
+    throw_on_error(env, result)
+}
+
+/// Runs `call` with the MCU of handle `ptr`, throwing its error or a
+/// caught panic as a Java exception
+unsafe fn with_mcu<T: Fallback>(
+    env: &mut JNIEnv,
+    ptr: jlong,
+    call: impl FnOnce(&mut JNIEnv, &mut Mcu) -> JniResult<T>,
+) -> T {
+    let mcu = match (ptr as *mut Mcu).as_mut() {
+        Some(mcu) => mcu,
+        None => return throw_on_error(env, Err(null_handle("MCU"))),
+    };
+    let result = match panic::catch_unwind(AssertUnwindSafe(|| call(env, mcu))) {
+        Ok(result) => result,
+        Err(_) => Err(simulator_panic()),
+    };
+    throw_on_error(env, result)
+}
+
+fn throw_on_error<T: Fallback>(env: &mut JNIEnv, result: JniResult<T>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(exception) => {
+            // A failed JNI call may have thrown already
+            if !env.exception_check().unwrap_or(false) {
+                let _ = env.throw_new(exception.class, exception.message);
+            }
+            T::fallback()
+        }
+    }
+}
 
-    #[no_mangle]
-    pub unsafe extern "C" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetRegister(
-        env: JNIEnv,
-        _: JClass,
-        ptr: jlong,
-        reg_num: jchar,
-    ) -> jchar {
-        let rust_ptr = ptr as *mut Mcu;
-        mcu_wrapper::mcu_get_register(&mut *rust_ptr, reg_num.try_into().unwrap()).into()
+/// Steps until cycle `end`, a breakpoint or `pc`, always running at
+/// least one instruction. Returns true if stopped at a breakpoint.
+fn run_to_cycle(mcu: &mut Mcu, end: usize, pc: Option<u16>) -> bool {
+    while mcu.get_cycle_count() < end {
+        mcu.step();
+        let current = mcu.get_program_counter();
+        if pc == Some(current) {
+            return false;
+        }
+        if mcu.get_breakpoints().contains(&current) {
+            return true;
+        }
     }
+    false
+}
+
+fn get_string(env: &mut JNIEnv, string: &JString) -> JniResult<String> {
+    if string.is_null() {
+        return Err(Exception {
+            class: "java/lang/NullPointerException",
+            message: "Null string".to_owned(),
+        });
+    }
+    env.get_string(string).map(String::from).map_err(jni_error)
+}
+
+fn byte_array(env: &mut JNIEnv, bytes: &[u8]) -> JniResult<jbyteArray> {
+    env.byte_array_from_slice(bytes)
+        .map(|array| array.into_raw())
+        .map_err(jni_error)
+}
+
+fn memory_range(memory: &[u8], address: jint, length: jint) -> JniResult<&[u8]> {
+    usize::try_from(address)
+        .ok()
+        .zip(usize::try_from(length).ok())
+        .and_then(|(address, length)| memory.get(address..address.checked_add(length)?))
+        .ok_or_else(|| index_out_of_bounds(format!("Invalid memory range: {}+{}", address, length)))
+}
+
+fn to_register(reg_num: jchar) -> JniResult<u8> {
+    u8::try_from(reg_num)
+        .ok()
+        .filter(|register| *register < 32)
+        .ok_or_else(|| index_out_of_bounds(format!("Invalid register r{}", reg_num)))
+}
+
+fn to_address(address: jint) -> JniResult<u16> {
+    u16::try_from(address).map_err(|_| illegal_argument(format!("Invalid address: {}", address)))
+}
+
+fn parse_pin(pin: &str, mcu: &Mcu) -> JniResult<Probe> {
+    match Probe::parse(pin, mcu.get_ports()) {
+        Some(probe @ Probe::Pin { .. }) => Ok(probe),
+        _ => Err(illegal_argument(format!("Invalid pin: {}", pin))),
+    }
+}
+
+/// Result of a runner command, which fails if the worker thread panicked
+fn sent(sent: bool) -> JniResult<()> {
+    if sent {
+        Ok(())
+    } else {
+        Err(simulator_panic())
+    }
+}
+
+fn null_handle(kind: &str) -> Exception {
+    Exception {
+        class: "java/lang/NullPointerException",
+        message: format!("Null {} handle", kind),
+    }
+}
+
+fn simulator_panic() -> Exception {
+    Exception {
+        class: "java/lang/RuntimeException",
+        message: "Simulator panic".to_owned(),
+    }
+}
+
+fn illegal_argument(message: String) -> Exception {
+    Exception {
+        class: "java/lang/IllegalArgumentException",
+        message,
+    }
+}
+
+fn index_out_of_bounds(message: String) -> Exception {
+    Exception {
+        class: "java/lang/IndexOutOfBoundsException",
+        message,
+    }
+}
 
-    #[no_mangle]
-    pub unsafe extern "C" fn Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuSetRegister(
-        env: JNIEnv,
-        _: JClass,
-        ptr: jlong,
-        reg_num: jchar,
-        value: jchar,
-    ) {
-        let rust_ptr = ptr as *mut Mcu;
-        mcu_wrapper::mcu_set_register(
-            &mut *rust_ptr,
-            reg_num.try_into().unwrap(),
-            value.try_into().unwrap(),
-        );
+fn jni_error(error: jni::errors::Error) -> Exception {
+    Exception {
+        class: "java/lang/RuntimeException",
+        message: error.to_string(),
     }
 }
@@ -1,220 +1,1454 @@
-//! This module contains the C API to interact directly with the MCU
+//! This module contains the C API to interact directly with the MCU.
+//!
+//! An MCU is created with `mcu_create` and freed with `mcu_destroy`. Every
+//! other function takes the returned handle and returns an `McuStatus`,
+//! writing values through output pointers. Panics are caught at the
+//! boundary: the call returns `MCU_STATUS_PANIC` and the handle can only be
+//! destroyed afterwards.
+//!
+//! The `include/avogadro.h` header is generated from this module with
+//! `cbindgen --config cbindgen.toml --output include/avogadro.h`.
+//!
+//! # Safety
+//!
+//! Handles must come from `mcu_create` and not be used after
+//! `mcu_destroy`. Output pointers may be null, which is reported as
+//! `MCU_STATUS_NULL_POINTER`, but otherwise must be valid.
+use crate::core::call_stack::CallStack;
+use crate::core::debugger::{SourceDebugger, StepStop};
+use crate::core::elf::ElfFile;
+use crate::core::events::{Event, EventFilter, Fault};
+use crate::core::history::History;
 use crate::core::mcu::Mcu;
+use crate::core::mcu_factory::McuFactory;
 use crate::core::register_bank::Flags;
+use crate::core::runner::{McuRunner, RunStatus};
+use crate::core::symbols::SymbolTable;
+use crate::core::vcd::VcdWriter;
 
-use std::convert::TryInto;
+use std::cell::Cell;
 use std::ffi::CStr;
-use std::mem::MaybeUninit;
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::ptr;
-use std::ptr::slice_from_raw_parts;
+use std::slice;
+use std::time::Duration;
 
+/// Version of the C API, incremented on incompatible changes
+pub const AVOGADRO_API_VERSION: u32 = 1;
+
+/// Result of C API calls
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum McuStatus {
+    Ok = 0,
+    /// A required pointer argument, or the handle, was null
+    NullPointer = 1,
+    /// An argument was out of range or not valid UTF-8
+    InvalidArgument = 2,
+    /// A file couldn't be read or written
+    IoError = 3,
+    /// The operation had nothing to do, like stepping back without history
+    NotFound = 4,
+    /// The simulator panicked, in this call or a previous one
+    Panic = 5,
+}
+
+/// Source level step of `mcu_source_step`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum McuSourceStep {
+    /// Runs to the next source line, entering calls
+    Into = 0,
+    /// Runs to the next source line, running calls to completion
+    Over = 1,
+    /// Runs until the current function returns
+    Out = 2,
+}
+
+/// Why a source level step stopped
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum McuStepStop {
+    Line = 0,
+    Return = 1,
+    Breakpoint = 2,
+    CycleLimit = 3,
+}
+
+/// Mask of every `McuEventKind`
+pub const MCU_EVENT_ALL: u32 = 0x3FF;
+
+/// Kind of an `McuEvent`, whose bit in event masks is `1 << kind`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum McuEventKind {
+    /// `value` is the raw instruction
+    Instruction = 0,
+    /// `address` and `value` are the data memory address and byte
+    MemoryRead = 1,
+    MemoryWrite = 2,
+    IoRead = 3,
+    IoWrite = 4,
+    /// `address` is the vector
+    InterruptEntry = 5,
+    InterruptExit = 6,
+    Sleep = 7,
+    Wake = 8,
+    /// `fault` tells which one
+    Fault = 9,
+}
+
+/// Kind of a fault event
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum McuFault {
+    None = 0,
+    Break = 1,
+    /// `address` is the data memory address accessed
+    AccessViolation = 2,
+    /// `address` is the stack pointer
+    StackCollision = 3,
+    /// `address` is the return address read from the stack
+    ReturnMismatch = 4,
+}
+
+/// Event passed to hook callbacks
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct McuEvent {
+    pub kind: McuEventKind,
+    /// Address of the instruction
+    pub pc: u16,
+    pub address: u16,
+    pub value: u16,
+    pub fault: McuFault,
+    pub cycle: u64,
+}
+
+/// Hook callback, receiving the event and the user data pointer given to
+/// `mcu_add_hook`
+pub type McuEventCallback =
+    Option<unsafe extern "C" fn(p_event: *const McuEvent, p_user_data: *mut c_void)>;
+
+/// Hook registered from C
+struct CallbackObserver {
+    callback: unsafe extern "C" fn(*const McuEvent, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// The caller of `mcu_add_hook` owns the user data and its thread safety
+unsafe impl Send for CallbackObserver {}
+
+impl CallbackObserver {
+    fn notify(&mut self, event: &Event) {
+        let event = c_event(event);
+        unsafe { (self.callback)(&event, self.user_data) }
+    }
+}
+
+/// Status of a runner, from `mcu_runner_get_status`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum McuRunStatus {
+    Paused = 0,
+    Running = 1,
+    /// Paused at a breakpoint
+    Breakpoint = 2,
+    /// The simulator panicked, only `mcu_runner_destroy` can be called
+    Stopped = 3,
+}
+
+/// Registers and cycle count of a running MCU, taken between two
+/// instructions
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct McuRunnerState {
+    pub registers: [u8; 32],
+    pub program_counter: u16,
+    pub stack_pointer: u16,
+    /// SREG, carry being bit 0
+    pub flags: u8,
+    pub cycle_count: u64,
+}
+
+/// Opaque MCU handle
+pub struct AvogadroMcu {
+    mcu: Mcu,
+    /// Debug info loaded with `mcu_load_debug_info`
+    debugger: Option<SourceDebugger>,
+    poisoned: Cell<bool>,
+}
+
+/// Returns `AVOGADRO_API_VERSION` of the library, so programs can check it
+/// matches the header they were built with
+#[no_mangle]
+pub extern "C" fn mcu_api_version() -> u32 {
+    AVOGADRO_API_VERSION
+}
+
+/// Creates an MCU of model `p_device`, like "attiny85", keeping a call stack
+/// for `mcu_get_backtrace`
+/// Returns null if the model is not supported
+/// # Safety
+///
+/// `p_device` must be a valid C string
+#[no_mangle]
+pub unsafe extern "C" fn mcu_create(p_device: *const c_char) -> *mut AvogadroMcu {
+    if p_device.is_null() {
+        return ptr::null_mut();
+    }
+    let device = match CStr::from_ptr(p_device).to_str() {
+        Ok(device) => device,
+        Err(_) => return ptr::null_mut(),
+    };
+    match panic::catch_unwind(|| McuFactory::try_create(device)) {
+        Ok(Some(mut mcu)) => {
+            mcu.set_call_stack(Some(CallStack::new(SymbolTable::new())));
+            Box::into_raw(Box::new(AvogadroMcu {
+                mcu,
+                debugger: None,
+                poisoned: Cell::new(false),
+            }))
+        }
+        Ok(None) => {
+            warn!("Unsupported MCU: {}", device);
+            ptr::null_mut()
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees an MCU created with `mcu_create`. Null handles are ignored.
+/// # Safety
+///
+/// `p_mcu` must be null or a handle returned by `mcu_create`, not used
+/// afterwards
 #[no_mangle]
-pub extern "C" fn mcu_step(p_mcu: &mut Mcu) {
-    p_mcu.step();
+pub unsafe extern "C" fn mcu_destroy(p_mcu: *mut AvogadroMcu) {
+    if !p_mcu.is_null() {
+        drop(Box::from_raw(p_mcu));
+    }
+}
+
+/// Executes one instruction
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_step(p_mcu: *mut AvogadroMcu) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        mcu.step();
+        McuStatus::Ok
+    })
 }
 
-/// Calls `Mcu::load_memory_from_file(filename)`
-/// Returns 0 if memory was loaded correctly
+/// Calls `Mcu::load_from_file(filename)`
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
+/// `p_mcu` must be a valid handle
 /// `p_filename` must be a valid C string
 #[no_mangle]
-pub extern "C" fn mcu_load_bin_file(
-    p_mcu: &mut Mcu,
+pub unsafe extern "C" fn mcu_load_bin_file(
+    p_mcu: *mut AvogadroMcu,
     p_filename: *const c_char,
     is_program: bool,
-) -> u8 {
-    let filename = unsafe { CStr::from_ptr(p_filename).to_str() };
-    if p_mcu.load_from_file(filename.unwrap(), is_program).is_ok() {
-        0
-    } else {
-        warn!("Error");
-        1
-    }
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        let filename = match read_str(p_filename) {
+            Ok(filename) => filename,
+            Err(status) => return status,
+        };
+        io_status(mcu.load_from_file(filename, is_program), filename)
+    })
 }
 
 /// Calls `Mcu::load_ihex_file(filename)`
-/// Returns 0 if memory was loaded correctly
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
+/// `p_mcu` must be a valid handle
 /// `p_filename` must be a valid C string
 #[no_mangle]
-pub extern "C" fn mcu_load_ihex_file(p_mcu: &mut Mcu, p_filename: *const c_char) -> u8 {
-    let filename = unsafe { CStr::from_ptr(p_filename).to_str() };
-    if p_mcu.load_ihex_file(filename.unwrap()).is_ok() {
-        0
-    } else {
-        warn!("Error");
-        1
-    }
+pub unsafe extern "C" fn mcu_load_ihex_file(
+    p_mcu: *mut AvogadroMcu,
+    p_filename: *const c_char,
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        let filename = match read_str(p_filename) {
+            Ok(filename) => filename,
+            Err(status) => return status,
+        };
+        io_status(mcu.load_ihex_file(filename), filename)
+    })
 }
 
-/// Creates a Rust vector with size `memory_size` and contents of `p_memory`
-/// and calls `Mcu::load_data_memory`
+/// Loads `memory_size` bytes from `p_memory` into data memory
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
-/// `p_memory` should be a char array with  size equals or larger than memory_size
-#[allow(clippy::unsound_collection_transmute)]
+/// `p_mcu` must be a valid handle
+/// `p_memory` must be an array of at least `memory_size` bytes
 #[no_mangle]
 pub unsafe extern "C" fn mcu_load_data_memory(
-    p_mcu: &mut Mcu,
+    p_mcu: *mut AvogadroMcu,
     p_memory: *const u8,
     memory_size: usize,
-) {
-    let mut buffer: Vec<MaybeUninit<u8>> = Vec::with_capacity(memory_size);
-    buffer.set_len(memory_size);
-    ptr::copy_nonoverlapping(p_memory, buffer.as_mut_ptr() as *mut u8, memory_size);
-    let init_buf = std::mem::transmute::<_, Vec<u8>>(buffer);
-    p_mcu.load_data_memory(&init_buf);
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| match read_slice(p_memory, memory_size) {
+        Ok(memory) => {
+            mcu.load_data_memory(memory);
+            McuStatus::Ok
+        }
+        Err(status) => status,
+    })
 }
 
-/// Creates a Rust vector with size `memory_size` and contents of `p_memory`
-/// and calls `Mcu::load_program_memory`
+/// Loads `memory_size` bytes from `p_memory` into program memory
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
-/// `p_memory` should be a char array with  size equals or larger than memory_size
-#[allow(clippy::unsound_collection_transmute)]
+/// `p_mcu` must be a valid handle
+/// `p_memory` must be an array of at least `memory_size` bytes
 #[no_mangle]
 pub unsafe extern "C" fn mcu_load_program_memory(
-    p_mcu: &mut Mcu,
+    p_mcu: *mut AvogadroMcu,
     p_memory: *const u8,
     memory_size: usize,
-) {
-    let mut buffer: Vec<MaybeUninit<u8>> = Vec::with_capacity(memory_size);
-    buffer.set_len(memory_size);
-    ptr::copy_nonoverlapping(p_memory, buffer.as_mut_ptr() as *mut u8, memory_size);
-    let init_buf = std::mem::transmute::<_, Vec<u8>>(buffer);
-    p_mcu.load_program_memory(&init_buf);
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| match read_slice(p_memory, memory_size) {
+        Ok(memory) => {
+            mcu.load_program_memory(memory);
+            McuStatus::Ok
+        }
+        Err(status) => status,
+    })
 }
 
-/// Gets data stored in a single register
+/// Gets data stored in register `reg_num`, from 0 to 31
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `p_value` must point to a byte
 #[no_mangle]
-pub extern "C" fn mcu_get_register(p_mcu: &Mcu, reg_num: u8) -> u8 {
-    p_mcu.get_register(reg_num)
+pub unsafe extern "C" fn mcu_get_register(
+    p_mcu: *const AvogadroMcu,
+    reg_num: u8,
+    p_value: *mut u8,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| {
+        if reg_num >= 32 {
+            return McuStatus::InvalidArgument;
+        }
+        write_out(p_value, mcu.get_register(reg_num))
+    })
 }
 
-/// Sets data into register `regnum`
+/// Sets data into register `reg_num`, from 0 to 31
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
 #[no_mangle]
-pub extern "C" fn mcu_set_register(p_mcu: &mut Mcu, reg_num: u8, value: u8) {
-    p_mcu.set_register(reg_num, value);
+pub unsafe extern "C" fn mcu_set_register(
+    p_mcu: *mut AvogadroMcu,
+    reg_num: u8,
+    value: u8,
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        if reg_num >= 32 {
+            return McuStatus::InvalidArgument;
+        }
+        mcu.set_register(reg_num, value);
+        McuStatus::Ok
+    })
 }
 
 /// Puts registers data into a buffer
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
+/// `p_mcu` must be a valid handle
 /// `buffer` must be a char array with at least 32 bytes
+#[no_mangle]
+pub unsafe extern "C" fn mcu_get_register_array(
+    p_mcu: *const AvogadroMcu,
+    buffer: *mut u8,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| {
+        if buffer.is_null() {
+            return McuStatus::NullPointer;
+        }
+        let registers = mcu.get_register_array();
+        ptr::copy_nonoverlapping(registers.as_ptr(), buffer, registers.len());
+        McuStatus::Ok
+    })
+}
+
+/// Sets every register from a buffer
+/// # Safety
 ///
+/// `p_mcu` must be a valid handle
+/// `p_reg` must be a char array with at least 32 bytes
 #[no_mangle]
-pub extern "C" fn mcu_get_register_array(p_mcu: &Mcu, buffer: *mut u8) {
-    let registers = p_mcu.get_register_array();
-    unsafe { ptr::copy_nonoverlapping(registers.as_ptr(), buffer, 32) };
+pub unsafe extern "C" fn mcu_set_register_array(
+    p_mcu: *mut AvogadroMcu,
+    p_reg: *const u8,
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| match read_slice(p_reg, 32) {
+        Ok(registers) => {
+            let mut reg_array = [0; 32];
+            reg_array.copy_from_slice(registers);
+            mcu.set_register_array(reg_array);
+            McuStatus::Ok
+        }
+        Err(status) => status,
+    })
 }
 
+/// Gets the program counter, in bytes
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `p_value` must point to a `uint16_t`
 #[no_mangle]
-pub unsafe extern "C" fn mcu_set_register_array(p_mcu: &mut Mcu, p_reg: *const u8) {
-    let reg_slice = slice_from_raw_parts(p_reg, 32);
-    let reg_array: [u8; 32] = (*reg_slice).try_into().unwrap();
-    p_mcu.set_register_array(reg_array);
+pub unsafe extern "C" fn mcu_get_program_counter(
+    p_mcu: *const AvogadroMcu,
+    p_value: *mut u16,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| write_out(p_value, mcu.get_program_counter()))
 }
 
+/// Sets the program counter, in bytes
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
 #[no_mangle]
-pub extern "C" fn mcu_get_program_counter(p_mcu: &Mcu) -> u16 {
-    p_mcu.get_program_counter()
+pub unsafe extern "C" fn mcu_set_program_counter(p_mcu: *mut AvogadroMcu, value: u16) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        mcu.set_program_counter(value);
+        McuStatus::Ok
+    })
 }
 
+/// Gets the stack pointer
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `p_value` must point to a `uint16_t`
 #[no_mangle]
-pub extern "C" fn mcu_get_stack_pointer(p_mcu: &Mcu) -> u16 {
-    p_mcu.get_stack_pointer()
+pub unsafe extern "C" fn mcu_get_stack_pointer(
+    p_mcu: *const AvogadroMcu,
+    p_value: *mut u16,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| write_out(p_value, mcu.get_stack_pointer()))
 }
 
+/// Gets the instruction word at the program counter
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `p_value` must point to a `uint16_t`
 #[no_mangle]
-pub extern "C" fn mcu_set_program_counter(p_mcu: &mut Mcu, value: u16) {
-    p_mcu.set_program_counter(value);
+pub unsafe extern "C" fn mcu_get_current_instruction(
+    p_mcu: *const AvogadroMcu,
+    p_value: *mut u16,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| {
+        write_out(p_value, mcu.get_current_instruction())
+    })
 }
 
+/// Gets the data memory byte at `address`
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `p_value` must point to a byte
 #[no_mangle]
-pub extern "C" fn mcu_get_current_instruction(p_mcu: &Mcu) -> u16 {
-    p_mcu.get_current_instruction()
+pub unsafe extern "C" fn mcu_get_data_byte(
+    p_mcu: *const AvogadroMcu,
+    address: u16,
+    p_value: *mut u8,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| write_out(p_value, mcu.get_data_byte(address)))
 }
 
+/// Writes the current instruction, in assembly, as a zero terminated string
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `c_buffer` must be a char array with `buf_size` size
 #[no_mangle]
-pub extern "C" fn mcu_get_data_byte(p_mcu: &Mcu, address: u16) -> u8 {
-    p_mcu.get_data_byte(address)
+pub unsafe extern "C" fn mcu_display_current_instruction(
+    p_mcu: *const AvogadroMcu,
+    c_buffer: *mut u8,
+    buf_size: usize,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| {
+        let mut string_buf = String::new();
+        mcu.display_current_instruction(&mut string_buf);
+        write_string(c_buffer, buf_size, &string_buf)
+    })
 }
 
-/// Puts registers data into a buffer
+/// Gets data memory size, in bytes
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
-/// `buffer` must be a char array with `buf_size` size
+/// `p_mcu` must be a valid handle
+/// `p_size` must point to a `size_t`
+#[no_mangle]
+pub unsafe extern "C" fn mcu_get_data_size(
+    p_mcu: *const AvogadroMcu,
+    p_size: *mut usize,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| write_out(p_size, mcu.get_data_size()))
+}
+
+/// Gets data memory contents, at most `buf_size` bytes
+/// # Safety
 ///
+/// `p_mcu` must be a valid handle
+/// `c_buffer` must be a char array with `buf_size` size
 #[no_mangle]
-pub unsafe extern "C" fn mcu_display_current_instruction(
-    p_mcu: &Mcu,
+pub unsafe extern "C" fn mcu_get_data_memory(
+    p_mcu: *const AvogadroMcu,
     c_buffer: *mut u8,
     buf_size: usize,
-) {
-    let mut string_buf = String::new();
-    p_mcu.display_current_instruction(&mut string_buf);
-    let bytes_to_copy = std::cmp::min(buf_size - 1, string_buf.len());
-    ptr::copy_nonoverlapping(string_buf.as_ptr(), c_buffer, bytes_to_copy);
-    *(c_buffer.add(bytes_to_copy)) = 0;
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| {
+        if c_buffer.is_null() {
+            return McuStatus::NullPointer;
+        }
+        mcu.get_data_memory(c_buffer, buf_size);
+        McuStatus::Ok
+    })
 }
 
-/// Gets data memory size
+/// Gets program memory size, in bytes
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
+/// `p_mcu` must be a valid handle
+/// `p_size` must point to a `size_t`
+#[no_mangle]
+pub unsafe extern "C" fn mcu_get_program_size(
+    p_mcu: *const AvogadroMcu,
+    p_size: *mut usize,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| write_out(p_size, mcu.get_program_size()))
+}
+
+/// Gets program memory contents, at most `buf_size` bytes
+/// # Safety
 ///
+/// `p_mcu` must be a valid handle
+/// `c_buffer` must be a char array with `buf_size` size
 #[no_mangle]
-pub extern "C" fn mcu_get_data_size(p_mcu: &Mcu) -> usize {
-    p_mcu.get_data_size()
+pub unsafe extern "C" fn mcu_get_program_memory(
+    p_mcu: *const AvogadroMcu,
+    c_buffer: *mut u8,
+    buf_size: usize,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| {
+        if c_buffer.is_null() {
+            return McuStatus::NullPointer;
+        }
+        mcu.get_program_memory(c_buffer, buf_size);
+        McuStatus::Ok
+    })
 }
 
-/// Gets data memory contents
+/// Gets SREG, carry being bit 0
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
-/// `buffer` must be a char array with `buf_size` size
+/// `p_mcu` must be a valid handle
+/// `p_value` must point to a byte
+#[no_mangle]
+pub unsafe extern "C" fn mcu_get_flags(p_mcu: *const AvogadroMcu, p_value: *mut u8) -> McuStatus {
+    with_mcu(p_mcu, |mcu| write_out(p_value, mcu.get_flags().into()))
+}
+
+/// Sets SREG, carry being bit 0
+/// # Safety
 ///
+/// `p_mcu` must be a valid handle
 #[no_mangle]
-pub unsafe extern "C" fn mcu_get_data_memory(p_mcu: &Mcu, c_buffer: *mut u8, buf_size: usize) {
-    p_mcu.get_data_memory(c_buffer, buf_size);
+pub unsafe extern "C" fn mcu_set_flags(p_mcu: *mut AvogadroMcu, flags: u8) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        mcu.set_flags(Flags::from(flags));
+        McuStatus::Ok
+    })
 }
 
-/// Gets program memory size
+/// Starts dumping waveforms into VCD file `p_filename`. `p_probes` is a
+/// comma separated list of probes, as accepted by `VcdWriter::add_probe_spec`
+/// (like "PB0,PORTB,sreg,pc,word:0x60"), and `clock_hz` converts cycles
+/// into time.
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
+/// `p_mcu` must be a valid handle
+/// `p_filename` and `p_probes` must be valid C strings
+#[no_mangle]
+pub unsafe extern "C" fn mcu_vcd_start(
+    p_mcu: *mut AvogadroMcu,
+    p_filename: *const c_char,
+    p_probes: *const c_char,
+    clock_hz: u64,
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        let (filename, probes) = match (read_str(p_filename), read_str(p_probes)) {
+            (Ok(filename), Ok(probes)) => (filename, probes),
+            (Err(status), _) | (_, Err(status)) => return status,
+        };
+        let mut vcd_writer = match VcdWriter::create(filename, clock_hz) {
+            Ok(vcd_writer) => vcd_writer,
+            Err(e) => {
+                warn!("Cannot create {}: {}", filename, e);
+                return McuStatus::IoError;
+            }
+        };
+        for spec in probes.split(',').filter(|spec| !spec.trim().is_empty()) {
+            if let Err(e) = vcd_writer.add_probe_spec(spec, mcu.get_ports()) {
+                warn!("{}", e);
+                return McuStatus::InvalidArgument;
+            }
+        }
+        mcu.set_vcd_writer(Some(vcd_writer));
+        McuStatus::Ok
+    })
+}
+
+/// Stops dumping waveforms and closes the VCD file
+/// # Safety
 ///
+/// `p_mcu` must be a valid handle
 #[no_mangle]
-pub unsafe extern "C" fn mcu_get_program_size(p_mcu: &Mcu) -> usize {
-    p_mcu.get_program_size()
+pub unsafe extern "C" fn mcu_vcd_stop(p_mcu: *mut AvogadroMcu) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| match mcu.take_vcd_writer() {
+        Some(vcd_writer) => io_status(vcd_writer.finish(), "VCD file"),
+        None => McuStatus::Ok,
+    })
 }
 
-/// Gets memory bank contents
+/// Saves registers, memories and cycle count into snapshot file `p_filename`
 /// # Safety
 ///
-/// `p_mcu` must be a pointer to a valid Mcu
-/// `buffer` must be a char array with `buf_size` size
+/// `p_mcu` must be a valid handle
+/// `p_filename` must be a valid C string
+#[no_mangle]
+pub unsafe extern "C" fn mcu_save_state(
+    p_mcu: *const AvogadroMcu,
+    p_filename: *const c_char,
+) -> McuStatus {
+    with_mcu(p_mcu, |mcu| match read_str(p_filename) {
+        Ok(filename) => io_status(mcu.save_state(filename), filename),
+        Err(status) => status,
+    })
+}
+
+/// Restores the machine state from snapshot file `p_filename`. On error the
+/// MCU is left untouched.
+/// # Safety
 ///
+/// `p_mcu` must be a valid handle
+/// `p_filename` must be a valid C string
 #[no_mangle]
-pub unsafe extern "C" fn mcu_get_program_memory(p_mcu: &Mcu, c_buffer: *mut u8, buf_size: usize) {
-    p_mcu.get_program_memory(c_buffer, buf_size);
+pub unsafe extern "C" fn mcu_load_state(
+    p_mcu: *mut AvogadroMcu,
+    p_filename: *const c_char,
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| match read_str(p_filename) {
+        Ok(filename) => io_status(mcu.load_state(filename), filename),
+        Err(status) => status,
+    })
 }
 
+/// Starts recording execution history, using at most about `budget` bytes,
+/// so steps can be undone. A budget of 0 stops recording.
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
 #[no_mangle]
-pub extern "C" fn mcu_get_flags(p_mcu: &Mcu) -> u8 {
-    p_mcu.get_flags().into()
+pub unsafe extern "C" fn mcu_set_history_budget(
+    p_mcu: *mut AvogadroMcu,
+    budget: usize,
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        let history = if budget > 0 {
+            Some(History::new(budget))
+        } else {
+            None
+        };
+        mcu.set_history(history);
+        McuStatus::Ok
+    })
 }
 
+/// Undoes the last step
+/// Returns `MCU_STATUS_NOT_FOUND` if there is no history left
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_step_back(p_mcu: *mut AvogadroMcu) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| found_status(mcu.step_back()))
+}
+
+/// Steps back until the program counter is `pc`
+/// Returns `MCU_STATUS_NOT_FOUND` if the history ran out first
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_run_back_to(p_mcu: *mut AvogadroMcu, pc: u16) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| found_status(mcu.run_back_to(pc)))
+}
+
+/// Steps back to the previous breakpoint or watchpoint hit
+/// Returns `MCU_STATUS_NOT_FOUND` if the history ran out first
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
 #[no_mangle]
-pub extern "C" fn mcu_set_flags(p_mcu: &mut Mcu, flags: Flags) {
-    p_mcu.set_flags(flags);
+pub unsafe extern "C" fn mcu_reverse_continue(p_mcu: *mut AvogadroMcu) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| found_status(mcu.reverse_continue()))
+}
+
+/// Stops `mcu_reverse_continue` at program address `pc`
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_add_breakpoint(p_mcu: *mut AvogadroMcu, pc: u16) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        mcu.add_breakpoint(pc);
+        McuStatus::Ok
+    })
+}
+
+/// Removes a breakpoint added with `mcu_add_breakpoint`
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_remove_breakpoint(p_mcu: *mut AvogadroMcu, pc: u16) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        mcu.remove_breakpoint(pc);
+        McuStatus::Ok
+    })
+}
+
+/// Stops `mcu_reverse_continue` at writes to data memory `address`
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_add_watchpoint(p_mcu: *mut AvogadroMcu, address: u16) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        mcu.add_watchpoint(address);
+        McuStatus::Ok
+    })
+}
+
+/// Removes a watchpoint added with `mcu_add_watchpoint`
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_remove_watchpoint(p_mcu: *mut AvogadroMcu, address: u16) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        mcu.remove_watchpoint(address);
+        McuStatus::Ok
+    })
+}
+
+/// Loads functions, variables and source lines of ELF file `p_filename`,
+/// used by the source level functions and backtraces below. The program
+/// isn't loaded.
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `p_filename` must be a valid C string
+#[no_mangle]
+pub unsafe extern "C" fn mcu_load_debug_info(
+    p_mcu: *mut AvogadroMcu,
+    p_filename: *const c_char,
+) -> McuStatus {
+    with_handle_mut(p_mcu, |handle| {
+        let filename = match read_str(p_filename) {
+            Ok(filename) => filename,
+            Err(status) => return status,
+        };
+        match ElfFile::load(filename) {
+            Ok(elf) => {
+                if let Some(call_stack) = handle.mcu.get_call_stack_mut() {
+                    call_stack.set_symbols(elf.symbols.clone());
+                }
+                handle.debugger = Some(SourceDebugger::new(elf));
+                McuStatus::Ok
+            }
+            Err(e) => io_status(Err(e), filename),
+        }
+    })
+}
+
+/// Steps by source lines, writing why it stopped into `p_stop`
+/// Returns `MCU_STATUS_NOT_FOUND` if no debug info was loaded
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `p_stop` must point to an `McuStepStop`
+#[no_mangle]
+pub unsafe extern "C" fn mcu_source_step(
+    p_mcu: *mut AvogadroMcu,
+    step: McuSourceStep,
+    p_stop: *mut McuStepStop,
+) -> McuStatus {
+    with_handle_mut(p_mcu, |handle| {
+        let debugger = match &handle.debugger {
+            Some(debugger) => debugger,
+            None => return McuStatus::NotFound,
+        };
+        if p_stop.is_null() {
+            return McuStatus::NullPointer;
+        }
+        let stop = match step {
+            McuSourceStep::Into => debugger.step(&mut handle.mcu),
+            McuSourceStep::Over => debugger.step_over(&mut handle.mcu),
+            McuSourceStep::Out => debugger.step_out(&mut handle.mcu),
+        };
+        let stop = match stop {
+            StepStop::Line => McuStepStop::Line,
+            StepStop::Return => McuStepStop::Return,
+            StepStop::Breakpoint => McuStepStop::Breakpoint,
+            StepStop::CycleLimit => McuStepStop::CycleLimit,
+        };
+        write_out(p_stop, stop)
+    })
+}
+
+/// Writes the source file of the current instruction, as a zero terminated
+/// string, and its line into `p_line`
+/// Returns `MCU_STATUS_NOT_FOUND` if it has no line info
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `c_buffer` must be a char array with `buf_size` size
+#[no_mangle]
+pub unsafe extern "C" fn mcu_get_source_line(
+    p_mcu: *const AvogadroMcu,
+    c_buffer: *mut u8,
+    buf_size: usize,
+    p_line: *mut u32,
+) -> McuStatus {
+    with_handle(p_mcu, |handle| {
+        let row = match &handle.debugger {
+            Some(debugger) => debugger.location(&handle.mcu),
+            None => None,
+        };
+        match row {
+            Some(row) if !p_line.is_null() => {
+                *p_line = row.line;
+                write_string(c_buffer, buf_size, &row.file)
+            }
+            Some(_) => McuStatus::NullPointer,
+            None => McuStatus::NotFound,
+        }
+    })
+}
+
+/// Writes the value of variable `p_name`, a local of the current function or
+/// a global, formatted as a zero terminated string like "{x = 1, y = 2}"
+/// Returns `MCU_STATUS_NOT_FOUND` if there is no such variable
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `p_name` must be a valid C string
+/// `c_buffer` must be a char array with `buf_size` size
+#[no_mangle]
+pub unsafe extern "C" fn mcu_read_variable(
+    p_mcu: *const AvogadroMcu,
+    p_name: *const c_char,
+    c_buffer: *mut u8,
+    buf_size: usize,
+) -> McuStatus {
+    with_handle(p_mcu, |handle| {
+        let name = match read_str(p_name) {
+            Ok(name) => name,
+            Err(status) => return status,
+        };
+        let value = match &handle.debugger {
+            Some(debugger) => debugger.read_variable(&handle.mcu, name),
+            None => None,
+        };
+        match value {
+            Some(value) => write_string(c_buffer, buf_size, &value.to_string()),
+            None => McuStatus::NotFound,
+        }
+    })
+}
+
+/// Writes parameters and local variables of the current function as a zero
+/// terminated string, a "type name = value" line for each one
+/// Returns `MCU_STATUS_NOT_FOUND` if no debug info was loaded
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `c_buffer` must be a char array with `buf_size` size
+#[no_mangle]
+pub unsafe extern "C" fn mcu_get_locals(
+    p_mcu: *const AvogadroMcu,
+    c_buffer: *mut u8,
+    buf_size: usize,
+) -> McuStatus {
+    with_handle(p_mcu, |handle| {
+        let debugger = match &handle.debugger {
+            Some(debugger) => debugger,
+            None => return McuStatus::NotFound,
+        };
+        let info = &debugger.get_elf().debug_info;
+        let mut locals = String::new();
+        for (variable, value) in debugger.locals(&handle.mcu) {
+            let type_name = info.type_name(variable.type_id);
+            locals.push_str(&format!("{} {} = {}\n", type_name, variable.name, value));
+        }
+        write_string(c_buffer, buf_size, &locals)
+    })
+}
+
+/// Writes the functions being executed as a zero terminated string, a
+/// "#0  0x0012 in add+0x6" line for each frame from the current one. Frames
+/// are named with the symbols of `mcu_load_debug_info`, and have their
+/// source line if it's known.
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `c_buffer` must be a char array with `buf_size` size
+#[no_mangle]
+pub unsafe extern "C" fn mcu_get_backtrace(
+    p_mcu: *const AvogadroMcu,
+    c_buffer: *mut u8,
+    buf_size: usize,
+) -> McuStatus {
+    with_handle(p_mcu, |handle| {
+        let backtrace = match &handle.debugger {
+            Some(debugger) => debugger.backtrace(&handle.mcu),
+            None => handle.mcu.backtrace(),
+        };
+        match backtrace {
+            Some(backtrace) => write_string(c_buffer, buf_size, &backtrace.to_string()),
+            None => McuStatus::NotFound,
+        }
+    })
+}
+
+/// Calls `callback` with `p_user_data` for every event whose kind is in the
+/// `kinds` mask, like `MCU_EVENT_ALL`. Memory and I/O accesses are only
+/// notified if their address is between `first_address` and `last_address`,
+/// both included. Writes the hook id to `p_id`, for `mcu_remove_hook`.
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+/// `callback` must be safe to call with `p_user_data` while the hook is
+/// registered
+#[no_mangle]
+pub unsafe extern "C" fn mcu_add_hook(
+    p_mcu: *mut AvogadroMcu,
+    kinds: u32,
+    first_address: u16,
+    last_address: u16,
+    callback: McuEventCallback,
+    p_user_data: *mut c_void,
+    p_id: *mut usize,
+) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        let callback = match callback {
+            Some(callback) => callback,
+            None => return McuStatus::NullPointer,
+        };
+        if p_id.is_null() {
+            return McuStatus::NullPointer;
+        }
+        let filter = EventFilter {
+            kinds,
+            addresses: Some(first_address..=last_address),
+        };
+        let mut observer = CallbackObserver {
+            callback,
+            user_data: p_user_data,
+        };
+        let id = mcu.add_hook(filter, move |event: &Event| observer.notify(event));
+        write_out(p_id, id)
+    })
+}
+
+/// Unregisters a hook added with `mcu_add_hook`
+/// Returns `MCU_STATUS_NOT_FOUND` if there is no hook `id`
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_remove_hook(p_mcu: *mut AvogadroMcu, id: usize) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        if mcu.remove_hook(id) {
+            McuStatus::Ok
+        } else {
+            McuStatus::NotFound
+        }
+    })
+}
+
+/// Calls `Mcu::enter_interrupt(vector)`
+/// # Safety
+///
+/// `p_mcu` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_enter_interrupt(p_mcu: *mut AvogadroMcu, vector: u16) -> McuStatus {
+    with_mcu_mut(p_mcu, |mcu| {
+        mcu.enter_interrupt(vector);
+        McuStatus::Ok
+    })
+}
+
+/// Opaque handle of an MCU running on a worker thread. Its functions can be
+/// called from any thread.
+pub struct AvogadroRunner {
+    runner: McuRunner,
+    /// Debug info of the MCU handle, given back by `mcu_runner_destroy`
+    debugger: Option<SourceDebugger>,
+}
+
+/// Moves the MCU of `p_mcu` into a new runner thread, paused. Hook
+/// callbacks are called from that thread while it runs.
+/// Returns null if `p_mcu` is null or poisoned, in which case the MCU
+/// handle is kept.
+/// # Safety
+///
+/// `p_mcu` must be a valid handle, not used afterwards unless null is
+/// returned
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_create(p_mcu: *mut AvogadroMcu) -> *mut AvogadroRunner {
+    match p_mcu.as_ref() {
+        Some(handle) if !handle.poisoned.get() => {
+            let handle = Box::from_raw(p_mcu);
+            Box::into_raw(Box::new(AvogadroRunner {
+                runner: McuRunner::new(handle.mcu),
+                debugger: handle.debugger,
+            }))
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Stops the runner thread and frees the runner, giving back the MCU as a
+/// new handle. Returns null if the simulator panicked or `p_runner` is null.
+/// # Safety
+///
+/// `p_runner` must be null or a handle returned by `mcu_runner_create`, not
+/// used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_destroy(p_runner: *mut AvogadroRunner) -> *mut AvogadroMcu {
+    if p_runner.is_null() {
+        return ptr::null_mut();
+    }
+    let handle = Box::from_raw(p_runner);
+    match handle.runner.into_mcu() {
+        Some(mcu) => Box::into_raw(Box::new(AvogadroMcu {
+            mcu,
+            debugger: handle.debugger,
+            poisoned: Cell::new(false),
+        })),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Runs until `mcu_runner_pause` or a breakpoint
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_run(p_runner: *const AvogadroRunner) -> McuStatus {
+    with_runner(p_runner, |runner| runner_status(runner.run()))
+}
+
+/// Stops running, between two instructions
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_pause(p_runner: *const AvogadroRunner) -> McuStatus {
+    with_runner(p_runner, |runner| runner_status(runner.pause()))
+}
+
+/// Runs `count` instructions, stopping early at a breakpoint after the
+/// first one
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_step(
+    p_runner: *const AvogadroRunner,
+    count: usize,
+) -> McuStatus {
+    with_runner(p_runner, |runner| runner_status(runner.step(count)))
+}
+
+/// Stops running at program address `pc`
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_add_breakpoint(
+    p_runner: *const AvogadroRunner,
+    pc: u16,
+) -> McuStatus {
+    with_runner(p_runner, |runner| runner_status(runner.add_breakpoint(pc)))
+}
+
+/// Removes a breakpoint added with `mcu_runner_add_breakpoint`
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_remove_breakpoint(
+    p_runner: *const AvogadroRunner,
+    pc: u16,
+) -> McuStatus {
+    with_runner(p_runner, |runner| {
+        runner_status(runner.remove_breakpoint(pc))
+    })
+}
+
+/// Paces running to `multiplier` times the MCU clock, like 1.0 for real
+/// time. Zero or negative multipliers run as fast as possible, the default.
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_set_speed(
+    p_runner: *const AvogadroRunner,
+    multiplier: f64,
+) -> McuStatus {
+    with_runner(p_runner, |runner| {
+        let multiplier = Some(multiplier).filter(|multiplier| *multiplier > 0.0);
+        runner_status(runner.set_speed(multiplier))
+    })
+}
+
+/// Gets the simulated clock cycles per second while running, measured over
+/// the last half second. 0 while paused.
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+/// `p_hz` must point to a `double`
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_get_achieved_speed(
+    p_runner: *const AvogadroRunner,
+    p_hz: *mut f64,
+) -> McuStatus {
+    with_runner(p_runner, |runner| write_out(p_hz, runner.achieved_hz()))
+}
+
+/// Gets the status after the last command handled by the runner
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+/// `p_status` must point to an `McuRunStatus`
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_get_status(
+    p_runner: *const AvogadroRunner,
+    p_status: *mut McuRunStatus,
+) -> McuStatus {
+    with_runner(p_runner, |runner| {
+        write_out(p_status, c_run_status(runner.status()))
+    })
+}
+
+/// Waits at most `timeout_ms` milliseconds for the runner to stop running,
+/// then gets its status
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+/// `p_status` must point to an `McuRunStatus`
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_wait(
+    p_runner: *const AvogadroRunner,
+    timeout_ms: u32,
+    p_status: *mut McuRunStatus,
+) -> McuStatus {
+    with_runner(p_runner, |runner| {
+        let status = runner.wait(Duration::from_millis(timeout_ms.into()));
+        write_out(p_status, c_run_status(status))
+    })
+}
+
+/// Gets registers, flags and cycle count, all from the same instruction
+/// boundary
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+/// `p_state` must point to an `McuRunnerState`
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_get_state(
+    p_runner: *const AvogadroRunner,
+    p_state: *mut McuRunnerState,
+) -> McuStatus {
+    with_runner(p_runner, |runner| {
+        let state = runner.with_mcu(|mcu| McuRunnerState {
+            registers: mcu.get_register_array(),
+            program_counter: mcu.get_program_counter(),
+            stack_pointer: mcu.get_stack_pointer(),
+            flags: mcu.get_flags().into(),
+            cycle_count: mcu.get_cycle_count() as u64,
+        });
+        match state {
+            Some(state) => write_out(p_state, state),
+            None => McuStatus::Panic,
+        }
+    })
+}
+
+/// Gets data memory contents, at most `buf_size` bytes, copied between two
+/// instructions
+/// # Safety
+///
+/// `p_runner` must be a valid handle
+/// `c_buffer` must be a char array with `buf_size` size
+#[no_mangle]
+pub unsafe extern "C" fn mcu_runner_get_data_memory(
+    p_runner: *const AvogadroRunner,
+    c_buffer: *mut u8,
+    buf_size: usize,
+) -> McuStatus {
+    with_runner(p_runner, |runner| {
+        if c_buffer.is_null() {
+            return McuStatus::NullPointer;
+        }
+        let memory = runner.with_mcu(move |mcu| {
+            let mut memory = vec![0; buf_size.min(mcu.get_data_size())];
+            // `memory` has the size it's copied with
+            unsafe { mcu.get_data_memory(memory.as_mut_ptr(), memory.len()) };
+            memory
+        });
+        match memory {
+            Some(memory) => {
+                ptr::copy_nonoverlapping(memory.as_ptr(), c_buffer, memory.len());
+                McuStatus::Ok
+            }
+            None => McuStatus::Panic,
+        }
+    })
+}
+
+/// C representation of `status`
+fn c_run_status(status: RunStatus) -> McuRunStatus {
+    match status {
+        RunStatus::Paused => McuRunStatus::Paused,
+        RunStatus::Running => McuRunStatus::Running,
+        RunStatus::Breakpoint(_) => McuRunStatus::Breakpoint,
+        RunStatus::Stopped => McuRunStatus::Stopped,
+    }
+}
+
+/// Status of a runner command, which fails if the worker thread is gone
+fn runner_status(sent: bool) -> McuStatus {
+    if sent {
+        McuStatus::Ok
+    } else {
+        McuStatus::Panic
+    }
+}
+
+/// C representation of `event`
+fn c_event(event: &Event) -> McuEvent {
+    let mut c_event = McuEvent {
+        kind: McuEventKind::Instruction,
+        pc: 0,
+        address: 0,
+        value: 0,
+        fault: McuFault::None,
+        cycle: 0,
+    };
+    let (kind, pc, cycle) = match event {
+        Event::Instruction {
+            pc,
+            raw_instruction,
+            cycle,
+        } => {
+            c_event.value = *raw_instruction;
+            (McuEventKind::Instruction, *pc, *cycle)
+        }
+        Event::MemoryRead(access)
+        | Event::MemoryWrite(access)
+        | Event::IoRead(access)
+        | Event::IoWrite(access) => {
+            c_event.address = access.address;
+            c_event.value = u16::from(access.value);
+            let kind = match event {
+                Event::MemoryRead(_) => McuEventKind::MemoryRead,
+                Event::MemoryWrite(_) => McuEventKind::MemoryWrite,
+                Event::IoRead(_) => McuEventKind::IoRead,
+                _ => McuEventKind::IoWrite,
+            };
+            (kind, access.pc, access.cycle)
+        }
+        Event::InterruptEntry { pc, vector, cycle } => {
+            c_event.address = *vector;
+            (McuEventKind::InterruptEntry, *pc, *cycle)
+        }
+        Event::InterruptExit { pc, cycle } => (McuEventKind::InterruptExit, *pc, *cycle),
+        Event::Sleep { pc, cycle } => (McuEventKind::Sleep, *pc, *cycle),
+        Event::Wake { pc, cycle } => (McuEventKind::Wake, *pc, *cycle),
+        Event::Fault(fault) => {
+            let (fault, pc, address, cycle) = match fault {
+                Fault::Break { pc, cycle } => (McuFault::Break, *pc, 0, *cycle),
+                Fault::AccessViolation(violation) => (
+                    McuFault::AccessViolation,
+                    violation.pc,
+                    violation.address,
+                    violation.cycle,
+                ),
+                Fault::StackCollision(collision) => (
+                    McuFault::StackCollision,
+                    collision.pc,
+                    collision.stack_pointer,
+                    collision.cycle,
+                ),
+                Fault::ReturnMismatch(mismatch) => (
+                    McuFault::ReturnMismatch,
+                    mismatch.pc,
+                    mismatch.actual,
+                    mismatch.cycle,
+                ),
+            };
+            c_event.fault = fault;
+            c_event.address = address;
+            (McuEventKind::Fault, pc, cycle)
+        }
+    };
+    c_event.kind = kind;
+    c_event.pc = pc;
+    c_event.cycle = cycle as u64;
+    c_event
+}
+
+/// Runs `call` with the MCU of handle `p_mcu`, catching panics
+unsafe fn with_mcu(p_mcu: *const AvogadroMcu, call: impl FnOnce(&Mcu) -> McuStatus) -> McuStatus {
+    with_handle(p_mcu, |handle| call(&handle.mcu))
+}
+
+/// Runs `call` with the MCU of handle `p_mcu`, catching panics
+unsafe fn with_mcu_mut(
+    p_mcu: *mut AvogadroMcu,
+    call: impl FnOnce(&mut Mcu) -> McuStatus,
+) -> McuStatus {
+    with_handle_mut(p_mcu, |handle| call(&mut handle.mcu))
+}
+
+/// Runs `call` with the runner of handle `p_runner`. Panics happen in the
+/// worker thread, and are reported by the runner itself.
+unsafe fn with_runner(
+    p_runner: *const AvogadroRunner,
+    call: impl FnOnce(&McuRunner) -> McuStatus,
+) -> McuStatus {
+    match p_runner.as_ref() {
+        Some(handle) => call(&handle.runner),
+        None => McuStatus::NullPointer,
+    }
+}
+
+/// Runs `call` with handle `p_mcu`, catching panics
+unsafe fn with_handle(
+    p_mcu: *const AvogadroMcu,
+    call: impl FnOnce(&AvogadroMcu) -> McuStatus,
+) -> McuStatus {
+    let handle = match p_mcu.as_ref() {
+        Some(handle) => handle,
+        None => return McuStatus::NullPointer,
+    };
+    if handle.poisoned.get() {
+        return McuStatus::Panic;
+    }
+    match panic::catch_unwind(AssertUnwindSafe(|| call(handle))) {
+        Ok(status) => status,
+        Err(_) => {
+            handle.poisoned.set(true);
+            McuStatus::Panic
+        }
+    }
+}
+
+/// Runs `call` with handle `p_mcu`, catching panics
+unsafe fn with_handle_mut(
+    p_mcu: *mut AvogadroMcu,
+    call: impl FnOnce(&mut AvogadroMcu) -> McuStatus,
+) -> McuStatus {
+    let handle = match p_mcu.as_mut() {
+        Some(handle) => handle,
+        None => return McuStatus::NullPointer,
+    };
+    if handle.poisoned.get() {
+        return McuStatus::Panic;
+    }
+    match panic::catch_unwind(AssertUnwindSafe(|| call(handle))) {
+        Ok(status) => status,
+        Err(_) => {
+            handle.poisoned.set(true);
+            McuStatus::Panic
+        }
+    }
+}
+
+unsafe fn write_out<T>(p_value: *mut T, value: T) -> McuStatus {
+    match p_value.as_mut() {
+        Some(out) => {
+            *out = value;
+            McuStatus::Ok
+        }
+        None => McuStatus::NullPointer,
+    }
+}
+
+/// Copies `string` into `c_buffer` as a zero terminated string, truncated to
+/// `buf_size` bytes
+unsafe fn write_string(c_buffer: *mut u8, buf_size: usize, string: &str) -> McuStatus {
+    if c_buffer.is_null() {
+        return McuStatus::NullPointer;
+    }
+    if buf_size == 0 {
+        return McuStatus::InvalidArgument;
+    }
+    let bytes_to_copy = std::cmp::min(buf_size - 1, string.len());
+    ptr::copy_nonoverlapping(string.as_ptr(), c_buffer, bytes_to_copy);
+    *(c_buffer.add(bytes_to_copy)) = 0;
+    McuStatus::Ok
+}
+
+unsafe fn read_str<'a>(p_string: *const c_char) -> Result<&'a str, McuStatus> {
+    if p_string.is_null() {
+        return Err(McuStatus::NullPointer);
+    }
+    CStr::from_ptr(p_string)
+        .to_str()
+        .map_err(|_| McuStatus::InvalidArgument)
+}
+
+unsafe fn read_slice<'a>(p_memory: *const u8, size: usize) -> Result<&'a [u8], McuStatus> {
+    if p_memory.is_null() {
+        return Err(McuStatus::NullPointer);
+    }
+    Ok(slice::from_raw_parts(p_memory, size))
+}
+
+fn io_status(result: std::io::Result<()>, filename: &str) -> McuStatus {
+    match result {
+        Ok(()) => McuStatus::Ok,
+        Err(e) => {
+            warn!("Error accessing {}: {}", filename, e);
+            McuStatus::IoError
+        }
+    }
+}
+
+fn found_status(found: bool) -> McuStatus {
+    if found {
+        McuStatus::Ok
+    } else {
+        McuStatus::NotFound
+    }
 }
@@ -1,5 +1,8 @@
-#[cfg(target_os = "android")]
-/// C API for JNI
+#[cfg(any(feature = "jni", target_os = "android"))]
+/// JNI interface for the android app, built for android targets or with the
+/// `jni` feature
+///
+/// cbindgen:ignore
 pub mod android;
 /// C API to interact with the MCU
 pub mod mcu_wrapper;
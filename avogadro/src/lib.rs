@@ -8,6 +8,8 @@
 extern crate log;
 /// # Core
 /// Main functions and components of the simulator.
+///
+/// cbindgen:ignore
 pub mod core;
 /// # FFI
 /// Functions exposed to the C API, including a JNI interface for android
@@ -0,0 +1,413 @@
+#![cfg(feature = "jni")]
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::ffi::android::*;
+use jni::objects::{JByteArray, JClass, JIntArray, JString};
+use jni::sys::{jint, JNI_FALSE, JNI_TRUE};
+use jni::{sys, JNIEnv, JavaVM};
+use std::env;
+use std::ffi::{c_void, CString};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+type CreateJavaVm =
+    unsafe extern "system" fn(*mut *mut sys::JavaVM, *mut *mut c_void, *mut c_void) -> jint;
+
+/// The JVM, kept along with its library as it can only be created once per
+/// process
+static JVM: OnceLock<Option<(libloading::Library, JavaVM)>> = OnceLock::new();
+
+/// Finds the JVM library from `JAVA_HOME`, or the `java` executable in `PATH`
+fn find_libjvm() -> Option<PathBuf> {
+    let java_home = env::var_os("JAVA_HOME").map(PathBuf::from).or_else(|| {
+        let path = env::var_os("PATH")?;
+        env::split_paths(&path)
+            .map(|dir| dir.join("java"))
+            .find(|java| java.is_file())
+            .and_then(|java| java.canonicalize().ok())
+            .and_then(|java| Some(java.parent()?.parent()?.to_path_buf()))
+    })?;
+    ["lib/server", "bin/server", "jre/lib/server"]
+        .iter()
+        .map(|dir| {
+            java_home
+                .join(dir)
+                .join(libloading::library_filename("jvm"))
+        })
+        .find(|library| library.is_file())
+}
+
+fn create_jvm() -> Option<(libloading::Library, JavaVM)> {
+    let library = unsafe { libloading::Library::new(find_libjvm()?) }.ok()?;
+    let check_jni = CString::new("-Xcheck:jni").unwrap();
+    let mut options = [sys::JavaVMOption {
+        optionString: check_jni.as_ptr() as *mut _,
+        extraInfo: std::ptr::null_mut(),
+    }];
+    let mut args = sys::JavaVMInitArgs {
+        version: sys::JNI_VERSION_1_8,
+        nOptions: options.len() as jint,
+        options: options.as_mut_ptr(),
+        ignoreUnrecognized: sys::JNI_FALSE,
+    };
+    let mut vm = std::ptr::null_mut();
+    let mut env = std::ptr::null_mut();
+    unsafe {
+        let create: libloading::Symbol<CreateJavaVm> = library.get(b"JNI_CreateJavaVM").ok()?;
+        if create(&mut vm, &mut env, &mut args as *mut _ as *mut c_void) != sys::JNI_OK {
+            return None;
+        }
+        Some((library, JavaVM::from_raw(vm).ok()?))
+    }
+}
+
+/// Runs `test` in a thread attached to the JVM, or skips it if no JVM is
+/// installed
+fn with_jvm(test: impl FnOnce(&mut JNIEnv)) {
+    match JVM.get_or_init(create_jvm) {
+        Some((_, vm)) => {
+            let mut env = vm.attach_current_thread().unwrap();
+            env.with_local_frame(64, |env| -> jni::errors::Result<()> {
+                test(env);
+                Ok(())
+            })
+            .unwrap();
+        }
+        None => eprintln!("No JVM found, skipping JNI test"),
+    }
+}
+
+/// Returns the class of the pending exception, clearing it
+fn take_exception(env: &mut JNIEnv) -> Option<String> {
+    if !env.exception_check().unwrap() {
+        return None;
+    }
+    let exception = env.exception_occurred().unwrap();
+    env.exception_clear().unwrap();
+    let class = env.get_object_class(&exception).unwrap();
+    let name = env
+        .call_method(&class, "getName", "()Ljava/lang/String;", &[])
+        .unwrap()
+        .l()
+        .unwrap();
+    Some(env.get_string(&JString::from(name)).unwrap().into())
+}
+
+fn create_mcu(env: &mut JNIEnv, device: &str) -> i64 {
+    let device = env.new_string(device).unwrap();
+    let env = unsafe { env.unsafe_clone() };
+    Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_createMcu(env, JClass::default(), device)
+}
+
+#[test]
+/// Handles are created by model name, errors are thrown as exceptions
+fn test_jni_exceptions() {
+    with_jvm(|env| unsafe {
+        assert_eq!(create_mcu(env, "z80"), 0);
+        assert_eq!(
+            take_exception(env).as_deref(),
+            Some("java.lang.IllegalArgumentException")
+        );
+        let mcu = create_mcu(env, "attiny85");
+        assert_ne!(mcu, 0);
+        assert_eq!(take_exception(env), None);
+
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuStep(
+            env.unsafe_clone(),
+            JClass::default(),
+            0,
+        );
+        assert_eq!(
+            take_exception(env).as_deref(),
+            Some("java.lang.NullPointerException")
+        );
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetRegister(
+            env.unsafe_clone(),
+            JClass::default(),
+            mcu,
+            32,
+        );
+        assert_eq!(
+            take_exception(env).as_deref(),
+            Some("java.lang.IndexOutOfBoundsException")
+        );
+        let pin = env.new_string("PZ9").unwrap();
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetPin(
+            env.unsafe_clone(),
+            JClass::default(),
+            mcu,
+            pin,
+        );
+        assert_eq!(
+            take_exception(env).as_deref(),
+            Some("java.lang.IllegalArgumentException")
+        );
+        let filename = env.new_string("/nonexistent.hex").unwrap();
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuLoadProgramFile(
+            env.unsafe_clone(),
+            JClass::default(),
+            mcu,
+            filename,
+        );
+        assert_eq!(take_exception(env).as_deref(), Some("java.io.IOException"));
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_freeMcu(
+            env.unsafe_clone(),
+            JClass::default(),
+            mcu,
+        );
+    });
+}
+
+#[test]
+/// Programs are loaded, run and inspected through the JNI functions
+fn test_jni_run() {
+    let bytes = avr_asm!(
+        "ldi r16, 0x01",
+        "out 0x17, r16",
+        "out 0x18, r16",
+        "rjmp .-2"
+    );
+    with_jvm(|env| unsafe {
+        let mcu = create_mcu(env, "attiny85");
+        let class = || JClass::default();
+        let program = env.byte_array_from_slice(&bytes).unwrap();
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuLoadProgramMemory(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+            program,
+        );
+        let instruction =
+            Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuDisplayCurrentInstruction(
+                env.unsafe_clone(),
+                class(),
+                mcu,
+            );
+        let instruction: String = env
+            .get_string(&JString::from_raw(instruction))
+            .unwrap()
+            .into();
+        assert!(instruction.to_lowercase().contains("ldi"));
+
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuStep(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+        );
+        assert_eq!(
+            Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetRegister(
+                env.unsafe_clone(),
+                class(),
+                mcu,
+                16
+            ),
+            1
+        );
+        assert_eq!(
+            Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetProgramCounter(
+                env.unsafe_clone(),
+                class(),
+                mcu
+            ),
+            2
+        );
+
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuAddBreakpoint(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+            6,
+        );
+        let breakpoints = JIntArray::from_raw(
+            Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetBreakpoints(
+                env.unsafe_clone(),
+                class(),
+                mcu,
+            ),
+        );
+        let mut pcs = [0; 1];
+        assert_eq!(env.get_array_length(&breakpoints).unwrap(), 1);
+        env.get_int_array_region(&breakpoints, 0, &mut pcs).unwrap();
+        assert_eq!(pcs, [6]);
+        let hit = Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuRun(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+            100,
+        );
+        assert_eq!(hit, JNI_TRUE);
+        let pin = env.new_string("PB0").unwrap();
+        let level = Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetPin(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+            pin,
+        );
+        assert_eq!(level, JNI_TRUE);
+        let port = JByteArray::from_raw(
+            Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuReadDataMemory(
+                env.unsafe_clone(),
+                class(),
+                mcu,
+                0x37,
+                2,
+            ),
+        );
+        assert_eq!(env.convert_byte_array(&port).unwrap(), vec![1, 1]);
+
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuRemoveBreakpoint(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+            6,
+        );
+        let reached = Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuRunUntil(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+            0,
+            100,
+        );
+        assert_eq!(reached, JNI_FALSE);
+        let cycles = Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetCycleCount(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+        );
+        assert!(cycles >= 100);
+
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuSetFlags(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+            0x83,
+        );
+        assert_eq!(
+            Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetFlags(
+                env.unsafe_clone(),
+                class(),
+                mcu
+            ),
+            0x83
+        );
+        assert_eq!(take_exception(env), None);
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_freeMcu(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+        );
+    });
+}
+
+#[test]
+/// Runners run the MCU on a worker thread, and give it back when freed
+fn test_jni_runner() {
+    let bytes = avr_asm!("loop:", "nop", "inc r16", "inc r17", "jmp loop");
+    with_jvm(|env| unsafe {
+        let class = || JClass::default();
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_createRunner(
+            env.unsafe_clone(),
+            class(),
+            0,
+        );
+        assert_eq!(
+            take_exception(env).as_deref(),
+            Some("java.lang.NullPointerException")
+        );
+        let mcu = create_mcu(env, "attiny85");
+        let program = env.byte_array_from_slice(&bytes).unwrap();
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuLoadProgramMemory(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+            program,
+        );
+        let runner = Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_createRunner(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+        );
+        assert_ne!(runner, 0);
+
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerSetSpeed(
+            env.unsafe_clone(),
+            class(),
+            runner,
+            0.0,
+        );
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerAddBreakpoint(
+            env.unsafe_clone(),
+            class(),
+            runner,
+            4,
+        );
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerRun(
+            env.unsafe_clone(),
+            class(),
+            runner,
+        );
+        let status = (0..10_000)
+            .map(|_| {
+                thread::sleep(Duration::from_millis(1));
+                Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerGetStatus(
+                    env.unsafe_clone(),
+                    class(),
+                    runner,
+                )
+            })
+            .find(|status| *status == 2);
+        assert_eq!(status, Some(2));
+        let state = JIntArray::from_raw(
+            Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerGetState(
+                env.unsafe_clone(),
+                class(),
+                runner,
+            ),
+        );
+        let mut values = [0; 33];
+        env.get_int_array_region(&state, 0, &mut values).unwrap();
+        assert_eq!(values[0], 4);
+        assert_eq!(values[17..19], [1, 0]);
+        assert_eq!(
+            Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerGetAchievedSpeed(
+                env.unsafe_clone(),
+                class(),
+                runner,
+            ),
+            0.0
+        );
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_runnerStep(
+            env.unsafe_clone(),
+            class(),
+            runner,
+            -1,
+        );
+        assert_eq!(
+            take_exception(env).as_deref(),
+            Some("java.lang.IllegalArgumentException")
+        );
+
+        let mcu = Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_freeRunner(
+            env.unsafe_clone(),
+            class(),
+            runner,
+        );
+        assert_eq!(
+            Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_mcuGetProgramCounter(
+                env.unsafe_clone(),
+                class(),
+                mcu
+            ),
+            4
+        );
+        assert_eq!(take_exception(env), None);
+        Java_com_mlafroce_avogadro_wrapper_AvrAvogadroWrapper_freeMcu(
+            env.unsafe_clone(),
+            class(),
+            mcu,
+        );
+    });
+}
@@ -0,0 +1,226 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::assembler::assemble;
+use avr_avogadro::core::decoder::Decoder;
+use avr_avogadro::core::disassembler::Disassembler;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use std::fs;
+use std::path::PathBuf;
+
+#[test]
+/// Assembles instructions as printed by `Display` and decodes them back
+fn test_display_round_trip() {
+    let instructions = vec![
+        "nop",
+        "movw\tr0, r2",
+        "muls\tr31, r31",
+        "mulsu\tr16, r23",
+        "fmul\tr23, r23",
+        "fmuls\tr16, r16",
+        "fmulsu\tr23, r23",
+        "cpc\tr0, r31",
+        "cp\tr31, r0",
+        "sbc\tr7, r25",
+        "sub\tr31, r31",
+        "add\tr1, r2",
+        "adc\tr31, r31",
+        "cpse\tr3, r30",
+        "and\tr31, r31",
+        "eor\tr31, r0",
+        "or\tr0, r31",
+        "mov\tr31, r31",
+        "cpi\tr19, 0x0F",
+        "sbci\tr31, 0xFF",
+        "subi\tr16, 0x00",
+        "ori\tr19, 0x0F",
+        "andi\tr31, 0xFF",
+        "ldi\tr16, 0xF0",
+        "ld\tr0, X",
+        "ld\tr31, X+",
+        "ld\tr5, -X",
+        "ld\tr0, Y+",
+        "ld\tr7, -Z",
+        "ldd\tr31, Z+63",
+        "ldd\tr1, Y+7",
+        "st\tX, r0",
+        "st\t-Y, r31",
+        "st\tZ+, r4",
+        "std\tY+32, r5",
+        "std\tZ+1, r31",
+        "push\tr31",
+        "pop\tr0",
+        "com\tr0",
+        "neg\tr31",
+        "swap\tr9",
+        "inc\tr10",
+        "asr\tr11",
+        "lsr\tr12",
+        "ror\tr13",
+        "sec",
+        "seh",
+        "sei",
+        "clc",
+        "clt",
+        "cli",
+        "ret",
+        "reti",
+        "sleep",
+        "break",
+        "wdr",
+        "lpm",
+        "elpm",
+        "spm",
+        "spm\tz+",
+        "adiw\tr24, 0x01",
+        "adiw\tr30, 0x0f",
+        "sbiw\tr26, 0x05",
+        "cbi\t0x1f, 7",
+        "sbic\t0x00, 0",
+        "sbi\t0x17, 0",
+        "sbis\t0x1f, 7",
+        "in\tr31, 0x3f",
+        "out\t0x18, r1",
+        "rjmp\t.+4094",
+        "rjmp\t.-4096",
+        "rjmp\t.-2",
+        "rcall\t.+0",
+        "brcs\t.+0",
+        "breq\t.-128",
+        "brne\t.+126",
+        "brge\t.-4",
+        "brid\t.+2",
+        ".word\t0x9528",
+    ];
+    for text in instructions {
+        let program = assemble(text).unwrap().image;
+        assert_eq!(program.len(), 2, "Wrong size for `{}`", text);
+        let raw = u16::from_le_bytes([program[0], program[1]]);
+        let decoded = Decoder::decode(raw).to_string();
+        assert_eq!(decoded, text, "Round trip failed for {:#06x}", raw);
+    }
+}
+
+#[test]
+/// Aliases assemble to their base instructions
+fn test_aliases() {
+    let program = avr_asm!(
+        "clr r1",     // eor r1, r1
+        "lsl r16",    // add r16, r16
+        "rol r17",    // adc r17, r17
+        "tst r18",    // and r18, r18
+        "ser r19",    // ldi r19, 0xFF
+        "sbr r20, 3", // ori r20, 3
+        "cbr r21, 3", // andi r21, 0xFC
+        "brlo .+0",   // brcs
+        "brsh .+0",   // brcc
+        "movw r25:r24, r31:r30",
+    );
+    let expected = vec![
+        0x11, 0x24, 0x00, 0x0f, 0x11, 0x1f, 0x22, 0x23, 0x3f, 0xef, 0x43, 0x60, 0x5c, 0x7f, 0x00,
+        0xf0, 0x00, 0xf4, 0xcf, 0x01,
+    ];
+    assert_eq!(program, expected);
+}
+
+#[test]
+/// Labels may be used before they are defined, and two word instructions
+/// take 4 bytes
+fn test_labels() {
+    let source = "
+        ; Jump over the data
+        rjmp start
+    table: .byte 1, 2, 3, 4
+    start:
+        ldi r30, lo8(table)
+        ldi r31, hi8(table)
+    loop:
+        lds r24, 0x0060
+        sts 0x0061, r24
+        brne loop
+        call start
+        jmp 0x8
+    ";
+    let program = assemble(source).unwrap();
+    assert_eq!(program.symbols.by_name("table").unwrap().address, 0x2);
+    assert_eq!(program.symbols.by_name("start").unwrap().address, 0x6);
+    assert_eq!(program.symbols.by_name("loop").unwrap().address, 0xa);
+    let expected = vec![
+        0x02, 0xc0, 0x01, 0x02, 0x03, 0x04, 0xe2, 0xe0, 0xf0, 0xe0, 0x80, 0x91, 0x60, 0x00, 0x80,
+        0x93, 0x61, 0x00, 0xd9, 0xf7, 0x0e, 0x94, 0x03, 0x00, 0x0c, 0x94, 0x04, 0x00,
+    ];
+    assert_eq!(program.image, expected);
+}
+
+#[test]
+/// `.equ` symbols, expressions, `.org` and data directives
+fn test_directives() {
+    let source = "
+        .equ PORTB, 0x18
+        .equ LED, PORTB + 2
+        .set MASK, (1 << 3) | 0b1
+        out PORTB, r1
+        sbi LED - 2, 3
+        ldi r16, MASK
+        ldi r17, -1
+        ldi r18, 'A'
+        .org 0x10
+    data:
+        .word 0x1234, pm(data)
+        .asciz \"hi\"
+    ";
+    let program = assemble(source).unwrap().image;
+    assert_eq!(program.len(), 0x17);
+    assert_eq!(
+        program[0..10],
+        [0x18, 0xba, 0xc3, 0x9a, 0x09, 0xe0, 0x1f, 0xef, 0x21, 0xe4]
+    );
+    assert!(program[10..0x10].iter().all(|byte| *byte == 0));
+    assert_eq!(program[0x10..], [0x34, 0x12, 0x08, 0x00, b'h', b'i', 0]);
+}
+
+#[test]
+/// Errors report the offending line
+fn test_errors() {
+    let error = assemble("nop\nldi r1, 0x10").unwrap_err();
+    assert_eq!(error.line, 2);
+    let error = assemble("nop\n\nfoo r1").unwrap_err();
+    assert_eq!(error.line, 3);
+    assert_eq!(error.to_string(), "line 3: unknown instruction `foo`");
+    let error = assemble("brne far\n.org 0x100\nfar: nop").unwrap_err();
+    assert_eq!(error.line, 1);
+    let error = assemble("rjmp missing").unwrap_err();
+    assert_eq!(error.message, "undefined symbol `missing`");
+    let error = assemble("a: nop\na: nop").unwrap_err();
+    assert_eq!(error.line, 2);
+    let error = assemble(".byte 1\nnop").unwrap_err();
+    assert_eq!(error.line, 2);
+}
+
+#[test]
+/// Programs assembled with `avr_asm!` run on the MCU
+fn test_run_assembled_program() {
+    let mut mcu = McuFactory::create("attiny85");
+    let program = avr_asm!("ldi r16, 0x10", "ldi r17, 0x22", "add r16, r17");
+    mcu.load_program_memory(&program);
+    mcu.step();
+    mcu.step();
+    mcu.step();
+    assert_eq!(mcu.get_register(16), 0x32);
+    assert_eq!(mcu.get_program_counter(), 0x6);
+}
+
+#[test]
+/// A disassembler listing assembles back to the original program
+fn test_listing_round_trip() {
+    let mut pathbuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    pathbuf.push("tests/blink.bin");
+    let program = fs::read(pathbuf).unwrap();
+    let mut disassembler = Disassembler::new(&program);
+    disassembler.set_vector_count(15);
+    let mut listing = String::new();
+    disassembler.write_listing(&mut listing).unwrap();
+    let assembled = assemble(&listing).unwrap();
+    assert_eq!(assembled.image, program);
+    assert_eq!(assembled.symbols.by_name("__vectors").unwrap().address, 0);
+}
@@ -0,0 +1,112 @@
+extern crate avr_avogadro;
+
+#[path = "fixtures/mod.rs"]
+mod fixtures;
+
+use avr_avogadro::core::assembler::Program;
+use avr_avogadro::core::call_stack::{CallStack, ReturnMismatch};
+use avr_avogadro::core::mcu::Mcu;
+use fixtures::create_mcu;
+
+/// `main` calls `f` twice, `f` calls `g`. `rcall .+0` only makes room in
+/// the stack.
+const SOURCE: &str = "
+main:
+    rcall f
+    rcall f
+    rcall .+0
+    rjmp .-2
+f:
+    rcall g
+    ret
+g:
+    nop
+    ret
+smash:
+    ldi r16, 0x40
+    ldi r28, 0xf0
+    ldi r29, 0x01
+    st Y, r16
+    ret
+";
+
+fn with_call_stack(mcu: &mut Mcu, program: Program) {
+    mcu.set_call_stack(Some(CallStack::new(program.symbols)));
+}
+
+#[test]
+/// Calls push frames and returns pop them, backtraces name each call site
+fn test_call_stack_frames() {
+    let mut mcu = create_mcu(SOURCE, with_call_stack);
+    mcu.step();
+    mcu.step();
+    let call_stack = mcu.get_call_stack().unwrap();
+    assert_eq!(call_stack.depth(), 2);
+    let frames = call_stack.frames();
+    assert_eq!((frames[0].call_site, frames[0].target), (0x0, 0x8));
+    assert_eq!((frames[1].call_site, frames[1].target), (0x8, 0xc));
+    assert_eq!(frames[1].stack_pointer, 0x1ec);
+    assert!(!frames[1].is_interrupt);
+    assert_eq!(
+        mcu.backtrace().unwrap().to_string(),
+        "#0  0x000c in g\n#1  0x0008 in f\n#2  0x0000 in main\n"
+    );
+
+    // Both calls return, `rcall .+0` pushes no frame
+    for _ in 0..11 {
+        mcu.step();
+    }
+    let call_stack = mcu.get_call_stack().unwrap();
+    assert_eq!(call_stack.depth(), 0);
+    assert!(call_stack.mismatches().is_empty());
+    assert_eq!(
+        mcu.backtrace().unwrap().to_string(),
+        "#0  0x0006 in main+0x6\n"
+    );
+}
+
+#[test]
+/// Returns to an address the call didn't store, as `smash` overwrote it,
+/// are recorded
+fn test_call_stack_mismatch() {
+    let source = format!("{}\nstart:\n    rcall smash\n", SOURCE);
+    let mut mcu = create_mcu(&source, with_call_stack);
+    mcu.set_program_counter(0x1a);
+    mcu.step();
+    assert_eq!(mcu.get_program_counter(), 0x10);
+    for _ in 0..5 {
+        mcu.step();
+    }
+    let call_stack = mcu.get_call_stack_mut().unwrap();
+    assert_eq!(call_stack.depth(), 0);
+    assert_eq!(
+        call_stack.take_mismatches(),
+        vec![ReturnMismatch {
+            pc: 0x18,
+            expected: 0x1c,
+            actual: 0x40,
+            cycle: 8,
+        }]
+    );
+    assert!(call_stack.mismatches().is_empty());
+}
+
+#[test]
+/// Interrupt frames are marked in backtraces, returns without frames are
+/// ignored
+fn test_call_stack_interrupts() {
+    let mut mcu = create_mcu(SOURCE, with_call_stack);
+    mcu.set_program_counter(0xc);
+    let call_stack = mcu.get_call_stack_mut().unwrap();
+    call_stack.enter_interrupt(0x6, 0xc, 0x1ee);
+    assert_eq!(
+        mcu.backtrace().unwrap().to_string(),
+        "#0  0x000c in g\n#1  0x0006 in main+0x6 <interrupt>\n"
+    );
+    mcu.get_call_stack_mut().unwrap().clear();
+    mcu.step();
+    mcu.step();
+    assert!(mcu.get_call_stack().unwrap().mismatches().is_empty());
+    assert!(mcu.take_call_stack().is_some());
+    assert!(mcu.backtrace().is_none());
+}
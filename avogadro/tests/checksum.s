@@ -0,0 +1,24 @@
+; CPU bound firmware for the decode cache tests and benchmark: fills a
+; 64 byte SRAM buffer with mixed counters and folds them into a running checksum
+; in r20:r21, forever. Assembled with `avr_avogadro::core::assembler`.
+        .equ    BUFFER, 0x100
+        .equ    LENGTH, 64
+
+        ldi     r16, 0
+outer:
+        ldi     r26, lo8(BUFFER)
+        ldi     r27, hi8(BUFFER)
+        ldi     r18, LENGTH
+inner:
+        mov     r0, r16
+        add     r0, r18
+        swap    r0
+        sub     r0, r21
+        st      X+, r0
+        eor     r20, r0
+        lsl     r20
+        adc     r21, r20
+        dec     r18
+        brne    inner
+        inc     r16
+        rjmp    outer
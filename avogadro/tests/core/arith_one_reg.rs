@@ -69,18 +69,18 @@ fn test_neg() {
     let mut flag_as_byte: u8 = mcu.get_flags().into();
     assert_eq!(mcu.get_register(24), 0);
     assert_eq!(
-        0x22, flag_as_byte,
+        0x02, flag_as_byte,
         "Flags assertion failed: {:08b} != {:08b}",
-        0x22, flag_as_byte
+        0x02, flag_as_byte
     );
     // 0xFF -> 0x01
     mcu.step();
     flag_as_byte = mcu.get_flags().into();
     assert_eq!(mcu.get_register(25), 0x01);
     assert_eq!(
-        0x1, flag_as_byte,
+        0x21, flag_as_byte,
         "Flags assertion failed: {:08b} != {:08b}",
-        0x1, flag_as_byte
+        0x21, flag_as_byte
     );
     // 0x77 -> 0x89
     mcu.step();
@@ -105,9 +105,9 @@ fn test_neg() {
     flag_as_byte = mcu.get_flags().into();
     assert_eq!(mcu.get_register(28), 0x80);
     assert_eq!(
-        0x2D, flag_as_byte,
+        0x0D, flag_as_byte,
         "Flags assertion failed: {:08b} != {:08b}",
-        0x2D, flag_as_byte
+        0x0D, flag_as_byte
     );
 }
 
@@ -1,5 +1,6 @@
 extern crate avr_avogadro;
 
+use avr_avogadro::avr_asm;
 use avr_avogadro::core::mcu_factory::McuFactory;
 
 const MEM_MAX: usize = 1024;
@@ -90,9 +91,78 @@ fn test_ret() {
     mcu.step(); // pc -> C
     mcu.step(); // pc -> E
     assert_eq!(mcu.get_program_counter(), 0xE);
-    mcu.step(); // exec ret -> PC should be 0x6 + 2
+    mcu.step(); // exec ret -> PC should be 0x6
     assert_eq!(mcu.get_stack_pointer(), 0);
+    assert_eq!(mcu.get_program_counter(), 0x6);
+    mcu.step(); // pc -> 8
     assert_eq!(mcu.get_program_counter(), 0x8);
-    mcu.step(); // pc -> E
+}
+
+/// Tests absolute call and jump, which differ only in bit 1 of the first word
+/// CALL opcode: 1001 010k kkkk 111k kkkk kkkk kkkk kkkk
+/// JMP opcode: 1001 010k kkkk 110k kkkk kkkk kkkk kkkk
+#[test]
+fn test_call_jmp() {
+    let mut mcu = McuFactory::create("atmega328p");
+    let mut program_memory = vec![0; MEM_MAX];
+    program_memory[..4].copy_from_slice(&[0x0E, 0x94, 0x10, 0x00]); // call 0x20
+    program_memory[0x20..0x24].copy_from_slice(&[0x0C, 0x94, 0x30, 0x00]); // jmp 0x60
+    mcu.load_program_memory(&program_memory);
+    let stack_top = mcu.get_data_size() as u16;
+    mcu.step(); // exec call -> PC should be 0x20
+    assert_eq!(mcu.get_program_counter(), 0x20);
+    assert_eq!(mcu.get_stack_pointer(), stack_top - 2);
+    assert_eq!(mcu.get_data_byte(0), 0x4); // return address low
+    mcu.step(); // exec jmp, the stack is untouched
+    assert_eq!(mcu.get_program_counter(), 0x60);
+    assert_eq!(mcu.get_stack_pointer(), stack_top - 2);
+}
+
+/// Tests indirect calls and jumps, to the word address in Z
+/// ICALL opcode: 1001 0101 0000 1001 = 0x9509
+/// IJMP opcode: 1001 0100 0000 1001 = 0x9409
+#[test]
+fn test_icall_ijmp() {
+    let mut mcu = McuFactory::create("attiny85");
+    let program = avr_asm!(
+        "ldi r30, 5",
+        "ldi r31, 0",
+        "icall",
+        "nop",
+        "nop",
+        "ldi r30, 8",
+        "ijmp",
+        "nop",
+        "nop"
+    );
+    mcu.load_program_memory(&program);
+    mcu.step();
+    mcu.step();
+    mcu.step(); // exec icall -> PC should be Z * 2
     assert_eq!(mcu.get_program_counter(), 0xA);
+    assert_eq!(mcu.get_stack_pointer(), mcu.get_data_size() as u16 - 2);
+    assert_eq!(mcu.get_data_byte(0), 0x6); // return address low
+    assert_eq!(mcu.get_data_byte(1), 0); // return address hi
+    assert_eq!(mcu.get_cycle_count(), 5);
+    mcu.step();
+    mcu.step(); // exec ijmp
+    assert_eq!(mcu.get_program_counter(), 0x10);
+    assert_eq!(mcu.get_stack_pointer(), mcu.get_data_size() as u16 - 2);
+    assert_eq!(mcu.get_cycle_count(), 8);
+}
+
+/// EICALL and EIJMP ignore EIND, as the program counter has 16 bits
+#[test]
+fn test_eicall_eijmp() {
+    let mut mcu = McuFactory::create("atmega2560");
+    let program = avr_asm!("ldi r30, 4", "eicall", "nop", "nop", "ldi r30, 1", "eijmp");
+    mcu.load_program_memory(&program);
+    mcu.step();
+    mcu.step();
+    assert_eq!(mcu.get_program_counter(), 0x8);
+    assert_eq!(mcu.get_cycle_count(), 5);
+    mcu.step();
+    mcu.step();
+    assert_eq!(mcu.get_program_counter(), 0x2);
+    assert_eq!(mcu.get_cycle_count(), 8);
 }
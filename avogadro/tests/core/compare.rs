@@ -89,6 +89,8 @@ fn test_cpc_equals() {
     mcu.load_program_memory(&memory_data);
     let mut flags = mcu.get_flags();
     flags.carry = true;
+    // Lower bytes were equal, CPC only clears Z
+    flags.zero = true;
     mcu.set_flags(flags);
 
     mcu.step();
@@ -110,6 +112,8 @@ fn test_cpc_rr_overflow() {
     mcu.load_program_memory(&memory_data);
     let mut flags = mcu.get_flags();
     flags.carry = true;
+    // Lower bytes were equal, CPC only clears Z
+    flags.zero = true;
     mcu.set_flags(flags);
 
     mcu.step();
@@ -1,6 +1,6 @@
 extern crate avr_avogadro;
 
-use avr_avogadro::core::decoder::Decoder;
+use avr_avogadro::core::decoder::{is_return, Decoder};
 use std::fmt::Write;
 
 #[test]
@@ -10,6 +10,7 @@ use std::fmt::Write;
 fn test_instruction_display() {
     let instructions_test_set: Vec<(u16, &str)> = vec![
         (0x0000, "nop"),            // 0000 0000 0000 0000: nop
+        (0x0001, ".word\t0x0001"),  // 0000 0000 xxxx xxxx (reserved)
         (0x0101, "movw\tr0, r2"),   // 0000 0001 DDDD RRRR: movw rd, rr (move register pair)
         (0x0200, "muls\tr16, r16"), // 0000 0010 dddd rrrr: muls rd,rr
         (0x02FF, "muls\tr31, r31"),
@@ -153,22 +154,22 @@ fn test_instruction_display() {
         (0x95d8, "elpm"),
         (0x95e8, "spm"),
         (0x95f8, "spm\tz+"),
-        //(0x9409, "ijmp"),
-        //(0x9419, "eijmp"),
-        //(0x9509, "icall"),
-        //(0x9519, "eicall"),
-        //(0x940a, "dec\tr0"),
-        //(0x95fa, "dec\tr31"),
+        (0x9409, "ijmp"),
+        (0x9419, "eijmp"),
+        (0x9509, "icall"),
+        (0x9519, "eicall"),
+        (0x940a, "dec\tr0"),
+        (0x95fa, "dec\tr31"),
         //(0x940b, "des\t0"),
         //(0x94fb, "des\t15"),
         //// (0x940c,""), JMP/CALL abs22
         //// (0x95fd,""), JMP/CALL abs22
         //// (0x940e,""), JMP/CALL abs22
         //// (0x95ff,""), JMP/CALL abs22
-        //(0x9600, "adiw"),
-        //(0x96ff, "adiw"),
-        //(0x9700, "sbiw"),
-        //(0x97ff, "sbiw"),
+        (0x9600, "adiw\tr24, 0x00"),
+        (0x96ff, "adiw\tr30, 0x3f"),
+        (0x9700, "sbiw\tr24, 0x00"),
+        (0x97ff, "sbiw\tr30, 0x3f"),
         (0x9800, "cbi\t0x00, 0"),
         (0x98ff, "cbi\t0x1f, 7"),
         (0x9900, "sbic\t0x00, 0"),
@@ -248,3 +249,13 @@ fn test_instruction_display() {
         buf.clear();
     }
 }
+
+#[test]
+/// `ret` and `reti` return, calls and other words with their bits don't
+fn test_is_return() {
+    assert!(is_return(0x9508)); // ret
+    assert!(is_return(0x9518)); // reti
+    assert!(!is_return(0x9509)); // icall
+    assert!(!is_return(0x9408)); // sec
+    assert!(!is_return(0x9588)); // sleep
+}
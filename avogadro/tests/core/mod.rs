@@ -9,7 +9,9 @@ mod in_out;
 mod load_store;
 mod logic_ops;
 mod mcu;
+mod opcodes;
 mod skip;
+mod sreg;
 mod sub;
 mod subi;
 mod transfer_indirect;
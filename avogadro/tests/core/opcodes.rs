@@ -0,0 +1,266 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::core::decoder::Decoder;
+use avr_avogadro::core::Instruction;
+use std::collections::HashSet;
+
+/// Opcode reference table, see its header for the format
+const OPCODE_TABLE: &str = include_str!("../opcodes.txt");
+
+/// Cores whose instructions the decoder knows. Reduced core `LDS`/`STS`
+/// reuse the `LDD`/`STD` encodings.
+const DECODER_CORES: [&str; 5] = ["AVR", "AVRe", "AVRe+", "AVRxm", "AVRxt"];
+
+/// Table entries, by syntax, that the decoder reports as `Unsupported`
+const UNSUPPORTED: [&str; 16] = [
+    "lds r{d}, k",
+    "sts k, r{r}",
+    "lpm r{d}, Z",
+    "lpm r{d}, Z+",
+    "elpm r{d}, Z",
+    "elpm r{d}, Z+",
+    "xch Z, r{r}",
+    "las Z, r{r}",
+    "lac Z, r{r}",
+    "lat Z, r{r}",
+    "des {K}",
+    "mul r{d}, r{r}",
+    "bld r{d}, {b}",
+    "bst r{d}, {b}",
+    "sbrc r{r}, {b}",
+    "sbrs r{r}, {b}",
+];
+
+struct Opcode {
+    mnemonic: String,
+    mask: u16,
+    value: u16,
+    /// Bit positions of each operand field, most significant first
+    fields: Vec<(char, Vec<u16>)>,
+    words: usize,
+    /// Minimum cycles, `None` if device dependent
+    cycles: Option<usize>,
+    cores: Vec<String>,
+    syntax: String,
+}
+
+impl Opcode {
+    fn parse(line: &str) -> Opcode {
+        let columns: Vec<&str> = line.split('|').map(str::trim).collect();
+        assert_eq!(columns.len(), 6, "Wrong column count: {}", line);
+        let bits: Vec<char> = columns[1].chars().filter(|c| *c != ' ').collect();
+        assert_eq!(bits.len(), 16, "Wrong pattern: {}", line);
+        let (mut mask, mut value) = (0, 0);
+        let mut fields: Vec<(char, Vec<u16>)> = Vec::new();
+        for (i, bit) in bits.iter().enumerate() {
+            let position = 15 - i as u16;
+            match bit {
+                '0' | '1' => {
+                    mask |= 1 << position;
+                    value |= u16::from(*bit == '1') << position;
+                }
+                field => match fields.iter_mut().find(|(name, _)| name == field) {
+                    Some((_, positions)) => positions.push(position),
+                    None => fields.push((*field, vec![position])),
+                },
+            }
+        }
+        Opcode {
+            mnemonic: columns[0].to_owned(),
+            mask,
+            value,
+            fields,
+            words: columns[2].parse().unwrap(),
+            cycles: columns[3].split('-').next().unwrap().parse().ok(),
+            cores: columns[4].split_whitespace().map(str::to_owned).collect(),
+            syntax: columns[5].to_owned(),
+        }
+    }
+
+    fn matches(&self, word: u16) -> bool {
+        word & self.mask == self.value
+    }
+
+    fn field(&self, name: char, word: u16) -> (i64, usize) {
+        let (_, positions) = self
+            .fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .unwrap_or_else(|| panic!("No field {} in {}", name, self.syntax));
+        let value = positions.iter().fold(0, |value, position| {
+            value << 1 | i64::from(word >> position & 1)
+        });
+        (value, positions.len())
+    }
+
+    /// Expected `Display` of `word`, tab separating mnemonic and operands
+    fn display(&self, word: u16) -> String {
+        let mut text = self.syntax.replacen(' ', "\t", 1);
+        while let Some(start) = text.find('{') {
+            let end = start + text[start..].find('}').unwrap();
+            let operand = self.operand(&text[start + 1..end], word);
+            text.replace_range(start..=end, &operand);
+        }
+        text
+    }
+
+    /// Formats operand `[scale*]field[+offset][:format]`
+    fn operand(&self, spec: &str, word: u16) -> String {
+        let (expression, format) = spec.split_once(':').unwrap_or((spec, ""));
+        let (scale, expression) = match expression.split_once('*') {
+            Some((scale, rest)) => (scale.parse().unwrap(), rest),
+            None => (1, expression),
+        };
+        let (name, offset) = match expression.split_once('+') {
+            Some((name, offset)) => (name, offset.parse().unwrap()),
+            None => (expression, 0),
+        };
+        let (mut value, width) = self.field(name.chars().next().unwrap(), word);
+        if format == "+" && value >= 1 << (width - 1) {
+            value -= 1 << width;
+        }
+        let value = value * scale + offset;
+        match format {
+            "+" => format!("{:+}", value),
+            "x2" => format!("{:02x}", value),
+            "X2" => format!("{:02X}", value),
+            _ => format!("{}", value),
+        }
+    }
+
+    /// Number of fixed bits, higher for special cases of an encoding
+    fn specificity(&self) -> u32 {
+        self.mask.count_ones()
+    }
+
+    fn is_decoded(&self) -> bool {
+        self.cores
+            .iter()
+            .any(|core| DECODER_CORES.contains(&core.as_str()))
+    }
+}
+
+fn read_table() -> Vec<Opcode> {
+    OPCODE_TABLE
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Opcode::parse)
+        .collect()
+}
+
+/// Most specific entry matching `word` among `opcodes`, checking it's the
+/// only one
+fn lookup<'a>(opcodes: &[&'a Opcode], word: u16, core: &str) -> Option<&'a Opcode> {
+    let matches: Vec<&Opcode> = opcodes
+        .iter()
+        .copied()
+        .filter(|opcode| opcode.matches(word))
+        .collect();
+    let best = matches.iter().map(|opcode| opcode.specificity()).max()?;
+    let best: Vec<&Opcode> = matches
+        .into_iter()
+        .filter(|opcode| opcode.specificity() == best)
+        .collect();
+    assert_eq!(
+        best.len(),
+        1,
+        "{:#06x} is ambiguous on {}: {:?}",
+        word,
+        core,
+        best.iter().map(|opcode| &opcode.syntax).collect::<Vec<_>>()
+    );
+    Some(best[0])
+}
+
+#[test]
+/// Each word has at most one interpretation on every core
+fn test_opcode_table_unambiguous() {
+    let table = read_table();
+    let cores = ["AVR", "AVRe", "AVRe+", "AVRxm", "AVRxt", "AVRrc"];
+    for opcode in &table {
+        assert!(
+            opcode
+                .cores
+                .iter()
+                .all(|core| cores.contains(&core.as_str())),
+            "Unknown core in {}",
+            opcode.syntax
+        );
+        assert!(opcode.words == 1 || opcode.words == 2);
+    }
+    for core in cores.iter() {
+        let opcodes: Vec<&Opcode> = table
+            .iter()
+            .filter(|opcode| opcode.cores.iter().any(|c| c == core))
+            .collect();
+        for word in 0..=u16::MAX {
+            lookup(&opcodes, word, core);
+        }
+    }
+}
+
+#[test]
+/// Every word decodes and displays as the opcode table says, or as
+/// `Unsupported` if the decoder doesn't know it
+fn test_decoder_conformance() {
+    let table = read_table();
+    let opcodes: Vec<&Opcode> = table.iter().filter(|opcode| opcode.is_decoded()).collect();
+    let unsupported: HashSet<&str> = UNSUPPORTED.iter().copied().collect();
+    for syntax in &unsupported {
+        assert!(
+            opcodes.iter().any(|opcode| opcode.syntax == *syntax),
+            "{} is not in the table",
+            syntax
+        );
+    }
+    for word in 0..=u16::MAX {
+        let instruction = Decoder::decode(word);
+        let display = instruction.to_string();
+        let is_unsupported = matches!(instruction, Instruction::Unsupported { .. });
+        let opcode = match lookup(&opcodes, word, "the decoder") {
+            Some(opcode) if !unsupported.contains(opcode.syntax.as_str()) => opcode,
+            _ => {
+                assert!(
+                    is_unsupported,
+                    "{:#06x} should be unsupported, decoded as {}",
+                    word, display
+                );
+                assert_eq!(display, format!(".word\t0x{:04x}", word));
+                continue;
+            }
+        };
+        assert!(
+            !is_unsupported,
+            "{:#06x} ({}) decoded as unsupported",
+            word, opcode.syntax
+        );
+        if opcode.words == 1 {
+            assert_eq!(
+                display,
+                opcode.display(word),
+                "Wrong display of {:#06x}",
+                word
+            );
+        } else {
+            // Operands are in the next word
+            assert_eq!(display.split('\t').next().unwrap(), opcode.mnemonic);
+        }
+        if let Some(cycles) = opcode.cycles {
+            assert_eq!(
+                instruction.cycles(),
+                cycles,
+                "Wrong cycles of {:#06x} ({})",
+                word,
+                display
+            );
+        }
+    }
+}
+
+#[test]
+/// One register operations without a mnemonic display as their raw word
+fn test_display_reserved_one_reg_op() {
+    let instruction = Instruction::OneRegOp { rd: 17, op: 0x4 };
+    assert_eq!(instruction.to_string(), ".word\t0x9514");
+}
@@ -0,0 +1,395 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use proptest::prelude::*;
+
+/// Status register bits
+const C: u8 = 0;
+const Z: u8 = 1;
+const N: u8 = 2;
+const V: u8 = 3;
+const S: u8 = 4;
+const H: u8 = 5;
+
+/// Initial SREG values, covering every combination of carry and zero with the
+/// other flags both cleared and set
+const SREG_IN: [u8; 4] = [0x00, 0xFD, 0xFE, 0x03];
+
+/// Destination and source registers, both usable by immediate instructions
+const RD: u8 = 20;
+const RR: u8 = 21;
+
+/// Instructions updating the status register, with their operands
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    Cp,
+    Cpc,
+    And,
+    Eor,
+    Or,
+    Mov,
+    Subi,
+    Sbci,
+    Cpi,
+    Andi,
+    Ori,
+    Com,
+    Neg,
+    Swap,
+    Inc,
+    Dec,
+    Asr,
+    Lsr,
+    Ror,
+}
+
+const TWO_REG_OPS: [Op; 10] = [
+    Op::Add,
+    Op::Adc,
+    Op::Sub,
+    Op::Sbc,
+    Op::Cp,
+    Op::Cpc,
+    Op::And,
+    Op::Eor,
+    Op::Or,
+    Op::Mov,
+];
+
+const IMMEDIATE_OPS: [Op; 5] = [Op::Subi, Op::Sbci, Op::Cpi, Op::Andi, Op::Ori];
+
+const ONE_REG_OPS: [Op; 8] = [
+    Op::Com,
+    Op::Neg,
+    Op::Swap,
+    Op::Inc,
+    Op::Dec,
+    Op::Asr,
+    Op::Lsr,
+    Op::Ror,
+];
+
+fn bit(value: impl Into<u32>, n: u8) -> bool {
+    value.into() >> n & 1 != 0
+}
+
+/// Status register after changing `flags`, keeping the other bits of `sreg`
+fn update(sreg: u8, flags: &[(u8, bool)]) -> u8 {
+    flags.iter().fold(sreg, |sreg, (flag, value)| {
+        sreg & !(1 << flag) | u8::from(*value) << flag
+    })
+}
+
+/// Flags of `Rd - Rr - C` from the manual formulas. `keep_zero` is true for
+/// `CPC`, `SBC` and `SBCI`, which leave Z unchanged on a zero result
+fn sub_flags(sreg: u8, rd: u8, rr: u8, r: u8, keep_zero: bool) -> u8 {
+    let h = !bit(rd, 3) && bit(rr, 3) || bit(rr, 3) && bit(r, 3) || bit(r, 3) && !bit(rd, 3);
+    let v = bit(rd, 7) && !bit(rr, 7) && !bit(r, 7) || !bit(rd, 7) && bit(rr, 7) && bit(r, 7);
+    let n = bit(r, 7);
+    let z = r == 0 && (!keep_zero || bit(sreg, Z));
+    let c = !bit(rd, 7) && bit(rr, 7) || bit(rr, 7) && bit(r, 7) || bit(r, 7) && !bit(rd, 7);
+    update(sreg, &[(H, h), (S, n ^ v), (V, v), (N, n), (Z, z), (C, c)])
+}
+
+/// Flags of logic instructions, clearing V
+fn logic_flags(sreg: u8, r: u8) -> u8 {
+    let n = bit(r, 7);
+    update(sreg, &[(S, n), (V, false), (N, n), (Z, r == 0)])
+}
+
+/// Flags of shifts, where C is the bit shifted out
+fn shift_flags(sreg: u8, r: u8, c: bool) -> u8 {
+    let n = bit(r, 7);
+    let v = n ^ c;
+    update(sreg, &[(S, n ^ v), (V, v), (N, n), (Z, r == 0), (C, c)])
+}
+
+/// Result and status register of `op`, following the instruction set manual
+fn reference(op: Op, rd: u8, rr: u8, sreg: u8) -> (u8, u8) {
+    let carry = u8::from(bit(sreg, C));
+    match op {
+        Op::Add | Op::Adc => {
+            let carry = if let Op::Adc = op { carry } else { 0 };
+            let r = rd.wrapping_add(rr).wrapping_add(carry);
+            let h =
+                bit(rd, 3) && bit(rr, 3) || bit(rr, 3) && !bit(r, 3) || !bit(r, 3) && bit(rd, 3);
+            let v =
+                bit(rd, 7) && bit(rr, 7) && !bit(r, 7) || !bit(rd, 7) && !bit(rr, 7) && bit(r, 7);
+            let n = bit(r, 7);
+            let c =
+                bit(rd, 7) && bit(rr, 7) || bit(rr, 7) && !bit(r, 7) || !bit(r, 7) && bit(rd, 7);
+            let sreg = update(
+                sreg,
+                &[(H, h), (S, n ^ v), (V, v), (N, n), (Z, r == 0), (C, c)],
+            );
+            (r, sreg)
+        }
+        Op::Sub | Op::Subi | Op::Cp | Op::Cpi => {
+            let r = rd.wrapping_sub(rr);
+            let result = if let Op::Sub | Op::Subi = op { r } else { rd };
+            (result, sub_flags(sreg, rd, rr, r, false))
+        }
+        Op::Sbc | Op::Sbci | Op::Cpc => {
+            let r = rd.wrapping_sub(rr).wrapping_sub(carry);
+            let result = if let Op::Cpc = op { rd } else { r };
+            (result, sub_flags(sreg, rd, rr, r, true))
+        }
+        Op::And | Op::Andi => (rd & rr, logic_flags(sreg, rd & rr)),
+        Op::Or | Op::Ori => (rd | rr, logic_flags(sreg, rd | rr)),
+        Op::Eor => (rd ^ rr, logic_flags(sreg, rd ^ rr)),
+        Op::Mov => (rr, sreg),
+        Op::Com => {
+            let r = 0xFF - rd;
+            (r, update(logic_flags(sreg, r), &[(C, true)]))
+        }
+        Op::Neg => {
+            let r = 0_u8.wrapping_sub(rd);
+            let h = bit(r, 3) || bit(rd, 3);
+            let v = r == 0x80;
+            let n = bit(r, 7);
+            let flags = [(H, h), (S, n ^ v), (V, v), (N, n), (Z, r == 0), (C, r != 0)];
+            (r, update(sreg, &flags))
+        }
+        Op::Swap => (rd.rotate_left(4), sreg),
+        Op::Inc | Op::Dec => {
+            let (r, v) = if let Op::Inc = op {
+                (rd.wrapping_add(1), rd == 0x7F)
+            } else {
+                (rd.wrapping_sub(1), rd == 0x80)
+            };
+            let n = bit(r, 7);
+            (r, update(sreg, &[(S, n ^ v), (V, v), (N, n), (Z, r == 0)]))
+        }
+        Op::Asr => {
+            let r = rd >> 1 | rd & 0x80;
+            (r, shift_flags(sreg, r, bit(rd, 0)))
+        }
+        Op::Lsr => {
+            let r = rd >> 1;
+            (r, shift_flags(sreg, r, bit(rd, 0)))
+        }
+        Op::Ror => {
+            let r = rd >> 1 | carry << 7;
+            (r, shift_flags(sreg, r, bit(rd, 0)))
+        }
+    }
+}
+
+/// Encodes `op` on `RD` and `RR`, or `RD` and constant `k`
+fn encode(op: Op, k: u8) -> u16 {
+    let (rd, rr, k) = (u16::from(RD), u16::from(RR), u16::from(k));
+    let two_reg = |base: u16| base | (rr & 0x10) << 5 | rd << 4 | rr & 0xF;
+    let immediate = |base: u16| base | (k & 0xF0) << 4 | (rd - 16) << 4 | k & 0xF;
+    let one_reg = |op: u16| 0x9400 | rd << 4 | op;
+    match op {
+        Op::Cpc => two_reg(0x0400),
+        Op::Sbc => two_reg(0x0800),
+        Op::Add => two_reg(0x0C00),
+        Op::Cp => two_reg(0x1400),
+        Op::Sub => two_reg(0x1800),
+        Op::Adc => two_reg(0x1C00),
+        Op::And => two_reg(0x2000),
+        Op::Eor => two_reg(0x2400),
+        Op::Or => two_reg(0x2800),
+        Op::Mov => two_reg(0x2C00),
+        Op::Cpi => immediate(0x3000),
+        Op::Sbci => immediate(0x4000),
+        Op::Subi => immediate(0x5000),
+        Op::Ori => immediate(0x6000),
+        Op::Andi => immediate(0x7000),
+        Op::Com => one_reg(0x0),
+        Op::Neg => one_reg(0x1),
+        Op::Swap => one_reg(0x2),
+        Op::Inc => one_reg(0x3),
+        Op::Asr => one_reg(0x5),
+        Op::Lsr => one_reg(0x6),
+        Op::Ror => one_reg(0x7),
+        Op::Dec => one_reg(0xA),
+    }
+}
+
+/// Loads `words` as program, one instruction per address
+fn load_words(mcu: &mut Mcu, words: &[u16]) {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    mcu.load_program_memory(&bytes);
+}
+
+/// Executes the instruction at word `address` with the given registers and
+/// status register
+fn execute(mcu: &mut Mcu, address: u16, registers: &[(u8, u8)], sreg: u8) -> u8 {
+    for (register, value) in registers {
+        mcu.set_register(*register, *value);
+    }
+    mcu.set_flags(sreg.into());
+    mcu.set_program_counter(address * 2);
+    mcu.step();
+    u8::from(mcu.get_flags())
+}
+
+/// Checks `op` against the reference model over all operands and initial
+/// status registers
+fn check_exhaustive(mcu: &mut Mcu, op: Op, address: impl Fn(u8) -> u16) {
+    for rd in 0..=u8::MAX {
+        for rr in 0..=u8::MAX {
+            for sreg in SREG_IN.iter().copied() {
+                let flags = execute(mcu, address(rr), &[(RD, rd), (RR, rr)], sreg);
+                let (expected, expected_flags) = reference(op, rd, rr, sreg);
+                assert_eq!(
+                    (mcu.get_register(RD), flags),
+                    (expected, expected_flags),
+                    "{:?} rd {:#04x}, rr {:#04x}, sreg {:#010b}",
+                    op,
+                    rd,
+                    rr,
+                    sreg
+                );
+            }
+        }
+    }
+}
+
+#[test]
+/// Two register instructions follow the reference model
+fn test_sreg_two_reg() {
+    let mut mcu = McuFactory::create("attiny85");
+    for op in TWO_REG_OPS.iter().copied() {
+        load_words(&mut mcu, &[encode(op, 0)]);
+        check_exhaustive(&mut mcu, op, |_| 0);
+    }
+}
+
+#[test]
+/// Register-immediate instructions follow the reference model, with every
+/// constant at its own address
+fn test_sreg_immediate() {
+    let mut mcu = McuFactory::create("attiny85");
+    for op in IMMEDIATE_OPS.iter().copied() {
+        let program: Vec<u16> = (0..=u8::MAX).map(|k| encode(op, k)).collect();
+        load_words(&mut mcu, &program);
+        check_exhaustive(&mut mcu, op, u16::from);
+    }
+}
+
+#[test]
+/// One register instructions follow the reference model
+fn test_sreg_one_reg() {
+    let mut mcu = McuFactory::create("attiny85");
+    for op in ONE_REG_OPS.iter().copied() {
+        load_words(&mut mcu, &[encode(op, 0)]);
+        for rd in 0..=u8::MAX {
+            for sreg in SREG_IN.iter().copied() {
+                let flags = execute(&mut mcu, 0, &[(RD, rd)], sreg);
+                let (expected, expected_flags) = reference(op, rd, 0, sreg);
+                assert_eq!(
+                    (mcu.get_register(RD), flags),
+                    (expected, expected_flags),
+                    "{:?} rd {:#04x}, sreg {:#010b}",
+                    op,
+                    rd,
+                    sreg
+                );
+            }
+        }
+    }
+}
+
+#[test]
+/// A multi-byte comparison is only zero if every byte is equal
+fn test_sreg_multi_byte_zero() {
+    let mut mcu = McuFactory::create("attiny85");
+    // cp r20, r22; cpc r21, r23
+    load_words(&mut mcu, &[0x1746, 0x0757]);
+    let registers = [(20, 0x01), (21, 0x12), (22, 0x00), (23, 0x12)];
+    execute(&mut mcu, 0, &registers, 0);
+    mcu.step();
+    let flags = mcu.get_flags();
+    assert!(!flags.zero);
+    assert!(!flags.carry);
+}
+
+#[test]
+/// `BSET` and `BCLR` change only their status register bit
+fn test_sreg_set_clear() {
+    let mut mcu = McuFactory::create("attiny85");
+    let program: Vec<u16> = (0..16).map(|op| 0x9408 | op << 4).collect();
+    load_words(&mut mcu, &program);
+    for s in 0..8 {
+        for sreg in 0..=u8::MAX {
+            assert_eq!(execute(&mut mcu, s, &[], sreg), sreg | 1 << s);
+            assert_eq!(execute(&mut mcu, s + 8, &[], sreg), sreg & !(1 << s));
+        }
+    }
+}
+
+/// Word result and status register of `ADIW`/`SBIW`
+fn reference_word(is_add: bool, rd: u16, k: u8, sreg: u8) -> (u16, u8) {
+    let r = if is_add {
+        rd.wrapping_add(k.into())
+    } else {
+        rd.wrapping_sub(k.into())
+    };
+    let (v, c) = if is_add {
+        (!bit(rd, 15) && bit(r, 15), !bit(r, 15) && bit(rd, 15))
+    } else {
+        (bit(rd, 15) && !bit(r, 15), bit(r, 15) && !bit(rd, 15))
+    };
+    let n = bit(r, 15);
+    (
+        r,
+        update(sreg, &[(S, n ^ v), (V, v), (N, n), (Z, r == 0), (C, c)]),
+    )
+}
+
+/// Product in `r1:r0` and status register of the multiplications, from
+/// sign or zero extended operands
+fn reference_mul(rd: i32, rr: i32, fractional: bool, sreg: u8) -> (u16, u8) {
+    let product = (rd * rr) as u16;
+    let r = if fractional { product << 1 } else { product };
+    (r, update(sreg, &[(Z, r == 0), (C, bit(product, 15))]))
+}
+
+proptest! {
+    #[test]
+    /// Word instructions follow the reference model, for every pointer pair
+    fn test_sreg_word(
+        is_add: bool,
+        pair in 0..4_u8,
+        rd: u16,
+        k in 0..64_u8,
+        sreg in any::<u8>(),
+    ) {
+        let mut mcu = McuFactory::create("attiny85");
+        let base: u16 = if is_add { 0x9600 } else { 0x9700 };
+        let word = base | u16::from(k & 0x30) << 2 | u16::from(pair) << 4 | u16::from(k & 0xF);
+        load_words(&mut mcu, &[word]);
+        let low = 24 + pair * 2;
+        let [rdl, rdh] = rd.to_le_bytes();
+        let flags = execute(&mut mcu, 0, &[(low, rdl), (low + 1, rdh)], sreg);
+        let result = u16::from_le_bytes([mcu.get_register(low), mcu.get_register(low + 1)]);
+        prop_assert_eq!((result, flags), reference_word(is_add, rd, k, sreg));
+    }
+
+    #[test]
+    /// Signed and fractional multiplications follow the reference model
+    fn test_sreg_mul(op in 0..5_u16, d in 0..4_u8, r in 4..8_u8, rd: u8, rr: u8, sreg: u8) {
+        let mut mcu = McuFactory::create("attiny85");
+        let (signed_rd, signed_rr) = (i32::from(rd as i8), i32::from(rr as i8));
+        let (unsigned_rd, unsigned_rr) = (i32::from(rd), i32::from(rr));
+        let (word, rd_value, rr_value, fractional) = match op {
+            0 => (0x0200, signed_rd, signed_rr, false),
+            1 => (0x0300, signed_rd, unsigned_rr, false),
+            2 => (0x0308, unsigned_rd, unsigned_rr, true),
+            3 => (0x0380, signed_rd, signed_rr, true),
+            _ => (0x0388, signed_rd, unsigned_rr, true),
+        };
+        load_words(&mut mcu, &[word | u16::from(d) << 4 | u16::from(r)]);
+        let flags = execute(&mut mcu, 0, &[(16 + d, rd), (16 + r, rr)], sreg);
+        let product = u16::from_le_bytes([mcu.get_register(0), mcu.get_register(1)]);
+        prop_assert_eq!((product, flags), reference_mul(rd_value, rr_value, fractional, sreg));
+    }
+}
@@ -96,9 +96,9 @@ fn test_std_z() {
     memory_data[1] = 0x8A;
     mcu.load_program_memory(&memory_data);
     mcu.set_register(30, 0xEA);
-    mcu.set_register(31, 0x03); // 0x3EA + 0x15 = 3FF
+    mcu.set_register(31, 0x01); // 0x1EA + 0x15 = 1FF
     mcu.set_register(15, 42);
     assert_eq!(mcu.get_program_counter(), 0x0);
     mcu.step();
-    assert_eq!(mcu.get_data_byte(0x3FF), 42);
+    assert_eq!(mcu.get_data_byte(0x1FF), 42);
 }
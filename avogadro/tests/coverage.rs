@@ -0,0 +1,138 @@
+extern crate avr_avogadro;
+
+#[path = "fixtures/mod.rs"]
+mod fixtures;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::coverage::{BranchCounts, Coverage};
+use avr_avogadro::core::elf::ElfFile;
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use fixtures::ElfBuilder;
+use std::fs;
+
+fn program() -> Vec<u8> {
+    avr_asm!(
+        "ldi r16, 3",
+        "loop:",
+        "subi r16, 1",
+        "brne loop",
+        "cpse r16, r17",
+        "ldi r18, 1",
+        "end:",
+        "rjmp end",
+        "breq end"
+    )
+}
+
+fn run_with_coverage(program: &[u8]) -> Mcu {
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(program);
+    mcu.set_coverage(Some(Coverage::new(mcu.get_program_size())));
+    for _ in 0..10 {
+        mcu.step();
+    }
+    mcu
+}
+
+#[test]
+/// Instructions count executions, conditional ones also their outcomes
+fn test_coverage_counters() {
+    let mut mcu = run_with_coverage(&program());
+    let coverage = mcu.take_coverage().unwrap();
+    assert_eq!(coverage.count(0x0), 1);
+    assert_eq!(coverage.count(0x2), 3);
+    assert_eq!(coverage.count(0x8), 0);
+    assert_eq!(coverage.count(0xa), 2);
+    assert_eq!(coverage.executed_instructions(), 5);
+    let brne = BranchCounts {
+        taken: 2,
+        not_taken: 1,
+    };
+    assert_eq!(coverage.branch(0x4), Some(brne));
+    // `cpse` skipped `ldi`
+    let cpse = BranchCounts {
+        taken: 1,
+        not_taken: 0,
+    };
+    assert_eq!(coverage.branch(0x6), Some(cpse));
+    assert_eq!(coverage.branch(0xc), None);
+    let mut report = Vec::new();
+    coverage.write_raw(&mut report).unwrap();
+    let expected = "0000: 1
+0002: 3
+0004: 3 taken=2 not_taken=1
+0006: 1 taken=1 not_taken=0
+000a: 2
+";
+    assert_eq!(String::from_utf8(report).unwrap(), expected);
+}
+
+#[test]
+/// Addresses are mapped to source lines with the ELF line table
+fn test_coverage_lcov() {
+    let path = ElfBuilder::new(&program())
+        .line(0x0, 3)
+        .line(0x2, 4)
+        .line(0x6, 5)
+        .line(0x8, 6)
+        .line(0xa, 7)
+        .line(0xc, 8)
+        .write_temp("avogadro_test_coverage_lcov.elf");
+    let elf = ElfFile::load(path.to_str().unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+    let mcu = run_with_coverage(&elf.program);
+    let mut report = Vec::new();
+    mcu.get_coverage()
+        .unwrap()
+        .write_lcov(&mut report, "blink", &elf.program, &elf.lines)
+        .unwrap();
+    let expected = "TN:blink
+SF:/src/main.c
+BRDA:4,0,0,2
+BRDA:4,0,1,1
+BRDA:5,0,0,1
+BRDA:5,0,1,0
+BRDA:8,0,0,-
+BRDA:8,0,1,-
+BRF:6
+BRH:3
+DA:3,1
+DA:4,3
+DA:5,1
+DA:6,0
+DA:7,2
+DA:8,0
+LF:6
+LH:4
+end_of_record
+";
+    assert_eq!(String::from_utf8(report).unwrap(), expected);
+}
+
+#[test]
+/// Branches are taken by their SREG bit test, even if they don't move the
+/// program counter
+fn test_coverage_branch_to_next() {
+    let program = avr_asm!("sez", "breq .+0", "clz", "breq .+0", "end:", "rjmp end");
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program);
+    mcu.set_coverage(Some(Coverage::new(mcu.get_program_size())));
+    for _ in 0..4 {
+        mcu.step();
+    }
+    assert_eq!(mcu.get_program_counter(), 0x8);
+    // `sez`, a taken `breq`, `clz` and a `breq` not taken
+    assert_eq!(mcu.get_cycle_count(), 5);
+    let coverage = mcu.take_coverage().unwrap();
+    let taken = BranchCounts {
+        taken: 1,
+        not_taken: 0,
+    };
+    assert_eq!(coverage.branch(0x2), Some(taken));
+    let not_taken = BranchCounts {
+        taken: 0,
+        not_taken: 1,
+    };
+    assert_eq!(coverage.branch(0x6), Some(not_taken));
+}
@@ -0,0 +1,445 @@
+extern crate avr_avogadro;
+
+#[path = "fixtures/mod.rs"]
+mod fixtures;
+
+use avr_avogadro::core::assembler;
+use avr_avogadro::core::call_stack::CallStack;
+use avr_avogadro::core::debug_info::Value;
+use avr_avogadro::core::debugger::{SourceDebugger, StepStop};
+use avr_avogadro::core::elf::ElfFile;
+use avr_avogadro::core::mcu::Mcu;
+use fixtures::{create_mcu, ElfBuilder};
+use gimli::write::{
+    Address, AttributeValue, Expression, Location, LocationList, Unit, UnitEntryId,
+};
+use gimli::Register;
+
+/// `main` calls `add`, lines as avr-gcc would map them
+const STEPPING_SOURCE: &str = "
+main:
+    ldi r24, 1
+    ldi r22, 2
+    rcall add
+    mov r16, r24
+loop:
+    rjmp loop
+add:
+    push r28
+    add r24, r22
+    pop r28
+    ret
+";
+
+fn stepping_program() -> (&'static str, SourceDebugger) {
+    let program = assembler::assemble(STEPPING_SOURCE).unwrap();
+    let elf = ElfBuilder::new(&program.image)
+        .function("main", 0x0, 0xa)
+        .function("add", 0xa, 0x8)
+        .line(0x0, 10)
+        .line(0x4, 11)
+        .line(0x6, 12)
+        .line(0x8, 13)
+        .line(0xa, 20)
+        .line(0xc, 21)
+        .line(0xe, 22)
+        .build();
+    let elf = ElfFile::parse(&elf).unwrap();
+    (STEPPING_SOURCE, SourceDebugger::new(elf))
+}
+
+fn line(debugger: &SourceDebugger, mcu: &Mcu) -> u32 {
+    debugger.location(mcu).unwrap().line
+}
+
+#[test]
+/// Steps stop at the first instruction of each line, entering calls
+fn test_source_step() {
+    let (source, debugger) = stepping_program();
+    let mut mcu = create_mcu(source, |_, _| {});
+    assert_eq!(debugger.location(&mcu).unwrap().file, "/src/main.c");
+    assert_eq!(line(&debugger, &mcu), 10);
+    let mut lines = Vec::new();
+    for _ in 0..6 {
+        assert_eq!(debugger.step(&mut mcu), StepStop::Line);
+        lines.push((line(&debugger, &mcu), mcu.get_program_counter()));
+    }
+    assert_eq!(
+        lines,
+        [
+            (11, 0x4),
+            (20, 0xa),
+            (21, 0xc),
+            (22, 0xe),
+            (12, 0x6),
+            (13, 0x8)
+        ]
+    );
+    // A loop back to the start of the line stops too
+    assert_eq!(debugger.step(&mut mcu), StepStop::Line);
+    assert_eq!(mcu.get_program_counter(), 0x8);
+}
+
+#[test]
+/// Stepping over runs calls to completion, stepping out stops after the
+/// return
+fn test_source_step_over_and_out() {
+    let (source, debugger) = stepping_program();
+    let mut mcu = create_mcu(source, |_, _| {});
+    debugger.step(&mut mcu);
+    assert_eq!(debugger.step_over(&mut mcu), StepStop::Line);
+    assert_eq!(line(&debugger, &mcu), 12);
+    assert_eq!(mcu.get_register(24), 3);
+
+    let mut mcu = create_mcu(source, |_, _| {});
+    debugger.step(&mut mcu);
+    debugger.step(&mut mcu);
+    debugger.step(&mut mcu);
+    assert_eq!(line(&debugger, &mcu), 21);
+    assert_eq!(debugger.step_out(&mut mcu), StepStop::Return);
+    assert_eq!(mcu.get_program_counter(), 0x6);
+    assert_eq!(mcu.get_stack_pointer(), 0x1f0);
+}
+
+#[test]
+/// Breakpoints and the cycle limit stop steps
+fn test_source_step_stops() {
+    let (source, debugger) = stepping_program();
+    let mut mcu = create_mcu(source, |_, _| {});
+    mcu.add_breakpoint(0xe);
+    debugger.step(&mut mcu);
+    assert_eq!(debugger.step_over(&mut mcu), StepStop::Breakpoint);
+    assert_eq!(mcu.get_program_counter(), 0xe);
+
+    let debugger = debugger.with_cycle_limit(100);
+    let mut mcu = create_mcu(source, |_, _| {});
+    mcu.set_program_counter(0x8);
+    let start = mcu.get_cycle_count();
+    assert_eq!(debugger.step_out(&mut mcu), StepStop::CycleLimit);
+    assert_eq!(mcu.get_cycle_count() - start, 100);
+}
+
+#[test]
+/// Backtraces show the source line of each frame
+fn test_source_backtrace() {
+    let (source, debugger) = stepping_program();
+    let mut mcu = create_mcu(source, |_, _| {});
+    assert!(debugger.backtrace(&mcu).is_none());
+    let symbols = debugger.get_elf().symbols.clone();
+    mcu.set_call_stack(Some(CallStack::new(symbols)));
+    debugger.step(&mut mcu);
+    debugger.step(&mut mcu);
+    debugger.step(&mut mcu);
+    assert_eq!(
+        debugger.backtrace(&mcu).unwrap().to_string(),
+        "#0  0x000c in add+0x2 at /src/main.c:21\n\
+         #1  0x0004 in main+0x4 at /src/main.c:11\n"
+    );
+    debugger.step_out(&mut mcu);
+    assert_eq!(debugger.backtrace(&mcu).unwrap().frames.len(), 1);
+}
+
+/// Adds a type entry with a name and size
+fn add_type(unit: &mut Unit, tag: gimli::DwTag, name: &str, size: u64) -> UnitEntryId {
+    let root = unit.root();
+    let id = unit.add(root, tag);
+    let entry = unit.get_mut(id);
+    if !name.is_empty() {
+        entry.set(gimli::DW_AT_name, AttributeValue::String(name.into()));
+    }
+    if size > 0 {
+        entry.set(gimli::DW_AT_byte_size, AttributeValue::Udata(size));
+    }
+    id
+}
+
+fn add_base_type(unit: &mut Unit, name: &str, size: u64, encoding: gimli::DwAte) -> UnitEntryId {
+    let id = add_type(unit, gimli::DW_TAG_base_type, name, size);
+    unit.get_mut(id)
+        .set(gimli::DW_AT_encoding, AttributeValue::Encoding(encoding));
+    id
+}
+
+fn add_reference(
+    unit: &mut Unit,
+    tag: gimli::DwTag,
+    name: &str,
+    target: UnitEntryId,
+) -> UnitEntryId {
+    let id = add_type(unit, tag, name, 0);
+    unit.get_mut(id)
+        .set(gimli::DW_AT_type, AttributeValue::UnitRef(target));
+    id
+}
+
+/// Adds a variable, or a parameter, with a location attribute
+fn add_variable(
+    unit: &mut Unit,
+    parent: UnitEntryId,
+    tag: gimli::DwTag,
+    name: &str,
+    type_id: UnitEntryId,
+    location: Option<AttributeValue>,
+) {
+    let id = unit.add(parent, tag);
+    let entry = unit.get_mut(id);
+    entry.set(gimli::DW_AT_name, AttributeValue::String(name.into()));
+    entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(type_id));
+    if let Some(location) = location {
+        entry.set(gimli::DW_AT_location, location);
+    }
+}
+
+fn expression(build: impl FnOnce(&mut Expression)) -> Option<AttributeValue> {
+    let mut expression = Expression::new();
+    build(&mut expression);
+    Some(AttributeValue::Exprloc(expression))
+}
+
+/// Globals, and function `f` at 0x0 to 0x10 with its frame base on Y + 1,
+/// as avr-gcc does without optimizations
+fn variables_debug_info(unit: &mut Unit) {
+    let root = unit.root();
+    let int = add_base_type(unit, "int", 2, gimli::DW_ATE_signed);
+    let char_type = add_base_type(unit, "char", 1, gimli::DW_ATE_signed_char);
+    let uint8 = add_base_type(unit, "unsigned char", 1, gimli::DW_ATE_unsigned_char);
+    let float = add_base_type(unit, "float", 4, gimli::DW_ATE_float);
+    let boolean = add_base_type(unit, "_Bool", 1, gimli::DW_ATE_boolean);
+
+    let point = add_type(unit, gimli::DW_TAG_structure_type, "point", 3);
+    let x = unit.add(point, gimli::DW_TAG_member);
+    unit.get_mut(x)
+        .set(gimli::DW_AT_name, AttributeValue::String("x".into()));
+    unit.get_mut(x)
+        .set(gimli::DW_AT_type, AttributeValue::UnitRef(int));
+    unit.get_mut(x)
+        .set(gimli::DW_AT_data_member_location, AttributeValue::Udata(0));
+    let flags = unit.add(point, gimli::DW_TAG_member);
+    unit.get_mut(flags)
+        .set(gimli::DW_AT_name, AttributeValue::String("flags".into()));
+    unit.get_mut(flags)
+        .set(gimli::DW_AT_type, AttributeValue::UnitRef(uint8));
+    // DWARF 2 style offset
+    unit.get_mut(flags).set(
+        gimli::DW_AT_data_member_location,
+        expression(|e| e.op_plus_uconst(2)).unwrap(),
+    );
+    let point_t = add_reference(unit, gimli::DW_TAG_typedef, "point_t", point);
+    let const_point = add_reference(unit, gimli::DW_TAG_const_type, "", point_t);
+    let point_pointer = add_reference(unit, gimli::DW_TAG_pointer_type, "", const_point);
+
+    let color = add_type(unit, gimli::DW_TAG_enumeration_type, "color", 1);
+    for (i, name) in ["RED", "GREEN", "BLUE"].iter().enumerate() {
+        let value = unit.add(color, gimli::DW_TAG_enumerator);
+        let entry = unit.get_mut(value);
+        entry.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(name.as_bytes().into()),
+        );
+        entry.set(gimli::DW_AT_const_value, AttributeValue::Udata(i as u64));
+    }
+
+    let name_type = add_reference(unit, gimli::DW_TAG_array_type, "", char_type);
+    let subrange = unit.add(name_type, gimli::DW_TAG_subrange_type);
+    unit.get_mut(subrange)
+        .set(gimli::DW_AT_upper_bound, AttributeValue::Udata(2));
+    let grid = add_reference(unit, gimli::DW_TAG_array_type, "", int);
+    for _ in 0..2 {
+        let subrange = unit.add(grid, gimli::DW_TAG_subrange_type);
+        unit.get_mut(subrange)
+            .set(gimli::DW_AT_count, AttributeValue::Udata(2));
+    }
+
+    let address = |address: u64| expression(|e| e.op_addr(Address::Constant(address)));
+    add_variable(
+        unit,
+        root,
+        gimli::DW_TAG_variable,
+        "counter",
+        int,
+        address(0x80_0100),
+    );
+    add_variable(
+        unit,
+        root,
+        gimli::DW_TAG_variable,
+        "ratio",
+        float,
+        address(0x80_0102),
+    );
+    add_variable(
+        unit,
+        root,
+        gimli::DW_TAG_variable,
+        "origin",
+        point_t,
+        address(0x80_0106),
+    );
+    // Declarations without location aren't globals
+    add_variable(unit, root, gimli::DW_TAG_variable, "missing", int, None);
+
+    let function = unit.add(root, gimli::DW_TAG_subprogram);
+    let entry = unit.get_mut(function);
+    entry.set(gimli::DW_AT_name, AttributeValue::String("f".into()));
+    entry.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0)),
+    );
+    entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(0x10));
+    entry.set(
+        gimli::DW_AT_frame_base,
+        expression(|e| e.op_breg(Register(28), 1)).unwrap(),
+    );
+    let parameter = gimli::DW_TAG_formal_parameter;
+    let pieces = expression(|e| {
+        e.op_reg(Register(20));
+        e.op_piece(1);
+        e.op_reg(Register(23));
+        e.op_piece(1);
+    });
+    add_variable(unit, function, parameter, "n", int, pieces);
+    let local = gimli::DW_TAG_variable;
+    let frame = |offset: i64| expression(|e| e.op_fbreg(offset));
+    add_variable(unit, function, local, "p", point_pointer, frame(0));
+    add_variable(unit, function, local, "name", name_type, frame(2));
+    add_variable(unit, function, local, "grid", grid, frame(5));
+    let in_r24 = expression(|e| e.op_reg(Register(24)));
+    add_variable(unit, function, local, "color", color, in_r24);
+    let mut in_r22 = Expression::new();
+    in_r22.op_reg(Register(22));
+    let mut in_frame = Expression::new();
+    in_frame.op_fbreg(13);
+    let list = unit.locations.add(LocationList(vec![
+        Location::StartEnd {
+            begin: Address::Constant(0),
+            end: Address::Constant(8),
+            data: in_r22,
+        },
+        Location::StartEnd {
+            begin: Address::Constant(8),
+            end: Address::Constant(0x10),
+            data: in_frame,
+        },
+    ]));
+    let block = unit.add(function, gimli::DW_TAG_lexical_block);
+    add_variable(
+        unit,
+        block,
+        local,
+        "ok",
+        boolean,
+        Some(AttributeValue::LocationListRef(list)),
+    );
+    add_variable(unit, block, local, "gone", int, None);
+}
+
+fn variables_program() -> (String, ElfFile) {
+    let source = "nop\n".repeat(16);
+    let program = assembler::assemble(&source).unwrap();
+    let elf = ElfBuilder::new(&program.image)
+        .debug_entries(variables_debug_info)
+        .build();
+    (source, ElfFile::parse(&elf).unwrap())
+}
+
+#[test]
+/// Globals are decoded by their type
+fn test_debug_info_globals() {
+    let (source, elf) = variables_program();
+    let info = &elf.debug_info;
+    let names: Vec<_> = info.globals().iter().map(|global| &global.name).collect();
+    assert_eq!(names, ["counter", "ratio", "origin"]);
+    let mut mcu = create_mcu(&source, |_, _| {});
+    mcu.load_data_memory(&[0; 0x200]);
+    for (i, byte) in [0xfe, 0xff].iter().enumerate() {
+        mcu.set_data_byte(0x100 + i as u16, *byte);
+    }
+    for (i, byte) in 1.5_f32.to_le_bytes().iter().enumerate() {
+        mcu.set_data_byte(0x102 + i as u16, *byte);
+    }
+    for (i, byte) in [0x2c, 0x01, 0x41].iter().enumerate() {
+        mcu.set_data_byte(0x106 + i as u16, *byte);
+    }
+    assert_eq!(info.read_variable(&mcu, "counter"), Some(Value::Signed(-2)));
+    assert_eq!(info.read_variable(&mcu, "ratio"), Some(Value::Float(1.5)));
+    let origin = info.read_variable(&mcu, "origin").unwrap();
+    assert_eq!(
+        origin,
+        Value::Struct(vec![
+            ("x".to_owned(), Value::Signed(300)),
+            ("flags".to_owned(), Value::Char(0x41))
+        ])
+    );
+    assert_eq!(origin.to_string(), "{x = 300, flags = 65 'A'}");
+    assert_eq!(info.read_variable(&mcu, "missing"), None);
+    let origin = info.global("origin").unwrap();
+    assert_eq!(info.type_name(origin.type_id), "point_t");
+    assert_eq!(info.type_size(origin.type_id), 3);
+}
+
+#[test]
+/// Locals are found relative to the frame base on Y, in registers, in
+/// pieces, and through location lists
+fn test_debug_info_locals() {
+    let (source, elf) = variables_program();
+    let debugger = SourceDebugger::new(elf);
+    let info = &debugger.get_elf().debug_info;
+    let mut mcu = create_mcu(&source, |_, _| {});
+    // Y = 0x150, so the frame base is 0x151
+    mcu.set_register(28, 0x50);
+    mcu.set_register(29, 0x01);
+    let frame = [0x06, 0x01, b'h', b'i', 0, 1, 0, 2, 0, 3, 0, 0xfc, 0xff, 1];
+    for (i, byte) in frame.iter().enumerate() {
+        mcu.set_data_byte(0x151 + i as u16, *byte);
+    }
+    mcu.set_register(20, 0x34);
+    mcu.set_register(23, 0x12);
+    mcu.set_register(24, 2);
+    mcu.set_register(22, 0);
+    mcu.set_program_counter(0x4);
+
+    let locals = debugger.locals(&mcu);
+    let text: Vec<_> = locals
+        .iter()
+        .map(|(variable, value)| {
+            format!(
+                "{} {} = {}",
+                info.type_name(variable.type_id),
+                variable.name,
+                value
+            )
+        })
+        .collect();
+    assert_eq!(
+        text,
+        [
+            "int n = 4660",
+            "point_t * p = 0x0106",
+            "char [3] name = {104 'h', 105 'i', 0 '\\u{0}'}",
+            "int [2] [2] grid = {{1, 2}, {3, -4}}",
+            "enum color color = BLUE",
+            "_Bool ok = false",
+            "int gone = <optimized out>",
+        ]
+    );
+    assert!(locals[0].0.is_parameter);
+    assert!(!locals[1].0.is_parameter);
+
+    // The location list moves `ok` to the frame
+    mcu.set_program_counter(0x8);
+    assert_eq!(debugger.read_variable(&mcu, "ok"), Some(Value::Bool(true)));
+    mcu.set_register(24, 7);
+    assert_eq!(
+        debugger.read_variable(&mcu, "color"),
+        Some(Value::Enum {
+            value: 7,
+            name: None
+        })
+    );
+    // Out of `f`, only globals are found
+    mcu.set_program_counter(0x10);
+    assert!(debugger.locals(&mcu).is_empty());
+    assert_eq!(debugger.read_variable(&mcu, "p"), None);
+    assert!(debugger.read_variable(&mcu, "counter").is_some());
+    assert_eq!(info.function_at(0x2).unwrap().name, "f");
+}
@@ -0,0 +1,64 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::assembler;
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+
+const CHECKSUM_SOURCE: &str = include_str!("checksum.s");
+
+fn checksum_mcu(cached: bool) -> Mcu {
+    let program = assembler::assemble(CHECKSUM_SOURCE).unwrap();
+    let mut mcu = McuFactory::create("atmega328p");
+    mcu.set_decode_cache_enabled(cached);
+    mcu.load_program_memory(&program.image);
+    mcu
+}
+
+fn step_n(mcu: &mut Mcu, count: usize) {
+    for _ in 0..count {
+        mcu.step();
+    }
+}
+
+#[test]
+/// Cached and uncached execution reach the same state in the same cycles
+fn test_decode_cache_same_execution() {
+    let mut cached = checksum_mcu(true);
+    let mut uncached = checksum_mcu(false);
+    assert!(cached.is_decode_cache_enabled());
+    assert!(!uncached.is_decode_cache_enabled());
+    for _ in 0..20 {
+        step_n(&mut cached, 1000);
+        step_n(&mut uncached, 1000);
+        assert_eq!(cached.snapshot(), uncached.snapshot());
+    }
+    assert_ne!(cached.get_register(21), 0);
+}
+
+#[test]
+/// Loaded programs, patched bytes and restored snapshots replace the
+/// instructions decoded before
+fn test_decode_cache_invalidation() {
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&avr_asm!("ldi r16, 1", "ldi r17, 1"));
+    step_n(&mut mcu, 2);
+    let snapshot = mcu.snapshot();
+    assert_eq!(mcu.get_register(16), 1);
+
+    mcu.load_program_memory(&avr_asm!("ldi r16, 2", "ldi r17, 2"));
+    mcu.set_program_counter(0);
+    step_n(&mut mcu, 2);
+    assert_eq!((mcu.get_register(16), mcu.get_register(17)), (2, 2));
+
+    // ldi r17, 3 is 0xe013
+    mcu.set_program_byte(2, 0x13);
+    mcu.set_program_counter(0);
+    step_n(&mut mcu, 2);
+    assert_eq!((mcu.get_register(16), mcu.get_register(17)), (2, 3));
+
+    mcu.restore(&snapshot).unwrap();
+    mcu.set_program_counter(0);
+    step_n(&mut mcu, 2);
+    assert_eq!((mcu.get_register(16), mcu.get_register(17)), (1, 1));
+}
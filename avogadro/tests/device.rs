@@ -0,0 +1,79 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::device::{DataRegion, DeviceDescriptor};
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::memory_map::Region;
+use avr_avogadro::ffi::mcu_wrapper::*;
+use std::ffi::CString;
+
+#[test]
+/// Data memory spans the regions backed by it, RAMEND is the last SRAM byte
+fn test_device_layouts() {
+    let layouts = [
+        ("attiny85", 0x260, 0x25f, 8 * 1024),
+        ("atmega328p", 0x900, 0x8ff, 32 * 1024),
+        ("atmega2560", 0x2200, 0x21ff, 256 * 1024),
+    ];
+    for (name, data_size, ramend, program_size) in layouts.iter() {
+        let device = DeviceDescriptor::find(name).unwrap();
+        assert_eq!(device.data_size(), *data_size, "{}", name);
+        assert_eq!(device.ramend(), *ramend, "{}", name);
+        let mcu = McuFactory::create(name);
+        assert_eq!(mcu.get_data_size(), *data_size, "{}", name);
+        assert_eq!(mcu.get_ramend(), *ramend, "{}", name);
+        assert_eq!(mcu.get_program_size(), *program_size, "{}", name);
+    }
+    assert!(DeviceDescriptor::find("atmega8").is_none());
+
+    let device = DeviceDescriptor::find("atmega2560").unwrap();
+    assert_eq!(
+        device.data_regions[3],
+        DataRegion::new(0x200, 8 * 1024, Region::Sram)
+    );
+    let memory_map = device.memory_map();
+    assert_eq!(memory_map.region(0x1ff), Region::ExtendedIo);
+    assert_eq!(memory_map.region(0x200), Region::Sram);
+    assert_eq!(memory_map.region(0x21ff), Region::Sram);
+    assert_eq!(memory_map.region(0x2200), Region::ExternalRam);
+    assert_eq!(memory_map.region(0xffff), Region::ExternalRam);
+}
+
+#[test]
+/// The stack wraps at the end of data memory, which isn't a power of 2
+fn test_device_stack() {
+    let mut mcu = McuFactory::create("atmega328p");
+    mcu.load_program_memory(&avr_asm!("ldi r16, 0x42", "push r16", "rcall .+0"));
+    mcu.step();
+    mcu.step();
+    assert_eq!(mcu.get_stack_pointer(), 0x8ff);
+    assert_eq!(mcu.get_data_byte(0x8ff), 0x42);
+    mcu.step();
+    assert_eq!(mcu.get_stack_pointer(), 0x8fd);
+}
+
+#[test]
+/// The whole data space can be fetched through the C API
+fn test_device_ffi_data_memory() {
+    let device = CString::new("atmega328p").unwrap();
+    unsafe {
+        let mcu = mcu_create(device.as_ptr());
+        assert!(!mcu.is_null());
+        let mut size = 0;
+        assert_eq!(mcu_get_data_size(mcu, &mut size), McuStatus::Ok);
+        assert_eq!(size, 0x900);
+        let mut memory = vec![0xAA; size];
+        memory[0x8ff] = 0x5A;
+        assert_eq!(
+            mcu_load_data_memory(mcu, memory.as_ptr(), memory.len()),
+            McuStatus::Ok
+        );
+        let mut fetched = vec![0; size];
+        assert_eq!(
+            mcu_get_data_memory(mcu, fetched.as_mut_ptr(), size),
+            McuStatus::Ok
+        );
+        assert_eq!(fetched, memory);
+        mcu_destroy(mcu);
+    }
+}
@@ -0,0 +1,93 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::core::disassembler::Disassembler;
+use avr_avogadro::core::symbols::SymbolTable;
+use std::fs;
+use std::path::PathBuf;
+
+fn read_fixture(name: &str) -> Vec<u8> {
+    let mut pathbuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    pathbuf.push("tests");
+    pathbuf.push(name);
+    fs::read(pathbuf).unwrap()
+}
+
+#[test]
+/// Disassembles `blink.bin` with the symbols listed by
+/// `avr-nm 03-blink.o > blink.sym` and compares it against the output of
+/// `avr-objdump -d 03-blink.o`
+fn test_objdump_blink() {
+    let program = read_fixture("blink.bin");
+    let symbols = String::from_utf8(read_fixture("blink.sym")).unwrap();
+    let expected = String::from_utf8(read_fixture("blink.objdump")).unwrap();
+    let mut disassembler = Disassembler::new(&program);
+    disassembler.set_symbols(SymbolTable::from_nm(&symbols));
+    let mut output = String::new();
+    disassembler.write_objdump(&mut output, ".text").unwrap();
+    for (line, (expected_line, actual_line)) in expected.lines().zip(output.lines()).enumerate() {
+        assert_eq!(expected_line, actual_line, "Mismatch at line {}", line + 1);
+    }
+    assert_eq!(expected, output);
+}
+
+#[test]
+/// Two word instructions take 4 bytes and targets are absolute
+///
+/// 0:  0c 94 04 00  jmp 0x8
+/// 4:  80 91 60 00  lds r24, 0x0060
+/// 8:  80 93 61 00  sts 0x0061, r24
+/// c:  0e 94 00 00  call 0
+fn test_two_word_instructions() {
+    let program = vec![
+        0x0c, 0x94, 0x04, 0x00, 0x80, 0x91, 0x60, 0x00, 0x80, 0x93, 0x61, 0x00, 0x0e, 0x94, 0x00,
+        0x00,
+    ];
+    let disassembler = Disassembler::new(&program);
+    let instructions = disassembler.instructions();
+    assert_eq!(instructions.len(), 4);
+    assert_eq!(instructions[0].text(), "jmp\t0x8");
+    assert_eq!(instructions[0].target, Some(0x8));
+    assert_eq!(instructions[1].address, 0x4);
+    assert_eq!(instructions[1].text(), "lds\tr24, 0x0060");
+    assert_eq!(instructions[1].data_address, Some(0x60));
+    assert_eq!(instructions[2].text(), "sts\t0x0061, r24");
+    assert_eq!(instructions[3].text(), "call\t0");
+    assert_eq!(instructions[3].target, Some(0));
+}
+
+#[test]
+/// Hex digits follow avr-objdump: uppercase for 8 bit immediates, lowercase
+/// for the 6 bit constant of word operations
+///
+/// 0:  ef ea  ldi  r30, 0xAF
+/// 2:  ff 97  sbiw r30, 0x3f
+fn test_immediate_case() {
+    let program = vec![0xef, 0xea, 0xff, 0x97];
+    let disassembler = Disassembler::new(&program);
+    let instructions = disassembler.instructions();
+    assert_eq!(instructions[0].text(), "ldi\tr30, 0xAF");
+    assert_eq!(instructions[1].text(), "sbiw\tr30, 0x3f");
+}
+
+#[test]
+/// Targets without symbols get synthesized labels and vector table entries
+/// are marked
+///
+/// 0:  01 c0  rjmp .+2
+/// 2:  00 c0  rjmp .+0
+/// 4:  01 e1  ldi  r16, 0x11
+/// 6:  f1 f7  brne .-4
+fn test_listing_labels() {
+    let program = vec![0x01, 0xc0, 0x00, 0xc0, 0x01, 0xe1, 0xf1, 0xf7];
+    let mut disassembler = Disassembler::new(&program);
+    disassembler.set_vector_count(2);
+    let mut output = String::new();
+    disassembler.write_listing(&mut output).unwrap();
+    let expected = "__vectors:\n\
+                    \trjmp\tL_0004\t; 0000: vector 0\n\
+                    \trjmp\tL_0004\t; 0002: vector 1\n\
+                    L_0004:\n\
+                    \tldi\tr16, 0x11\t; 0004: 17\n\
+                    \tbrne\tL_0004\t; 0006\n";
+    assert_eq!(output, expected);
+}
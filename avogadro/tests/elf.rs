@@ -0,0 +1,55 @@
+extern crate avr_avogadro;
+
+#[path = "fixtures/mod.rs"]
+mod fixtures;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::elf::ElfFile;
+use avr_avogadro::core::loader;
+use fixtures::ElfBuilder;
+use std::fs;
+
+#[test]
+/// Program image, symbols and line table are read from ELF files
+fn test_elf_load() {
+    let program = avr_asm!(
+        "main:",
+        "ldi r16, 1",
+        "rcall delay",
+        "rjmp main",
+        "delay:",
+        "ret"
+    );
+    let path = ElfBuilder::new(&program)
+        .function("main", 0x0, 6)
+        .function("delay", 0x6, 2)
+        .variable("counter", 0x60, 2)
+        .line(0x0, 10)
+        .line(0x2, 11)
+        .line(0x6, 20)
+        .write_temp("avogadro_test_elf_load.elf");
+    let filename = path.to_str().unwrap();
+    let elf = ElfFile::load(filename).unwrap();
+    let loaded = loader::read_program_file(filename).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(elf.program, program);
+    assert_eq!(loaded, program);
+    assert_eq!(elf.symbols.len(), 3);
+    let delay = elf.symbols.get(0x6).unwrap();
+    assert_eq!(delay.name, "delay");
+    assert_eq!(delay.size, 2);
+    let counter = elf.symbols.by_name("counter").unwrap();
+    assert_eq!(counter.address, 0x0080_0060);
+    let row = elf.lines.find(0x4).unwrap();
+    assert_eq!(row.file, "/src/main.c");
+    assert_eq!(row.line, 11);
+    assert_eq!(elf.lines.find(0x6).unwrap().line, 20);
+    assert!(elf.lines.find(0x8).is_none());
+}
+
+#[test]
+/// Files that aren't AVR ELF files are rejected
+fn test_elf_invalid() {
+    assert!(ElfFile::parse(b"\x7fELF").is_err());
+    assert!(ElfFile::parse(&[0; 64]).is_err());
+}
@@ -0,0 +1,162 @@
+extern crate avr_avogadro;
+
+#[path = "fixtures/mod.rs"]
+mod fixtures;
+
+use avr_avogadro::core::events::{Event, EventFilter, EventKind, Fault, MemoryAccess, Observer};
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::memory_map::AccessMode;
+use fixtures::create_mcu;
+use std::sync::{Arc, Mutex};
+
+/// Adds a hook collecting the events matching `filter`
+fn collect(mcu: &mut Mcu, filter: EventFilter) -> (usize, Arc<Mutex<Vec<Event>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let collected = events.clone();
+    let id = mcu.add_hook(filter, move |event: &Event| {
+        collected.lock().unwrap().push(event.clone())
+    });
+    (id, events)
+}
+
+struct InstructionCounter(Arc<Mutex<usize>>);
+
+impl Observer for InstructionCounter {
+    fn notify(&mut self, _event: &Event) {
+        *self.0.lock().unwrap() += 1;
+    }
+}
+
+#[test]
+/// Hooks receive instructions and the memory and I/O accesses they make,
+/// filtered by kind and address
+fn test_memory_hooks() {
+    let source = "
+        ldi r16, 0x42
+        ldi r26, 0x60
+        ldi r27, 0x00
+        st X+, r16
+        st X, r16
+        ld r17, -X
+        out 0x18, r16
+        in r18, 0x16
+    ";
+    let mut mcu = create_mcu(source, |_, _| {});
+    let count = Arc::new(Mutex::new(0));
+    let counter = mcu.add_hook(
+        EventFilter::kinds(&[EventKind::Instruction]),
+        InstructionCounter(count.clone()),
+    );
+    let filter = EventFilter::kinds(&[EventKind::MemoryRead, EventKind::MemoryWrite])
+        .with_addresses(0x60..=0x60);
+    let (_, memory) = collect(&mut mcu, filter);
+    let (_, io) = collect(
+        &mut mcu,
+        EventFilter::kinds(&[EventKind::IoRead, EventKind::IoWrite]),
+    );
+    for _ in 0..8 {
+        mcu.step();
+    }
+    assert_eq!(*count.lock().unwrap(), 8);
+    let access = |pc, address, value, cycle| MemoryAccess {
+        pc,
+        address,
+        value,
+        cycle,
+    };
+    assert_eq!(
+        *memory.lock().unwrap(),
+        [
+            Event::MemoryWrite(access(0x6, 0x60, 0x42, 3)),
+            Event::MemoryRead(access(0xa, 0x60, 0x42, 7)),
+        ]
+    );
+    assert_eq!(
+        *io.lock().unwrap(),
+        [
+            Event::IoWrite(access(0xc, 0x38, 0x42, 9)),
+            Event::IoRead(access(0xe, 0x36, 0, 10)),
+        ]
+    );
+
+    assert!(mcu.remove_hook(counter));
+    assert!(!mcu.remove_hook(counter));
+    mcu.set_program_counter(0);
+    mcu.step();
+    assert_eq!(*count.lock().unwrap(), 8);
+}
+
+#[test]
+/// Interrupts wake the MCU up, push the PC and clear the I flag, and RETI
+/// returns to the pushed PC
+fn test_interrupt_hooks() {
+    let source = "
+        sei
+        sleep
+        nop
+        nop
+        nop
+        nop
+        nop
+        nop
+        reti
+    ";
+    let mut mcu = create_mcu(source, |_, _| {});
+    let (_, events) = collect(
+        &mut mcu,
+        EventFilter::kinds(&[
+            EventKind::InterruptEntry,
+            EventKind::InterruptExit,
+            EventKind::Sleep,
+            EventKind::Wake,
+        ]),
+    );
+    mcu.step();
+    mcu.step();
+    assert!(mcu.is_sleeping());
+    assert!(mcu.get_flags().int);
+    mcu.enter_interrupt(0x10);
+    assert!(!mcu.is_sleeping());
+    assert!(!mcu.get_flags().int);
+    assert_eq!(mcu.get_program_counter(), 0x10);
+    assert_eq!(mcu.get_stack_pointer(), 0x1ee);
+    assert_eq!(mcu.get_data_byte(0x1f0), 0x04);
+    assert_eq!(mcu.get_cycle_count(), 6);
+    mcu.step();
+    // RETI resumes at the pushed address, the `nop` after `sleep`
+    assert_eq!(mcu.get_program_counter(), 0x4);
+    assert_eq!(mcu.get_stack_pointer(), 0x1f0);
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            Event::Sleep { pc: 0x2, cycle: 1 },
+            Event::Wake { pc: 0x4, cycle: 2 },
+            Event::InterruptEntry {
+                pc: 0x4,
+                vector: 0x10,
+                cycle: 2
+            },
+            Event::InterruptExit { pc: 0x10, cycle: 6 },
+        ]
+    );
+}
+
+#[test]
+/// `BREAK` and rejected accesses are faults
+fn test_fault_hooks() {
+    let mut mcu = create_mcu("ldi r31, 0x40\nld r0, Z\nbreak", |_, _| {});
+    mcu.set_access_mode(AccessMode::Strict);
+    let (_, events) = collect(&mut mcu, EventFilter::kinds(&[EventKind::Fault]));
+    for _ in 0..3 {
+        mcu.step();
+    }
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    match &events[0] {
+        Event::Fault(Fault::AccessViolation(violation)) => {
+            assert_eq!((violation.pc, violation.address), (0x2, 0x4000))
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert_eq!(events[1], Event::Fault(Fault::Break { pc: 0x4, cycle: 3 }));
+}
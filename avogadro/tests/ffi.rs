@@ -0,0 +1,323 @@
+extern crate avr_avogadro;
+
+#[path = "fixtures/mod.rs"]
+mod fixtures;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::ffi::mcu_wrapper::*;
+use fixtures::ElfBuilder;
+use gimli::write::{Address, AttributeValue, Expression};
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::raw::c_void;
+use std::ptr;
+
+#[test]
+/// MCUs are created by model name, and destroyed
+fn test_ffi_lifecycle() {
+    let device = CString::new("attiny85").unwrap();
+    let unknown = CString::new("z80").unwrap();
+    unsafe {
+        assert!(mcu_create(unknown.as_ptr()).is_null());
+        assert!(mcu_create(ptr::null()).is_null());
+        let mcu = mcu_create(device.as_ptr());
+        assert!(!mcu.is_null());
+        let mut size = 0;
+        assert_eq!(mcu_get_data_size(mcu, &mut size), McuStatus::Ok);
+        assert_eq!(size, 0x260);
+        mcu_destroy(mcu);
+        mcu_destroy(ptr::null_mut());
+    }
+    assert_eq!(mcu_api_version(), AVOGADRO_API_VERSION);
+}
+
+#[test]
+/// Calls report errors instead of crashing
+fn test_ffi_status() {
+    let device = CString::new("attiny85").unwrap();
+    unsafe {
+        let mcu = mcu_create(device.as_ptr());
+        let mut value = 0;
+        assert_eq!(mcu_step(ptr::null_mut()), McuStatus::NullPointer);
+        assert_eq!(
+            mcu_get_register(ptr::null(), 0, &mut value),
+            McuStatus::NullPointer
+        );
+        assert_eq!(
+            mcu_get_register(mcu, 0, ptr::null_mut()),
+            McuStatus::NullPointer
+        );
+        assert_eq!(mcu_set_register(mcu, 32, 1), McuStatus::InvalidArgument);
+        assert_eq!(
+            mcu_load_bin_file(mcu, ptr::null(), true),
+            McuStatus::NullPointer
+        );
+        let missing = CString::new("missing.bin").unwrap();
+        assert_eq!(
+            mcu_load_bin_file(mcu, missing.as_ptr(), true),
+            McuStatus::IoError
+        );
+        assert_eq!(mcu_step_back(mcu), McuStatus::NotFound);
+        mcu_destroy(mcu);
+    }
+}
+
+#[test]
+/// Runs a program through the C API
+fn test_ffi_step() {
+    let device = CString::new("attiny85").unwrap();
+    let program = avr_asm!("ldi r16, 0x2a", "mov r17, r16");
+    unsafe {
+        let mcu = mcu_create(device.as_ptr());
+        // Bigger than the program memory, the rest is ignored
+        let mut memory = program.clone();
+        memory.resize(16 * 1024, 0);
+        assert_eq!(
+            mcu_load_program_memory(mcu, memory.as_ptr(), memory.len()),
+            McuStatus::Ok
+        );
+        assert_eq!(mcu_set_history_budget(mcu, 1024 * 1024), McuStatus::Ok);
+        assert_eq!(mcu_step(mcu), McuStatus::Ok);
+        assert_eq!(mcu_step(mcu), McuStatus::Ok);
+        let (mut value, mut pc) = (0, 0);
+        assert_eq!(mcu_get_register(mcu, 17, &mut value), McuStatus::Ok);
+        assert_eq!(value, 0x2a);
+        assert_eq!(mcu_get_program_counter(mcu, &mut pc), McuStatus::Ok);
+        assert_eq!(pc, 4);
+        let mut instruction = [0xff; 4];
+        assert_eq!(
+            mcu_display_current_instruction(mcu, instruction.as_mut_ptr(), instruction.len()),
+            McuStatus::Ok
+        );
+        assert_eq!(instruction[3], 0);
+        assert_eq!(mcu_step_back(mcu), McuStatus::Ok);
+        assert_eq!(mcu_get_register(mcu, 17, &mut value), McuStatus::Ok);
+        assert_eq!(value, 0);
+        mcu_destroy(mcu);
+    }
+}
+
+#[test]
+/// Steps by source lines and reads variables through the C API
+fn test_ffi_source_debugging() {
+    let device = CString::new("attiny85").unwrap();
+    let program = avr_asm!("ldi r16, 1", "ldi r17, 2", "loop:", "rjmp loop");
+    let path = ElfBuilder::new(&program)
+        .line(0x0, 3)
+        .line(0x2, 4)
+        .line(0x4, 5)
+        .debug_entries(|unit| {
+            let root = unit.root();
+            let int = unit.add(root, gimli::DW_TAG_base_type);
+            let entry = unit.get_mut(int);
+            entry.set(gimli::DW_AT_name, AttributeValue::String(b"int".to_vec()));
+            entry.set(gimli::DW_AT_byte_size, AttributeValue::Udata(2));
+            let encoding = AttributeValue::Encoding(gimli::DW_ATE_signed);
+            entry.set(gimli::DW_AT_encoding, encoding);
+            let counter = unit.add(root, gimli::DW_TAG_variable);
+            let entry = unit.get_mut(counter);
+            entry.set(
+                gimli::DW_AT_name,
+                AttributeValue::String(b"counter".to_vec()),
+            );
+            entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(int));
+            let mut location = Expression::new();
+            location.op_addr(Address::Constant(0x80_0100));
+            entry.set(gimli::DW_AT_location, AttributeValue::Exprloc(location));
+        })
+        .write_temp("avogadro_test_ffi_source.elf");
+    let filename = CString::new(path.to_str().unwrap()).unwrap();
+    let counter = CString::new("counter").unwrap();
+    let mut buffer = [0xff_u8; 32];
+    let (mut stop, mut line) = (McuStepStop::CycleLimit, 0);
+    unsafe {
+        let mcu = mcu_create(device.as_ptr());
+        assert_eq!(
+            mcu_load_program_memory(mcu, program.as_ptr(), program.len()),
+            McuStatus::Ok
+        );
+        assert_eq!(
+            mcu_source_step(mcu, McuSourceStep::Into, &mut stop),
+            McuStatus::NotFound
+        );
+        assert_eq!(mcu_load_debug_info(mcu, filename.as_ptr()), McuStatus::Ok);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            mcu_source_step(mcu, McuSourceStep::Over, &mut stop),
+            McuStatus::Ok
+        );
+        assert_eq!(stop, McuStepStop::Line);
+        assert_eq!(
+            mcu_get_source_line(mcu, buffer.as_mut_ptr(), buffer.len(), &mut line),
+            McuStatus::Ok
+        );
+        let file = CStr::from_bytes_until_nul(&buffer).unwrap();
+        assert_eq!(file.to_str().unwrap(), "/src/main.c");
+        assert_eq!(line, 4);
+
+        assert_eq!(
+            mcu_read_variable(mcu, counter.as_ptr(), buffer.as_mut_ptr(), buffer.len()),
+            McuStatus::Ok
+        );
+        assert_eq!(
+            CStr::from_bytes_until_nul(&buffer).unwrap().to_str(),
+            Ok("0")
+        );
+        let missing = CString::new("missing").unwrap();
+        assert_eq!(
+            mcu_read_variable(mcu, missing.as_ptr(), buffer.as_mut_ptr(), buffer.len()),
+            McuStatus::NotFound
+        );
+        // No function here, so no locals
+        assert_eq!(
+            mcu_get_locals(mcu, buffer.as_mut_ptr(), buffer.len()),
+            McuStatus::Ok
+        );
+        assert_eq!(buffer[0], 0);
+        mcu_destroy(mcu);
+    }
+}
+
+#[test]
+/// Backtraces are named once debug info is loaded
+fn test_ffi_backtrace() {
+    let device = CString::new("attiny85").unwrap();
+    let program = avr_asm!("main:", "rcall f", "nop", "f:", "rjmp f");
+    let path = ElfBuilder::new(&program)
+        .function("main", 0x0, 0x4)
+        .function("f", 0x4, 0x2)
+        .line(0x0, 3)
+        .line(0x4, 7)
+        .write_temp("avogadro_test_ffi_backtrace.elf");
+    let filename = CString::new(path.to_str().unwrap()).unwrap();
+    let mut buffer = [0xff_u8; 128];
+    unsafe {
+        let mcu = mcu_create(device.as_ptr());
+        mcu_load_program_memory(mcu, program.as_ptr(), program.len());
+        mcu_step(mcu);
+        assert_eq!(
+            mcu_get_backtrace(mcu, buffer.as_mut_ptr(), buffer.len()),
+            McuStatus::Ok
+        );
+        assert_eq!(
+            CStr::from_bytes_until_nul(&buffer).unwrap().to_str(),
+            Ok("#0  0x0004 in ??\n#1  0x0000 in ??\n")
+        );
+
+        assert_eq!(mcu_load_debug_info(mcu, filename.as_ptr()), McuStatus::Ok);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            mcu_get_backtrace(mcu, buffer.as_mut_ptr(), buffer.len()),
+            McuStatus::Ok
+        );
+        assert_eq!(
+            CStr::from_bytes_until_nul(&buffer).unwrap().to_str(),
+            Ok("#0  0x0004 in f at /src/main.c:7\n#1  0x0000 in main at /src/main.c:3\n")
+        );
+        mcu_destroy(mcu);
+    }
+}
+
+unsafe extern "C" fn push_event(p_event: *const McuEvent, p_user_data: *mut c_void) {
+    let events = &mut *(p_user_data as *mut Vec<McuEvent>);
+    events.push(*p_event);
+}
+
+#[test]
+/// Runners take the MCU to a worker thread, and give it back when destroyed
+fn test_ffi_runner() {
+    let device = CString::new("attiny85").unwrap();
+    let program = avr_asm!("loop:", "nop", "inc r16", "inc r17", "jmp loop");
+    unsafe {
+        assert!(mcu_runner_create(ptr::null_mut()).is_null());
+        assert_eq!(mcu_runner_run(ptr::null()), McuStatus::NullPointer);
+        let mcu = mcu_create(device.as_ptr());
+        mcu_load_program_memory(mcu, program.as_ptr(), program.len());
+        let mut memory = [0; 0x61];
+        memory[0x60] = 0x42;
+        mcu_load_data_memory(mcu, memory.as_ptr(), memory.len());
+        let runner = mcu_runner_create(mcu);
+        assert!(!runner.is_null());
+
+        assert_eq!(mcu_runner_set_speed(runner, 0.0), McuStatus::Ok);
+        assert_eq!(mcu_runner_add_breakpoint(runner, 0x4), McuStatus::Ok);
+        assert_eq!(mcu_runner_run(runner), McuStatus::Ok);
+        let mut status = McuRunStatus::Paused;
+        assert_eq!(mcu_runner_wait(runner, 10_000, &mut status), McuStatus::Ok);
+        assert_eq!(status, McuRunStatus::Breakpoint);
+        assert_eq!(mcu_runner_remove_breakpoint(runner, 0x4), McuStatus::Ok);
+        assert_eq!(mcu_runner_step(runner, 1), McuStatus::Ok);
+        let mut state = std::mem::zeroed::<McuRunnerState>();
+        assert_eq!(mcu_runner_get_state(runner, &mut state), McuStatus::Ok);
+        assert_eq!(state.program_counter, 0x6);
+        assert_eq!(state.registers[16..18], [1, 1]);
+        assert_eq!(state.cycle_count, 3);
+        assert_eq!(mcu_runner_get_status(runner, &mut status), McuStatus::Ok);
+        assert_eq!(status, McuRunStatus::Paused);
+        let mut hz = -1.0;
+        assert_eq!(
+            mcu_runner_get_achieved_speed(runner, &mut hz),
+            McuStatus::Ok
+        );
+        assert_eq!(hz, 0.0);
+        memory = [0; 0x61];
+        assert_eq!(
+            mcu_runner_get_data_memory(runner, memory.as_mut_ptr(), memory.len()),
+            McuStatus::Ok
+        );
+        assert_eq!(memory[0x60], 0x42);
+
+        let mcu = mcu_runner_destroy(runner);
+        assert!(!mcu.is_null());
+        let mut pc = 0;
+        assert_eq!(mcu_get_program_counter(mcu, &mut pc), McuStatus::Ok);
+        assert_eq!(pc, 0x6);
+        mcu_destroy(mcu);
+        assert!(mcu_runner_destroy(ptr::null_mut()).is_null());
+    }
+}
+
+#[test]
+/// C callbacks receive the events of their mask and address range, with
+/// their user data
+fn test_ffi_hooks() {
+    let device = CString::new("attiny85").unwrap();
+    let program = avr_asm!("ldi r16, 7", "out 0x18, r16", "out 0x17, r16");
+    let mut events: Vec<McuEvent> = Vec::new();
+    let user_data = &mut events as *mut Vec<McuEvent> as *mut c_void;
+    unsafe {
+        let mcu = mcu_create(device.as_ptr());
+        mcu_load_program_memory(mcu, program.as_ptr(), program.len());
+        let kinds = 1 << McuEventKind::IoWrite as u32 | 1 << McuEventKind::InterruptEntry as u32;
+        let mut id = 0;
+        assert_eq!(
+            mcu_add_hook(mcu, kinds, 0x38, 0x38, None, user_data, &mut id),
+            McuStatus::NullPointer
+        );
+        assert_eq!(
+            mcu_add_hook(mcu, kinds, 0x38, 0x38, Some(push_event), user_data, &mut id),
+            McuStatus::Ok
+        );
+        for _ in 0..3 {
+            mcu_step(mcu);
+        }
+        assert_eq!(mcu_enter_interrupt(mcu, 0x2), McuStatus::Ok);
+        assert_eq!(mcu_remove_hook(mcu, id), McuStatus::Ok);
+        assert_eq!(mcu_remove_hook(mcu, id), McuStatus::NotFound);
+        mcu_step(mcu);
+        mcu_destroy(mcu);
+    }
+    let summary: Vec<_> = events
+        .iter()
+        .map(|event| (event.kind, event.pc, event.address, event.value))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (McuEventKind::IoWrite, 0x2, 0x38, 7),
+            (McuEventKind::InterruptEntry, 0x6, 0x2, 0),
+        ]
+    );
+    assert_eq!(events[1].cycle, 3);
+}
@@ -0,0 +1,295 @@
+//! Builders for test input files and MCUs
+#![allow(dead_code)]
+
+use avr_avogadro::core::assembler::{self, Program};
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use gimli::write::{
+    Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections, Unit,
+};
+use gimli::{Encoding, Format, LineEncoding, LittleEndian};
+use object::elf;
+use object::write::elf::{FileHeader, ProgramHeader, SectionHeader, Sym, Writer};
+use object::Endianness;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Base address of data memory symbols in avr-gcc output
+pub const DATA_SPACE_OFFSET: u32 = 0x0080_0000;
+/// Directory of the fake sources referenced by debug info
+pub const COMP_DIR: &str = "/src";
+
+/// Adds debug info entries to a compilation unit
+type EntryBuilder = Box<dyn Fn(&mut Unit)>;
+
+struct FixtureSymbol {
+    name: String,
+    address: u32,
+    size: u32,
+    is_function: bool,
+}
+
+/// Builds AVR executables like the ones linked by avr-gcc: program at 0,
+/// symbols and a DWARF line table for a single source file
+pub struct ElfBuilder {
+    text: Vec<u8>,
+    symbols: Vec<FixtureSymbol>,
+    source_file: String,
+    lines: Vec<(u32, u32)>,
+    entries: Option<EntryBuilder>,
+}
+
+impl ElfBuilder {
+    pub fn new(text: &[u8]) -> ElfBuilder {
+        ElfBuilder {
+            text: text.to_vec(),
+            symbols: Vec::new(),
+            source_file: "main.c".to_owned(),
+            lines: Vec::new(),
+            entries: None,
+        }
+    }
+
+    /// Adds a function symbol at program byte address `address`
+    pub fn function(mut self, name: &str, address: u32, size: u32) -> ElfBuilder {
+        self.symbols.push(FixtureSymbol {
+            name: name.to_owned(),
+            address,
+            size,
+            is_function: true,
+        });
+        self
+    }
+
+    /// Adds a variable symbol at data memory address `address`
+    pub fn variable(mut self, name: &str, address: u16, size: u32) -> ElfBuilder {
+        self.symbols.push(FixtureSymbol {
+            name: name.to_owned(),
+            address: DATA_SPACE_OFFSET + u32::from(address),
+            size,
+            is_function: false,
+        });
+        self
+    }
+
+    /// Maps instructions from `address` to the next line row to source
+    /// line `line`
+    pub fn line(mut self, address: u32, line: u32) -> ElfBuilder {
+        self.lines.push((address, line));
+        self
+    }
+
+    /// Adds debug info entries with `build`, which gets the compilation
+    /// unit
+    pub fn debug_entries(mut self, build: impl Fn(&mut Unit) + 'static) -> ElfBuilder {
+        self.entries = Some(Box::new(build));
+        self
+    }
+
+    pub fn source_file(mut self, name: &str) -> ElfBuilder {
+        self.source_file = name.to_owned();
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let debug_sections = self.debug_sections();
+        let mut buffer = Vec::new();
+        let mut writer = Writer::new(Endianness::Little, false, &mut buffer);
+        writer.reserve_file_header();
+        writer.reserve_program_headers(1);
+        let text_offset = writer.reserve(self.text.len(), 2);
+        let debug_offsets = debug_sections
+            .iter()
+            .map(|(_, data)| writer.reserve(data.len(), 1))
+            .collect::<Vec<_>>();
+        writer.reserve_null_section_index();
+        let text_name = writer.add_section_name(b".text");
+        let text_index = writer.reserve_section_index();
+        let debug_names = debug_sections
+            .iter()
+            .map(|(name, _)| {
+                let name = writer.add_section_name(name.as_bytes());
+                writer.reserve_section_index();
+                name
+            })
+            .collect::<Vec<_>>();
+        writer.reserve_null_symbol_index();
+        let symbol_names = self
+            .symbols
+            .iter()
+            .map(|symbol| {
+                let name = writer.add_string(symbol.name.as_bytes());
+                writer.reserve_symbol_index(Some(text_index));
+                name
+            })
+            .collect::<Vec<_>>();
+        writer.reserve_symtab_section_index();
+        writer.reserve_strtab_section_index();
+        writer.reserve_shstrtab_section_index();
+        writer.reserve_symtab();
+        writer.reserve_strtab();
+        writer.reserve_shstrtab();
+        writer.reserve_section_headers();
+
+        writer
+            .write_file_header(&FileHeader {
+                os_abi: 0,
+                abi_version: 0,
+                e_type: elf::ET_EXEC,
+                e_machine: elf::EM_AVR,
+                e_entry: 0,
+                e_flags: 0,
+            })
+            .unwrap();
+        writer.write_align_program_headers();
+        writer.write_program_header(&ProgramHeader {
+            p_type: elf::PT_LOAD,
+            p_flags: elf::PF_R | elf::PF_X,
+            p_offset: text_offset as u64,
+            p_vaddr: 0,
+            p_paddr: 0,
+            p_filesz: self.text.len() as u64,
+            p_memsz: self.text.len() as u64,
+            p_align: 2,
+        });
+        writer.write_align(2);
+        writer.write(&self.text);
+        for (_, data) in &debug_sections {
+            writer.write(data);
+        }
+        writer.write_null_symbol();
+        for (symbol, name) in self.symbols.iter().zip(&symbol_names) {
+            let (kind, section) = if symbol.is_function {
+                (elf::STT_FUNC, Some(text_index))
+            } else {
+                (elf::STT_OBJECT, None)
+            };
+            writer.write_symbol(&Sym {
+                name: Some(*name),
+                section,
+                st_info: (elf::STB_GLOBAL << 4) | kind,
+                st_other: 0,
+                st_shndx: if section.is_some() { 0 } else { elf::SHN_ABS },
+                st_value: u64::from(symbol.address),
+                st_size: u64::from(symbol.size),
+            });
+        }
+        writer.write_strtab();
+        writer.write_shstrtab();
+        writer.write_null_section_header();
+        writer.write_section_header(&SectionHeader {
+            name: Some(text_name),
+            sh_type: elf::SHT_PROGBITS,
+            sh_flags: u64::from(elf::SHF_ALLOC | elf::SHF_EXECINSTR),
+            sh_addr: 0,
+            sh_offset: text_offset as u64,
+            sh_size: self.text.len() as u64,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 2,
+            sh_entsize: 0,
+        });
+        for ((name, offset), (_, data)) in
+            debug_names.iter().zip(&debug_offsets).zip(&debug_sections)
+        {
+            writer.write_section_header(&SectionHeader {
+                name: Some(*name),
+                sh_type: elf::SHT_PROGBITS,
+                sh_flags: 0,
+                sh_addr: 0,
+                sh_offset: *offset as u64,
+                sh_size: data.len() as u64,
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: 1,
+                sh_entsize: 0,
+            });
+        }
+        writer.write_symtab_section_header(1);
+        writer.write_strtab_section_header();
+        writer.write_shstrtab_section_header();
+        buffer
+    }
+
+    /// Writes the file into the temporary directory, returning its path
+    pub fn write_temp(&self, name: &str) -> PathBuf {
+        let mut path = env::temp_dir();
+        path.push(name);
+        fs::write(&path, self.build()).unwrap();
+        path
+    }
+
+    fn debug_sections(&self) -> Vec<(&'static str, Vec<u8>)> {
+        if self.lines.is_empty() && self.entries.is_none() {
+            return Vec::new();
+        }
+        // Addresses are 32 bits wide, so they fit data symbol addresses
+        let encoding = Encoding {
+            format: Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+        let mut dwarf = DwarfUnit::new(encoding);
+        let mut program = LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            LineString::String(COMP_DIR.as_bytes().to_vec()),
+            LineString::String(self.source_file.as_bytes().to_vec()),
+            None,
+        );
+        let directory = program.default_directory();
+        let file = program.add_file(
+            LineString::String(self.source_file.as_bytes().to_vec()),
+            directory,
+            None,
+        );
+        program.begin_sequence(Some(Address::Constant(0)));
+        for (address, line) in &self.lines {
+            program.row().address_offset = u64::from(*address);
+            program.row().file = file;
+            program.row().line = u64::from(*line);
+            program.generate_row();
+        }
+        program.end_sequence(self.text.len() as u64);
+        if !self.lines.is_empty() {
+            dwarf.unit.line_program = program;
+        }
+        if let Some(build) = &self.entries {
+            build(&mut dwarf.unit);
+        }
+        let root = dwarf.unit.root();
+        let root = dwarf.unit.get_mut(root);
+        root.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(self.source_file.as_bytes().to_vec()),
+        );
+        root.set(
+            gimli::DW_AT_comp_dir,
+            AttributeValue::String(COMP_DIR.as_bytes().to_vec()),
+        );
+        let mut sections = Sections::new(EndianVec::new(LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+        let mut result = Vec::new();
+        sections
+            .for_each(|id, data| -> Result<(), ()> {
+                if !data.slice().is_empty() {
+                    result.push((id.name(), data.slice().to_vec()));
+                }
+                Ok(())
+            })
+            .unwrap();
+        result
+    }
+}
+
+/// Creates an attiny85 running `source`, with its stack at 0x1f0.
+/// `observer` attaches what watches the run, given the assembled program
+pub fn create_mcu(source: &str, observer: impl FnOnce(&mut Mcu, Program)) -> Mcu {
+    let program = assembler::assemble(source).unwrap();
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program.image);
+    mcu.set_stack_pointer(0x1f0);
+    observer(&mut mcu, program);
+    mcu
+}
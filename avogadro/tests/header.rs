@@ -0,0 +1,28 @@
+extern crate avr_avogadro;
+
+use std::fs;
+use std::path::PathBuf;
+
+#[test]
+/// `include/avogadro.h` must match the C API. Regenerate it with
+/// `UPDATE_HEADER=1 cargo test header` or with the cbindgen CLI.
+fn test_header_up_to_date() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .unwrap()
+        .write(&mut generated);
+    let header_path = crate_dir.join("include").join("avogadro.h");
+    if std::env::var_os("UPDATE_HEADER").is_some() {
+        fs::write(&header_path, &generated).unwrap();
+    }
+    let header = fs::read(&header_path).unwrap_or_default();
+    assert!(
+        header == generated,
+        "include/avogadro.h is outdated, regenerate it with UPDATE_HEADER=1"
+    );
+}
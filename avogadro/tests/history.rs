@@ -0,0 +1,175 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::coverage::Coverage;
+use avr_avogadro::core::events::{Event, EventFilter};
+use avr_avogadro::core::history::History;
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Fills memory from 0x60 with a countdown, pushing each value
+fn countdown_mcu() -> Mcu {
+    let mut mcu = McuFactory::create("attiny85");
+    let program = avr_asm!(
+        "ldi r26, 0x60",
+        "ldi r16, 0x40",
+        "loop:",
+        "st X+, r16",
+        "push r16",
+        "subi r16, 1",
+        "brne loop"
+    );
+    mcu.load_program_memory(&program);
+    mcu
+}
+
+#[test]
+/// Every step is undone back to the exact previous state, including steps
+/// whose undo records were dropped to fit the budget
+fn test_step_back() {
+    for budget in &[usize::MAX, 64 * 1024] {
+        let mut mcu = countdown_mcu();
+        mcu.set_history(Some(History::new(*budget).with_checkpoint_interval(16)));
+        let mut snapshots = Vec::new();
+        for _ in 0..200 {
+            snapshots.push(mcu.snapshot());
+            mcu.step();
+        }
+        let history = mcu.get_history().unwrap();
+        assert!(history.memory_usage() <= *budget);
+        let steps = history.len() as usize;
+        assert!(steps == 200 || *budget != usize::MAX);
+        assert!(steps > 16);
+        for snapshot in snapshots.iter().rev().take(steps) {
+            assert!(mcu.step_back());
+            assert_eq!(mcu.snapshot(), *snapshot);
+        }
+        assert!(!mcu.step_back());
+    }
+}
+
+#[test]
+/// Old steps are forgotten to stay within the memory budget
+fn test_history_budget() {
+    let mut mcu = countdown_mcu();
+    let budget = 100 * 1024;
+    mcu.set_history(Some(History::new(budget).with_checkpoint_interval(100)));
+    let mut cycle_counts = Vec::new();
+    for _ in 0..10_000 {
+        cycle_counts.push(mcu.get_cycle_count());
+        mcu.step();
+    }
+    let history = mcu.get_history().unwrap();
+    assert!(history.memory_usage() <= budget);
+    let steps = history.len();
+    assert!(steps > 0 && steps < 10_000);
+    for _ in 0..steps {
+        assert!(mcu.step_back());
+    }
+    assert!(!mcu.step_back());
+    assert_eq!(mcu.get_cycle_count(), cycle_counts[10_000 - steps as usize]);
+}
+
+#[test]
+/// Running backwards stops at breakpoints, watched writes or a given PC
+fn test_reverse_continue() {
+    let mut mcu = countdown_mcu();
+    mcu.set_history(Some(History::new(usize::MAX)));
+    for _ in 0..100 {
+        mcu.step();
+    }
+    assert!(mcu.run_back_to(0x4));
+    assert_eq!(mcu.get_register(26), 0x60 + 24);
+    mcu.add_breakpoint(0x8);
+    assert!(mcu.reverse_continue());
+    assert_eq!(mcu.get_program_counter(), 0x8);
+    assert_eq!(mcu.get_register(16), 0x40 - 23);
+    mcu.remove_breakpoint(0x8);
+    mcu.add_watchpoint(0x61);
+    assert!(mcu.reverse_continue());
+    // Stops before `st X+, r16` writes the watched address
+    assert_eq!(mcu.get_program_counter(), 0x4);
+    assert_eq!(mcu.get_register(26), 0x61);
+    assert_eq!(mcu.get_data_byte(0x61), 0);
+    assert!(!mcu.reverse_continue());
+    assert_eq!(mcu.get_program_counter(), 0);
+    assert_eq!(mcu.get_cycle_count(), 0);
+}
+
+#[test]
+/// Changing the state from outside starts the history again
+fn test_history_cleared_by_changes() {
+    let mut mcu = countdown_mcu();
+    mcu.set_history(Some(History::new(usize::MAX)));
+    for _ in 0..10 {
+        mcu.step();
+    }
+    // Setting the same value keeps the history
+    mcu.set_register(16, mcu.get_register(16));
+    assert_eq!(mcu.get_history().unwrap().len(), 10);
+    mcu.set_register(16, 0x10);
+    assert!(mcu.get_history().unwrap().is_empty());
+    assert!(!mcu.step_back());
+    mcu.step();
+    assert!(mcu.step_back());
+    assert_eq!(mcu.get_register(16), 0x10);
+}
+
+#[test]
+/// Steps replayed from a checkpoint to step back aren't observed again
+fn test_step_back_replay_not_observed() {
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&avr_asm!("nop", "loop:", "inc r20", "rjmp loop"));
+    mcu.set_coverage(Some(Coverage::new(mcu.get_program_size())));
+    let events = Arc::new(AtomicUsize::new(0));
+    let hook_events = Arc::clone(&events);
+    mcu.add_hook(EventFilter::all(), move |_: &Event| {
+        hook_events.fetch_add(1, Ordering::Relaxed);
+    });
+    // Without room for undo records, every step back replays
+    mcu.set_history(Some(History::new(1).with_checkpoint_interval(10)));
+    for _ in 0..20 {
+        mcu.step();
+    }
+    assert_eq!(mcu.get_coverage().unwrap().count(0x2), 10);
+    assert_eq!(events.load(Ordering::Relaxed), 20);
+    assert!(mcu.step_back());
+    assert!(mcu.step_back());
+    assert_eq!(mcu.get_program_counter(), 0x4);
+    assert_eq!(mcu.get_coverage().unwrap().count(0x2), 10);
+    assert_eq!(events.load(Ordering::Relaxed), 20);
+}
+
+#[test]
+/// Interrupts are recorded as steps, and replayed when stepping back from a
+/// checkpoint
+fn test_step_back_interrupt() {
+    for budget in &[usize::MAX, 1] {
+        let mut mcu = McuFactory::create("attiny85");
+        let program = avr_asm!("sei", "sleep", "loop:", "inc r20", "rjmp loop");
+        mcu.load_program_memory(&program);
+        mcu.set_history(Some(History::new(*budget).with_checkpoint_interval(4)));
+        let mut snapshots = Vec::new();
+        for step in 0..7 {
+            snapshots.push(mcu.snapshot());
+            if step == 4 {
+                assert!(mcu.is_sleeping());
+                mcu.enter_interrupt(0x4);
+            } else {
+                mcu.step();
+            }
+        }
+        assert!(!mcu.is_sleeping());
+        // Without room for undo records, stepping back to the interrupt
+        // replays it from the checkpoint taken right before
+        let steps = mcu.get_history().unwrap().len() as usize;
+        assert!(steps >= 3);
+        for snapshot in snapshots.iter().rev().take(steps) {
+            assert!(mcu.step_back());
+            assert_eq!(mcu.snapshot(), *snapshot);
+        }
+        assert!(!mcu.step_back());
+    }
+}
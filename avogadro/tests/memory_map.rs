@@ -0,0 +1,149 @@
+extern crate avr_avogadro;
+
+#[path = "fixtures/mod.rs"]
+mod fixtures;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::memory_map::{AccessMode, MemoryMap, Region, ViolationKind};
+use avr_avogadro::core::semihost::Semihost;
+use fixtures::create_mcu;
+
+/// Stores r16 at 0x1234, loads it back into r17, writes ADCL and reads a
+/// reserved register
+const WILD_ACCESSES: &str = "
+    ldi r16, 0x55
+    ldi r26, 0x34
+    ldi r27, 0x12
+    st X, r16
+    ld r17, X
+    out 0x04, r16
+    in r18, 0x3c
+";
+
+#[test]
+/// Regions added later take precedence, addresses outside them are unmapped
+fn test_memory_map_regions() {
+    let mut memory_map = MemoryMap::new(0x200);
+    assert_eq!(memory_map.region(0x1f), Region::Registers);
+    assert_eq!(memory_map.region(0x20), Region::Io);
+    assert_eq!(memory_map.region(0x60), Region::Sram);
+    assert_eq!(memory_map.region(0x1ff), Region::Sram);
+    assert_eq!(memory_map.region(0x200), Region::Unmapped);
+    memory_map.add_region(0x60..0x100, Region::ExtendedIo);
+    memory_map.add_region(0x200..0x10000, Region::ExternalRam);
+    assert_eq!(memory_map.region(0x60), Region::ExtendedIo);
+    assert_eq!(memory_map.region(0x100), Region::Sram);
+    assert_eq!(memory_map.region(0xffff), Region::ExternalRam);
+
+    memory_map.add_reserved(0x21);
+    memory_map.add_read_only(0x22);
+    assert_eq!(memory_map.check(0x21, false), Some(ViolationKind::Reserved));
+    assert_eq!(memory_map.check(0x22, false), None);
+    assert_eq!(memory_map.check(0x22, true), Some(ViolationKind::ReadOnly));
+    assert_eq!(
+        MemoryMap::empty().check(0, false),
+        Some(ViolationKind::Unmapped)
+    );
+}
+
+#[test]
+/// Compatibility mode mirrors addresses without reporting anything
+fn test_compat_access() {
+    let mut mcu = create_mcu(WILD_ACCESSES, |_, _| {});
+    assert_eq!(mcu.get_access_mode(), AccessMode::Compat);
+    for _ in 0..7 {
+        mcu.step();
+    }
+    assert_eq!(mcu.get_data_byte(0x1234 & 0x3ff), 0x55);
+    assert_eq!(mcu.get_register(17), 0x55);
+    assert_eq!(mcu.get_data_byte(0x24), 0x55);
+    assert!(mcu.access_violations().is_empty());
+}
+
+#[test]
+/// Data memory mirrors every power of two above its size, and addresses in
+/// between have no memory, like external RAM without a device
+fn test_compat_out_of_range() {
+    let mut mcu = McuFactory::create("atmega2560");
+    let program = avr_asm!(
+        "ldi r16, 0x55",
+        "ldi r26, 0x00",
+        "ldi r27, 0x22",
+        "st X, r16",
+        "ld r17, X",
+        "ldi r27, 0x42",
+        "st X, r16"
+    );
+    mcu.load_program_memory(&program);
+    for _ in 0..program.len() / 2 {
+        mcu.step();
+    }
+    assert_eq!(mcu.get_data_byte(0x0000), 0);
+    assert_eq!(mcu.get_data_byte(0x2200), 0);
+    assert_eq!(mcu.get_register(17), 0);
+    assert_eq!(mcu.get_data_byte(0x0200), 0x55);
+
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.set_data_byte(0x260, 0x55);
+    assert_eq!(mcu.get_data_byte(0x260), 0);
+    assert_eq!(mcu.get_data_byte(0x000), 0);
+    mcu.set_data_byte(0x460, 0x55);
+    assert_eq!(mcu.get_data_byte(0x060), 0x55);
+}
+
+#[test]
+/// Strict mode reports and drops accesses outside the map and writes to
+/// read-only registers, reserved ones are reported only
+fn test_strict_access() {
+    let mut mcu = create_mcu(WILD_ACCESSES, |_, _| {});
+    mcu.set_access_mode(AccessMode::Strict);
+    mcu.set_data_byte(0x5c, 0x77);
+    for _ in 0..7 {
+        mcu.step();
+    }
+    assert_eq!(mcu.get_data_byte(0x34), 0);
+    assert_eq!(mcu.get_register(17), 0);
+    assert_eq!(mcu.get_data_byte(0x24), 0);
+    assert_eq!(mcu.get_register(18), 0x77);
+
+    let violations = mcu.take_access_violations();
+    let summary: Vec<_> = violations
+        .iter()
+        .map(|v| (v.pc, v.address, v.is_write, v.region, v.kind))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (0x6, 0x1234, true, Region::Unmapped, ViolationKind::Unmapped),
+            (
+                0x8,
+                0x1234,
+                false,
+                Region::Unmapped,
+                ViolationKind::Unmapped
+            ),
+            (0xa, 0x24, true, Region::Io, ViolationKind::ReadOnly),
+            (0xc, 0x5c, false, Region::Io, ViolationKind::Reserved),
+        ]
+    );
+    assert_eq!(
+        violations[0].to_string(),
+        "Write to unmapped address 0x1234 at 0x0006, cycle 3"
+    );
+    assert_eq!(
+        violations[2].to_string(),
+        "Write to read-only I/O address 0x0024 at 0x000a, cycle 7"
+    );
+    assert!(mcu.access_violations().is_empty());
+
+    // The host isn't checked, nor are devices
+    mcu.set_data_byte(0x24, 1);
+    assert_eq!(mcu.get_data_byte(0x24), 1);
+    let mut mcu = create_mcu("ldi r16, 1\nout 0x01, r16", |_, _| {});
+    mcu.set_access_mode(AccessMode::Strict);
+    mcu.set_semihost(Some(Semihost::new(0x20)));
+    mcu.step();
+    mcu.step();
+    assert!(mcu.access_violations().is_empty());
+}
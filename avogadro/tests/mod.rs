@@ -1,5 +1,30 @@
+// `fixtures` is shared by several test files, each one also built on its own
+#![allow(clippy::duplicate_mod)]
 extern crate avr_avogadro;
+mod assembler;
+mod android;
 mod blink;
+mod call_stack;
 #[cfg(test)]
 mod core;
+mod coverage;
+mod debugger;
+mod decode_cache;
+mod device;
+mod disassembler;
+mod elf;
+mod events;
+mod ffi;
+mod header;
+mod history;
+mod memory_map;
+mod profiler;
+mod runner;
+mod semihost;
+mod shadow;
+mod snapshot;
+mod stack_monitor;
 mod stack;
+mod throttle;
+mod trace;
+mod vcd;
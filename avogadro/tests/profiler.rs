@@ -0,0 +1,121 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::core::assembler;
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::profiler::{CallEdge, Profiler};
+
+/// `main` calls `f` twice, `f` calls `g`. `rcall .+0` only makes room in
+/// the stack.
+const SOURCE: &str = "
+main:
+    rcall f
+    rcall f
+    rcall .+0
+    rjmp .-2
+f:
+    rcall g
+    ret
+g:
+    nop
+    ret
+isr:
+    nop
+    reti
+";
+
+fn run_profiled(steps: usize) -> Mcu {
+    let program = assembler::assemble(SOURCE).unwrap();
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program.image);
+    mcu.set_profiler(Some(Profiler::new(program.symbols)));
+    for _ in 0..steps {
+        mcu.step();
+    }
+    mcu
+}
+
+#[test]
+/// Cycles are attributed to the executing function and to its callers
+fn test_profiler_functions() {
+    let mut mcu = run_profiled(13);
+    let cycles = mcu.get_cycle_count() as u64;
+    let profiler = mcu.take_profiler().unwrap();
+    assert_eq!(profiler.total_cycles(), cycles);
+    assert_eq!(profiler.depth(), 0);
+    let main = profiler.function("main").unwrap();
+    let f = profiler.function("f").unwrap();
+    let g = profiler.function("g").unwrap();
+    assert_eq!(main.calls, 0);
+    assert_eq!(f.calls, 2);
+    assert_eq!(g.calls, 2);
+    assert_eq!(main.total_cycles, cycles);
+    assert_eq!(f.total_cycles, f.self_cycles + g.total_cycles);
+    assert_eq!(main.self_cycles + f.self_cycles + g.self_cycles, cycles);
+    assert_eq!(g.self_cycles, g.total_cycles);
+    assert!(profiler.function("isr").is_none());
+    assert_eq!(
+        profiler.call_edges(),
+        vec![
+            CallEdge {
+                caller: "f".to_owned(),
+                callee: "g".to_owned(),
+                calls: 2,
+                cycles: g.total_cycles,
+            },
+            CallEdge {
+                caller: "main".to_owned(),
+                callee: "f".to_owned(),
+                calls: 2,
+                cycles: f.total_cycles,
+            },
+        ]
+    );
+}
+
+#[test]
+/// Folded stacks hold the self cycles of each call stack
+fn test_profiler_folded() {
+    let mut mcu = run_profiled(13);
+    let profiler = mcu.take_profiler().unwrap();
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    let stacks: Vec<&str> = folded
+        .lines()
+        .map(|line| line.rsplit_once(' ').unwrap().0)
+        .collect();
+    assert_eq!(stacks, vec!["main", "main;f", "main;f;g"]);
+    let g = profiler.function("g").unwrap();
+    assert!(folded.contains(&format!("main;f;g {}\n", g.self_cycles)));
+    let mut flat = Vec::new();
+    profiler.write_flat(&mut flat).unwrap();
+    let flat = String::from_utf8(flat).unwrap();
+    assert_eq!(flat.lines().count(), 4);
+    let top = &profiler.functions()[0];
+    assert!(top.self_cycles >= profiler.function("main").unwrap().self_cycles);
+    assert!(flat
+        .lines()
+        .nth(1)
+        .unwrap()
+        .ends_with(&format!(" {}", top.name)));
+}
+
+#[test]
+/// Interrupts push a frame that `reti` pops
+fn test_profiler_interrupt() {
+    let program = assembler::assemble(SOURCE).unwrap();
+    let isr = program.symbols.by_name("isr").unwrap().address as u16;
+    let mut mcu = run_profiled(1);
+    mcu.enter_interrupt(isr);
+    mcu.step();
+    assert_eq!(mcu.get_profiler().unwrap().depth(), 2);
+    mcu.step();
+    let profiler = mcu.get_profiler().unwrap();
+    assert_eq!(profiler.depth(), 1);
+    assert_eq!(profiler.function("isr").unwrap().calls, 1);
+    let edges = profiler.call_edges();
+    assert!(edges
+        .iter()
+        .any(|edge| edge.caller == "f" && edge.callee == "isr" && edge.calls == 1));
+}
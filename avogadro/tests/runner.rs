@@ -0,0 +1,95 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::runner::{McuRunner, RunStatus};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Increments r16 and r17 forever, so they are equal but between the
+/// increments, at 0x2
+fn create_runner() -> McuRunner {
+    let program = avr_asm!("loop:", "inc r16", "inc r17", "jmp loop");
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program);
+    McuRunner::new(mcu)
+}
+
+#[test]
+/// Steps and runs to breakpoints on the worker thread, then gives the MCU
+/// back
+fn test_runner_breakpoints() {
+    let runner = create_runner();
+    assert_eq!(runner.status(), RunStatus::Paused);
+    runner.step(1);
+    let snapshot = runner.snapshot().unwrap();
+    assert_eq!(snapshot.program_counter, 0x2);
+    assert_eq!(snapshot.registers[16..18], [1, 0]);
+
+    runner.add_breakpoint(0x2);
+    runner.run();
+    assert_eq!(runner.wait(TIMEOUT), RunStatus::Breakpoint(0x2));
+    // Running again from a breakpoint stops at it one loop later
+    runner.run();
+    assert_eq!(runner.wait(TIMEOUT), RunStatus::Breakpoint(0x2));
+    runner.remove_breakpoint(0x2);
+    runner.step(1);
+    assert_eq!(runner.wait(TIMEOUT), RunStatus::Paused);
+
+    let mcu = runner.into_mcu().unwrap();
+    assert_eq!(mcu.get_program_counter(), 0x4);
+    assert_eq!(mcu.get_register(16), 3);
+    assert_eq!(mcu.get_register(17), 3);
+    assert_eq!(mcu.get_cycle_count(), 12);
+}
+
+#[test]
+/// Snapshots taken from other threads while running are never in the
+/// middle of an instruction
+fn test_runner_snapshots() {
+    let runner = Arc::new(create_runner());
+    runner.run();
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let runner = Arc::clone(&runner);
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    let snapshot = runner.snapshot().unwrap();
+                    let [r16, r17] = [snapshot.registers[16], snapshot.registers[17]];
+                    match snapshot.program_counter {
+                        0x2 => assert_eq!(r16, r17.wrapping_add(1)),
+                        _ => assert_eq!(r16, r17),
+                    }
+                }
+            })
+        })
+        .collect();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(runner.status(), RunStatus::Running);
+    runner.pause();
+    let paused = runner.snapshot().unwrap();
+    assert_eq!(runner.status(), RunStatus::Paused);
+    assert!(paused.cycle_count > 0);
+    assert_eq!(runner.snapshot().unwrap(), paused);
+}
+
+#[test]
+/// Calls run with the MCU between commands, and a panic stops the worker
+fn test_runner_with_mcu() {
+    let runner = create_runner();
+    runner.with_mcu(|mcu| mcu.set_register(16, 0x10)).unwrap();
+    runner.step(2);
+    assert_eq!(runner.with_mcu(|mcu| mcu.get_register(16)), Some(0x11));
+
+    let result = runner.with_mcu(|_| -> u8 { panic!("Simulator panic") });
+    assert_eq!(result, None);
+    assert_eq!(runner.wait(TIMEOUT), RunStatus::Stopped);
+    assert!(!runner.run());
+    assert!(runner.snapshot().is_none());
+    assert!(runner.into_mcu().is_none());
+}
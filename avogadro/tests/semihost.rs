@@ -0,0 +1,149 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::history::History;
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::semihost::{self, Semihost, Stop};
+
+/// Steps until the firmware stops, at most `max_steps` times
+fn run_until_stop(mcu: &mut Mcu, max_steps: usize) -> Option<Stop> {
+    for _ in 0..max_steps {
+        mcu.step();
+        if let Some(stop) = mcu.get_semihost().and_then(Semihost::get_stop) {
+            return Some(stop);
+        }
+    }
+    None
+}
+
+#[test]
+/// Firmware writes characters and exits with a code, at the default address
+fn test_semihost_output_and_exit() {
+    let program = avr_asm!(
+        "ldi r16, 0x4f",
+        "out 0x00, r16",
+        "ldi r16, 0x6b",
+        "out 0x00, r16",
+        "ldi r16, 0x0a",
+        "out 0x00, r16",
+        "ldi r16, 3",
+        "out 0x01, r16",
+        "ldi r16, 4",
+        "out 0x01, r16",
+        "rjmp .-2"
+    );
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program);
+    mcu.set_semihost(Some(Semihost::new(semihost::DEFAULT_ADDRESS)));
+
+    assert_eq!(run_until_stop(&mut mcu, 100), Some(Stop::Exit(3)));
+    assert_eq!(mcu.get_program_counter(), 16);
+    let semihost = mcu.get_semihost_mut().unwrap();
+    assert_eq!(semihost.take_output(), b"Ok\n");
+    assert!(semihost.take_output().is_empty());
+    // The first exit code is kept
+    mcu.step();
+    mcu.step();
+    assert_eq!(mcu.get_semihost().unwrap().get_exit_code(), Some(3));
+}
+
+#[test]
+/// Writing the cycle counter latches it, so its bytes are read consistently
+fn test_semihost_cycles() {
+    // Device mapped in SRAM, accessed through X
+    let program = avr_asm!(
+        "ldi r26, 0x02",
+        "ldi r27, 0x01",
+        "nop",
+        "st X, r16",
+        "ld r20, X+",
+        "ld r21, X+",
+        "ld r22, X+",
+        "ld r23, X+",
+        "st -X, r16"
+    );
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program);
+    mcu.set_semihost(Some(Semihost::new(0x100)));
+    for _ in 0..8 {
+        mcu.step();
+    }
+    // Latched before executing `st`, after 3 cycles
+    assert_eq!(
+        [
+            mcu.get_register(20),
+            mcu.get_register(21),
+            mcu.get_register(22),
+            mcu.get_register(23)
+        ],
+        [3, 0, 0, 0]
+    );
+    // Writing other bytes doesn't latch
+    mcu.step();
+    assert_eq!(mcu.get_data_byte(0x102), 3);
+    assert_eq!(mcu.get_data_byte(0x105), 0);
+    assert_eq!(mcu.get_semihost().unwrap().get_stop(), None);
+}
+
+#[test]
+/// `BREAK` is a host trap only if the device is mapped
+fn test_semihost_break_trap() {
+    let program = avr_asm!("nop", "break", "nop");
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program);
+    mcu.step();
+    mcu.step();
+    assert_eq!(mcu.get_program_counter(), 4);
+
+    mcu.set_program_counter(0);
+    mcu.set_semihost(Some(Semihost::new(semihost::DEFAULT_ADDRESS)));
+    assert_eq!(run_until_stop(&mut mcu, 10), Some(Stop::Trap(2)));
+    assert_eq!(mcu.get_semihost().unwrap().get_exit_code(), None);
+}
+
+#[test]
+/// Without the device, its addresses are plain memory
+fn test_semihost_unmapped() {
+    let program = avr_asm!("ldi r16, 0x41", "out 0x00, r16", "out 0x01, r16");
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program);
+    mcu.set_semihost(Some(Semihost::new(semihost::DEFAULT_ADDRESS)));
+    let semihost = mcu.take_semihost();
+    assert!(semihost.is_some());
+    for _ in 0..3 {
+        mcu.step();
+    }
+    assert!(mcu.get_semihost().is_none());
+    assert_eq!(mcu.get_data_byte(0x20), 0x41);
+    assert_eq!(mcu.get_data_byte(0x21), 0x41);
+}
+
+#[test]
+/// Steps replayed by the history to step back don't write to the device
+/// again
+fn test_semihost_step_back() {
+    let program = avr_asm!(
+        "ldi r16, 0x2e",
+        "loop:",
+        "out 0x00, r16",
+        "out 0x02, r16",
+        "rjmp loop"
+    );
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program);
+    mcu.set_history(Some(History::new(256).with_checkpoint_interval(4)));
+    mcu.set_semihost(Some(Semihost::new(semihost::DEFAULT_ADDRESS)));
+    for _ in 0..30 {
+        mcu.step();
+    }
+    let output = mcu.get_semihost_mut().unwrap().take_output();
+    assert_eq!(output, vec![b'.'; 10]);
+    let snapshot = mcu.snapshot();
+    mcu.step();
+    assert!(mcu.step_back());
+    assert!(mcu.step_back());
+    mcu.step();
+    assert_eq!(mcu.snapshot().data_memory, snapshot.data_memory);
+    assert!(mcu.get_semihost_mut().unwrap().take_output().is_empty());
+}
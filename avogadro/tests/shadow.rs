@@ -0,0 +1,167 @@
+extern crate avr_avogadro;
+
+#[path = "fixtures/mod.rs"]
+mod fixtures;
+
+use avr_avogadro::core::assembler::{self, Program};
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::shadow::{FillPolicy, ShadowMemory, UninitializedUse, UseKind, DEFINED};
+use fixtures::create_mcu;
+
+fn with_shadow(policy: FillPolicy) -> impl FnOnce(&mut Mcu, Program) {
+    move |mcu, _| {
+        let shadow = ShadowMemory::new(mcu.get_data_size(), policy);
+        mcu.set_shadow_memory(Some(shadow));
+    }
+}
+
+fn run(mcu: &mut Mcu, steps: usize) -> Vec<UninitializedUse> {
+    for _ in 0..steps {
+        mcu.step();
+    }
+    mcu.get_shadow_memory_mut().unwrap().take_uses()
+}
+
+#[test]
+/// Registers and SRAM get power-on values, I/O registers keep theirs
+fn test_shadow_fill_policies() {
+    let mut values = [0; 8];
+    FillPolicy::Ones.fill(&mut values);
+    assert_eq!(values, [0xff; 8]);
+    FillPolicy::Zeros.fill(&mut values);
+    assert_eq!(values, [0; 8]);
+    let mut other = [0; 8];
+    FillPolicy::Random(1).fill(&mut values);
+    FillPolicy::Random(1).fill(&mut other);
+    assert_eq!(values, other);
+    FillPolicy::Random(2).fill(&mut other);
+    assert_ne!(values, other);
+
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.set_data_byte(0x38, 0x12);
+    mcu.set_shadow_memory(Some(ShadowMemory::new(512, FillPolicy::Ones)));
+    assert_eq!(mcu.get_register(16), 0xff);
+    assert_eq!(mcu.get_data_byte(0x60), 0xff);
+    assert_eq!(mcu.get_data_byte(0x1ff), 0xff);
+    assert_eq!(mcu.get_data_byte(0x38), 0x12);
+    let shadow = mcu.get_shadow_memory().unwrap();
+    assert_eq!(shadow.get_policy(), FillPolicy::Ones);
+    assert_eq!(shadow.register_mask(16), 0);
+    assert_eq!(shadow.data_mask(0x60), 0);
+    assert_eq!(shadow.data_mask(0x38), DEFINED);
+    assert_eq!(shadow.flags_mask(), DEFINED);
+
+    // Values written by the host are defined
+    mcu.set_register(16, 1);
+    mcu.set_data_byte(0x60, 0xff);
+    let shadow = mcu.get_shadow_memory().unwrap();
+    assert_eq!(shadow.register_mask(16), DEFINED);
+    assert_eq!(shadow.data_mask(0x60), DEFINED);
+}
+
+#[test]
+/// Moves copy defined bits, bitwise operations track them bit by bit
+fn test_shadow_propagation() {
+    let source = "
+        mov r2, r24
+        andi r24, 0x0f
+        ori r25, 0x80
+        eor r1, r1
+        ldi r26, 0x00
+        ldi r27, 0x01
+        st X+, r24
+        st X, r1
+        ld r3, -X
+        add r1, r2
+        in r4, 0x16
+        push r1
+        pop r5
+    ";
+    let mut mcu = create_mcu(source, with_shadow(FillPolicy::Zeros));
+    assert!(run(&mut mcu, 13).is_empty());
+    let shadow = mcu.get_shadow_memory().unwrap();
+    assert_eq!(shadow.register_mask(2), 0);
+    assert_eq!(shadow.register_mask(24), 0xf0);
+    assert_eq!(shadow.register_mask(25), 0x80);
+    assert_eq!(shadow.data_mask(0x100), 0xf0);
+    assert_eq!(shadow.data_mask(0x101), DEFINED);
+    assert_eq!(shadow.register_mask(3), 0xf0);
+    // Arithmetic on undefined bits is undefined, flags too
+    assert_eq!(shadow.register_mask(1), 0);
+    assert_eq!(shadow.flags_mask() & 0x3f, 0);
+    assert_eq!(shadow.register_mask(4), DEFINED);
+    assert_eq!(shadow.register_mask(5), 0);
+}
+
+#[test]
+/// Branches, pointers and I/O writes of undefined values are reported once
+/// per instruction
+fn test_shadow_uses() {
+    let source = "
+        ldi r16, 2
+    again:
+        cpi r24, 5
+        breq again
+        dec r16
+        brne again
+        out 0x18, r24
+        ld r0, Z
+        sbis 0x16, 0
+        nop
+        ret
+    ";
+    let mut mcu = create_mcu(source, with_shadow(FillPolicy::Random(7)));
+    let uses = run(&mut mcu, 14);
+    let kinds: Vec<(u16, UseKind)> = uses.iter().map(|used| (used.pc, used.kind)).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (0x4, UseKind::Branch),
+            (0xa, UseKind::IoWrite { address: 0x38 }),
+            (0xc, UseKind::Address),
+            (0x12, UseKind::IndirectJump),
+        ]
+    );
+    assert_eq!(
+        uses[0].to_string(),
+        format!(
+            "Conditional branch at 0x0004 depends on an uninitialized value, cycle {}",
+            uses[0].cycle
+        )
+    );
+
+    // Defined values aren't reported
+    let mut mcu = create_mcu(source, with_shadow(FillPolicy::Zeros));
+    mcu.set_register(24, 5);
+    mcu.set_register(30, 0x80);
+    mcu.set_register(31, 0);
+    mcu.set_data_byte(0x1f2, 0);
+    mcu.set_data_byte(0x1f3, 0);
+    for _ in 0..4 {
+        mcu.step();
+    }
+    mcu.set_register(24, 0);
+    assert!(run(&mut mcu, 9).is_empty());
+}
+
+#[test]
+/// Extended I/O registers are laid out from the device, defined at reset
+/// and checked like the other I/O registers
+fn test_shadow_extended_io() {
+    let program = assembler::assemble("ldi r26, 0x80\nldi r27, 0\nst X, r16").unwrap();
+    let mut mcu = McuFactory::create("atmega328p");
+    mcu.load_program_memory(&program.image);
+    mcu.set_data_byte(0x80, 0x12);
+    let shadow = ShadowMemory::new(mcu.get_data_size(), FillPolicy::Ones);
+    mcu.set_shadow_memory(Some(shadow));
+    assert_eq!(mcu.get_data_byte(0x80), 0x12);
+    assert_eq!(mcu.get_data_byte(0x100), 0xff);
+    let shadow = mcu.get_shadow_memory().unwrap();
+    assert_eq!(shadow.data_mask(0x80), DEFINED);
+    assert_eq!(shadow.data_mask(0xff), DEFINED);
+    assert_eq!(shadow.data_mask(0x100), 0);
+    let uses = run(&mut mcu, 3);
+    let uses: Vec<_> = uses.iter().map(|u| (u.pc, u.kind)).collect();
+    assert_eq!(uses, [(0x4, UseKind::IoWrite { address: 0x80 })]);
+}
@@ -0,0 +1,149 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::semihost::{Semihost, Stop, DEFAULT_ADDRESS};
+use avr_avogadro::core::snapshot::Snapshot;
+use std::env;
+use std::fs;
+
+#[test]
+/// Restoring a snapshot resumes execution from the same state
+fn test_snapshot_restore() {
+    let mut mcu = McuFactory::create("attiny85");
+    let program = avr_asm!(
+        "ldi r16, 0x10",
+        "ldi r26, 0x60",
+        "loop:",
+        "st X+, r16",
+        "subi r16, 1",
+        "brne loop"
+    );
+    mcu.load_program_memory(&program);
+    for _ in 0..8 {
+        mcu.step();
+    }
+    let snapshot = mcu.snapshot();
+    for _ in 0..20 {
+        mcu.step();
+    }
+    let expected = mcu.snapshot();
+    mcu.restore(&snapshot).unwrap();
+    assert_eq!(mcu.snapshot(), snapshot);
+    assert_eq!(mcu.get_cycle_count() as u64, snapshot.cycle_count);
+    for _ in 0..20 {
+        mcu.step();
+    }
+    assert_eq!(mcu.snapshot(), expected);
+}
+
+#[test]
+/// Snapshot files keep registers, flags, memories and cycle count
+fn test_snapshot_file() {
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&avr_asm!("ldi r16, 0xff", "subi r16, 1", "push r16"));
+    mcu.load_data_memory(&[1, 2, 3, 4]);
+    for _ in 0..2 {
+        mcu.step();
+    }
+    let mut path = env::temp_dir();
+    path.push("avogadro_test_snapshot_file.avs");
+    let filename = path.to_str().unwrap();
+    mcu.save_state(filename).unwrap();
+    let mut other = McuFactory::create("attiny85");
+    other.load_state(filename).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(other.snapshot(), mcu.snapshot());
+    assert_eq!(other.get_register(16), 0xfe);
+    assert_eq!(other.get_data_byte(2), 3);
+    assert!(other.get_flags().neg);
+}
+
+#[test]
+/// Invalid files and snapshots of other MCU models are rejected, sections
+/// added by newer versions are skipped
+fn test_snapshot_errors() {
+    let mut mcu = McuFactory::create("attiny85");
+    let mut buffer = Vec::new();
+    mcu.snapshot().write_to(&mut buffer).unwrap();
+    // Bad magic
+    let mut bad = buffer.clone();
+    bad[0] = b'X';
+    assert!(Snapshot::read_from(&mut bad.as_slice()).is_err());
+    // Unknown version
+    let mut bad = buffer.clone();
+    bad[4] = 0xff;
+    assert!(Snapshot::read_from(&mut bad.as_slice()).is_err());
+    // Truncated
+    let bad = &buffer[..buffer.len() - 10];
+    assert!(Snapshot::read_from(&mut &bad[..]).is_err());
+    // Unknown sections are skipped
+    let mut extended = buffer.clone();
+    let end = extended.len() - 8;
+    extended.splice(end..end, b"PERI\x02\0\0\0\x12\x34".iter().copied());
+    let snapshot = Snapshot::read_from(&mut extended.as_slice()).unwrap();
+    assert_eq!(snapshot, mcu.snapshot());
+    // Memory sizes must match
+    let mut snapshot = Snapshot::read_from(&mut buffer.as_slice()).unwrap();
+    snapshot.data_memory.push(0);
+    assert!(mcu.restore(&snapshot).is_err());
+}
+
+#[test]
+/// Snapshots keep the MCU sleeping until an interrupt
+fn test_snapshot_sleeping() {
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&avr_asm!("sleep", "nop"));
+    mcu.step();
+    let mut buffer = Vec::new();
+    mcu.snapshot().write_to(&mut buffer).unwrap();
+    let snapshot = Snapshot::read_from(&mut buffer.as_slice()).unwrap();
+    assert!(snapshot.sleeping);
+    let mut other = McuFactory::create("attiny85");
+    other.restore(&snapshot).unwrap();
+    assert!(other.is_sleeping());
+    other.enter_interrupt(0x0);
+    assert!(!other.is_sleeping());
+}
+
+#[test]
+/// Snapshots keep the device model, and only restore on the same one
+fn test_snapshot_device() {
+    let mcu = McuFactory::create("attiny85");
+    let mut buffer = Vec::new();
+    mcu.snapshot().write_to(&mut buffer).unwrap();
+    let snapshot = Snapshot::read_from(&mut buffer.as_slice()).unwrap();
+    assert_eq!(snapshot.device.as_deref(), Some("attiny85"));
+    assert_eq!(snapshot.ramend, mcu.get_ramend());
+    assert!(McuFactory::create("atmega328p").restore(&snapshot).is_err());
+    // Same memory sizes, but not the same model
+    let data_size = mcu.get_data_size();
+    let mut custom = Mcu::new(data_size, snapshot.program_memory.len(), 1000);
+    assert!(custom.restore(&snapshot).is_err());
+}
+
+#[test]
+/// Snapshot files keep the debug I/O device output and stop reason, and the
+/// breakpoints and watchpoints
+fn test_snapshot_debug_state() {
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.set_semihost(Some(Semihost::new(DEFAULT_ADDRESS)));
+    let semihost = mcu.get_semihost_mut().unwrap();
+    semihost.write(DEFAULT_ADDRESS, b'o', 0);
+    semihost.write(DEFAULT_ADDRESS, b'k', 0);
+    semihost.write(DEFAULT_ADDRESS + 1, 3, 0);
+    mcu.add_breakpoint(0x10);
+    mcu.add_watchpoint(0x60);
+    let mut buffer = Vec::new();
+    mcu.snapshot().write_to(&mut buffer).unwrap();
+    let snapshot = Snapshot::read_from(&mut buffer.as_slice()).unwrap();
+    let mut other = McuFactory::create("attiny85");
+    other.restore(&snapshot).unwrap();
+    assert_eq!(other.snapshot(), mcu.snapshot());
+    assert_eq!(other.get_breakpoints(), &[0x10]);
+    assert_eq!(other.get_watchpoints(), &[0x60]);
+    let semihost = other.get_semihost_mut().unwrap();
+    assert_eq!(semihost.get_stop(), Some(Stop::Exit(3)));
+    assert_eq!(semihost.take_output(), b"ok");
+}
@@ -20,7 +20,7 @@ use avr_avogadro::core::mcu_factory::McuFactory;
 ///   e: cf 91         pop r28
 ///  10: 08 95         ret
 ///
-/// Since stack is not configured, ret will jump to 93CF + 2 => 93D1
+/// Since stack is not configured, ret pops a null address and jumps to 0
 fn test_basic_stack() {
     let mut mcu = McuFactory::create("attiny85");
     let program_memory = vec![
@@ -31,5 +31,5 @@ fn test_basic_stack() {
     for _ in 0..9 {
         mcu.step()
     }
-    assert_eq!(mcu.get_program_counter(), 2);
+    assert_eq!(mcu.get_program_counter(), 0);
 }
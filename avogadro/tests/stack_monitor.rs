@@ -0,0 +1,161 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::core::assembler;
+use avr_avogadro::core::call_stack::CallStack;
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::stack_monitor::{
+    StackCollision, StackMonitor, StackPolicy, StackRegion, StackUsage,
+};
+use avr_avogadro::core::symbols::{Symbol, DATA_SPACE_OFFSET};
+
+/// `recurse` never returns, each level takes 3 bytes of stack. `isr` takes
+/// 2 bytes.
+const SOURCE: &str = "
+main:
+    rcall recurse
+    nop
+recurse:
+    push r16
+    rcall recurse
+isr:
+    push r16
+    push r17
+    pop r17
+    pop r16
+    reti
+";
+
+const RAMEND: u16 = 0x1f0;
+const HEAP_START: u16 = 0x120;
+const BRKVAL: u16 = 0x110;
+
+fn create_mcu(policy: StackPolicy) -> Mcu {
+    let program = assembler::assemble(SOURCE).unwrap();
+    let mut symbols = program.symbols;
+    for (name, address) in &[("__heap_start", HEAP_START), ("__brkval", BRKVAL)] {
+        symbols.add(Symbol {
+            name: (*name).to_owned(),
+            address: DATA_SPACE_OFFSET + u32::from(*address),
+            size: 0,
+            is_global: true,
+        });
+    }
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program.image);
+    mcu.set_stack_pointer(RAMEND);
+    mcu.set_call_stack(Some(CallStack::new(symbols.clone())));
+    mcu.set_stack_monitor(Some(StackMonitor::new(symbols, RAMEND, policy)));
+    mcu
+}
+
+/// Steps until the stack monitor faults, returning the number of steps
+fn run_to_fault(mcu: &mut Mcu) -> usize {
+    let mut steps = 0;
+    while mcu
+        .get_stack_monitor()
+        .and_then(StackMonitor::get_fault)
+        .is_none()
+    {
+        mcu.step();
+        steps += 1;
+        assert!(steps < 1000);
+    }
+    steps
+}
+
+#[test]
+/// The stack growing into static data is recorded once per excursion, and
+/// the high-water mark is the lowest stack pointer reached
+fn test_stack_collision_static_data() {
+    let mut mcu = create_mcu(StackPolicy::Warn);
+    let monitor = mcu.get_stack_monitor().unwrap();
+    assert_eq!(monitor.get_static_end(), Some(HEAP_START));
+    assert_eq!(monitor.get_brkval_address(), Some(BRKVAL));
+    for _ in 0..200 {
+        mcu.step();
+    }
+    let stack_pointer = mcu.get_stack_pointer();
+    let monitor = mcu.get_stack_monitor_mut().unwrap();
+    assert!(monitor.get_fault().is_none());
+    let collisions = monitor.take_collisions();
+    assert_eq!(collisions.len(), 1);
+    let collision = &collisions[0];
+    assert_eq!(collision.region, StackRegion::StaticData);
+    assert_eq!(collision.limit, HEAP_START);
+    assert!(collision.stack_pointer < HEAP_START - 1);
+    assert!(collision.stack_pointer >= HEAP_START - 4);
+    assert_eq!(
+        monitor.usage(),
+        vec![StackUsage {
+            name: "main".to_owned(),
+            lowest_stack_pointer: stack_pointer,
+            high_water: RAMEND - stack_pointer,
+        }]
+    );
+    let mut report = Vec::new();
+    monitor.write_report(&mut report).unwrap();
+    assert_eq!(
+        String::from_utf8(report).unwrap(),
+        format!(
+            " lowest SP high-water  context\n    0x{:04x} {:>10}  main\n",
+            stack_pointer,
+            RAMEND - stack_pointer
+        )
+    );
+}
+
+#[test]
+/// With a fault policy, growing into the malloc heap is a fault
+fn test_stack_collision_heap() {
+    let mut mcu = create_mcu(StackPolicy::Fault);
+    let [low, high] = 0x180_u16.to_le_bytes();
+    mcu.set_data_byte(BRKVAL, low);
+    mcu.set_data_byte(BRKVAL + 1, high);
+    run_to_fault(&mut mcu);
+    let fault = mcu.get_stack_monitor().unwrap().get_fault().unwrap();
+    assert_eq!(
+        *fault,
+        StackCollision {
+            pc: 0x4,
+            stack_pointer: 0x17e,
+            limit: 0x180,
+            region: StackRegion::Heap,
+            cycle: fault.cycle,
+        }
+    );
+    assert_eq!(
+        fault.to_string(),
+        format!(
+            "Stack overflow into heap at 0x0004: SP 0x017e, limit 0x0180, cycle {}",
+            fault.cycle
+        )
+    );
+}
+
+#[test]
+/// Interrupts and tasks get their own high-water marks
+fn test_stack_usage_contexts() {
+    let mut mcu = create_mcu(StackPolicy::Warn);
+    mcu.get_stack_monitor_mut()
+        .unwrap()
+        .add_task("task", 0x180..0x1a0);
+    mcu.set_stack_pointer(0x19f);
+    mcu.step();
+    mcu.step();
+    // An interrupt arrives in the task
+    mcu.set_program_counter(0x8);
+    let stack_pointer = mcu.get_stack_pointer();
+    mcu.get_call_stack_mut()
+        .unwrap()
+        .enter_interrupt(0x4, 0x8, stack_pointer);
+    for _ in 0..4 {
+        mcu.step();
+    }
+    let usage = mcu.get_stack_monitor().unwrap().usage();
+    let names: Vec<(&str, u16)> = usage
+        .iter()
+        .map(|usage| (usage.name.as_str(), usage.high_water))
+        .collect();
+    assert_eq!(names, vec![("main", 0), ("task", 3), ("isr", 2)]);
+}
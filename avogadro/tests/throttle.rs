@@ -0,0 +1,126 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::runner::{McuRunner, RunStatus};
+use avr_avogadro::core::throttle::Throttle;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MS: Duration = Duration::from_millis(1);
+
+#[test]
+/// Execution runs a millisecond ahead of the wall clock, catches up after
+/// late wake-ups and restarts the schedule after long stalls
+fn test_throttle_schedule() {
+    let start = Instant::now();
+    let mut throttle = Throttle::new(1_000_000, Some(1.0));
+    throttle.reset(0, start);
+    assert!((999..=1000).contains(&throttle.cycle_limit(0, start)));
+    assert_eq!(throttle.delay(1000, start), MS);
+    assert_eq!(throttle.delay(1000, start + 2 * MS), Duration::ZERO);
+    // Woken up half a millisecond late, the next slice is longer
+    let late = start + MS + MS / 2;
+    assert!((2499..=2500).contains(&throttle.cycle_limit(1000, late)));
+    // 200 ms behind, it runs from the current cycle instead of bursting
+    let stalled = start + 200 * MS;
+    assert!((1999..=2000).contains(&throttle.cycle_limit(1000, stalled)));
+    assert_eq!(throttle.delay(1000, stalled), Duration::ZERO);
+    assert_eq!(throttle.delay(2000, stalled), MS);
+
+    throttle.set_multiplier(Some(0.1));
+    throttle.reset(0, start);
+    assert!((99..=100).contains(&throttle.cycle_limit(0, start)));
+    assert_eq!(throttle.delay(1000, start), 10 * MS);
+
+    for multiplier in [None, Some(0.0), Some(-1.0), Some(f64::NAN)] {
+        throttle.set_multiplier(multiplier);
+        assert_eq!(throttle.get_multiplier(), None);
+        assert_eq!(throttle.cycle_limit(0, start), usize::MAX);
+        assert_eq!(throttle.delay(1_000_000, start), Duration::ZERO);
+    }
+}
+
+#[test]
+/// The achieved speed is measured every half second
+fn test_throttle_achieved_speed() {
+    let start = Instant::now();
+    let mut throttle = Throttle::new(16_000_000, None);
+    throttle.reset(1000, start);
+    throttle.record(2_001_000, start + 250 * MS);
+    assert_eq!(throttle.achieved_hz(), 0.0);
+    throttle.record(4_001_000, start + 500 * MS);
+    assert_eq!(throttle.achieved_hz(), 8_000_000.0);
+    assert_eq!(throttle.achieved_multiplier(), 0.5);
+    throttle.record(12_001_000, start + 1000 * MS);
+    assert_eq!(throttle.achieved_hz(), 16_000_000.0);
+}
+
+#[test]
+/// Running in slices up to `cycle_limit` and waiting `delay` between them
+/// keeps the MCU on schedule, even with late wake-ups. Wall time is
+/// simulated, each slice taking 100 us to run.
+fn test_throttle_pacing() {
+    for multiplier in [1.0, 0.1] {
+        let mut mcu = McuFactory::create("attiny85");
+        mcu.load_program_memory(&avr_asm!("nop", "loop:", "nop", "rjmp loop"));
+        let start = Instant::now();
+        let mut now = start;
+        let mut throttle = Throttle::new(1_000_000, Some(multiplier));
+        throttle.reset(0, start);
+        for slice in 0.. {
+            now += throttle.delay(mcu.get_cycle_count(), now);
+            if now >= start + 700 * MS {
+                break;
+            }
+            // Every tenth wake-up is 3 ms late
+            if slice % 10 == 9 {
+                now += 3 * MS;
+            }
+            let cycle_limit = throttle.cycle_limit(mcu.get_cycle_count(), now);
+            while mcu.get_cycle_count() < cycle_limit {
+                mcu.step();
+            }
+            now += MS / 10;
+            throttle.record(mcu.get_cycle_count(), now);
+        }
+        let expected = 700_000.0 * multiplier;
+        let cycles = mcu.get_cycle_count() as f64;
+        assert!(
+            (cycles - expected).abs() < 0.01 * expected,
+            "{} cycles",
+            cycles
+        );
+        let achieved = throttle.achieved_multiplier();
+        assert!(
+            (achieved - multiplier).abs() < 0.01 * multiplier,
+            "{}x",
+            achieved
+        );
+    }
+}
+
+#[test]
+/// Runners read the MCU clock again when the speed is set, so slowing the
+/// clock down slows running down. Only an upper bound of the cycles is
+/// checked, as the host may run the worker late but never early.
+fn test_runner_clock_change() {
+    let program = avr_asm!("nop", "loop:", "nop", "rjmp loop");
+    let mut mcu = McuFactory::create("attiny85");
+    mcu.load_program_memory(&program);
+    let runner = McuRunner::new(mcu);
+    // 1 kHz clock
+    runner.with_mcu(|mcu| mcu.set_speed(1));
+    let start = Instant::now();
+    runner.set_speed(Some(1.0));
+    runner.run();
+    thread::sleep(50 * MS);
+    runner.pause();
+    assert_eq!(runner.wait(Duration::from_secs(10)), RunStatus::Paused);
+    let elapsed = start.elapsed().as_secs_f64();
+    let cycles = runner.snapshot().unwrap().cycle_count;
+    // A slice runs a millisecond ahead, and `rjmp` may end past it
+    let max_cycles = (elapsed * 1000.0) as u64 + 3;
+    assert!(cycles <= max_cycles, "{} cycles in {} s", cycles, elapsed);
+    assert_eq!(runner.achieved_hz(), 0.0);
+}
@@ -0,0 +1,112 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::trace::{TraceEntry, Tracer};
+
+#[test]
+/// Each entry holds cycle, changed registers, flags, stack pointer and
+/// memory writes
+fn test_trace_deltas() {
+    let mut mcu = McuFactory::create("attiny85");
+    let program = avr_asm!(
+        "ldi r16, 0x80",
+        "push r16",
+        "add r16, r16", // Carry, Zero and Overflow set
+        "rjmp .+0",
+        "nop",
+    );
+    mcu.load_program_memory(&program);
+    mcu.set_tracer(Some(Tracer::new()));
+    for _ in 0..5 {
+        mcu.step();
+    }
+    assert_eq!(mcu.get_cycle_count(), 7);
+    let entries = mcu.get_tracer().unwrap().entries().collect::<Vec<_>>();
+    assert_eq!(entries.len(), 5);
+    assert_eq!(
+        entries.iter().map(|e| e.cycle).collect::<Vec<_>>(),
+        [0, 1, 3, 4, 6]
+    );
+    assert_eq!(entries[0].registers, [(16, 0x80)]);
+    assert_eq!(entries[0].disassembly(), "ldi\tr16, 0x80");
+    assert_eq!(entries[1].memory_writes, [(0x25F, 0x80)]);
+    assert_eq!(entries[1].stack_pointer, 0x25F);
+    assert!(entries[1].registers.is_empty());
+    assert_eq!(entries[2].registers, [(16, 0x00)]);
+    assert_eq!(entries[2].sreg_before, 0);
+    assert_eq!(entries[2].sreg_after & 0x0B, 0x0B);
+    assert_eq!(entries[3].pc, 0x6);
+    assert_eq!(entries[4].pc, 0x8);
+}
+
+#[test]
+/// Text output has one line per instruction
+fn test_trace_text() {
+    let mut mcu = McuFactory::create("attiny85");
+    let program = avr_asm!("ldi r16, 0x80", "push r16", "add r16, r16");
+    mcu.load_program_memory(&program);
+    mcu.set_tracer(Some(Tracer::new()));
+    for _ in 0..3 {
+        mcu.step();
+    }
+    let mut output = Vec::new();
+    mcu.get_tracer().unwrap().write_text(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "       0  0000: e800  ldi r16, 0x80            r16=80 SP=0000"
+    );
+    assert_eq!(
+        lines[1],
+        "       1  0002: 930f  push r16                 SP=025f [025f]=80"
+    );
+    assert_eq!(
+        lines[2],
+        "       3  0004: 0f00  add r16, r16             r16=00 SREG=........->...SV.ZC SP=025f"
+    );
+}
+
+#[test]
+/// Binary output can be read back
+fn test_trace_binary_round_trip() {
+    let mut mcu = McuFactory::create("attiny85");
+    let program = avr_asm!("ldi r16, 0x80", "push r16", "add r16, r16");
+    mcu.load_program_memory(&program);
+    mcu.set_tracer(Some(Tracer::new()));
+    for _ in 0..3 {
+        mcu.step();
+    }
+    let tracer = mcu.take_tracer().unwrap();
+    let mut output = Vec::new();
+    tracer.write_binary(&mut output).unwrap();
+    assert_eq!(&output[..5], b"AVTR\x01");
+    let entries = Tracer::read_binary(&mut output.as_slice()).unwrap();
+    assert_eq!(
+        entries,
+        tracer.entries().cloned().collect::<Vec<TraceEntry>>()
+    );
+    assert!(Tracer::read_binary(&mut &b"nope"[..]).is_err());
+}
+
+#[test]
+/// Address filters and ring buffer mode
+fn test_trace_filters() {
+    let mut mcu = McuFactory::create("attiny85");
+    let program = avr_asm!("nop", "loop:", "inc r16", "inc r17", "inc r18", "rjmp loop");
+    mcu.load_program_memory(&program);
+    let mut tracer = Tracer::ring_buffer(3);
+    tracer.add_address_range(0x4, 0x6);
+    mcu.set_tracer(Some(tracer));
+    for _ in 0..13 {
+        mcu.step();
+    }
+    let tracer = mcu.get_tracer().unwrap();
+    assert_eq!(tracer.len(), 3);
+    let entries = tracer.entries().collect::<Vec<_>>();
+    assert_eq!(entries[0].pc, 0x6);
+    assert_eq!(entries[1].pc, 0x4);
+    assert_eq!(entries[2].pc, 0x6);
+    assert_eq!(entries[2].registers, [(18, 3)]);
+}
@@ -0,0 +1,142 @@
+extern crate avr_avogadro;
+
+use avr_avogadro::avr_asm;
+use avr_avogadro::core::device::DEFAULT_PORTS;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::vcd::{Probe, VcdWriter};
+use std::env;
+use std::fs;
+
+#[test]
+/// Pins, I/O registers, SREG bits and PC are dumped when they change, with
+/// timestamps taken from the cycle counter
+fn test_vcd_dump() {
+    let mut mcu = McuFactory::create("attiny85");
+    let program = avr_asm!("sbi 0x17, 0", "sbi 0x18, 0", "cbi 0x18, 0", "subi r16, 1");
+    mcu.load_program_memory(&program);
+    let mut path = env::temp_dir();
+    path.push("avogadro_test_vcd_dump.vcd");
+    let filename = path.to_str().unwrap();
+    // 1 MHz, so each cycle takes 1us
+    let mut vcd_writer = VcdWriter::create(filename, 1_000_000).unwrap();
+    vcd_writer.add_probe_spec("PB0", mcu.get_ports()).unwrap();
+    vcd_writer.add_probe_spec("PORTB", mcu.get_ports()).unwrap();
+    vcd_writer
+        .add_probe_spec("sreg.C", mcu.get_ports())
+        .unwrap();
+    vcd_writer.add_probe("pc", Probe::ProgramCounter).unwrap();
+    mcu.set_vcd_writer(Some(vcd_writer));
+    for _ in 0..4 {
+        mcu.step();
+    }
+    mcu.take_vcd_writer().unwrap().finish().unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let expected = "$version avr-avogadro $end
+$timescale 1ps $end
+$scope module mcu $end
+$var wire 1 ! PB0 $end
+$var wire 8 \" PORTB $end
+$var wire 1 # SREG_C $end
+$var wire 16 $ pc $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+0!
+b00000000 \"
+0#
+b0000000000000000 $
+$end
+#2000000
+b0000000000000010 $
+#4000000
+1!
+b00000001 \"
+b0000000000000100 $
+#6000000
+0!
+b00000000 \"
+b0000000000000110 $
+#7000000
+1#
+b0000000000001000 $
+";
+    assert_eq!(contents, expected);
+}
+
+#[test]
+/// Probe descriptions used by the CLI and FFI
+fn test_vcd_probe_specs() {
+    let ports = &DEFAULT_PORTS;
+    let mut vcd_writer = VcdWriter::new(Box::new(Vec::new()), 1_000_000);
+    assert!(vcd_writer.add_probe_spec("pc", ports).is_ok());
+    assert!(vcd_writer.add_probe_spec("sreg", ports).is_ok());
+    assert!(vcd_writer.add_probe_spec("PD7", ports).is_ok());
+    assert!(vcd_writer.add_probe_spec("DDRB", ports).is_ok());
+    assert!(vcd_writer
+        .add_probe_spec("counter=word:0x60", ports)
+        .is_ok());
+    assert!(vcd_writer.add_probe_spec("96", ports).is_ok());
+    assert!(vcd_writer.add_probe_spec("PB8", ports).is_err());
+    assert!(vcd_writer.add_probe_spec("PE0", ports).is_err());
+    assert!(vcd_writer.add_probe_spec("P", ports).is_err());
+    assert!(vcd_writer.add_probe_spec("Pñ", ports).is_err());
+    assert!(vcd_writer.add_probe_spec("sreg.X", ports).is_err());
+    assert!(vcd_writer.add_probe_spec("word:zz", ports).is_err());
+    let mcu = McuFactory::create("attiny85");
+    vcd_writer.sample(&mcu);
+    // Probes can't be added once the header is written
+    assert!(vcd_writer.add_probe_spec("pc", ports).is_err());
+    assert!(vcd_writer.finish().is_ok());
+}
+
+#[test]
+/// Port registers are the ones of the device
+fn test_vcd_device_ports() {
+    let attiny85 = McuFactory::create("attiny85");
+    let ports = attiny85.get_ports();
+    assert_eq!(
+        Probe::parse("PB3", ports),
+        Some(Probe::Pin { port: 0x38, bit: 3 })
+    );
+    assert_eq!(Probe::parse("DDRB", ports), Some(Probe::DataByte(0x37)));
+    assert_eq!(Probe::parse("PD7", ports), None);
+    let atmega328p = McuFactory::create("atmega328p");
+    let ports = atmega328p.get_ports();
+    assert_eq!(
+        Probe::parse("PB5", ports),
+        Some(Probe::Pin { port: 0x25, bit: 5 })
+    );
+    assert_eq!(Probe::parse("PIND", ports), Some(Probe::DataByte(0x29)));
+    assert_eq!(Probe::parse("PA0", ports), None);
+    let atmega2560 = McuFactory::create("atmega2560");
+    let ports = atmega2560.get_ports();
+    assert_eq!(
+        Probe::parse("PH0", ports),
+        Some(Probe::Pin {
+            port: 0x102,
+            bit: 0
+        })
+    );
+    assert_eq!(Probe::parse("PORTL", ports), Some(Probe::DataByte(0x10B)));
+    assert_eq!(Probe::parse("PI0", ports), None);
+}
+
+#[test]
+/// Pin probes sample the port registers of the device, `PORTB` at 0x25 on
+/// the ATmega328P
+fn test_vcd_pin_atmega328p() {
+    let mut mcu = McuFactory::create("atmega328p");
+    // DDRB and PORTB, at I/O addresses 0x04 and 0x05
+    let program = avr_asm!("sbi 0x04, 5", "sbi 0x05, 5", "cbi 0x04, 5");
+    mcu.load_program_memory(&program);
+    let probe = Probe::parse("PB5", mcu.get_ports()).unwrap();
+    mcu.step();
+    assert_eq!(probe.sample(&mcu), 0);
+    mcu.step();
+    assert_eq!(probe.sample(&mcu), 1);
+    // Inputs read PINB, which isn't driven
+    mcu.step();
+    assert_eq!(probe.sample(&mcu), 0);
+}
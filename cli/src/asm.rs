@@ -0,0 +1,75 @@
+use avr_avogadro::core::assembler;
+use std::fs;
+
+const USAGE: &str = "Usage: avogadro asm [options] <file.s>
+
+Options:
+    -o <file>           Output binary image (default: input with .bin extension)
+    --symbols <file>    Writes labels in `avr-nm` format";
+
+struct Options {
+    filename: String,
+    output: Option<String>,
+    symbols: Option<String>,
+}
+
+/// Assembles a source file into a binary program image
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let source = fs::read_to_string(&options.filename)
+        .map_err(|e| format!("Cannot read {}: {}", options.filename, e))?;
+    let program =
+        assembler::assemble(&source).map_err(|e| format!("{}: {}", options.filename, e))?;
+    let filename = &options.filename;
+    let output = options.output.unwrap_or_else(|| default_output(filename));
+    fs::write(&output, &program.image).map_err(|e| format!("Cannot write {}: {}", output, e))?;
+    if let Some(filename) = &options.symbols {
+        let contents = program
+            .symbols
+            .iter()
+            .map(|symbol| format!("{:08x} T {}\n", symbol.address, symbol.name))
+            .collect::<String>();
+        fs::write(filename, contents).map_err(|e| format!("Cannot write {}: {}", filename, e))?;
+    }
+    Ok(())
+}
+
+/// Replaces the `.s` extension with `.bin`
+fn default_output(filename: &str) -> String {
+    let stem = filename
+        .strip_suffix(".s")
+        .or_else(|| filename.strip_suffix(".S"))
+        .unwrap_or(filename);
+    format!("{}.bin", stem)
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        filename: String::new(),
+        output: None,
+        symbols: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => options.output = Some(next_value(&mut args, arg)?),
+            "--symbols" => options.symbols = Some(next_value(&mut args, arg)?),
+            "--help" => return Err(USAGE.to_owned()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}\n{}", arg, USAGE)),
+            _ => options.filename = arg.clone(),
+        }
+    }
+    if options.filename.is_empty() {
+        return Err(USAGE.to_owned());
+    }
+    Ok(options)
+}
+
+fn next_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<String, String> {
+    args.next()
+        .cloned()
+        .ok_or_else(|| format!("Missing value for {}", option))
+}
@@ -0,0 +1,83 @@
+use avr_avogadro::core::disassembler::Disassembler;
+use avr_avogadro::core::loader;
+use avr_avogadro::core::symbols::SymbolTable;
+use std::fs;
+
+const USAGE: &str = "Usage: avogadro disasm [options] <file>
+
+Options:
+    --objdump           Output with the same layout as `avr-objdump -d`
+    --symbols <file>    Symbols listed by `avr-nm`
+    --vectors <count>   Number of entries of the interrupt vector table
+    --section <name>    Section name for objdump output (default: .text)";
+
+struct Options {
+    filename: String,
+    objdump: bool,
+    symbols: Option<String>,
+    vectors: usize,
+    section: String,
+}
+
+/// Disassembles a program file into stdout
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let program = loader::read_program_file(&options.filename)
+        .map_err(|e| format!("Cannot read {}: {}", options.filename, e))?;
+    let mut disassembler = Disassembler::new(&program);
+    if let Some(filename) = &options.symbols {
+        let contents =
+            fs::read_to_string(filename).map_err(|e| format!("Cannot read {}: {}", filename, e))?;
+        disassembler.set_symbols(SymbolTable::from_nm(&contents));
+    }
+    disassembler.set_vector_count(options.vectors);
+    let mut output = String::new();
+    if options.objdump {
+        disassembler.write_objdump(&mut output, &options.section)
+    } else {
+        disassembler.write_listing(&mut output)
+    }
+    .map_err(|e| e.to_string())?;
+    print!("{}", output);
+    Ok(())
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        filename: String::new(),
+        objdump: false,
+        symbols: None,
+        vectors: 0,
+        section: ".text".to_owned(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--objdump" => options.objdump = true,
+            "--symbols" => options.symbols = Some(next_value(&mut args, arg)?),
+            "--vectors" => {
+                let value = next_value(&mut args, arg)?;
+                options.vectors = value
+                    .parse()
+                    .map_err(|_| format!("Invalid vector count: {}", value))?;
+            }
+            "--section" => options.section = next_value(&mut args, arg)?,
+            "--help" => return Err(USAGE.to_owned()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}\n{}", arg, USAGE)),
+            _ => options.filename = arg.clone(),
+        }
+    }
+    if options.filename.is_empty() {
+        return Err(USAGE.to_owned());
+    }
+    Ok(options)
+}
+
+fn next_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<String, String> {
+    args.next()
+        .cloned()
+        .ok_or_else(|| format!("Missing value for {}", option))
+}
@@ -0,0 +1,42 @@
+//! # AVR-Avogadro CLI
+//!
+//! Command line tools built on top of the simulator library.
+extern crate avr_avogadro;
+extern crate env_logger;
+
+/// `asm` command
+mod asm;
+/// `disasm` command
+mod disasm;
+/// `run` command
+mod run;
+
+use std::process;
+
+const USAGE: &str = "Usage: avogadro <command> [options] <file>
+
+Commands:
+    asm       Assembles GNU as style source into a binary image
+    disasm    Disassembles a program image (.bin or .hex)
+    run       Runs a program image, optionally dumping traces and waveforms
+
+Run `avogadro <command> --help` for command options";
+
+fn main() {
+    env_logger::init();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.first().map(String::as_str) {
+        Some("asm") => asm::run(&args[1..]).map(|_| 0),
+        Some("disasm") => disasm::run(&args[1..]).map(|_| 0),
+        Some("run") => run::run(&args[1..]),
+        _ => Err(USAGE.to_owned()),
+    };
+    match result {
+        Ok(0) => (),
+        Ok(code) => process::exit(code),
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    }
+}
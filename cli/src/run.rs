@@ -0,0 +1,392 @@
+use avr_avogadro::core::call_stack::CallStack;
+use avr_avogadro::core::coverage::Coverage;
+use avr_avogadro::core::elf::{self, ElfFile};
+use avr_avogadro::core::loader;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::memory_map::AccessMode;
+use avr_avogadro::core::profiler::Profiler;
+use avr_avogadro::core::semihost::{self, Semihost, Stop};
+use avr_avogadro::core::shadow::{FillPolicy, ShadowMemory};
+use avr_avogadro::core::stack_monitor::{StackMonitor, StackPolicy};
+use avr_avogadro::core::throttle::Throttle;
+use avr_avogadro::core::trace::Tracer;
+use avr_avogadro::core::vcd::VcdWriter;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Instant;
+
+const USAGE: &str = "Usage: avogadro run [options] <file>
+
+Options:
+    --mcu <name>        MCU model: attiny85, atmega328p or atmega2560 (default:
+                        attiny85)
+    --cycles <count>    Stops after running this many clock cycles (default: 1000000)
+    --clock <hz>        Clock frequency, used for VCD timestamps and --speed
+                        (default: the MCU clock, 1000000 on the attiny85 and
+                        16000000 on the atmegas)
+    --speed <n|max>     Paces the run to n times the clock frequency, like 1 for
+                        real time or 0.1, and prints the achieved speed
+                        (default: max)
+    --vcd <file>        Dumps waveforms of the probes into a VCD file
+    --probe <spec>      Value traced in the VCD file, can be repeated or comma
+                        separated: pc, sreg, sreg.Z, PB0, PORTB, 0x60, word:0x60
+                        or name=spec
+    --trace <file>      Writes the execution trace as text
+    --trace-last <n>    Only keeps the last n instructions of the trace
+    --coverage <file>   Writes execution counts of each address
+    --lcov <file>       Writes an lcov coverage file, needs an ELF file with debug info
+    --profile <file>    Writes a flat profile and call graph of the functions,
+                        needs an ELF file with symbols
+    --folded <file>     Writes folded stacks for flamegraph tools
+    --semihost <addr>   Maps the debug I/O device at this data address, which
+                        must be free on the MCU (like 0x20 on the attiny85):
+                        firmware output goes to stdout, the exit code stops
+                        the run and is returned, and BREAK stops it with an
+                        error and a backtrace
+    --stack-check <policy>
+                        Checks the stack doesn't grow into static data or the
+                        heap, needs an ELF file with avr-libc symbols: warn
+                        prints collisions to stderr, fault stops the run with
+                        an error
+    --stack <file>      Writes the stack high-water mark of each context
+    --uninit <fill>     Reports branches, addresses and I/O writes depending on
+                        uninitialized registers or SRAM, which start as zeros,
+                        ones or random[:seed]
+    --strict            Reports accesses to unmapped or reserved addresses and
+                        writes to read-only registers instead of wrapping
+                        addresses";
+
+struct Options {
+    filename: String,
+    mcu: String,
+    cycles: usize,
+    clock: Option<u64>,
+    speed: Option<f64>,
+    vcd: Option<String>,
+    probes: Vec<String>,
+    trace: Option<String>,
+    trace_last: Option<usize>,
+    coverage: Option<String>,
+    lcov: Option<String>,
+    profile: Option<String>,
+    folded: Option<String>,
+    semihost: Option<u16>,
+    stack_check: Option<StackPolicy>,
+    stack: Option<String>,
+    uninit: Option<FillPolicy>,
+    strict: bool,
+}
+
+/// Runs a program for some cycles, optionally tracing it. Returns the
+/// firmware exit code if it was written to the debug I/O device
+pub fn run(args: &[String]) -> Result<i32, String> {
+    let options = parse_options(args)?;
+    let elf = read_program(&options.filename)
+        .map_err(|e| format!("Cannot read {}: {}", options.filename, e))?;
+    if options.lcov.is_some() && elf.lines.is_empty() {
+        return Err(format!("{} has no debug line info", options.filename));
+    }
+    let profiled = options.profile.is_some() || options.folded.is_some();
+    if profiled && elf.symbols.is_empty() {
+        return Err(format!("{} has no symbols", options.filename));
+    }
+    let has_static_end = ["__heap_start", "__bss_end"]
+        .iter()
+        .any(|name| elf.symbols.by_name(name).is_some());
+    if options.stack_check.is_some() && !has_static_end {
+        return Err(format!("{} has no __heap_start symbol", options.filename));
+    }
+    let mut mcu = McuFactory::try_create(&options.mcu)
+        .ok_or_else(|| format!("Unsupported MCU: {}", options.mcu))?;
+    mcu.load_program_memory(&elf.program);
+    let clock = options.clock.unwrap_or(mcu.get_speed() as u64 * 1000);
+    if let Some(filename) = &options.vcd {
+        let mut vcd_writer = VcdWriter::create(filename, clock)
+            .map_err(|e| format!("Cannot create {}: {}", filename, e))?;
+        for spec in options.probes.iter().flat_map(|probes| probes.split(',')) {
+            vcd_writer.add_probe_spec(spec, mcu.get_ports())?;
+        }
+        mcu.set_vcd_writer(Some(vcd_writer));
+    }
+    if options.trace.is_some() {
+        let tracer = match options.trace_last {
+            Some(count) => Tracer::ring_buffer(count),
+            None => Tracer::new(),
+        };
+        mcu.set_tracer(Some(tracer));
+    }
+    if options.coverage.is_some() || options.lcov.is_some() {
+        mcu.set_coverage(Some(Coverage::new(mcu.get_program_size())));
+    }
+    if profiled {
+        mcu.set_profiler(Some(Profiler::new(elf.symbols.clone())));
+    }
+    if let Some(address) = options.semihost {
+        mcu.set_semihost(Some(Semihost::new(address)));
+    }
+    if options.semihost.is_some() || options.stack_check.is_some() || options.stack.is_some() {
+        mcu.set_call_stack(Some(CallStack::new(elf.symbols.clone())));
+    }
+    if options.stack_check.is_some() || options.stack.is_some() {
+        let ramend = mcu.get_ramend();
+        let policy = options.stack_check.unwrap_or(StackPolicy::Warn);
+        mcu.set_stack_monitor(Some(StackMonitor::new(elf.symbols.clone(), ramend, policy)));
+    }
+    if let Some(policy) = options.uninit {
+        mcu.set_shadow_memory(Some(ShadowMemory::new(mcu.get_data_size(), policy)));
+    }
+    if options.strict {
+        mcu.set_access_mode(AccessMode::Strict);
+    }
+    let mut stdout = io::stdout();
+    let mut throttle = options
+        .speed
+        .map(|multiplier| Throttle::new(clock, Some(multiplier)));
+    let start = Instant::now();
+    let mut cycle_limit = 0;
+    while mcu.get_cycle_count() < options.cycles {
+        if let Some(throttle) = &mut throttle {
+            if mcu.get_cycle_count() >= cycle_limit {
+                cycle_limit = throttle.pace(mcu.get_cycle_count());
+            }
+        }
+        mcu.step();
+        if let Some(semihost) = mcu.get_semihost_mut() {
+            let output = semihost.take_output();
+            if !output.is_empty() {
+                stdout
+                    .write_all(&output)
+                    .map_err(|e| format!("Cannot write output: {}", e))?;
+            }
+            if semihost.get_stop().is_some() {
+                break;
+            }
+        }
+        if mcu
+            .get_stack_monitor()
+            .and_then(StackMonitor::get_fault)
+            .is_some()
+        {
+            break;
+        }
+    }
+    stdout
+        .flush()
+        .map_err(|e| format!("Cannot write output: {}", e))?;
+    if throttle.is_some() {
+        let seconds = start.elapsed().as_secs_f64();
+        let simulated = mcu.get_cycle_count() as f64 / clock as f64;
+        eprintln!(
+            "Ran {} cycles in {:.3} s, {:.2}x real time",
+            mcu.get_cycle_count(),
+            seconds,
+            simulated / seconds
+        );
+    }
+    if let Some(vcd_writer) = mcu.take_vcd_writer() {
+        vcd_writer
+            .finish()
+            .map_err(|e| format!("Cannot write VCD file: {}", e))?;
+    }
+    if let (Some(filename), Some(tracer)) = (&options.trace, mcu.take_tracer()) {
+        write_file(filename, |out| tracer.write_text(out))?;
+    }
+    if let Some(coverage) = mcu.take_coverage() {
+        if let Some(filename) = &options.coverage {
+            write_file(filename, |out| coverage.write_raw(out))?;
+        }
+        if let Some(filename) = &options.lcov {
+            write_file(filename, |out| {
+                coverage.write_lcov(out, &options.filename, &elf.program, &elf.lines)
+            })?;
+        }
+    }
+    if let Some(profiler) = mcu.take_profiler() {
+        if let Some(filename) = &options.profile {
+            write_file(filename, |out| {
+                profiler.write_flat(out)?;
+                writeln!(out)?;
+                profiler.write_call_graph(out)
+            })?;
+        }
+        if let Some(filename) = &options.folded {
+            write_file(filename, |out| profiler.write_folded(out))?;
+        }
+    }
+    if let Some(stack_monitor) = mcu.take_stack_monitor() {
+        if let Some(filename) = &options.stack {
+            write_file(filename, |out| stack_monitor.write_report(out))?;
+        }
+        if options.stack_check == Some(StackPolicy::Warn) {
+            for collision in stack_monitor.collisions() {
+                eprintln!("Warning: {}", collision);
+            }
+        }
+        if let Some(collision) = stack_monitor.get_fault() {
+            return Err(format!(
+                "{}\n\nBacktrace:\n{}",
+                collision,
+                mcu.get_call_stack()
+                    .map(|call_stack| call_stack.backtrace(collision.pc))
+                    .unwrap_or_default()
+            ));
+        }
+    }
+    if let Some(shadow) = mcu.take_shadow_memory() {
+        for used in shadow.uses() {
+            eprintln!("Warning: {}", used);
+        }
+    }
+    for violation in mcu.take_access_violations() {
+        eprintln!("Warning: {}", violation);
+    }
+    match mcu.take_semihost().map(|semihost| semihost.get_stop()) {
+        Some(Some(Stop::Exit(code))) => Ok(code.into()),
+        Some(Some(Stop::Trap(pc))) => Err(format!(
+            "BREAK trap at 0x{:04x}, cycle {}\n\nBacktrace:\n{}",
+            pc,
+            mcu.get_cycle_count(),
+            mcu.get_call_stack()
+                .map(|call_stack| call_stack.backtrace(pc))
+                .unwrap_or_default()
+        )),
+        Some(None) => Err(format!(
+            "No exit code after {} cycles",
+            mcu.get_cycle_count()
+        )),
+        None => Ok(0),
+    }
+}
+
+/// Reads a program image, along with its symbols and line table if it's an
+/// ELF file
+fn read_program(filename: &str) -> std::io::Result<ElfFile> {
+    if elf::is_elf_file(filename) {
+        ElfFile::load(filename)
+    } else {
+        Ok(ElfFile {
+            program: loader::read_program_file(filename)?,
+            ..ElfFile::default()
+        })
+    }
+}
+
+fn write_file(
+    filename: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> Result<(), String> {
+    let file = File::create(filename).map_err(|e| format!("Cannot create {}: {}", filename, e))?;
+    write(&mut BufWriter::new(file)).map_err(|e| format!("Cannot write {}: {}", filename, e))
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        filename: String::new(),
+        mcu: "attiny85".to_owned(),
+        cycles: 1_000_000,
+        clock: None,
+        speed: None,
+        vcd: None,
+        probes: Vec::new(),
+        trace: None,
+        trace_last: None,
+        coverage: None,
+        lcov: None,
+        profile: None,
+        folded: None,
+        semihost: None,
+        stack_check: None,
+        stack: None,
+        uninit: None,
+        strict: false,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mcu" => options.mcu = next_value(&mut args, arg)?,
+            "--cycles" => options.cycles = parse_number(&next_value(&mut args, arg)?)?,
+            "--clock" => options.clock = Some(parse_number(&next_value(&mut args, arg)?)? as u64),
+            "--speed" => options.speed = parse_speed(&next_value(&mut args, arg)?)?,
+            "--vcd" => options.vcd = Some(next_value(&mut args, arg)?),
+            "--probe" => options.probes.push(next_value(&mut args, arg)?),
+            "--trace" => options.trace = Some(next_value(&mut args, arg)?),
+            "--trace-last" => {
+                options.trace_last = Some(parse_number(&next_value(&mut args, arg)?)?)
+            }
+            "--coverage" => options.coverage = Some(next_value(&mut args, arg)?),
+            "--lcov" => options.lcov = Some(next_value(&mut args, arg)?),
+            "--profile" => options.profile = Some(next_value(&mut args, arg)?),
+            "--folded" => options.folded = Some(next_value(&mut args, arg)?),
+            "--semihost" => options.semihost = Some(parse_address(&next_value(&mut args, arg)?)?),
+            "--stack-check" => {
+                options.stack_check = Some(parse_policy(&next_value(&mut args, arg)?)?)
+            }
+            "--stack" => options.stack = Some(next_value(&mut args, arg)?),
+            "--uninit" => options.uninit = Some(parse_fill(&next_value(&mut args, arg)?)?),
+            "--strict" => options.strict = true,
+            "--help" => return Err(USAGE.to_owned()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}\n{}", arg, USAGE)),
+            _ => options.filename = arg.clone(),
+        }
+    }
+    if options.filename.is_empty() {
+        return Err(USAGE.to_owned());
+    }
+    Ok(options)
+}
+
+fn parse_speed(value: &str) -> Result<Option<f64>, String> {
+    match value {
+        "max" => Ok(None),
+        _ => match value.parse::<f64>() {
+            Ok(multiplier) if multiplier.is_finite() && multiplier > 0.0 => Ok(Some(multiplier)),
+            _ => Err(format!("Invalid speed: {}", value)),
+        },
+    }
+}
+
+fn parse_policy(value: &str) -> Result<StackPolicy, String> {
+    match value {
+        "warn" => Ok(StackPolicy::Warn),
+        "fault" => Ok(StackPolicy::Fault),
+        _ => Err(format!("Invalid stack check policy: {}", value)),
+    }
+}
+
+fn parse_fill(value: &str) -> Result<FillPolicy, String> {
+    match value {
+        "zeros" => Ok(FillPolicy::Zeros),
+        "ones" => Ok(FillPolicy::Ones),
+        "random" => Ok(FillPolicy::Random(1)),
+        _ => match value.strip_prefix("random:").map(str::parse) {
+            Some(Ok(seed)) => Ok(FillPolicy::Random(seed)),
+            _ => Err(format!("Invalid fill: {}", value)),
+        },
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number: {}", value))
+}
+
+/// Parses a data address, in hex if prefixed by `0x`
+fn parse_address(value: &str) -> Result<u16, String> {
+    let address = match value.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+    address
+        .filter(|address| address.checked_add(semihost::SIZE).is_some())
+        .ok_or_else(|| format!("Invalid address: {}", value))
+}
+
+fn next_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<String, String> {
+    args.next()
+        .cloned()
+        .ok_or_else(|| format!("Missing value for {}", option))
+}
@@ -0,0 +1,118 @@
+/// First register after the argument registers, `r25` is the highest one
+const FIRST_ARG_REGISTER: usize = 26;
+/// Lowest register used for arguments, the rest go on the stack
+const LAST_ARG_REGISTER: usize = 8;
+
+/// Function argument, passed in registers per the avr-gcc calling
+/// convention. Signed values and pointers convert into the unsigned variant
+/// of their size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+}
+
+impl Arg {
+    fn to_le_bytes(self) -> Vec<u8> {
+        match self {
+            Arg::U8(value) => vec![value],
+            Arg::U16(value) => value.to_le_bytes().to_vec(),
+            Arg::U32(value) => value.to_le_bytes().to_vec(),
+            Arg::U64(value) => value.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+macro_rules! impl_from_arg {
+    ($($from:ty => $variant:ident as $to:ty),*) => {
+        $(impl From<$from> for Arg {
+            fn from(value: $from) -> Arg {
+                Arg::$variant(value as $to)
+            }
+        })*
+    };
+}
+
+impl_from_arg!(
+    u8 => U8 as u8,
+    i8 => U8 as u8,
+    bool => U8 as u8,
+    u16 => U16 as u16,
+    i16 => U16 as u16,
+    u32 => U32 as u32,
+    i32 => U32 as u32,
+    u64 => U64 as u64,
+    i64 => U64 as u64
+);
+
+/// Places `args` into the register file. Each argument takes an even number
+/// of registers, from `r25` downwards, with its least significant byte in the
+/// lowest register. Returns `None` if some argument would go on the stack.
+pub(crate) fn place_args(registers: &mut [u8; 32], args: &[Arg]) -> Option<()> {
+    let mut next = FIRST_ARG_REGISTER;
+    for arg in args {
+        let bytes = arg.to_le_bytes();
+        let size = (bytes.len() + 1) & !1;
+        next = next.checked_sub(size)?;
+        if next < LAST_ARG_REGISTER {
+            return None;
+        }
+        registers[next..next + bytes.len()].copy_from_slice(&bytes);
+    }
+    Some(())
+}
+
+/// Registers after a function call, with accessors for its return value:
+/// 8 bit values in `r24`, 16 bit in `r25:r24`, 32 bit in `r25..r22` and
+/// 64 bit in `r25..r18`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Return {
+    pub registers: [u8; 32],
+}
+
+impl Return {
+    fn bytes<const N: usize>(&self) -> [u8; N] {
+        let mut bytes = [0; N];
+        let start = FIRST_ARG_REGISTER - N.max(2);
+        bytes.copy_from_slice(&self.registers[start..start + N]);
+        bytes
+    }
+
+    pub fn u8(&self) -> u8 {
+        self.registers[24]
+    }
+
+    pub fn i8(&self) -> i8 {
+        self.u8() as i8
+    }
+
+    pub fn bool(&self) -> bool {
+        self.u8() != 0
+    }
+
+    pub fn u16(&self) -> u16 {
+        u16::from_le_bytes(self.bytes())
+    }
+
+    pub fn i16(&self) -> i16 {
+        i16::from_le_bytes(self.bytes())
+    }
+
+    pub fn u32(&self) -> u32 {
+        u32::from_le_bytes(self.bytes())
+    }
+
+    pub fn i32(&self) -> i32 {
+        i32::from_le_bytes(self.bytes())
+    }
+
+    pub fn u64(&self) -> u64 {
+        u64::from_le_bytes(self.bytes())
+    }
+
+    pub fn i64(&self) -> i64 {
+        i64::from_le_bytes(self.bytes())
+    }
+}
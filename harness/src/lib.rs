@@ -0,0 +1,530 @@
+//! # AVR-Avogadro test harness
+//!
+//! Runs firmware on the simulator from Rust tests: loads an ELF file, runs
+//! it until a symbol is reached or a function returns, calls functions with
+//! the avr-gcc calling convention, and reads globals by name. Failures show
+//! the last executed instructions and a backtrace.
+//!
+//! ~~~no_run
+//! use avogadro_harness::Firmware;
+//!
+//! let mut firmware = Firmware::load("blink.elf").unwrap();
+//! firmware.run_until("main").unwrap();
+//! let sum = firmware.call("add", &[2_u8.into(), 3_u8.into()]).unwrap();
+//! assert_eq!(sum.u8(), 5);
+//! firmware.assert_global("counter", &[0]);
+//! ~~~
+extern crate avr_avogadro;
+
+/// Function arguments and return values of the avr-gcc calling convention
+mod abi;
+/// Software UART decoder and driver for pin waveforms
+mod uart;
+
+pub use abi::{Arg, Return};
+pub use uart::{UartDriver, UartMonitor};
+
+use avr_avogadro::core::call_stack::{CallStack, StackFrame};
+use avr_avogadro::core::decoder::is_return;
+use avr_avogadro::core::elf::ElfFile;
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::semihost::{Semihost, Stop};
+use avr_avogadro::core::stack_monitor::{StackMonitor, StackPolicy, StackUsage};
+use avr_avogadro::core::symbols::DATA_SPACE_OFFSET;
+use avr_avogadro::core::trace::Tracer;
+use avr_avogadro::core::vcd::Probe;
+use std::convert::TryInto;
+use std::fmt;
+use std::path::Path;
+
+/// Cycles each run can take before failing, unless changed with
+/// `with_cycle_limit`
+pub const DEFAULT_CYCLE_LIMIT: usize = 10_000_000;
+/// Instructions shown on failures, unless changed with `with_trace_tail`
+pub const DEFAULT_TRACE_TAIL: usize = 16;
+/// Clock frequency used to time UART bits, like the CLI default
+pub const DEFAULT_CLOCK_HZ: u64 = 1_000_000;
+/// MCU model used by `Firmware::load`
+pub const DEFAULT_MCU: &str = "attiny85";
+
+pub type Result<T> = std::result::Result<T, HarnessError>;
+
+/// A failed harness operation, with the instructions that led to it. `Debug`
+/// shows the same text as `Display`, so `unwrap` failures are readable.
+pub struct HarnessError {
+    pub message: String,
+    /// Last executed instructions, one per line
+    pub trace_tail: String,
+    /// Functions being executed when it failed, one per line
+    pub backtrace: String,
+}
+
+impl fmt::Display for HarnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if !self.trace_tail.is_empty() {
+            write!(f, "\n\nLast instructions:\n{}", self.trace_tail)?;
+        }
+        if !self.backtrace.is_empty() {
+            write!(f, "\n\nBacktrace:\n{}", self.backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for HarnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for HarnessError {}
+
+/// Firmware running on a simulated MCU
+pub struct Firmware {
+    mcu: Mcu,
+    elf: ElfFile,
+    cycle_limit: usize,
+    clock_hz: u64,
+    uarts: Vec<(String, UartMonitor)>,
+    uart_drivers: Vec<(String, UartDriver)>,
+}
+
+impl Firmware {
+    /// Loads ELF file `path` into a `DEFAULT_MCU`
+    pub fn load(path: impl AsRef<Path>) -> Result<Firmware> {
+        let path = path.as_ref();
+        let elf = ElfFile::load(&path.to_string_lossy()).map_err(|e| HarnessError {
+            message: format!("Cannot load {}: {}", path.display(), e),
+            trace_tail: String::new(),
+            backtrace: String::new(),
+        })?;
+        Firmware::from_elf(elf, DEFAULT_MCU)
+    }
+
+    /// Loads an already read program into MCU model `mcu_name`
+    pub fn from_elf(elf: ElfFile, mcu_name: &str) -> Result<Firmware> {
+        let mut mcu = McuFactory::try_create(mcu_name).ok_or_else(|| HarnessError {
+            message: format!("Unsupported MCU: {}", mcu_name),
+            trace_tail: String::new(),
+            backtrace: String::new(),
+        })?;
+        mcu.load_program_memory(&elf.program);
+        mcu.set_tracer(Some(Tracer::ring_buffer(DEFAULT_TRACE_TAIL)));
+        mcu.set_call_stack(Some(CallStack::new(elf.symbols.clone())));
+        Ok(Firmware {
+            mcu,
+            elf,
+            cycle_limit: DEFAULT_CYCLE_LIMIT,
+            clock_hz: DEFAULT_CLOCK_HZ,
+            uarts: Vec::new(),
+            uart_drivers: Vec::new(),
+        })
+    }
+
+    /// Cycles each run can take before failing
+    pub fn with_cycle_limit(mut self, cycles: usize) -> Firmware {
+        self.cycle_limit = cycles;
+        self
+    }
+
+    /// Number of instructions shown on failures
+    pub fn with_trace_tail(mut self, instructions: usize) -> Firmware {
+        self.mcu.set_tracer(Some(Tracer::ring_buffer(instructions)));
+        self
+    }
+
+    /// Clock frequency, used to time UART bits
+    pub fn with_clock_hz(mut self, clock_hz: u64) -> Firmware {
+        self.clock_hz = clock_hz;
+        self
+    }
+
+    /// Maps the semihosting device at data address `address`. Runs fail if
+    /// the firmware exits or traps before reaching their goal.
+    pub fn with_semihost(mut self, address: u16) -> Firmware {
+        self.mcu.set_semihost(Some(Semihost::new(address)));
+        self
+    }
+
+    /// Checks the stack doesn't grow into static data or the heap, found
+    /// with avr-libc symbols. With `StackPolicy::Fault`, runs fail on the
+    /// first collision.
+    pub fn with_stack_check(mut self, policy: StackPolicy) -> Firmware {
+        let ramend = self.mcu.get_ramend();
+        let monitor = StackMonitor::new(self.elf.symbols.clone(), ramend, policy);
+        self.mcu.set_stack_monitor(Some(monitor));
+        self
+    }
+
+    pub fn mcu(&self) -> &Mcu {
+        &self.mcu
+    }
+
+    pub fn mcu_mut(&mut self) -> &mut Mcu {
+        &mut self.mcu
+    }
+
+    pub fn elf(&self) -> &ElfFile {
+        &self.elf
+    }
+
+    /// Executes one instruction
+    pub fn step(&mut self) {
+        for (_, driver) in &mut self.uart_drivers {
+            driver.drive(&mut self.mcu);
+        }
+        self.mcu.step();
+        for (_, uart) in &mut self.uarts {
+            uart.sample(&self.mcu);
+        }
+    }
+
+    /// Runs until the program counter reaches function or label `symbol`,
+    /// executing at least one instruction
+    pub fn run_until(&mut self, symbol: &str) -> Result<()> {
+        let target = self.function_address(symbol)?;
+        let goal = format!("reaching {}", symbol);
+        let start = self.mcu.get_cycle_count();
+        loop {
+            self.checked_step(start, &goal)?;
+            if self.mcu.get_program_counter() == target {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs until the function being executed returns, even if it pushed
+    /// values since it was called
+    pub fn run_until_return(&mut self) -> Result<()> {
+        let frame = self.mcu.get_stack_pointer();
+        let start = self.mcu.get_cycle_count();
+        loop {
+            let is_return = self.is_return_from(frame);
+            self.checked_step(start, "returning")?;
+            if is_return {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs for `cycles` clock cycles, or until the firmware stops
+    pub fn run_for(&mut self, cycles: usize) {
+        let end = self.mcu.get_cycle_count() + cycles;
+        while self.mcu.get_cycle_count() < end && self.stop().is_none() {
+            self.step();
+        }
+    }
+
+    /// Calls `function` with `args` in registers, as avr-gcc does, and runs
+    /// until it returns. The program counter is restored afterwards, so the
+    /// firmware can keep running where it was.
+    pub fn call(&mut self, function: &str, args: &[Arg]) -> Result<Return> {
+        let address = self.function_address(function)?;
+        let mut registers = self.mcu.get_register_array();
+        if abi::place_args(&mut registers, args).is_none() {
+            return Err(self.error(format!(
+                "Arguments of {} don't fit in registers, stack arguments aren't supported",
+                function
+            )));
+        }
+        self.mcu.set_register_array(registers);
+        let pc = self.mcu.get_program_counter();
+        let frame = self.push_return_address(pc);
+        if let Some(call_stack) = self.mcu.get_call_stack_mut() {
+            call_stack.push(StackFrame {
+                call_site: pc,
+                target: address,
+                return_address: pc,
+                stack_pointer: frame,
+                is_interrupt: false,
+            });
+        }
+        self.mcu.set_program_counter(address);
+        let start = self.mcu.get_cycle_count();
+        let goal = format!("returning from {}", function);
+        loop {
+            let is_return = self.is_return_from(frame);
+            self.checked_step(start, &goal)?;
+            if is_return {
+                break;
+            }
+        }
+        self.mcu.set_program_counter(pc);
+        Ok(Return {
+            registers: self.mcu.get_register_array(),
+        })
+    }
+
+    /// Reads global variable `name`, as many bytes as its symbol size
+    pub fn read_global(&self, name: &str) -> Result<Vec<u8>> {
+        let (address, size) = self.global(name)?;
+        Ok((0..size)
+            .map(|offset| self.mcu.get_data_byte(address.wrapping_add(offset)))
+            .collect())
+    }
+
+    pub fn read_u8(&self, name: &str) -> Result<u8> {
+        Ok(self.read_sized::<1>(name)?[0])
+    }
+
+    pub fn read_u16(&self, name: &str) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_sized(name)?))
+    }
+
+    pub fn read_u32(&self, name: &str) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_sized(name)?))
+    }
+
+    /// Writes `bytes` into global variable `name`, from its first byte
+    pub fn write_global(&mut self, name: &str, bytes: &[u8]) -> Result<()> {
+        let (address, size) = self.global(name)?;
+        if bytes.len() > usize::from(size) {
+            return Err(self.error(format!(
+                "{} has {} bytes, can't write {}",
+                name,
+                size,
+                bytes.len()
+            )));
+        }
+        for (offset, byte) in (0..).zip(bytes) {
+            self.mcu.set_data_byte(address.wrapping_add(offset), *byte);
+        }
+        Ok(())
+    }
+
+    /// Level of GPIO pin `pin`, like `PB3`
+    pub fn pin(&self, pin: &str) -> Result<bool> {
+        Ok(self.pin_probe(pin)?.sample(&self.mcu) != 0)
+    }
+
+    /// Starts decoding serial frames sent on `pin` at `baud`
+    pub fn monitor_uart(&mut self, pin: &str, baud: u32) -> Result<()> {
+        let probe = self.pin_probe(pin)?;
+        let monitor = UartMonitor::new(&self.mcu, probe, baud, self.clock_hz);
+        self.uarts
+            .retain(|(name, _)| !name.eq_ignore_ascii_case(pin));
+        self.uarts.push((pin.to_owned(), monitor));
+        Ok(())
+    }
+
+    /// Sends `bytes` to the firmware as serial frames on input pin `pin` at
+    /// `baud`, while it runs. Frames are queued after the ones still being
+    /// sent, and a pin keeps the baud of its first `send_uart`.
+    pub fn send_uart(&mut self, pin: &str, baud: u32, bytes: &[u8]) -> Result<()> {
+        let index = match self
+            .uart_drivers
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(pin))
+        {
+            Some(index) => index,
+            None => {
+                let probe = self.pin_probe(pin)?;
+                let driver = UartDriver::new(&mut self.mcu, probe, baud, self.clock_hz)
+                    .ok_or_else(|| self.error(format!("Invalid pin {}", pin)))?;
+                self.uart_drivers.push((pin.to_owned(), driver));
+                self.uart_drivers.len() - 1
+            }
+        };
+        self.uart_drivers[index].1.send(&self.mcu, bytes);
+        Ok(())
+    }
+
+    /// Bytes received on a pin monitored with `monitor_uart`
+    pub fn uart_output(&self, pin: &str) -> Result<Vec<u8>> {
+        self.uarts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(pin))
+            .map(|(_, uart)| uart.decode(self.mcu.get_cycle_count()))
+            .ok_or_else(|| self.error(format!("{} isn't monitored as UART", pin)))
+    }
+
+    /// Characters written to the semihosting device since the last call
+    pub fn take_semihost_output(&mut self) -> Vec<u8> {
+        self.mcu
+            .get_semihost_mut()
+            .map(Semihost::take_output)
+            .unwrap_or_default()
+    }
+
+    /// Exit code or trap that stopped the firmware, if any
+    pub fn stop(&self) -> Option<Stop> {
+        self.mcu.get_semihost().and_then(Semihost::get_stop)
+    }
+
+    /// Panics unless `pin` is at `level`
+    pub fn assert_pin(&self, pin: &str, level: bool) {
+        match self.pin(pin) {
+            Ok(actual) if actual == level => (),
+            Ok(actual) => self.fail(format!(
+                "{} is {}, expected {}",
+                pin,
+                u8::from(actual),
+                u8::from(level)
+            )),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Panics unless the bytes received on UART `pin` are `expected`
+    pub fn assert_uart_output(&self, pin: &str, expected: &str) {
+        match self.uart_output(pin) {
+            Ok(bytes) if bytes == expected.as_bytes() => (),
+            Ok(bytes) => self.fail(format!(
+                "{} UART output is {:?}, expected {:?}",
+                pin,
+                String::from_utf8_lossy(&bytes),
+                expected
+            )),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Panics unless global `name` holds `expected`
+    pub fn assert_global(&self, name: &str, expected: &[u8]) {
+        match self.read_global(name) {
+            Ok(bytes) if bytes == expected => (),
+            Ok(bytes) => self.fail(format!(
+                "{} is {:02x?}, expected {:02x?}",
+                name, bytes, expected
+            )),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Last executed instructions, named after the function containing them
+    pub fn trace_tail(&self) -> String {
+        let tracer = match self.mcu.get_tracer() {
+            Some(tracer) => tracer,
+            None => return String::new(),
+        };
+        tracer
+            .entries()
+            .map(|entry| match self.elf.symbols.lookup(u32::from(entry.pc)) {
+                Some((symbol, 0)) => format!("{}  <{}>\n", entry, symbol.name),
+                Some((symbol, offset)) => {
+                    format!("{}  <{}+0x{:x}>\n", entry, symbol.name, offset)
+                }
+                None => format!("{}\n", entry),
+            })
+            .collect()
+    }
+
+    /// Stack high-water marks of each context, if checked with
+    /// `with_stack_check`
+    pub fn stack_usage(&self) -> Vec<StackUsage> {
+        self.mcu
+            .get_stack_monitor()
+            .map(StackMonitor::usage)
+            .unwrap_or_default()
+    }
+
+    /// Functions being executed, from the current one to `main`
+    pub fn backtrace(&self) -> String {
+        self.mcu
+            .backtrace()
+            .map(|backtrace| backtrace.to_string())
+            .unwrap_or_default()
+    }
+
+    fn error(&self, message: String) -> HarnessError {
+        HarnessError {
+            message,
+            trace_tail: self.trace_tail(),
+            backtrace: self.backtrace(),
+        }
+    }
+
+    fn fail(&self, message: String) -> ! {
+        panic!("{}", self.error(message))
+    }
+
+    /// Steps, failing if the run took too long or the firmware stopped
+    fn checked_step(&mut self, start: usize, goal: &str) -> Result<()> {
+        if let Some(stop) = self.stop() {
+            let reason = match stop {
+                Stop::Exit(code) => format!("exited with code {}", code),
+                Stop::Trap(pc) => format!("trapped at 0x{:04x}", pc),
+            };
+            return Err(self.error(format!("Firmware {} before {}", reason, goal)));
+        }
+        if let Some(collision) = self
+            .mcu
+            .get_stack_monitor()
+            .and_then(StackMonitor::get_fault)
+        {
+            return Err(self.error(format!("{} before {}", collision, goal)));
+        }
+        if self.mcu.get_cycle_count() - start >= self.cycle_limit {
+            return Err(self.error(format!(
+                "Cycle limit of {} reached before {}",
+                self.cycle_limit, goal
+            )));
+        }
+        self.step();
+        Ok(())
+    }
+
+    /// Returns true if the next instruction returns from the function whose
+    /// return address is above stack pointer `frame`. Functions it called have
+    /// lower stack pointers.
+    fn is_return_from(&self, frame: u16) -> bool {
+        is_return(self.mcu.get_current_instruction()) && self.mcu.get_stack_pointer() >= frame
+    }
+
+    /// Pushes a return address as `call` does, returning the new stack
+    /// pointer
+    fn push_return_address(&mut self, pc: u16) -> u16 {
+        let stack_pointer = self.mcu.get_stack_pointer();
+        let [low, high] = pc.to_le_bytes();
+        self.mcu.set_data_byte(stack_pointer, low);
+        self.mcu.set_data_byte(stack_pointer.wrapping_add(1), high);
+        let frame = if stack_pointer < 2 {
+            self.mcu.get_data_size() as u16 - 2
+        } else {
+            stack_pointer - 2
+        };
+        self.mcu.set_stack_pointer(frame);
+        frame
+    }
+
+    fn function_address(&self, name: &str) -> Result<u16> {
+        match self.elf.symbols.by_name(name) {
+            Some(symbol) if symbol.address < DATA_SPACE_OFFSET => Ok(symbol.address as u16),
+            Some(_) => Err(self.error(format!("{} isn't in program memory", name))),
+            None => Err(self.error(format!("Unknown symbol {}", name))),
+        }
+    }
+
+    /// Data memory address and size of global `name`
+    fn global(&self, name: &str) -> Result<(u16, u16)> {
+        match self.elf.symbols.by_name(name) {
+            Some(symbol) if symbol.address >= DATA_SPACE_OFFSET => Ok((
+                (symbol.address - DATA_SPACE_OFFSET) as u16,
+                symbol.size as u16,
+            )),
+            Some(_) => Err(self.error(format!("{} isn't in data memory", name))),
+            None => Err(self.error(format!("Unknown symbol {}", name))),
+        }
+    }
+
+    fn read_sized<const N: usize>(&self, name: &str) -> Result<[u8; N]> {
+        let bytes = self.read_global(name)?;
+        bytes.as_slice().try_into().map_err(|_| {
+            self.error(format!(
+                "{} has {} bytes, expected {}",
+                name,
+                bytes.len(),
+                N
+            ))
+        })
+    }
+
+    fn pin_probe(&self, pin: &str) -> Result<Probe> {
+        match Probe::parse(pin, self.mcu.get_ports()) {
+            Some(probe @ Probe::Pin { .. }) => Ok(probe),
+            _ => Err(self.error(format!("Invalid pin {}", pin))),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use avr_avogadro::core::mcu::Mcu;
+use avr_avogadro::core::vcd::Probe;
+use std::collections::VecDeque;
+
+/// Decodes 8N1 serial frames sent by firmware on a GPIO pin, like a bit-banged
+/// UART. The pin is sampled after each instruction, so bits must last a few
+/// cycles.
+pub struct UartMonitor {
+    probe: Probe,
+    cycles_per_bit: f64,
+    /// Level before the first transition
+    initial_level: bool,
+    /// Cycle and new level of each change
+    transitions: Vec<(usize, bool)>,
+}
+
+impl UartMonitor {
+    /// Monitors `probe` at `baud` bits per second, for a MCU clocked at
+    /// `clock_hz`
+    pub fn new(mcu: &Mcu, probe: Probe, baud: u32, clock_hz: u64) -> UartMonitor {
+        UartMonitor {
+            probe,
+            cycles_per_bit: clock_hz as f64 / f64::from(baud.max(1)),
+            initial_level: probe.sample(mcu) != 0,
+            transitions: Vec::new(),
+        }
+    }
+
+    /// Records the pin level, called after each step
+    pub fn sample(&mut self, mcu: &Mcu) {
+        let level = self.probe.sample(mcu) != 0;
+        if level != self.level_at(usize::MAX) {
+            self.transitions.push((mcu.get_cycle_count(), level));
+        }
+    }
+
+    /// Bytes of the frames completed before `cycle`. Frames without a stop
+    /// bit are dropped.
+    pub fn decode(&self, cycle: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut previous = self.initial_level;
+        let mut frame_end = 0.0;
+        for (start, level) in &self.transitions {
+            let is_start_bit = previous && !level && *start as f64 >= frame_end;
+            previous = *level;
+            if !is_start_bit {
+                continue;
+            }
+            let at = |bit: f64| (*start as f64 + (bit + 0.5) * self.cycles_per_bit) as usize;
+            if at(9.0) > cycle {
+                break;
+            }
+            frame_end = *start as f64 + 9.5 * self.cycles_per_bit;
+            if !self.level_at(at(9.0)) {
+                continue;
+            }
+            let byte = (0..8).fold(0, |byte, bit| {
+                byte | u8::from(self.level_at(at(f64::from(bit + 1)))) << bit
+            });
+            bytes.push(byte);
+        }
+        bytes
+    }
+
+    fn level_at(&self, cycle: usize) -> bool {
+        let index = self
+            .transitions
+            .partition_point(|(change, _)| *change <= cycle);
+        match index {
+            0 => self.initial_level,
+            _ => self.transitions[index - 1].1,
+        }
+    }
+}
+
+/// Sends 8N1 serial frames to firmware through a GPIO input pin, like the
+/// other end of a bit-banged UART. The pin is driven by setting its `PINx`
+/// bit before each instruction, and idles high.
+pub struct UartDriver {
+    /// `PINx` address
+    input_register: u16,
+    bit: u8,
+    cycles_per_bit: f64,
+    /// Cycle and new level of each pending change
+    changes: VecDeque<(usize, bool)>,
+    /// Cycle the last queued frame ends
+    frames_end: f64,
+}
+
+impl UartDriver {
+    /// Drives pin `probe` at `baud` bits per second, for a MCU clocked at
+    /// `clock_hz`. Returns `None` unless `probe` is a pin.
+    pub fn new(mcu: &mut Mcu, probe: Probe, baud: u32, clock_hz: u64) -> Option<UartDriver> {
+        let (port, bit) = match probe {
+            Probe::Pin { port, bit } => (port, bit),
+            _ => return None,
+        };
+        let cycles_per_bit = clock_hz as f64 / f64::from(baud.max(1));
+        let driver = UartDriver {
+            input_register: port - 2,
+            bit,
+            cycles_per_bit,
+            changes: VecDeque::new(),
+            // The line idles for a bit before the first start bit
+            frames_end: mcu.get_cycle_count() as f64 + cycles_per_bit,
+        };
+        driver.set_level(mcu, true);
+        Some(driver)
+    }
+
+    /// Queues `bytes`, sent from the current cycle or after the frames
+    /// already queued
+    pub fn send(&mut self, mcu: &Mcu, bytes: &[u8]) {
+        let mut start = self.frames_end.max(mcu.get_cycle_count() as f64);
+        for byte in bytes {
+            // Start bit, data bits from the least significant, stop bit
+            let frame = (u16::from(*byte) << 1) | 0x200;
+            for bit in 0..10 {
+                let cycle = (start + f64::from(bit) * self.cycles_per_bit) as usize;
+                self.changes.push_back((cycle, frame & 1 << bit != 0));
+            }
+            start += 10.0 * self.cycles_per_bit;
+        }
+        self.frames_end = start;
+    }
+
+    /// True if every queued frame was sent
+    pub fn is_idle(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Sets the pin level for the current cycle, called before each step
+    pub fn drive(&mut self, mcu: &mut Mcu) {
+        let cycle = mcu.get_cycle_count();
+        let mut level = None;
+        while let Some(&(at, change)) = self.changes.front() {
+            if at > cycle {
+                break;
+            }
+            level = Some(change);
+            self.changes.pop_front();
+        }
+        if let Some(level) = level {
+            self.set_level(mcu, level);
+        }
+    }
+
+    fn set_level(&self, mcu: &mut Mcu, level: bool) {
+        let value = mcu.get_data_byte(self.input_register);
+        let value = if level {
+            value | 1 << self.bit
+        } else {
+            value & !(1 << self.bit)
+        };
+        mcu.set_data_byte(self.input_register, value);
+    }
+}
@@ -0,0 +1,288 @@
+extern crate avogadro_harness;
+extern crate avr_avogadro;
+
+use avogadro_harness::{Arg, Firmware};
+use avr_avogadro::core::assembler;
+use avr_avogadro::core::elf::ElfFile;
+use avr_avogadro::core::semihost::{self, Stop};
+use avr_avogadro::core::stack_monitor::StackPolicy;
+use avr_avogadro::core::symbols::{Symbol, DATA_SPACE_OFFSET};
+use std::panic::{self, AssertUnwindSafe};
+
+/// `main` keeps incrementing `counter` through `increment`
+const SOURCE: &str = "
+main:
+    rcall increment
+main_loop:
+    rcall increment
+    rjmp main_loop
+increment:
+    ldi r26, 0x00
+    ldi r27, 0x01
+    ld r24, X
+    push r24
+    inc r24
+    st X, r24
+    pop r25
+    ret
+add16:
+    add r24, r22
+    adc r25, r23
+    ret
+sum4:
+    add r24, r22
+    add r24, r20
+    add r24, r18
+    ret
+negate32:
+    com r22
+    com r23
+    com r24
+    com r25
+    subi r22, 0xff
+    sbci r23, 0xff
+    sbci r24, 0xff
+    sbci r25, 0xff
+    ret
+hang:
+    rjmp hang
+";
+
+/// Sends \"Ok\" on PB1 as 8N1 frames of 9 cycles per bit
+const UART_SOURCE: &str = "
+    sbi 0x17, 1
+    sbi 0x18, 1
+    ldi r20, 0x4f
+    ldi r21, 0x6b
+    ldi r22, 2
+byte:
+    mov r24, r20
+    mov r20, r21
+    cbi 0x18, 1
+    ldi r25, 8
+    nop
+    nop
+    nop
+    nop
+bit:
+    lsr r24
+    brcs one
+    cbi 0x18, 1
+    rjmp next
+one:
+    sbi 0x18, 1
+    nop
+next:
+    dec r25
+    brne bit
+    nop
+    nop
+    nop
+    sbi 0x18, 1
+    ldi r23, 4
+stop_bit:
+    dec r23
+    brne stop_bit
+    dec r22
+    brne byte
+done:
+    rjmp done
+";
+
+fn firmware(source: &str) -> Firmware {
+    let program = assembler::assemble(source).unwrap();
+    let mut elf = ElfFile {
+        program: program.image,
+        symbols: program.symbols,
+        ..ElfFile::default()
+    };
+    elf.symbols.add(Symbol {
+        name: "counter".to_owned(),
+        address: DATA_SPACE_OFFSET + 0x100,
+        size: 1,
+        is_global: true,
+    });
+    Firmware::from_elf(elf, "attiny85").unwrap()
+}
+
+#[test]
+/// Runs stop at symbols and at the return of the current function
+fn test_run_until() {
+    let mut firmware = firmware(SOURCE);
+    firmware.run_until("increment").unwrap();
+    assert_eq!(firmware.read_u8("counter").unwrap(), 0);
+    firmware.run_until_return().unwrap();
+    assert_eq!(firmware.read_u8("counter").unwrap(), 1);
+    // `ret` resumed at the loop, which runs `increment` once more per turn
+    assert_eq!(firmware.mcu().get_program_counter(), 2);
+    firmware.run_until("main_loop").unwrap();
+    assert_eq!(firmware.read_u8("counter").unwrap(), 2);
+
+    firmware.write_global("counter", &[0x40]).unwrap();
+    firmware.run_until("main_loop").unwrap();
+    firmware.assert_global("counter", &[0x41]);
+}
+
+#[test]
+/// Arguments go in registers from r25 down, results come back in r25 down
+fn test_call() {
+    let mut firmware = firmware(SOURCE);
+    firmware.run_until("main_loop").unwrap();
+    let pc = firmware.mcu().get_program_counter();
+
+    let sum = firmware
+        .call("add16", &[1000_u16.into(), 0x1234_u16.into()])
+        .unwrap();
+    assert_eq!(sum.u16(), 5660);
+    let sum = firmware
+        .call(
+            "sum4",
+            &[1_u8.into(), 2_i8.into(), 3_u8.into(), true.into()],
+        )
+        .unwrap();
+    assert_eq!(sum.u8(), 7);
+    let negated = firmware.call("negate32", &[123_456_i32.into()]).unwrap();
+    assert_eq!(negated.i32(), -123_456);
+    // Calling a function with pushes and nested frames
+    firmware.call("increment", &[]).unwrap();
+    firmware.assert_global("counter", &[2]);
+
+    assert_eq!(firmware.mcu().get_program_counter(), pc);
+    let too_many = [Arg::U64(0), Arg::U64(0), Arg::U64(0)];
+    let error = firmware.call("sum4", &too_many).unwrap_err();
+    assert!(error.message.contains("stack arguments"));
+}
+
+#[test]
+/// Errors name the problem and show the last instructions with their
+/// function, and a backtrace
+fn test_errors() {
+    let mut firmware = firmware(SOURCE).with_cycle_limit(100).with_trace_tail(4);
+    let error = firmware.run_until("missing").unwrap_err();
+    assert_eq!(error.message, "Unknown symbol missing");
+    assert!(firmware.read_u8("main").is_err());
+
+    let error = firmware.call("hang", &[]).unwrap_err();
+    assert_eq!(
+        error.message,
+        "Cycle limit of 100 reached before returning from hang"
+    );
+    assert_eq!(error.trace_tail.lines().count(), 4);
+    assert!(error
+        .trace_tail
+        .lines()
+        .all(|line| line.ends_with("<hang>")));
+    assert_eq!(error.backtrace, "#0  0x0036 in hang\n#1  0x0000 in main\n");
+    let text = format!("{:?}", error);
+    assert!(text.contains("Last instructions:\n"));
+    assert!(text.contains("rjmp .-2"));
+    assert!(text.ends_with("Backtrace:\n#0  0x0036 in hang\n#1  0x0000 in main\n"));
+}
+
+#[test]
+/// Pins are read by name, failed assertions panic with the trace tail
+fn test_pins() {
+    let mut firmware = firmware(UART_SOURCE);
+    firmware.assert_pin("PB1", false);
+    firmware.step();
+    firmware.step();
+    firmware.assert_pin("PB1", true);
+    assert!(firmware.pin("PX1").is_err());
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| firmware.assert_pin("PB1", false)));
+    let message = *result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.starts_with("PB1 is 1, expected 0\n\nLast instructions:\n"));
+    assert!(message.contains("sbi 0x18, 1"));
+}
+
+#[test]
+/// Frames sent by a bit-banged UART are decoded
+fn test_uart() {
+    let mut firmware = firmware(UART_SOURCE);
+    firmware.monitor_uart("PB1", 111_111).unwrap();
+    firmware.run_until("done").unwrap();
+    firmware.run_for(100);
+    firmware.assert_uart_output("PB1", "Ok");
+    assert!(firmware.uart_output("PB0").is_err());
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        firmware.assert_uart_output("PB1", "Ko")
+    }));
+    let message = *result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.starts_with("PB1 UART output is \"Ok\", expected \"Ko\""));
+}
+
+#[test]
+/// Frames sent to an input pin are decoded back by a monitor on the same pin
+fn test_uart_loopback() {
+    let mut firmware = firmware("    nop\nloop:\n    rjmp loop\n");
+    firmware.monitor_uart("PB2", 9600).unwrap();
+    firmware.send_uart("PB2", 9600, b"He").unwrap();
+    firmware.send_uart("pb2", 9600, b"llo").unwrap();
+    firmware.assert_pin("PB2", true);
+    // An idle bit and 5 frames of 10 bits, 104 cycles each at 1 MHz
+    firmware.run_for(51 * 105);
+    firmware.assert_uart_output("PB2", "Hello");
+    assert!(firmware.send_uart("PX0", 9600, b"!").is_err());
+}
+
+#[test]
+/// Semihosting output is collected, and runs fail once the firmware exits
+fn test_semihost() {
+    let source = "
+        ldi r16, 0x68
+        out 0x00, r16
+        ldi r16, 0x69
+        out 0x00, r16
+        out 0x01, r1
+        nop
+    end:
+        rjmp end
+    ";
+    let mut firmware = firmware(source).with_semihost(semihost::DEFAULT_ADDRESS);
+    let error = firmware.run_until("end").unwrap_err();
+    assert_eq!(
+        error.message,
+        "Firmware exited with code 0 before reaching end"
+    );
+    assert_eq!(firmware.stop(), Some(Stop::Exit(0)));
+    assert_eq!(firmware.take_semihost_output(), b"hi");
+}
+
+#[test]
+/// Runs fail once the stack grows into static data
+fn test_stack_check() {
+    let source = "
+    main:
+        rcall recurse
+    recurse:
+        push r16
+        rcall recurse
+    ";
+    let program = assembler::assemble(source).unwrap();
+    let mut elf = ElfFile {
+        program: program.image,
+        symbols: program.symbols,
+        ..ElfFile::default()
+    };
+    elf.symbols.add(Symbol {
+        name: "__heap_start".to_owned(),
+        address: DATA_SPACE_OFFSET + 0x120,
+        size: 0,
+        is_global: true,
+    });
+    let mut firmware = Firmware::from_elf(elf, "attiny85")
+        .unwrap()
+        .with_stack_check(StackPolicy::Fault);
+    firmware.mcu_mut().set_stack_pointer(0x1f0);
+    let error = firmware.run_until("main").unwrap_err();
+    assert!(error
+        .message
+        .starts_with("Stack overflow into static data at 0x0002: SP 0x011e"));
+    assert!(error.message.ends_with("before reaching main"));
+    assert!(error.backtrace.contains("in recurse"));
+    let usage = firmware.stack_usage();
+    assert_eq!(usage.len(), 1);
+    // Without `__stack`, measured from RAMEND
+    assert_eq!(usage[0].high_water, 0x25f - 0x11e);
+}
@@ -0,0 +1,90 @@
+//! # AVR-Avogadro Python bindings
+//!
+//! Exposes the simulator as the `avogadro` Python module, meant for writing
+//! firmware tests. Built with `maturin develop` from this folder.
+/// The `Mcu` class
+mod mcu;
+
+use avr_avogadro::core::register_bank;
+use pyo3::prelude::*;
+
+/// SREG flags, with the same names as the Rust `Flags` struct
+#[pyclass(module = "avogadro")]
+#[derive(Clone, Copy, Default)]
+struct Flags {
+    #[pyo3(get, set)]
+    carry: bool,
+    #[pyo3(get, set)]
+    zero: bool,
+    #[pyo3(get, set)]
+    neg: bool,
+    #[pyo3(get, set)]
+    over: bool,
+    #[pyo3(get, set)]
+    sign: bool,
+    #[pyo3(get, set)]
+    half: bool,
+    #[pyo3(get, set)]
+    trans: bool,
+    #[pyo3(get, set)]
+    int: bool,
+}
+
+#[pymethods]
+impl Flags {
+    #[new]
+    #[pyo3(signature = (sreg = 0))]
+    fn new(sreg: u8) -> Flags {
+        Flags::from(register_bank::Flags::from(sreg))
+    }
+
+    /// SREG value, carry being bit 0
+    fn __int__(&self) -> u8 {
+        register_bank::Flags::from(*self).into()
+    }
+
+    fn __eq__(&self, other: &Flags) -> bool {
+        self.__int__() == other.__int__()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Flags(0x{:02x})", self.__int__())
+    }
+}
+
+impl From<register_bank::Flags> for Flags {
+    fn from(flags: register_bank::Flags) -> Flags {
+        Flags {
+            carry: flags.carry,
+            zero: flags.zero,
+            neg: flags.neg,
+            over: flags.over,
+            sign: flags.sign,
+            half: flags.half,
+            trans: flags.trans,
+            int: flags.int,
+        }
+    }
+}
+
+impl From<Flags> for register_bank::Flags {
+    fn from(flags: Flags) -> register_bank::Flags {
+        register_bank::Flags {
+            carry: flags.carry,
+            zero: flags.zero,
+            neg: flags.neg,
+            over: flags.over,
+            sign: flags.sign,
+            half: flags.half,
+            trans: flags.trans,
+            int: flags.int,
+        }
+    }
+}
+
+#[pymodule]
+fn avogadro(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Flags>()?;
+    module.add_class::<mcu::Mcu>()?;
+    Ok(())
+}
@@ -0,0 +1,302 @@
+//! The `Mcu` class. PyO3 converts the errors of methods returning `PyResult`
+//! with `into` in wrappers generated outside the `#[pymethods]` block, so
+//! the lint is allowed for the whole module.
+#![allow(clippy::useless_conversion)]
+
+use super::Flags;
+use avogadro_harness::{UartDriver, UartMonitor};
+use avr_avogadro::core::loader;
+use avr_avogadro::core::mcu;
+use avr_avogadro::core::mcu_factory::McuFactory;
+use avr_avogadro::core::vcd::Probe;
+use pyo3::exceptions::{PyIOError, PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+/// Cycles run by `run_until` when no limit is given
+const DEFAULT_MAX_CYCLES: usize = 1_000_000;
+
+/// A simulated MCU, created by model name like `Mcu("attiny85")`
+#[pyclass(module = "avogadro")]
+pub struct Mcu {
+    mcu: mcu::Mcu,
+    /// Pins decoded as UART output, by name
+    uarts: Vec<(String, UartMonitor)>,
+    /// Pins driven as UART input, by name
+    uart_drivers: Vec<(String, UartDriver)>,
+}
+
+#[pymethods]
+impl Mcu {
+    #[new]
+    #[pyo3(signature = (device = "attiny85"))]
+    fn new(device: &str) -> PyResult<Mcu> {
+        McuFactory::try_create(device)
+            .map(|mcu| Mcu {
+                mcu,
+                uarts: Vec::new(),
+                uart_drivers: Vec::new(),
+            })
+            .ok_or_else(|| PyValueError::new_err(format!("Unsupported MCU: {}", device)))
+    }
+
+    /// Loads a binary, Intel HEX or ELF program file
+    fn load_program(&mut self, filename: &str) -> PyResult<()> {
+        let program = loader::read_program_file(filename)
+            .map_err(|e| PyIOError::new_err(format!("Cannot read {}: {}", filename, e)))?;
+        self.mcu.load_program_memory(&program);
+        Ok(())
+    }
+
+    /// Loads a program image from `bytes`
+    fn load_program_bytes(&mut self, program: &[u8]) {
+        self.mcu.load_program_memory(program);
+    }
+
+    /// Executes `count` instructions
+    #[pyo3(signature = (count = 1))]
+    fn step(&mut self, count: usize) {
+        for _ in 0..count {
+            self.step_once();
+        }
+    }
+
+    /// Runs for at least `cycles` clock cycles, stopping early at a
+    /// breakpoint. Returns true if a breakpoint was hit.
+    fn run(&mut self, cycles: usize) -> bool {
+        let end = self.mcu.get_cycle_count() + cycles;
+        self.run_to_cycle(end, None)
+    }
+
+    /// Runs until the program counter reaches `pc` or a breakpoint, for at
+    /// most `max_cycles`. Returns true if `pc` was reached.
+    #[pyo3(signature = (pc, max_cycles = DEFAULT_MAX_CYCLES))]
+    fn run_until(&mut self, pc: u16, max_cycles: usize) -> bool {
+        let end = self.mcu.get_cycle_count() + max_cycles;
+        self.run_to_cycle(end, Some(pc));
+        self.mcu.get_program_counter() == pc
+    }
+
+    #[getter]
+    fn pc(&self) -> u16 {
+        self.mcu.get_program_counter()
+    }
+
+    #[setter]
+    fn set_pc(&mut self, pc: u16) {
+        self.mcu.set_program_counter(pc);
+    }
+
+    #[getter]
+    fn sp(&self) -> u16 {
+        self.mcu.get_stack_pointer()
+    }
+
+    /// Clock cycles run since the MCU was created
+    #[getter]
+    fn cycles(&self) -> usize {
+        self.mcu.get_cycle_count()
+    }
+
+    #[getter]
+    fn registers(&self) -> Vec<u8> {
+        self.mcu.get_register_array().to_vec()
+    }
+
+    fn get_register(&self, register: u8) -> PyResult<u8> {
+        check_register(register)?;
+        Ok(self.mcu.get_register(register))
+    }
+
+    fn set_register(&mut self, register: u8, value: u8) -> PyResult<()> {
+        check_register(register)?;
+        self.mcu.set_register(register, value);
+        Ok(())
+    }
+
+    #[getter]
+    fn flags(&self) -> Flags {
+        Flags::from(self.mcu.get_flags())
+    }
+
+    #[setter]
+    fn set_flags(&mut self, flags: Flags) {
+        self.mcu.set_flags(flags.into());
+    }
+
+    /// Reads `length` bytes of data memory, I/O registers included
+    #[pyo3(signature = (address, length = 1))]
+    fn read_data<'py>(&self, py: Python<'py>, address: u16, length: usize) -> Bound<'py, PyBytes> {
+        let bytes: Vec<u8> = (0..length)
+            .map(|offset| self.mcu.get_data_byte(address.wrapping_add(offset as u16)))
+            .collect();
+        PyBytes::new_bound(py, &bytes)
+    }
+
+    /// Writes `data` into data memory, starting at `address`
+    fn write_data(&mut self, address: u16, data: &[u8]) {
+        for (offset, byte) in data.iter().enumerate() {
+            self.mcu
+                .set_data_byte(address.wrapping_add(offset as u16), *byte);
+        }
+    }
+
+    /// Reads `length` bytes of program memory
+    fn read_program<'py>(
+        &self,
+        py: Python<'py>,
+        address: usize,
+        length: usize,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let mut program = vec![0; self.mcu.get_program_size()];
+        // `program` has the size of the program memory
+        unsafe {
+            self.mcu
+                .get_program_memory(program.as_mut_ptr(), program.len())
+        };
+        let bytes = program
+            .get(address..address + length)
+            .ok_or_else(|| PyIndexError::new_err("Address out of program memory"))?;
+        Ok(PyBytes::new_bound(py, bytes))
+    }
+
+    fn add_breakpoint(&mut self, pc: u16) {
+        self.mcu.add_breakpoint(pc);
+    }
+
+    fn remove_breakpoint(&mut self, pc: u16) {
+        self.mcu.remove_breakpoint(pc);
+    }
+
+    #[getter]
+    fn breakpoints(&self) -> Vec<u16> {
+        self.mcu.get_breakpoints().to_vec()
+    }
+
+    /// Level of GPIO pin `pin`, like `"PB0"`. Output pins read `PORTx`,
+    /// input pins read `PINx`.
+    fn get_pin(&self, pin: &str) -> PyResult<bool> {
+        let probe = parse_pin(pin, &self.mcu)?;
+        Ok(probe.sample(&self.mcu) != 0)
+    }
+
+    /// Drives input pin `pin`, like `"PB0"`, by setting its `PINx` bit
+    fn set_pin(&mut self, pin: &str, level: bool) -> PyResult<()> {
+        if let Probe::Pin { port, bit } = parse_pin(pin, &self.mcu)? {
+            let input_register = port - 2;
+            let value = self.mcu.get_data_byte(input_register);
+            let value = if level {
+                value | 1 << bit
+            } else {
+                value & !(1 << bit)
+            };
+            self.mcu.set_data_byte(input_register, value);
+        }
+        Ok(())
+    }
+
+    /// Starts decoding serial 8N1 frames sent by the firmware on `pin` at
+    /// `baud`, read with `uart_output`. Bits are timed with `clock_hz`, the
+    /// device clock by default.
+    #[pyo3(signature = (pin, baud, clock_hz = None))]
+    fn monitor_uart(&mut self, pin: &str, baud: u32, clock_hz: Option<u64>) -> PyResult<()> {
+        let probe = parse_pin(pin, &self.mcu)?;
+        let clock_hz = clock_hz.unwrap_or_else(|| self.clock_hz());
+        let monitor = UartMonitor::new(&self.mcu, probe, baud, clock_hz);
+        self.uarts
+            .retain(|(name, _)| !name.eq_ignore_ascii_case(pin));
+        self.uarts.push((pin.to_owned(), monitor));
+        Ok(())
+    }
+
+    /// Bytes received on a pin monitored with `monitor_uart`
+    fn uart_output<'py>(&self, py: Python<'py>, pin: &str) -> PyResult<Bound<'py, PyBytes>> {
+        self.uarts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(pin))
+            .map(|(_, uart)| PyBytes::new_bound(py, &uart.decode(self.mcu.get_cycle_count())))
+            .ok_or_else(|| PyValueError::new_err(format!("{} isn't monitored as UART", pin)))
+    }
+
+    /// Sends `data` to the firmware as serial 8N1 frames on input pin `pin`
+    /// at `baud`, while it runs. Frames are queued after the ones still being
+    /// sent, and a pin keeps the baud and clock of its first `send_uart`.
+    #[pyo3(signature = (pin, baud, data, clock_hz = None))]
+    fn send_uart(
+        &mut self,
+        pin: &str,
+        baud: u32,
+        data: &[u8],
+        clock_hz: Option<u64>,
+    ) -> PyResult<()> {
+        let index = match self
+            .uart_drivers
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(pin))
+        {
+            Some(index) => index,
+            None => {
+                let probe = parse_pin(pin, &self.mcu)?;
+                let clock_hz = clock_hz.unwrap_or_else(|| self.clock_hz());
+                let driver = UartDriver::new(&mut self.mcu, probe, baud, clock_hz)
+                    .ok_or_else(|| PyValueError::new_err(format!("Invalid pin: {}", pin)))?;
+                self.uart_drivers.push((pin.to_owned(), driver));
+                self.uart_drivers.len() - 1
+            }
+        };
+        self.uart_drivers[index].1.send(&self.mcu, data);
+        Ok(())
+    }
+}
+
+impl Mcu {
+    /// Executes one instruction, driving and sampling the UART pins
+    fn step_once(&mut self) {
+        for (_, driver) in &mut self.uart_drivers {
+            driver.drive(&mut self.mcu);
+        }
+        self.mcu.step();
+        for (_, uart) in &mut self.uarts {
+            uart.sample(&self.mcu);
+        }
+    }
+
+    /// Device clock frequency
+    fn clock_hz(&self) -> u64 {
+        self.mcu.get_speed() as u64 * 1000
+    }
+
+    /// Steps until cycle `end`, a breakpoint or `pc`, always running at least
+    /// one instruction. Returns true if stopped at a breakpoint.
+    fn run_to_cycle(&mut self, end: usize, pc: Option<u16>) -> bool {
+        while self.mcu.get_cycle_count() < end {
+            self.step_once();
+            let current = self.mcu.get_program_counter();
+            if pc == Some(current) {
+                return false;
+            }
+            if self.mcu.get_breakpoints().contains(&current) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn check_register(register: u8) -> PyResult<()> {
+    if register < 32 {
+        Ok(())
+    } else {
+        Err(PyIndexError::new_err(format!(
+            "Invalid register r{}",
+            register
+        )))
+    }
+}
+
+fn parse_pin(pin: &str, mcu: &mcu::Mcu) -> PyResult<Probe> {
+    match Probe::parse(pin, mcu.get_ports()) {
+        Some(probe @ Probe::Pin { .. }) => Ok(probe),
+        _ => Err(PyValueError::new_err(format!("Invalid pin: {}", pin))),
+    }
+}
@@ -0,0 +1,113 @@
+"""Tests of the avogadro module, running the blink firmware of the Rust tests.
+
+Run with `maturin develop && pytest` from the `python` folder.
+"""
+from pathlib import Path
+
+import pytest
+
+import avogadro
+
+BLINK = Path(__file__).resolve().parents[2] / "avogadro" / "tests" / "blink.bin"
+PORTB = 0x38
+# First instruction of the blink loop, `in r24, PORTB`
+LOOP = 0x36
+# The delay loop takes about 100000 cycles
+HALF_PERIOD = 100_005
+
+
+@pytest.fixture
+def mcu():
+    mcu = avogadro.Mcu("attiny85")
+    mcu.load_program(str(BLINK))
+    return mcu
+
+
+def test_unsupported_mcu():
+    with pytest.raises(ValueError):
+        avogadro.Mcu("z80")
+
+
+def test_missing_file():
+    with pytest.raises(OSError):
+        avogadro.Mcu().load_program("missing.bin")
+
+
+def test_step(mcu):
+    assert mcu.pc == 0
+    mcu.step()
+    assert mcu.pc == 0x1E
+    assert mcu.cycles == 2
+
+
+def test_blink(mcu):
+    assert mcu.run_until(LOOP)
+    assert not mcu.get_pin("PB0")
+    assert mcu.read_data(PORTB) == b"\x00"
+    for level in [True, False, True, False]:
+        mcu.run(HALF_PERIOD)
+        assert mcu.get_pin("PB0") == level
+        assert mcu.read_data(PORTB)[0] & 1 == level
+
+
+def test_breakpoint(mcu):
+    mcu.add_breakpoint(LOOP)
+    assert mcu.breakpoints == [LOOP]
+    assert mcu.run(1_000_000)
+    assert mcu.pc == LOOP
+    first = mcu.cycles
+    assert mcu.run(1_000_000)
+    assert mcu.cycles - first == pytest.approx(HALF_PERIOD, abs=10)
+    mcu.remove_breakpoint(LOOP)
+    assert not mcu.run(1000)
+
+
+def test_registers_and_flags(mcu):
+    assert mcu.run_until(LOOP)
+    # `ldi r25, 0x01` is the value xored into PORTB
+    assert mcu.get_register(25) == 1
+    assert mcu.registers[25] == 1
+    mcu.set_register(16, 0xAB)
+    assert mcu.get_register(16) == 0xAB
+    with pytest.raises(IndexError):
+        mcu.get_register(32)
+    flags = avogadro.Flags()
+    flags.carry = True
+    flags.zero = True
+    mcu.flags = flags
+    assert int(mcu.flags) == 0x03
+    assert mcu.flags == avogadro.Flags(0x03)
+
+
+def test_memory(mcu):
+    mcu.write_data(0x60, b"\x01\x02\x03")
+    assert mcu.read_data(0x60, 3) == b"\x01\x02\x03"
+    # `rjmp` to the reset handler
+    assert mcu.read_program(0, 2) == BLINK.read_bytes()[:2]
+    with pytest.raises(IndexError):
+        mcu.read_program(0x2000, 2)
+
+
+def test_input_pin(mcu):
+    # PB1 is an input, driven through PINB
+    mcu.set_pin("PB1", True)
+    assert mcu.get_pin("PB1")
+    assert mcu.read_data(PORTB - 2)[0] == 0x02
+    mcu.set_pin("PB1", False)
+    assert not mcu.get_pin("PB1")
+    with pytest.raises(ValueError):
+        mcu.set_pin("PORTB", True)
+
+
+def test_uart_loopback(mcu):
+    # Frames sent to input pin PB2 are decoded back by a monitor on it
+    mcu.monitor_uart("PB2", 9600, clock_hz=1_000_000)
+    mcu.send_uart("PB2", 9600, b"Hi", clock_hz=1_000_000)
+    assert mcu.get_pin("PB2")
+    # An idle bit and 2 frames of 10 bits, 104 cycles each at 1 MHz
+    mcu.run(21 * 105)
+    assert mcu.uart_output("PB2") == b"Hi"
+    with pytest.raises(ValueError):
+        mcu.uart_output("PB3")
+    with pytest.raises(ValueError):
+        mcu.send_uart("PX0", 9600, b"!")
@@ -1,10 +1,17 @@
 #include <QApplication>
+#include <iostream>
 #include "MainWindow.h"
+#include "avogadro.h"
 
 extern "C" {
-    int run_avogadro_gui(int argc, char *argv[], void* mcu) {
+    int run_avogadro_gui(int argc, char *argv[]) {
+        if (mcu_api_version() != AVOGADRO_API_VERSION) {
+            std::cerr << "Avogadro library API version mismatch" << std::endl;
+            return 1;
+        }
         QApplication app(argc, argv);
-        MainWindow mainWindow(0, mcu);
+        // The window owns the MCU, and destroys it
+        MainWindow mainWindow(0, mcu_create("attiny85"));
         mainWindow.show();
         return app.exec();
     }
@@ -2,18 +2,24 @@
 #define MAIN_WINDOW_H
 
 #include <QMainWindow>
+#include <QTimer>
+#include <set>
 #include "McuRunner.h"
 #include "McuWrapper.h"
 
 class MainWindow : public QMainWindow {
 public:
-    explicit MainWindow(QMainWindow *parent, void* mcu);
+    explicit MainWindow(QMainWindow *parent, AvogadroMcu* mcu);
     virtual ~MainWindow();
 private:
     /**
      * Executes a single instruction
      */
     void mcuStep() const;
+    /**
+     * Undoes the last executed instruction
+     */
+    void mcuStepBack() const;
     /**
      * Opens a QFileDialog and gets selected file name
      */
@@ -22,6 +28,19 @@ private:
      * Loads raw content of a binary file into program memory
      */
     void loadProgramFile();
+    /**
+     * Saves registers, memories and cycle count into a snapshot file
+     */
+    void saveStateFile();
+    /**
+     * Restores the MCU state from a snapshot file
+     */
+    void loadStateFile();
+    /**
+     * Asks for a program address and adds a breakpoint there, or removes
+     * the one it had
+     */
+    void toggleBreakpoint();
     /**
      * Opens online help
      */
@@ -39,6 +58,7 @@ private:
     void updateDecodedInstruction() const;
     void updateMemoryBank() const;
     void updateFlags() const;
+    void showFlags(unsigned char flags) const;
     /**
      * Fired when program counter line input changes
      */
@@ -51,8 +71,19 @@ private:
      * Fired when Start button is toggled
      */
     void mcuStartClicked(bool enabled);
+    /**
+     * Fired when a speed is chosen, from the speed combo box index
+     */
+    void onSpeedChanged(int index);
+    /**
+     * Shows the speed and registers while running, and stops at breakpoints
+     */
+    void pollRunner();
+    void updateAchievedSpeed() const;
     McuWrapper mcu;
     McuRunner runner;
+    QTimer speedTimer;
+    std::set<unsigned short> breakpoints;
 };
 
 #endif // MAIN_WINDOW_H
@@ -1,24 +1,56 @@
 #ifndef MCU_RUNNER_H
 #define MCU_RUNNER_H
 
-#include <QThread>
-#include <atomic>
+#include "avogadro.h"
 
 class McuWrapper;
 
-class McuRunner : public QThread {
-Q_OBJECT
+/**
+ * Runs the MCU of a wrapper on the simulator runner thread. The MCU is
+ * moved out of the wrapper while running, so the GUI never reads it while
+ * it's being stepped.
+ */
+class McuRunner {
 public:
-
-    McuRunner(McuWrapper& mcuWrapper);
-    void run() override;
+    explicit McuRunner(McuWrapper& mcuWrapper);
+    ~McuRunner();
+    McuRunner(const McuRunner&) = delete;
+    McuRunner& operator=(const McuRunner&) = delete;
+    void start();
+    /**
+     * Stops running and gives the MCU back to the wrapper
+     */
     void stop();
-
-signals:
-    void runnerFinished();
+    /**
+     * Paces running to multiplier times the MCU clock, like 1.0 for real
+     * time, or as fast as possible if 0. Kept for the next start.
+     */
+    void setSpeed(double multiplier);
+    /**
+     * Simulated clock cycles per second, measured every half second. 0
+     * when stopped.
+     */
+    double getAchievedSpeed() const;
+    /**
+     * Status after the last command handled by the runner, paused when
+     * stopped
+     */
+    McuRunStatus getStatus() const;
+    /**
+     * Gets registers, flags and cycle count while running. Returns false
+     * when stopped.
+     */
+    bool getState(McuRunnerState& state) const;
+    /**
+     * Stops running at program address pc. Sent to the runner while
+     * running, so it's kept by the MCU given back.
+     */
+    void addBreakpoint(short pc) const;
+    void removeBreakpoint(short pc) const;
 private:
     McuWrapper& mcuWrapper;
-    std::atomic<bool> running = false;
+    AvogadroRunner* runner = nullptr;
+    double speed = 1.0;
 };
 
 #endif // MCU_RUNNER_H
\ No newline at end of file
@@ -2,27 +2,49 @@
 #define MCU_WRAPPER_H
 #include <cstddef>
 #include <vector>
+#include "avogadro.h"
 
 class McuWrapper {
 public:
-    explicit McuWrapper(void* mcu);
+    /**
+     * Takes ownership of handle `mcu`, destroyed along with the wrapper
+     */
+    explicit McuWrapper(AvogadroMcu* mcu);
+    ~McuWrapper();
+    McuWrapper(const McuWrapper&) = delete;
+    McuWrapper& operator=(const McuWrapper&) = delete;
+    /**
+     * Gives up the MCU handle. Calls do nothing until another one is set
+     * with reset.
+     */
+    AvogadroMcu* release();
+    void reset(AvogadroMcu* mcu);
     void step() const;
-    void getRegisterArray(const unsigned char* buffer) const;
+    void getRegisterArray(unsigned char* buffer) const;
     void setRegisterArray(const unsigned char* buffer) const;
     void setRegister(char registerId, char value) const;
     short getProgramCounter() const;
     void setProgramCounter(short value) const;
     short getStackPointer() const;
     short getCurrentInstruction() const;
-    void displayCurrentInstruction(const char* buffer, std::size_t size) const;
+    void displayCurrentInstruction(char* buffer, std::size_t size) const;
     void loadBinFile(const char* filename, bool isProgram) const;
     void loadIhexFile(const char* filename) const;
     void getDataMemory(std::vector<char>& buffer) const;
     void getProgramMemory(std::vector<char>& buffer) const;
     unsigned char getDataByte(short int);
     unsigned char getFlags() const;
+    bool startVcd(const char* filename, const char* probes, unsigned long long clockHz) const;
+    bool stopVcd() const;
+    bool saveState(const char* filename) const;
+    bool loadState(const char* filename) const;
+    void setHistoryBudget(std::size_t budget) const;
+    bool stepBack() const;
+    bool reverseContinue() const;
+    void addBreakpoint(short pc) const;
+    void removeBreakpoint(short pc) const;
 private:
-    void* mcu;
+    AvogadroMcu* mcu;
 };
 
 #endif // MCU_WRAPPER_H
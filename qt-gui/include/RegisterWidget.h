@@ -14,7 +14,7 @@ public:
     void setMcu(const McuWrapper& mcu);
 private:
     void connectEvents();
-    McuWrapper mcu;
+    const McuWrapper* mcu;
 };
 
 #endif // REGISTER_WIDGET_H
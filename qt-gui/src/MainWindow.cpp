@@ -5,20 +5,28 @@
 #include "qhexedit.h"
 #include <cstddef>
 #include <QByteArray>
+#include <QComboBox>
 #include <QDesktopServices>
 #include <QFileDialog>
+#include <QInputDialog>
 #include <QLineEdit>
+#include <QMessageBox>
 #include <QPushButton>
 #include <QThread>
 
 const std::size_t NUM_REGISTERS = 32;
 const std::size_t DECODED_INSTRUCTION_BUF = 64;
+const std::size_t HISTORY_BUDGET = 64 * 1024 * 1024;
+// Multipliers of the speed combo box items, 0 runs as fast as possible
+const double SPEEDS[] = {0.1, 1.0, 0.0};
+const int SPEED_UPDATE_MS = 500;
 
-MainWindow::MainWindow(QMainWindow *parent, void* rustMcu)
+MainWindow::MainWindow(QMainWindow *parent, AvogadroMcu* rustMcu)
  : QMainWindow(parent), mcu(rustMcu), runner(mcu) {
     Ui::MainWindow window;
     window.setupUi(this);
     findChild<RegisterWidget*>("registerWidget")->setMcu(this->mcu);
+    this->mcu.setHistoryBudget(HISTORY_BUDGET);
     this->updateMcuStatus();
     connectEvents();
 }
@@ -30,6 +38,13 @@ void MainWindow::mcuStep() const {
     this->updateMcuStatus();
 }
 
+void MainWindow::mcuStepBack() const {
+    if (!this->mcu.stepBack()) {
+        statusBar()->showMessage(tr("No history left"), 2000);
+    }
+    this->updateMcuStatus();
+}
+
 void MainWindow::updateMcuStatus() const {
     updateProgramCounter();
     updateRegisters();
@@ -72,7 +87,10 @@ void MainWindow::updateDecodedInstruction() const {
 }
 
 void MainWindow::updateFlags() const {
-    unsigned char flags = this->mcu.getFlags();
+    showFlags(this->mcu.getFlags());
+}
+
+void MainWindow::showFlags(unsigned char flags) const {
     findChild<QCheckBox*>("iCheckBox")->setChecked((flags & 0x80) != 0);
     findChild<QCheckBox*>("tCheckBox")->setChecked((flags & 0x40) != 0);
     findChild<QCheckBox*>("hCheckBox")->setChecked((flags & 0x20) != 0);
@@ -92,19 +110,36 @@ void MainWindow::onProgramCounterChanged() const {
 void MainWindow::connectEvents() {
     QPushButton *stepButton = findChild<QPushButton *>("stepButton");
     QPushButton *startButton = findChild<QPushButton *>("startButton");
+    QPushButton *stepBackButton = findChild<QPushButton *>("stepBackButton");
     QAction *loadProgamFileMenuAction = findChild<QAction *>("loadProgamFileMenuAction");
+    QAction *saveStateMenuAction = findChild<QAction *>("saveStateMenuAction");
+    QAction *loadStateMenuAction = findChild<QAction *>("loadStateMenuAction");
+    QAction *toggleBreakpointMenuAction = findChild<QAction *>("toggleBreakpointMenuAction");
     QAction *gettingStartedMenuAction = findChild<QAction *>("gettingStartedMenuAction");
     QLineEdit *pcEdit = findChild<QLineEdit *>("pcEdit");
+    QComboBox *speedComboBox = findChild<QComboBox *>("speedComboBox");
     QObject::connect(stepButton, &QPushButton::clicked,
                      this, &MainWindow::mcuStep);
+    QObject::connect(stepBackButton, &QPushButton::clicked,
+                     this, &MainWindow::mcuStepBack);
     QObject::connect(startButton, &QPushButton::clicked,
                      this, &MainWindow::mcuStartClicked);
     QObject::connect(loadProgamFileMenuAction, &QAction::triggered,
                      this, &MainWindow::loadProgramFile);
+    QObject::connect(saveStateMenuAction, &QAction::triggered,
+                     this, &MainWindow::saveStateFile);
+    QObject::connect(loadStateMenuAction, &QAction::triggered,
+                     this, &MainWindow::loadStateFile);
+    QObject::connect(toggleBreakpointMenuAction, &QAction::triggered,
+                     this, &MainWindow::toggleBreakpoint);
     QObject::connect(gettingStartedMenuAction, &QAction::triggered,
                      this, &MainWindow::goToHelpUrl);
     QObject::connect(pcEdit, &NumericEdit::editingFinished,
                      this, &MainWindow::onProgramCounterChanged);
+    QObject::connect(speedComboBox, QOverload<int>::of(&QComboBox::currentIndexChanged),
+                     this, &MainWindow::onSpeedChanged);
+    QObject::connect(&speedTimer, &QTimer::timeout,
+                     this, &MainWindow::pollRunner);
 }
 
 void MainWindow::loadProgramFile() {
@@ -120,17 +155,98 @@ void MainWindow::loadProgramFile() {
     this->updateMemoryBank();
 }
 
+void MainWindow::saveStateFile() {
+    std::string filename = QFileDialog::getSaveFileName(this,
+        tr("Save state"), "",
+        tr("Avogadro state (*.avs);;All Files (*)")).toStdString();
+    if (filename.size() != 0 && !this->mcu.saveState(filename.c_str())) {
+        QMessageBox::warning(this, tr("Save state"), tr("Cannot save state file"));
+    }
+}
+
+void MainWindow::loadStateFile() {
+    std::string filename = QFileDialog::getOpenFileName(this,
+        tr("Load state"), "",
+        tr("Avogadro state (*.avs);;All Files (*)")).toStdString();
+    if (filename.size() != 0) {
+        if (!this->mcu.loadState(filename.c_str())) {
+            QMessageBox::warning(this, tr("Load state"), tr("Cannot load state file"));
+        }
+        this->updateMcuStatus();
+        this->updateMemoryBank();
+    }
+}
+
+void MainWindow::toggleBreakpoint() {
+    unsigned short pc = findChild<NumericEdit*>("pcEdit")->getWord();
+    bool ok = false;
+    QString text = QInputDialog::getText(this, tr("Toggle breakpoint"),
+        tr("Program address:"), QLineEdit::Normal,
+        QString("0x%1").arg(pc, 4, 16, QChar('0')), &ok);
+    if (!ok) {
+        return;
+    }
+    pc = text.toUShort(&ok, 0);
+    if (!ok) {
+        QMessageBox::warning(this, tr("Toggle breakpoint"), tr("Invalid address"));
+    } else if (this->breakpoints.erase(pc)) {
+        runner.removeBreakpoint(pc);
+        statusBar()->showMessage(tr("Breakpoint removed"), 2000);
+    } else {
+        this->breakpoints.insert(pc);
+        runner.addBreakpoint(pc);
+        statusBar()->showMessage(tr("Breakpoint added"), 2000);
+    }
+}
+
 void MainWindow::mcuStartClicked(const bool enabled) {
     QPushButton *startButton = findChild<QPushButton *>("startButton");
     if (enabled) {
         runner.start();
+        speedTimer.start(SPEED_UPDATE_MS);
         startButton->setText("Stop");
+        findChild<QPushButton *>("stepButton")->setEnabled(false);
+        findChild<QPushButton *>("stepBackButton")->setEnabled(false);
     } else {
         runner.stop();
+        speedTimer.stop();
+        findChild<QLabel*>("achievedSpeedLabel")->clear();
         startButton->setText("Start");
+        findChild<QPushButton *>("stepButton")->setEnabled(true);
+        findChild<QPushButton *>("stepBackButton")->setEnabled(true);
+        this->updateMcuStatus();
     }
 }
 
+void MainWindow::onSpeedChanged(const int index) {
+    if (index >= 0 && index < static_cast<int>(sizeof(SPEEDS) / sizeof(SPEEDS[0]))) {
+        runner.setSpeed(SPEEDS[index]);
+    }
+}
+
+void MainWindow::pollRunner() {
+    this->updateAchievedSpeed();
+    McuRunnerState state;
+    if (runner.getState(state)) {
+        findChild<NumericEdit*>("pcEdit")->setWord(state.program_counter);
+        findChild<NumericEdit*>("stackPointerEdit")->setWord(state.stack_pointer);
+        findChild<RegisterWidget*>("registerWidget")->updateRegisters(state.registers);
+        showFlags(state.flags);
+    }
+    if (runner.getStatus() == MCU_RUN_STATUS_BREAKPOINT) {
+        // Gives the MCU back, which refreshes the whole view
+        findChild<QPushButton *>("startButton")->setChecked(false);
+        this->mcuStartClicked(false);
+        statusBar()->showMessage(tr("Breakpoint reached"), 2000);
+    }
+}
+
+void MainWindow::updateAchievedSpeed() const {
+    double mhz = runner.getAchievedSpeed() / 1e6;
+    findChild<QLabel*>("achievedSpeedLabel")->setText(
+        tr("%1 MHz").arg(mhz, 0, 'f', 2));
+}
+
 void MainWindow::goToHelpUrl() const {
     QUrl helpUrl("https://mlafroce.github.io/avr-avogadro/getting-started");
     QDesktopServices::openUrl(helpUrl);
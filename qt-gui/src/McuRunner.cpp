@@ -1,17 +1,76 @@
 #include "McuRunner.h"
-#include "moc_McuRunner.cpp"
 
 #include "McuWrapper.h"
 
 McuRunner::McuRunner(McuWrapper& mcuWrapper) : mcuWrapper(mcuWrapper) {}
 
-void McuRunner::run() {
-    this->running.store(true);
-    while(this->running.load()) {
-        this->mcuWrapper.step();
+McuRunner::~McuRunner() {
+    this->stop();
+}
+
+void McuRunner::start() {
+    if (this->runner) {
+        return;
+    }
+    AvogadroMcu* mcu = this->mcuWrapper.release();
+    this->runner = mcu_runner_create(mcu);
+    if (!this->runner) {
+        // The handle is kept when it can't run
+        this->mcuWrapper.reset(mcu);
+        return;
     }
+    mcu_runner_set_speed(this->runner, this->speed);
+    mcu_runner_run(this->runner);
 }
 
 void McuRunner::stop() {
-    this->running.store(false);
+    if (!this->runner) {
+        return;
+    }
+    // Null if the simulator panicked, leaving the wrapper without an MCU
+    this->mcuWrapper.reset(mcu_runner_destroy(this->runner));
+    this->runner = nullptr;
+}
+
+void McuRunner::setSpeed(double multiplier) {
+    this->speed = multiplier;
+    if (this->runner) {
+        mcu_runner_set_speed(this->runner, multiplier);
+    }
+}
+
+double McuRunner::getAchievedSpeed() const {
+    double hz = 0.0;
+    if (this->runner) {
+        mcu_runner_get_achieved_speed(this->runner, &hz);
+    }
+    return hz;
+}
+
+McuRunStatus McuRunner::getStatus() const {
+    McuRunStatus status = MCU_RUN_STATUS_PAUSED;
+    if (this->runner) {
+        mcu_runner_get_status(this->runner, &status);
+    }
+    return status;
+}
+
+bool McuRunner::getState(McuRunnerState& state) const {
+    return this->runner && mcu_runner_get_state(this->runner, &state) == MCU_STATUS_OK;
+}
+
+void McuRunner::addBreakpoint(short pc) const {
+    if (this->runner) {
+        mcu_runner_add_breakpoint(this->runner, pc);
+    } else {
+        this->mcuWrapper.addBreakpoint(pc);
+    }
+}
+
+void McuRunner::removeBreakpoint(short pc) const {
+    if (this->runner) {
+        mcu_runner_remove_breakpoint(this->runner, pc);
+    } else {
+        this->mcuWrapper.removeBreakpoint(pc);
+    }
 }
@@ -1,34 +1,28 @@
 #include "McuWrapper.h"
 #include <vector>
 
-extern "C" {
-void mcu_step(void* mcu);
-void mcu_get_register_array(void* mcu, const unsigned char* buffer);
-void mcu_set_register_array(void* mcu, const unsigned char* buffer);
-void mcu_set_register(void* mcu, char registerId, char value);
-short mcu_get_program_counter(void* mcu);
-void mcu_set_program_counter(void* mcu, short value);
-short mcu_get_stack_pointer(void* mcu);
-short mcu_get_current_instruction(void* mcu);
-void mcu_display_current_instruction(void* mcu, const char* buffer, size_t size);
-void mcu_load_bin_file(void* mcu, const char* filename, bool isProgram);
-void mcu_load_ihex_file(void* mcu, const char* filename);
-size_t mcu_get_data_size(void* mcu);
-void mcu_get_data_memory(void* mcu, const char* buffer, size_t size);
-size_t mcu_get_program_size(void* mcu);
-void mcu_get_program_memory(void* mcu, const char* buffer, size_t size);
-unsigned char mcu_get_data_byte(void* mcu, short address);
-unsigned char mcu_get_flags(void* mcu);
-void mcu_set_flags(void* mcu, unsigned char flags);
-}
-
-McuWrapper::McuWrapper(void* mcu) : mcu(mcu) {}
+McuWrapper::McuWrapper(AvogadroMcu* mcu) : mcu(mcu) {}
+
+McuWrapper::~McuWrapper() {
+    mcu_destroy(this->mcu);
+}
+
+AvogadroMcu* McuWrapper::release() {
+    AvogadroMcu* mcu = this->mcu;
+    this->mcu = nullptr;
+    return mcu;
+}
+
+void McuWrapper::reset(AvogadroMcu* mcu) {
+    mcu_destroy(this->mcu);
+    this->mcu = mcu;
+}
 
 void McuWrapper::step() const {
     mcu_step(this->mcu);
 }
 
-void McuWrapper::getRegisterArray(const unsigned char* buffer) const {
+void McuWrapper::getRegisterArray(unsigned char* buffer) const {
     mcu_get_register_array(this->mcu, buffer);
 }
 
@@ -41,7 +35,9 @@ void McuWrapper::setRegister(char registerId, char value) const {
 }
 
 short McuWrapper::getProgramCounter() const {
-    return mcu_get_program_counter(this->mcu);
+    uint16_t value = 0;
+    mcu_get_program_counter(this->mcu, &value);
+    return value;
 }
 
 void McuWrapper::setProgramCounter(short value) const {
@@ -49,35 +45,45 @@ void McuWrapper::setProgramCounter(short value) const {
 }
 
 short McuWrapper::getStackPointer() const {
-    return mcu_get_stack_pointer(this->mcu);
+    uint16_t value = 0;
+    mcu_get_stack_pointer(this->mcu, &value);
+    return value;
 }
 
 short McuWrapper::getCurrentInstruction() const {
-    return mcu_get_current_instruction(this->mcu);
+    uint16_t value = 0;
+    mcu_get_current_instruction(this->mcu, &value);
+    return value;
 }
 
-void McuWrapper::displayCurrentInstruction(const char* buffer, std::size_t size) const {
-    mcu_display_current_instruction(this->mcu, buffer, size);
+void McuWrapper::displayCurrentInstruction(char* buffer, std::size_t size) const {
+    mcu_display_current_instruction(this->mcu, reinterpret_cast<uint8_t*>(buffer), size);
 }
 
 unsigned char McuWrapper::getDataByte(short address) {
-    return mcu_get_data_byte(this->mcu, address);
+    uint8_t value = 0;
+    mcu_get_data_byte(this->mcu, address, &value);
+    return value;
 }
 
 void McuWrapper::getDataMemory(std::vector<char>& buffer) const {
-    size_t buf_size = mcu_get_data_size(this->mcu);
+    size_t buf_size = 0;
+    mcu_get_data_size(this->mcu, &buf_size);
     buffer.resize(buf_size);
-    mcu_get_data_memory(this->mcu, buffer.data(), buf_size);
+    mcu_get_data_memory(this->mcu, reinterpret_cast<uint8_t*>(buffer.data()), buf_size);
 }
 
 void McuWrapper::getProgramMemory(std::vector<char>& buffer) const {
-    size_t buf_size = mcu_get_program_size(this->mcu);
+    size_t buf_size = 0;
+    mcu_get_program_size(this->mcu, &buf_size);
     buffer.resize(buf_size);
-    mcu_get_program_memory(this->mcu, buffer.data(), buf_size);
+    mcu_get_program_memory(this->mcu, reinterpret_cast<uint8_t*>(buffer.data()), buf_size);
 }
 
 unsigned char McuWrapper::getFlags() const {
-    return mcu_get_flags(this->mcu);
+    uint8_t value = 0;
+    mcu_get_flags(this->mcu, &value);
+    return value;
 }
 
 void McuWrapper::loadBinFile(const char* filename, bool isProgram) const {
@@ -87,3 +93,39 @@ void McuWrapper::loadBinFile(const char* filename, bool isProgram) const {
 void McuWrapper::loadIhexFile(const char* filename) const {
     mcu_load_ihex_file(this->mcu, filename);
 }
+
+bool McuWrapper::startVcd(const char* filename, const char* probes, unsigned long long clockHz) const {
+    return mcu_vcd_start(this->mcu, filename, probes, clockHz) == MCU_STATUS_OK;
+}
+
+bool McuWrapper::stopVcd() const {
+    return mcu_vcd_stop(this->mcu) == MCU_STATUS_OK;
+}
+
+bool McuWrapper::saveState(const char* filename) const {
+    return mcu_save_state(this->mcu, filename) == MCU_STATUS_OK;
+}
+
+bool McuWrapper::loadState(const char* filename) const {
+    return mcu_load_state(this->mcu, filename) == MCU_STATUS_OK;
+}
+
+void McuWrapper::setHistoryBudget(std::size_t budget) const {
+    mcu_set_history_budget(this->mcu, budget);
+}
+
+bool McuWrapper::stepBack() const {
+    return mcu_step_back(this->mcu) == MCU_STATUS_OK;
+}
+
+bool McuWrapper::reverseContinue() const {
+    return mcu_reverse_continue(this->mcu) == MCU_STATUS_OK;
+}
+
+void McuWrapper::addBreakpoint(short pc) const {
+    mcu_add_breakpoint(this->mcu, pc);
+}
+
+void McuWrapper::removeBreakpoint(short pc) const {
+    mcu_remove_breakpoint(this->mcu, pc);
+}
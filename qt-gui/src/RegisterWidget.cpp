@@ -8,7 +8,7 @@ const int NUM_REGISTERS = 32;
 const int NAME_BUF_SIZE = sizeof("rxxEdit") + 1;
 
 RegisterWidget::RegisterWidget(QWidget *parent) : 
-        QWidget(parent), mcu(0) {
+        QWidget(parent), mcu(nullptr) {
     Ui::RegisterWidget registerWidgetUi;
     registerWidgetUi.setupUi(this);
     connectEvents();
@@ -17,11 +17,13 @@ RegisterWidget::RegisterWidget(QWidget *parent) :
 RegisterWidget::~RegisterWidget() {}
 
 void RegisterWidget::onRegisterChanged(int id, int value) {
-    this->mcu.setRegister(id, value);
+    if (this->mcu) {
+        this->mcu->setRegister(id, value);
+    }
 }
 
 void RegisterWidget::setMcu(const McuWrapper& mcu) {
-    this->mcu = mcu;
+    this->mcu = &mcu;
 }
 
 void RegisterWidget::connectEvents() {
@@ -2,9 +2,9 @@ extern crate env_logger;
 extern crate ihex;
 extern crate log;
 
-use avr_avogadro::core::mcu::Mcu;
-use avr_avogadro::core::mcu_factory::McuFactory;
-use libc::{c_char, c_void};
+use avr_avogadro::ffi::mcu_wrapper::mcu_api_version;
+use libc::c_char;
+use log::info;
 use std::ffi::CString;
 
 #[link(name = "avogadrogui")]
@@ -13,11 +13,10 @@ use std::ffi::CString;
 #[link(name = "Qt5Core")]
 #[link(name = "stdc++")]
 extern "C" {
-    pub fn run_avogadro_gui(argc: usize, argv: *const *const c_char, mcu: *const c_void);
+    pub fn run_avogadro_gui(argc: usize, argv: *const *const c_char) -> i32;
 }
 
 fn main() {
-    let mcu = McuFactory::create("attiny85");
     // create a vector of zero terminated strings
     let args = std::env::args()
         .map(|arg| CString::new(arg).unwrap())
@@ -29,12 +28,11 @@ fn main() {
         .collect::<Vec<*const c_char>>();
     // Init logger
     env_logger::init();
+    // The GUI creates its MCU through the C API, referencing it here keeps
+    // the library linked
+    info!("Avogadro C API version {}", mcu_api_version());
     unsafe {
         #[cfg(not(test))]
-        run_avogadro_gui(
-            c_args.len(),
-            c_args.as_ptr(),
-            &mcu as *const Mcu as *const c_void,
-        );
+        std::process::exit(run_avogadro_gui(c_args.len(), c_args.as_ptr()));
     }
 }